@@ -0,0 +1,46 @@
+use std::path::PathBuf;
+
+use clap::{ArgAction, Parser, ValueHint};
+use tracing::{debug, info};
+use z33_emulator::{
+    parse,
+    parser::location::{AbsoluteLocation, MapLocation},
+};
+
+#[derive(Parser, Debug)]
+pub struct AstOpt {
+    /// Input file
+    #[clap(value_parser, value_hint = ValueHint::FilePath)]
+    input: PathBuf,
+
+    /// Pretty-print the JSON output
+    #[clap(short, long, action = ArgAction::SetTrue)]
+    pretty: bool,
+}
+
+impl AstOpt {
+    pub fn exec(&self) -> anyhow::Result<()> {
+        info!(path = ?self.input, "Reading program");
+        let preprocessor = crate::config::preprocessor()?.and_load(&self.input);
+
+        let source = preprocessor.preprocess(&self.input)?;
+        let source = source.as_str();
+
+        debug!("Parsing program");
+        let program = parse(source)?;
+
+        debug!("Transforming AST");
+        let ast = program.to_node();
+        let ast = ast.map_location(&AbsoluteLocation::<()>::default());
+
+        let stdout = std::io::stdout().lock();
+        if self.pretty {
+            serde_json::to_writer_pretty(stdout, &ast)?;
+        } else {
+            serde_json::to_writer(stdout, &ast)?;
+        }
+        println!();
+
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use clap::{ArgAction, Parser, ValueHint};
+use tracing::info;
+use z33_emulator::{compiler::artifacts, runtime::ProcessorError};
+
+use super::run::{build, BuildOpt};
+
+#[derive(Parser, Debug)]
+pub struct CfgOpt {
+    /// Input file. Defaults to the main program of the project
+    #[clap(value_parser, value_hint = ValueHint::FilePath)]
+    input: Option<PathBuf>,
+
+    /// Start label. Defaults to the entrypoint of the project, or `main`
+    #[clap(value_parser)]
+    entrypoint: Option<String>,
+
+    /// Write the graph to this file, instead of the standard output
+    #[clap(long, value_parser, value_hint = ValueHint::FilePath)]
+    dot: Option<PathBuf>,
+
+    /// Run the program first, and show how many times each block was executed
+    #[clap(long, action = ArgAction::SetTrue)]
+    profile: bool,
+
+    /// Maximum number of instructions executed by the profile run
+    #[clap(long, value_parser, default_value_t = 1_000_000, requires = "profile")]
+    max_steps: usize,
+
+    #[clap(flatten)]
+    build: BuildOpt,
+}
+
+impl CfgOpt {
+    pub fn exec(&self) -> anyhow::Result<()> {
+        let config = crate::config::config()?;
+        let input = config.input(self.input.as_deref())?;
+        let entrypoint = config.entrypoint(self.entrypoint.as_deref());
+        let (_, mut computer, debug_info) = build(&input, entrypoint, &self.build)?;
+
+        let counts = if self.profile {
+            info!("Profiling program");
+            let entry = computer.registers.pc;
+            let mut counts: HashMap<_, usize> = HashMap::new();
+            for _ in 0..self.max_steps {
+                *counts.entry(computer.registers.pc).or_default() += 1;
+                match computer.step() {
+                    Ok(()) | Err(ProcessorError::Breakpoint) => {}
+                    Err(e) => {
+                        info!("Program stopped: {e}");
+                        break;
+                    }
+                }
+            }
+
+            // The graph is explored from the entrypoint
+            computer.registers.pc = entry;
+            Some(counts)
+        } else {
+            None
+        };
+
+        let dot = artifacts::dot_control_flow(&computer, &debug_info, counts.as_ref());
+        if let Some(path) = &self.dot {
+            info!(path = ?path, "Writing control flow graph");
+            std::fs::write(path, dot)?;
+        } else {
+            print!("{dot}");
+        }
+
+        Ok(())
+    }
+}
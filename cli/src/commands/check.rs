@@ -0,0 +1,31 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueHint};
+use tracing::info;
+
+use super::run::{build, BuildOpt};
+
+#[derive(Parser, Debug)]
+pub struct CheckOpt {
+    /// Input file. Defaults to the main program of the project
+    #[clap(value_parser, value_hint = ValueHint::FilePath)]
+    input: Option<PathBuf>,
+
+    /// Start label. Defaults to the entrypoint of the project, or `main`
+    #[clap(value_parser)]
+    entrypoint: Option<String>,
+
+    #[clap(flatten)]
+    build: BuildOpt,
+}
+
+impl CheckOpt {
+    pub fn exec(&self) -> anyhow::Result<()> {
+        let config = crate::config::config()?;
+        let input = config.input(self.input.as_deref())?;
+        let entrypoint = config.entrypoint(self.entrypoint.as_deref());
+        let (_, _, debug_info) = build(&input, entrypoint, &self.build)?;
+        info!(warnings = debug_info.warnings.len(), "The program compiles");
+        Ok(())
+    }
+}
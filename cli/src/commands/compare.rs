@@ -0,0 +1,56 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum, ValueHint};
+use tracing::info;
+
+use crate::interactive::SessionFile;
+
+/// Format of the differences
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum CompareFormat {
+    /// One `%a: 0 -> 5 (0x5)` line per changed register or memory cell
+    Text,
+
+    /// An object with the lists of changed registers and memory cells
+    Json,
+}
+
+#[derive(Parser, Debug)]
+pub struct CompareOpt {
+    /// Session file with the state to compare from, as saved by "session save"
+    #[clap(value_parser, value_hint = ValueHint::FilePath)]
+    before: PathBuf,
+
+    /// Session file with the state to compare to
+    #[clap(value_parser, value_hint = ValueHint::FilePath)]
+    after: PathBuf,
+
+    /// Format of the differences
+    #[clap(long, value_enum, default_value = "text", value_name = "FORMAT")]
+    report: CompareFormat,
+}
+
+impl CompareOpt {
+    pub fn exec(&self) -> anyhow::Result<()> {
+        let before = SessionFile::read(&self.before)?;
+        let after = SessionFile::read(&self.after)?;
+        let diff = before.snapshot.diff(&after.snapshot);
+
+        let mut stdout = std::io::stdout().lock();
+        match self.report {
+            CompareFormat::Text => {
+                if diff.is_empty() {
+                    info!("Both states are the same");
+                }
+                write!(stdout, "{diff}")?;
+            }
+            CompareFormat::Json => {
+                serde_json::to_writer_pretty(&mut stdout, &diff)?;
+                writeln!(stdout)?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -6,7 +6,6 @@ use tracing::{debug, info};
 use z33_emulator::{
     parse,
     parser::location::{AbsoluteLocation, MapLocation},
-    preprocessor::{NativeFilesystem, Preprocessor},
 };
 
 #[derive(Parser, Debug)]
@@ -18,15 +17,14 @@ pub struct DumpOpt {
 
 impl DumpOpt {
     pub fn exec(&self) -> anyhow::Result<()> {
-        let fs = NativeFilesystem::from_env()?;
         info!(path = ?self.input, "Reading program");
-        let preprocessor = Preprocessor::new(fs).and_load(&self.input);
+        let preprocessor = crate::config::preprocessor()?.and_load(&self.input);
 
         let source = preprocessor.preprocess(&self.input)?;
         let source = source.as_str();
 
         debug!("Parsing program");
-        let program = parse(source).unwrap(); // TODO: the error is tied to the input
+        let program = parse(source)?;
 
         debug!("Transforming AST");
         let ast = program.to_node();
@@ -0,0 +1,65 @@
+use clap::Parser;
+use tracing::info;
+use z33_emulator::examples::{example, Example, EXAMPLES};
+
+use super::run::run_to_reset;
+
+#[derive(Parser, Debug)]
+pub struct ExamplesOpt {
+    #[clap(subcommand)]
+    command: ExamplesCommand,
+}
+
+#[derive(Parser, Debug)]
+enum ExamplesCommand {
+    /// List the bundled examples
+    List,
+
+    /// Print the source of an example, to save it and edit it
+    Show {
+        #[clap(value_parser)]
+        name: String,
+    },
+
+    /// Run an example from its "main" label, and show the registers once it ends
+    Run {
+        #[clap(value_parser)]
+        name: String,
+    },
+}
+
+fn find(name: &str) -> anyhow::Result<&'static Example> {
+    example(name).ok_or_else(|| {
+        let names: Vec<_> = EXAMPLES.iter().map(|example| example.name).collect();
+        anyhow::anyhow!(
+            "unknown example {name:?}, expected one of: {}",
+            names.join(", ")
+        )
+    })
+}
+
+impl ExamplesOpt {
+    pub fn exec(&self) -> anyhow::Result<()> {
+        match &self.command {
+            ExamplesCommand::List => {
+                for example in EXAMPLES {
+                    println!("{:<11}{}", example.name, example.description);
+                }
+            }
+
+            ExamplesCommand::Show { name } => {
+                print!("{}", find(name)?.source);
+            }
+
+            ExamplesCommand::Run { name } => {
+                let example = find(name)?;
+                info!(file = %example.file_name(), "Running example");
+                let mut emulator = example.builder().build()?;
+                run_to_reset(emulator.computer_mut())?;
+                info!(registers = %emulator.computer().registers, "End of program");
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+use clap::Parser;
+use z33_emulator::codes::{explain, ErrorCode, EXPLANATIONS};
+
+#[derive(Parser, Debug)]
+pub struct ExplainOpt {
+    /// Code of the error, like E0007. Lists the codes if omitted
+    #[clap(value_parser)]
+    code: Option<String>,
+}
+
+impl ExplainOpt {
+    pub fn exec(&self) -> anyhow::Result<()> {
+        let Some(code) = &self.code else {
+            for explanation in EXPLANATIONS {
+                println!("{}  {}", explanation.code, explanation.title);
+            }
+            return Ok(());
+        };
+
+        let explanation = code
+            .parse::<ErrorCode>()
+            .ok()
+            .and_then(explain)
+            .ok_or_else(|| {
+                anyhow::anyhow!("unknown error code {code:?}, run \"z33-cli explain\" to list them")
+            })?;
+
+        println!("{}: {}\n", explanation.code, explanation.title);
+        print!("{}", explanation.text);
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{ArgAction, Parser, ValueEnum, ValueHint};
+use rayon::prelude::*;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+use z33_emulator::runtime::MachineConfig;
+
+use crate::grading::{Outcome, Script};
+
+/// Format of the results matrix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum GradeFormat {
+    /// One row per submission, with the status of each test and the number of passed tests
+    Csv,
+
+    /// The status and failure message of each test, for each submission
+    Json,
+}
+
+#[derive(Parser, Debug)]
+pub struct GradeOpt {
+    /// Test script, listing the tests run against each submission
+    #[clap(value_parser, value_hint = ValueHint::FilePath)]
+    script: PathBuf,
+
+    /// Submissions to grade. Glob patterns like "submissions/*/main.S" are expanded
+    #[clap(value_parser, required = true, value_hint = ValueHint::AnyPath)]
+    submissions: Vec<String>,
+
+    /// Maximum number of instructions executed by each test
+    #[clap(long, value_parser, default_value_t = 1_000_000)]
+    max_steps: usize,
+
+    /// Maximum number of cycles taken by each test
+    #[clap(long, value_parser)]
+    max_cycles: Option<usize>,
+
+    /// Maximum number of memory writes done by each test
+    #[clap(long, value_parser)]
+    max_memory_writes: Option<usize>,
+
+    /// Maximum number of bytes written on the console by each test
+    #[clap(long, value_parser, default_value_t = 1 << 20)]
+    max_output_bytes: usize,
+
+    /// Maximum time taken by each test, in milliseconds
+    #[clap(long, value_parser, default_value_t = 10_000, value_name = "MS")]
+    timeout: u64,
+
+    /// Raise an exception on signed arithmetic overflows, instead of wrapping around
+    #[clap(long, action = ArgAction::SetTrue)]
+    trap_on_overflow: bool,
+
+    /// Format of the results
+    #[clap(long, value_enum, default_value = "csv", value_name = "FORMAT")]
+    report: GradeFormat,
+}
+
+/// Quote a CSV field if needed
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+impl GradeOpt {
+    /// Expand the glob patterns, keeping the paths which match nothing so they are reported
+    fn submissions(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for pattern in &self.submissions {
+            let matches = glob::glob(pattern)?.collect::<Result<Vec<_>, _>>()?;
+            if matches.is_empty() {
+                paths.push(PathBuf::from(pattern));
+            } else {
+                paths.extend(matches);
+            }
+        }
+        Ok(paths)
+    }
+
+    pub fn exec(&self) -> anyhow::Result<()> {
+        info!(path = ?self.script, "Reading test script");
+        let script: Script = std::fs::read_to_string(&self.script)?.parse()?;
+        let machine = &crate::config::config()?.machine;
+        let config = MachineConfig {
+            max_steps: Some(self.max_steps),
+            max_cycles: self.max_cycles,
+            max_memory_writes: self.max_memory_writes,
+            timeout: Some(Duration::from_millis(self.timeout)),
+            max_output_bytes: Some(self.max_output_bytes),
+            trap_on_overflow: self.trap_on_overflow,
+            arch: machine.word_bits.unwrap_or_default(),
+            endianness: machine.endianness.unwrap_or_default(),
+            ..MachineConfig::default()
+        };
+
+        // Submissions are graded in parallel, and reported in the order they were given
+        let results = self
+            .submissions()?
+            .into_par_iter()
+            .map(|path| {
+                info!(path = ?path, "Grading submission");
+                let preprocessor = crate::config::preprocessor()?.and_load(&path);
+                let outcomes: Vec<Outcome> = match preprocessor.preprocess(&path) {
+                    Ok(source) => script.run(&source, config),
+                    Err(e) => {
+                        warn!(path = ?path, error = %e, "Could not preprocess the submission");
+                        script
+                            .tests
+                            .iter()
+                            .map(|_| Outcome::Error(e.to_string()))
+                            .collect()
+                    }
+                };
+                Ok((path, outcomes))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut stdout = std::io::stdout().lock();
+        match self.report {
+            GradeFormat::Csv => {
+                let names: Vec<_> = script.tests.iter().map(|test| quote(&test.name)).collect();
+                writeln!(stdout, "submission,{},passed,total", names.join(","))?;
+                for (path, outcomes) in &results {
+                    let statuses: Vec<_> = outcomes.iter().map(Outcome::status).collect();
+                    let passed = outcomes
+                        .iter()
+                        .filter(|outcome| matches!(outcome, Outcome::Passed))
+                        .count();
+                    writeln!(
+                        stdout,
+                        "{},{},{passed},{}",
+                        quote(&path.display().to_string()),
+                        statuses.join(","),
+                        outcomes.len()
+                    )?;
+                }
+            }
+            GradeFormat::Json => {
+                let submissions: Vec<Value> = results
+                    .iter()
+                    .map(|(path, outcomes)| {
+                        let tests: Vec<Value> = script
+                            .tests
+                            .iter()
+                            .zip(outcomes)
+                            .map(|(test, outcome)| {
+                                json!({
+                                    "name": test.name,
+                                    "status": outcome.status(),
+                                    "message": outcome.message(),
+                                })
+                            })
+                            .collect();
+                        let passed = outcomes
+                            .iter()
+                            .filter(|outcome| matches!(outcome, Outcome::Passed))
+                            .count();
+                        json!({
+                            "path": path,
+                            "passed": passed,
+                            "total": outcomes.len(),
+                            "tests": tests,
+                        })
+                    })
+                    .collect();
+                serde_json::to_writer_pretty(&mut stdout, &json!({ "submissions": submissions }))?;
+                writeln!(stdout)?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,25 @@
+use clap::Parser;
+use z33_emulator::runtime::{help, mnemonics};
+
+#[derive(Parser, Debug)]
+pub struct IsaOpt {
+    /// Only show the documentation of this instruction
+    #[clap(value_parser)]
+    mnemonic: Option<String>,
+}
+
+impl IsaOpt {
+    pub fn exec(&self) -> anyhow::Result<()> {
+        if let Some(mnemonic) = &self.mnemonic {
+            let text = help(mnemonic)
+                .ok_or_else(|| anyhow::anyhow!("unknown instruction {mnemonic:?}"))?;
+            println!("{}: {text}", mnemonic.to_lowercase());
+        } else {
+            for mnemonic in mnemonics() {
+                println!("{mnemonic:<9}{}", help(mnemonic).unwrap_or_default());
+            }
+        }
+
+        Ok(())
+    }
+}
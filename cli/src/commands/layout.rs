@@ -0,0 +1,47 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueHint};
+use tracing::{debug, info};
+use z33_emulator::{
+    compiler::{artifacts, layout},
+    parse,
+    parser::location::{AbsoluteLocation, MapLocation},
+};
+
+#[derive(Parser, Debug)]
+pub struct LayoutOpt {
+    /// Input file
+    #[clap(value_parser, value_hint = ValueHint::FilePath)]
+    input: PathBuf,
+
+    /// Draw the memory map as an SVG diagram in this file, instead of listing the cells
+    #[clap(long, value_parser, value_hint = ValueHint::FilePath)]
+    svg: Option<PathBuf>,
+}
+
+impl LayoutOpt {
+    pub fn exec(&self) -> anyhow::Result<()> {
+        info!(path = ?self.input, "Reading program");
+        let preprocessor = crate::config::preprocessor()?.and_load(&self.input);
+        let source = preprocessor.preprocess(&self.input)?;
+
+        debug!("Parsing program");
+        let program = parse(&source)?;
+        let program = program.map_location(&AbsoluteLocation::<()>::default());
+
+        debug!("Laying out memory");
+        let layout = layout(program.inner)?;
+
+        if let Some(path) = &self.svg {
+            info!(path = ?path, "Writing memory map");
+            std::fs::write(path, artifacts::svg_memory_map(&layout.memory_map()))?;
+            return Ok(());
+        }
+
+        for (address, content) in layout.memory_report() {
+            println!("{address:>5}  {content}");
+        }
+
+        Ok(())
+    }
+}
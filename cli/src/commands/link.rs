@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+use clap::{ArgAction, Parser, ValueHint};
+use rayon::prelude::*;
+use tracing::info;
+use z33_emulator::compiler::{
+    artifacts,
+    object::{link, Object},
+};
+
+use super::run::run_to_reset;
+
+#[derive(Parser, Debug)]
+pub struct LinkOpt {
+    /// Object files, as produced by the `object` subcommand
+    #[clap(value_parser, required = true, value_hint = ValueHint::FilePath)]
+    objects: Vec<PathBuf>,
+
+    /// Start label
+    #[clap(short, long, value_parser, default_value = "main")]
+    entrypoint: String,
+
+    /// Write the memory image of the linked program to this file
+    #[clap(short, long, value_parser, value_hint = ValueHint::FilePath)]
+    output: Option<PathBuf>,
+
+    /// Print the address of each label
+    #[clap(short, long, action = ArgAction::SetTrue)]
+    map: bool,
+
+    /// Run the linked program
+    #[clap(short, long, action = ArgAction::SetTrue)]
+    run: bool,
+}
+
+impl LinkOpt {
+    pub fn exec(&self) -> anyhow::Result<()> {
+        let objects = self
+            .objects
+            .par_iter()
+            .map(|path| {
+                info!(path = ?path, "Reading object");
+                let file = std::fs::File::open(path)?;
+                let object: Object = serde_json::from_reader(std::io::BufReader::new(file))?;
+                Ok(object)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let (mut computer, debug_info) = link(&objects, &self.entrypoint)?;
+
+        if self.map {
+            print!("{}", artifacts::map(&debug_info));
+        }
+
+        if let Some(output) = &self.output {
+            info!(path = ?output, "Writing memory image");
+            std::fs::write(output, artifacts::image(&computer))?;
+        }
+
+        if self.run {
+            info!("Running program");
+            run_to_reset(&mut computer)?;
+            info!(registers = %computer.registers, "End of program");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueHint};
+use codespan_reporting::files::SimpleFiles;
+use tracing::{debug, info};
+use z33_emulator::{
+    compile,
+    compiler::dataflow::check_registers,
+    parse,
+    parser::location::{AbsoluteLocation, MapLocation},
+};
+
+use crate::diagnostics::report_warnings;
+
+#[derive(Parser, Debug)]
+pub struct LintOpt {
+    /// Input file. Defaults to the main program of the project
+    #[clap(value_parser, value_hint = ValueHint::FilePath)]
+    input: Option<PathBuf>,
+
+    /// Start label. Defaults to the entrypoint of the project, or `main`
+    #[clap(value_parser)]
+    entrypoint: Option<String>,
+}
+
+impl LintOpt {
+    pub fn exec(&self) -> anyhow::Result<()> {
+        let config = crate::config::config()?;
+        let input = config.input(self.input.as_deref())?;
+        let entrypoint = config.entrypoint(self.entrypoint.as_deref());
+        info!(path = ?input, "Reading program");
+        let preprocessor = config.preprocessor()?.and_load(&input);
+        let (source, map) = preprocessor.preprocess_with_map(&input)?;
+
+        debug!("Parsing program");
+        let program = parse(&source)?;
+        let program = program.map_location(&AbsoluteLocation::<()>::default());
+
+        debug!("Compiling program");
+        let (computer, debug_info) = compile(program.inner, entrypoint)?;
+
+        debug!("Checking the registers");
+        let mut warnings = debug_info.warnings.clone();
+        warnings.extend(check_registers(&computer, &debug_info));
+
+        let mut files = SimpleFiles::new();
+        let file_id = files.add("preprocessed", source.as_str());
+        let count = report_warnings(&warnings, &files, file_id, &map, preprocessor.sources())?;
+        if count > 0 {
+            anyhow::bail!("found {count} warning(s)");
+        }
+
+        Ok(())
+    }
+}
@@ -1,26 +1,164 @@
 use clap::Parser;
 
+mod ast;
+mod cfg;
+mod check;
+mod compare;
 mod completion;
 mod dump;
+mod examples;
+mod explain;
+mod grade;
+mod isa;
+mod layout;
+mod link;
+mod lint;
+mod new;
+mod object;
 mod preprocess;
 mod print;
+mod resume;
 mod run;
+mod stats;
+mod test;
+#[cfg(not(target_os = "wasi"))]
+mod tui;
+mod xref;
 
 #[derive(Parser)]
 pub enum Subcommand {
+    /// Create a project with a starter program and its tests
+    #[clap(after_help = "Examples:
+  z33-cli new my-project
+  cd my-project && z33-cli run")]
+    New(self::new::NewOpt),
+
     /// Preprocess, compile and run a program
-    Run(self::run::RunOpt),
+    #[clap(after_help = "Examples:
+  z33-cli run program.S main
+  z33-cli run
+  z33-cli run program.S main --interactive
+  z33-cli run program.S main --trace pretty --watch %a
+  z33-cli run program.S main -- first second")]
+    Run(Box<self::run::RunOpt>),
+
+    /// Preprocess and compile a program, reporting the errors and warnings without running it
+    #[clap(after_help = "Examples:
+  z33-cli check program.S main
+  z33-cli check --deny-warnings")]
+    Check(self::check::CheckOpt),
+
+    /// Resume a debugging session saved in interactive mode, or inspect a core file
+    #[clap(
+        alias = "debug",
+        after_help = "Examples:
+  z33-cli resume session.z33sess
+  z33-cli debug --core core.json"
+    )]
+    Resume(self::resume::ResumeOpt),
+
+    /// Debug a program in a full-screen interface
+    #[cfg(not(target_os = "wasi"))]
+    #[clap(after_help = "Examples:
+  z33-cli tui program.S main")]
+    Tui(self::tui::TuiOpt),
+
+    /// Compile a program to a relocatable object file
+    #[clap(after_help = "Examples:
+  z33-cli object lib.S -o lib.o
+  z33-cli object main.S lib.S list.S")]
+    Object(self::object::ObjectOpt),
+
+    /// Link object files together
+    #[clap(after_help = "Examples:
+  z33-cli link main.o lib.o --run
+  z33-cli link main.o lib.o -o image.bin --map")]
+    Link(self::link::LinkOpt),
 
     /// Run the preprocessor
+    #[clap(after_help = "Examples:
+  z33-cli preprocess program.S --annotate")]
     Preprocess(self::preprocess::PreprocessOpt),
 
     /// Print the program as parsed
+    #[clap(after_help = "Examples:
+  z33-cli print program.S")]
     Print(self::print::PrintOpt),
 
     /// Dump the AST of the program
+    #[clap(after_help = "Examples:
+  z33-cli dump program.S")]
     Dump(self::dump::DumpOpt),
 
+    /// Export the AST of the program as JSON
+    #[clap(after_help = "Examples:
+  z33-cli ast program.S --pretty")]
+    Ast(self::ast::AstOpt),
+
+    /// Show where each line of the program is placed in memory
+    #[clap(after_help = "Examples:
+  z33-cli layout program.S --svg map.svg")]
+    Layout(self::layout::LayoutOpt),
+
+    /// Report the compilation warnings, along with the misuses of the registers
+    #[clap(after_help = "Examples:
+  z33-cli lint program.S main")]
+    Lint(self::lint::LintOpt),
+
+    /// Draw the control flow graph of a program in the Graphviz format
+    #[clap(after_help = "Examples:
+  z33-cli cfg program.S --dot graph.dot
+  z33-cli cfg program.S main --profile")]
+    Cfg(self::cfg::CfgOpt),
+
+    /// List the labels with their definition and references
+    #[clap(after_help = "Examples:
+  z33-cli xref program.S")]
+    Xref(self::xref::XrefOpt),
+
+    /// Report the instructions, addressing modes and directives used by a set of programs
+    #[clap(after_help = "Examples:
+  z33-cli stats submissions/*.S --report json")]
+    Stats(self::stats::StatsOpt),
+
+    /// Run the tests of the project
+    #[clap(after_help = "Examples:
+  z33-cli test
+  z33-cli test other-tests.txt --input solution.S")]
+    Test(self::test::TestOpt),
+
+    /// Show the registers and memory cells which differ between two saved debugging sessions
+    #[clap(after_help = "Examples:
+  z33-cli compare before.z33sess after.z33sess
+  z33-cli compare before.z33sess after.z33sess --report json")]
+    Compare(self::compare::CompareOpt),
+
+    /// Run a test script against many submissions and report the results
+    #[clap(after_help = "Examples:
+  z33-cli grade tests.txt 'submissions/*/main.S' --max-steps 100000")]
+    Grade(self::grade::GradeOpt),
+
+    /// List, show or run the bundled example programs
+    #[clap(after_help = "Examples:
+  z33-cli examples list
+  z33-cli examples run factorial")]
+    Examples(self::examples::ExamplesOpt),
+
+    /// Explain an error code in detail, with an example and how to fix it
+    #[clap(after_help = "Examples:
+  z33-cli explain
+  z33-cli explain E0008")]
+    Explain(self::explain::ExplainOpt),
+
+    /// List the instructions, or show the documentation of one of them
+    #[clap(after_help = "Examples:
+  z33-cli isa
+  z33-cli isa add")]
+    Isa(self::isa::IsaOpt),
+
     /// Generate shell completion
+    #[clap(after_help = "Examples:
+  z33-cli completion bash > /etc/bash_completion.d/z33-cli")]
     Completion(self::completion::CompletionOpt),
 }
 
@@ -28,10 +166,29 @@ impl Subcommand {
     /// Run a subcommand
     pub fn exec(self) -> anyhow::Result<()> {
         match self {
+            Self::New(opt) => opt.exec()?,
             Self::Run(opt) => opt.exec()?,
+            Self::Check(opt) => opt.exec()?,
+            Self::Resume(opt) => opt.exec()?,
+            #[cfg(not(target_os = "wasi"))]
+            Self::Tui(opt) => opt.exec()?,
+            Self::Object(opt) => opt.exec()?,
+            Self::Link(opt) => opt.exec()?,
             Self::Preprocess(opt) => opt.exec()?,
             Self::Print(opt) => opt.exec()?,
             Self::Dump(opt) => opt.exec()?,
+            Self::Ast(opt) => opt.exec()?,
+            Self::Layout(opt) => opt.exec()?,
+            Self::Lint(opt) => opt.exec()?,
+            Self::Cfg(opt) => opt.exec()?,
+            Self::Xref(opt) => opt.exec()?,
+            Self::Stats(opt) => opt.exec()?,
+            Self::Test(opt) => opt.exec()?,
+            Self::Compare(opt) => opt.exec()?,
+            Self::Grade(opt) => opt.exec()?,
+            Self::Examples(opt) => opt.exec()?,
+            Self::Explain(opt) => opt.exec()?,
+            Self::Isa(opt) => opt.exec()?,
             Self::Completion(opt) => opt.exec(),
         }
 
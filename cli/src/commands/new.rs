@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueHint};
+use tracing::info;
+
+/// Files of a new project, with their content
+const TEMPLATE: &[(&str, &str)] = &[
+    ("main.S", include_str!("../template/main.S")),
+    ("tests.txt", include_str!("../template/tests.txt")),
+    ("z33.toml", include_str!("../template/z33.toml")),
+];
+
+#[derive(Parser, Debug)]
+pub struct NewOpt {
+    /// Directory to create the project in. It must not exist, or be empty
+    #[clap(value_parser, value_hint = ValueHint::DirPath)]
+    path: PathBuf,
+}
+
+impl NewOpt {
+    pub fn exec(&self) -> anyhow::Result<()> {
+        if self.path.exists() && std::fs::read_dir(&self.path)?.next().is_some() {
+            anyhow::bail!("{} already exists and is not empty", self.path.display());
+        }
+
+        std::fs::create_dir_all(&self.path)?;
+        for (name, content) in TEMPLATE {
+            let path = self.path.join(name);
+            info!(path = ?path, "Creating file");
+            std::fs::write(path, content)?;
+        }
+
+        info!(
+            "Project created. Run it with \"z33-cli run\" and test it with \"z33-cli test\" from {}",
+            self.path.display()
+        );
+        Ok(())
+    }
+}
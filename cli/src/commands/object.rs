@@ -0,0 +1,61 @@
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, ValueHint};
+use rayon::prelude::*;
+use tracing::{debug, info};
+use z33_emulator::{
+    compiler::object::Object,
+    parse,
+    parser::location::{AbsoluteLocation, MapLocation},
+};
+
+#[derive(Parser, Debug)]
+pub struct ObjectOpt {
+    /// Input files, assembled in parallel
+    #[clap(value_parser, required = true, value_hint = ValueHint::FilePath)]
+    inputs: Vec<PathBuf>,
+
+    /// Output file, when there is a single input. Defaults to each input file with the `.o`
+    /// extension
+    #[clap(short, long, value_parser, value_hint = ValueHint::FilePath)]
+    output: Option<PathBuf>,
+}
+
+/// Assemble a single file into an object file
+fn assemble(input: &Path, output: &Path) -> anyhow::Result<()> {
+    info!(path = ?input, "Reading program");
+    let preprocessor = crate::config::preprocessor()?.and_load(input);
+
+    let source = preprocessor.preprocess(input)?;
+
+    debug!(path = ?input, "Parsing program");
+    let program = parse(&source)?;
+    let program = program.map_location(&AbsoluteLocation::<()>::default());
+
+    debug!(path = ?input, "Assembling object");
+    let name = input.to_string_lossy();
+    let object = Object::assemble(&name, program.inner)?;
+
+    info!(path = ?output, "Writing object");
+    let file = std::fs::File::create(output)?;
+    serde_json::to_writer(file, &object)?;
+
+    Ok(())
+}
+
+impl ObjectOpt {
+    pub fn exec(&self) -> anyhow::Result<()> {
+        if self.output.is_some() && self.inputs.len() > 1 {
+            anyhow::bail!("--output can only be used with a single input file");
+        }
+
+        // Each file is a translation unit of its own, which does not depend on the others
+        self.inputs.par_iter().try_for_each(|input| {
+            let output = self
+                .output
+                .clone()
+                .unwrap_or_else(|| input.with_extension("o"));
+            assemble(input, &output)
+        })
+    }
+}
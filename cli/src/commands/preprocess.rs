@@ -1,24 +1,39 @@
 use std::path::PathBuf;
 
-use clap::{Parser, ValueHint};
+use clap::{ArgAction, Parser, ValueHint};
 use tracing::info;
 
-use z33_emulator::preprocessor::{NativeFilesystem, Preprocessor};
-
 #[derive(Parser, Debug)]
 pub struct PreprocessOpt {
     /// Input file
     #[clap(value_parser, value_hint = ValueHint::FilePath)]
     input: PathBuf,
+
+    /// Annotate the lines with the files they were included from and the macros they expand
+    #[clap(long, action = ArgAction::SetTrue)]
+    annotate: bool,
+
+    /// Insert `#line` directives, so the output keeps pointing to the original files when
+    /// preprocessed again
+    #[clap(long, action = ArgAction::SetTrue, conflicts_with = "annotate")]
+    line_markers: bool,
 }
 
 impl PreprocessOpt {
     pub fn exec(&self) -> anyhow::Result<()> {
-        let fs = NativeFilesystem::from_env()?;
         info!(path = ?self.input, "Reading program");
-        let preprocessor = Preprocessor::new(fs).and_load(&self.input);
-        let source = preprocessor.preprocess(&self.input)?;
-        println!("{source}");
+        let preprocessor = crate::config::preprocessor()?.and_load(&self.input);
+        if self.annotate || self.line_markers {
+            let (source, map) = preprocessor.preprocess_with_map(&self.input)?;
+            if self.annotate {
+                println!("{}", map.annotate(&source));
+            } else {
+                println!("{}", map.line_markers(&source));
+            }
+        } else {
+            let source = preprocessor.preprocess(&self.input)?;
+            println!("{source}");
+        }
         Ok(())
     }
 }
@@ -2,10 +2,7 @@ use std::path::PathBuf;
 
 use clap::{Parser, ValueHint};
 use tracing::{debug, info};
-use z33_emulator::{
-    parse,
-    preprocessor::{NativeFilesystem, Preprocessor},
-};
+use z33_emulator::parse;
 
 #[derive(Parser, Debug)]
 pub struct PrintOpt {
@@ -16,15 +13,14 @@ pub struct PrintOpt {
 
 impl PrintOpt {
     pub fn exec(&self) -> anyhow::Result<()> {
-        let fs = NativeFilesystem::from_env()?;
         info!(path = ?self.input, "Reading program");
-        let preprocessor = Preprocessor::new(fs).and_load(&self.input);
+        let preprocessor = crate::config::preprocessor()?.and_load(&self.input);
 
         let source = preprocessor.preprocess(&self.input)?;
         let source = source.as_str();
 
         debug!("Parsing program");
-        let program = parse(source).unwrap(); // TODO: the error is tied to the input
+        let program = parse(source)?;
         println!("{program}");
 
         Ok(())
@@ -0,0 +1,30 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueHint};
+use z33_emulator::runtime::{Computer, InterruptLog};
+
+use crate::interactive::resume_interactive;
+
+#[derive(Parser, Debug)]
+pub struct ResumeOpt {
+    /// Session file, as saved by "session save" in interactive mode
+    #[clap(value_parser, value_hint = ValueHint::FilePath, required_unless_present = "core")]
+    session: Option<PathBuf>,
+
+    /// Core file, as written by "run --core" when the program faulted, to inspect the state it
+    /// stopped in
+    #[clap(long, value_parser, value_hint = ValueHint::FilePath, conflicts_with = "session")]
+    core: Option<PathBuf>,
+}
+
+impl ResumeOpt {
+    pub fn exec(&self) -> anyhow::Result<()> {
+        let mut computer = Computer::default();
+        let mut interrupts = InterruptLog::new();
+        // One of them is required by the arguments
+        let Some(path) = self.session.as_ref().or(self.core.as_ref()) else {
+            anyhow::bail!("no session or core file given");
+        };
+        resume_interactive(&mut computer, path, &mut interrupts)
+    }
+}
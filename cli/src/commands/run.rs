@@ -1,190 +1,672 @@
-use std::collections::HashMap;
-use std::{path::PathBuf, process::exit};
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+use std::{
+    path::{Path, PathBuf},
+    process::exit,
+};
 
 use clap::{ArgAction, Parser, ValueHint};
 use codespan_reporting::diagnostic::{Diagnostic, Label};
 use codespan_reporting::files::SimpleFiles;
-use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
 use tracing::{debug, error, info};
-use z33_emulator::preprocessor::Preprocessor;
 use z33_emulator::{
-    compile,
-    compiler::CompilationError,
+    compiler::{compile_with, CompilationError, DebugInfo, MemoryScheme, Symbols},
+    constants::{Address, HEAP_PORT, TIMER_PORT},
     parse,
-    parser::location::{AbsoluteLocation, MapLocation},
-    preprocessor::NativeFilesystem,
+    parser::location::{AbsoluteLocation, LineIndex, MapLocation},
+    parser::register_fix,
+    runtime::{
+        Arch, Computer, ConventionChecker, Endianness, EntryState, Flags, Heap, HeapRegion,
+        InitPolicy, InterruptLog, InterruptSource, LoopDetector, MachineConfig, ProcessorError,
+        RegisterAssignment, SchedulerMetrics, Shadow, Snapshot, Timer,
+    },
 };
 
-use crate::interactive::run_interactive;
+use crate::diagnostics::{emit, origin_note, report_warnings};
+use crate::export::{ChromeTrace, Transfer, VcdTrace};
+use crate::interactive::{display_watches, run_interactive, FaultReport, SessionFile, Watch};
+use crate::metadata::metadata;
+use crate::trace::{TraceFormat, Tracer};
 
 #[derive(Parser, Debug)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct RunOpt {
-    /// Input file
+    /// Input file. Defaults to the main program of the project
     #[clap(value_parser, value_hint = ValueHint::FilePath)]
-    input: PathBuf,
+    input: Option<PathBuf>,
 
-    /// Start label
+    /// Start label. Defaults to the entrypoint of the project, or `main`
     #[clap(value_parser)]
-    entrypoint: String,
+    entrypoint: Option<String>,
 
     /// Run the program in interactive mode
     #[clap(short, long, action = ArgAction::SetTrue)]
     interactive: bool,
+
+    /// Print the value of a register ("%a"), memory cell ("[counter]") or expression over them
+    /// ("[counter] * 2 + %a") each time it changes. Can be used multiple times.
+    #[clap(short, long, value_parser)]
+    watch: Vec<Watch>,
+
+    /// Print each instruction as it gets executed
+    #[clap(short, long, value_enum)]
+    trace: Option<TraceFormat>,
+
+    /// Only print the last N traced instructions, when the program stops
+    #[clap(long, value_parser, requires = "trace", value_name = "N")]
+    trace_last: Option<usize>,
+
+    /// Write the routines called by the program to this file, as a Chrome trace to open in
+    /// Perfetto
+    #[clap(long, value_parser, value_hint = ValueHint::FilePath)]
+    chrome_trace: Option<PathBuf>,
+
+    /// Write the registers over time to this file, as a VCD waveform to open in `GTKWave`
+    #[clap(long, value_parser, value_hint = ValueHint::FilePath)]
+    vcd: Option<PathBuf>,
+
+    /// Stop with an error when the program gets stuck in an infinite loop
+    #[clap(long, action = ArgAction::SetTrue, conflicts_with_all = ["timer", "replay"])]
+    detect_loops: bool,
+
+    /// Stop with an error when a routine returns without restoring %b, with an unbalanced stack
+    /// or with its return address overwritten
+    #[clap(long, action = ArgAction::SetTrue)]
+    check_calls: bool,
+
+    /// Attach the allocator used by <std/heap.s>, managing SIZE cells from START, and report the
+    /// leaks and invalid frees at the end of the program
+    #[clap(long, value_parser, value_name = "START:SIZE")]
+    heap: Option<HeapRegion>,
+
+    /// Stop when an instruction reads or writes code as data, or memory which belongs neither to
+    /// the program, the stack nor a block allocated on the heap
+    #[clap(long, action = ArgAction::SetTrue)]
+    shadow: bool,
+
+    /// Deliver a hardware interrupt every given number of milliseconds, when interrupts are
+    /// enabled
+    #[clap(long, value_parser, value_name = "MS", conflicts_with = "replay")]
+    timer: Option<u64>,
+
+    /// Measure the latency of the hardware interrupts, and print it at the end of the program
+    #[clap(long, action = ArgAction::SetTrue, conflicts_with = "interactive")]
+    scheduler_metrics: bool,
+
+    /// Label of the instruction marking a context switch, with the identifier of the next process
+    /// in %a, to measure the time of each process. Implies "--scheduler-metrics"
+    #[clap(
+        long,
+        value_parser,
+        value_name = "LABEL",
+        conflicts_with = "interactive"
+    )]
+    switch_at: Option<String>,
+
+    /// Write the time slices of the processes to this file, as CSV to draw a Gantt chart
+    #[clap(long, value_parser, value_hint = ValueHint::FilePath, requires = "switch_at")]
+    gantt: Option<PathBuf>,
+
+    /// Show the addresses in traces, memory dumps and faults alone, without the label before
+    /// them
+    #[clap(long, action = ArgAction::SetTrue)]
+    no_symbols: bool,
+
+    /// Write the steps at which hardware interrupts were delivered to this file
+    #[clap(long, value_parser, value_hint = ValueHint::FilePath)]
+    record: Option<PathBuf>,
+
+    /// Deliver hardware interrupts at the steps recorded in this file
+    #[clap(long, value_parser, value_hint = ValueHint::FilePath, conflicts_with = "interactive")]
+    replay: Option<PathBuf>,
+
+    /// When the program stops on a fault, write its state, labels and the fault to this core
+    /// file, to inspect it later with "resume --core"
+    #[clap(long, value_parser, value_hint = ValueHint::FilePath, conflicts_with = "interactive")]
+    core: Option<PathBuf>,
+
+    #[clap(flatten)]
+    build: BuildOpt,
+
+    /// Exit with the value of %a when the program resets
+    #[clap(long, action = ArgAction::SetTrue, conflicts_with = "interactive")]
+    exit_code: bool,
+
+    /// Arguments passed to the program, loaded in memory before it starts
+    #[clap(last = true, value_parser)]
+    args: Vec<String>,
 }
 
-fn char_offset(a: &str, b: &str) -> usize {
-    let a = a.as_ptr();
-    let b = b.as_ptr();
-    b as usize - a as usize
+/// Options controlling how the program is built
+#[derive(Parser, Debug)]
+pub struct BuildOpt {
+    /// Fail if the compilation emits warnings
+    #[clap(long, action = ArgAction::SetTrue)]
+    deny_warnings: bool,
+
+    /// Write metadata about the compiled program as JSON to this file
+    #[clap(long, value_parser, value_hint = ValueHint::FilePath)]
+    emit: Option<PathBuf>,
+
+    /// Initial content of the memory cells not assigned by the program: `zero`, a number like
+    /// `0xDEAD`, or `random(SEED)`
+    #[clap(long, value_parser, default_value = "zero", value_name = "POLICY")]
+    init_memory: InitPolicy,
+
+    /// Raise an exception on signed arithmetic overflows, instead of wrapping around
+    #[clap(long, action = ArgAction::SetTrue)]
+    trap_on_overflow: bool,
+
+    /// Width of the words of the machine, in bits, to emulate a simplified variant
+    #[clap(long, value_parser, value_name = "BITS")]
+    word_bits: Option<Arch>,
+
+    /// Order of the cells of `.long` directives and of the bytes packed by `.byte` directives:
+    /// `little` or `big`
+    #[clap(long, value_parser, value_name = "ORDER")]
+    endianness: Option<Endianness>,
+
+    /// Initial value of %sp, instead of the top of the memory
+    #[clap(long, value_parser, value_name = "ADDRESS")]
+    sp: Option<Address>,
+
+    /// Initial arithmetic flags, as letters among `ZNCV`
+    #[clap(long, value_parser)]
+    flags: Option<Flags>,
+
+    /// Set a register before running, like `%a=5`. Can be used multiple times.
+    #[clap(long = "set", value_parser, value_name = "REG=VALUE")]
+    registers: Vec<RegisterAssignment>,
 }
 
-impl RunOpt {
-    #[allow(clippy::too_many_lines)]
-    pub fn exec(&self) -> anyhow::Result<()> {
-        let fs = NativeFilesystem::from_env()?;
-        info!(path = ?self.input, "Reading program");
-        let preprocessor = Preprocessor::new(fs).and_load(&self.input);
-        let source = match preprocessor.preprocess(&self.input) {
-            Ok(p) => p,
-            Err(e) => {
-                for error in anyhow::Chain::new(&e) {
-                    // TODO: get the location of individual errors
-                    error!("{}", error);
-                }
+/// Preprocess, parse and compile a program, reporting errors on the terminal
+///
+/// Returns the preprocessed source along with the computer and its debug informations.
+#[allow(clippy::too_many_lines)]
+pub(crate) fn build(
+    input: &Path,
+    entrypoint: &str,
+    opt: &BuildOpt,
+) -> anyhow::Result<(String, Computer, DebugInfo)> {
+    let config = crate::config::config()?;
+    info!(path = ?input, "Reading program");
+    let preprocessor = config.preprocessor()?.and_load(input);
+    let (owned_source, source_map) = match preprocessor.preprocess_with_map(input) {
+        Ok(p) => p,
+        Err(e) => {
+            for error in anyhow::Chain::new(&e) {
+                // TODO: get the location of individual errors
+                error!("{}", error);
+            }
 
-                let msg = format!("{e}");
-                let mut files = SimpleFiles::new();
-                let file_ids: HashMap<_, _> = preprocessor
-                    .sources()
-                    .iter()
-                    .map(|(name, source)| {
-                        (
-                            name.clone(),
-                            files.add(name.to_string_lossy().into_owned(), source),
-                        )
-                    })
-                    .collect();
+            let msg = format!("{e}");
+            let mut files = SimpleFiles::new();
+            let file_ids: HashMap<_, _> = preprocessor
+                .sources()
+                .iter()
+                .map(|(name, source)| {
+                    (
+                        name.clone(),
+                        files.add(name.to_string_lossy().into_owned(), source),
+                    )
+                })
+                .collect();
 
-                let mut labels = Vec::new();
+            let mut labels = Vec::new();
 
-                if let Some(location) = e.location() {
-                    labels.push(Label::primary(
-                        file_ids[&location.file],
-                        location.offset..(location.offset + location.length),
-                    ));
-                }
+            if let Some(location) = e.location() {
+                labels.push(Label::primary(
+                    file_ids[&location.file],
+                    location.offset..(location.offset + location.length),
+                ));
+            }
 
-                let diagnostic = Diagnostic::error().with_message(msg).with_labels(labels);
+            let diagnostic = Diagnostic::error()
+                .with_message(msg)
+                .with_code(e.error_code().to_string())
+                .with_labels(labels);
 
-                let writer = StandardStream::stderr(ColorChoice::Auto);
-                let config = codespan_reporting::term::Config {
-                    before_label_lines: 3,
-                    after_label_lines: 3,
-                    ..Default::default()
-                };
+            emit(&files, &diagnostic, None)?;
+            exit(1);
+        }
+    };
+    let source = owned_source.as_str();
 
-                codespan_reporting::term::emit(&mut writer.lock(), &config, &files, &diagnostic)?;
-                exit(1);
+    let mut files = SimpleFiles::new();
+    let file_id = files.add("preprocessed", source);
+
+    debug!("Parsing program");
+    let program = match parse(source) {
+        Ok(p) => p,
+        Err(e) => {
+            let msg = format!("{e}");
+            let labels: Vec<_> = e
+                .errors
+                .iter()
+                .map(|(offset, kind)| {
+                    Label::primary(file_id, *offset..*offset).with_message(kind.to_string())
+                })
+                .chain(e.suggestions.iter().map(|suggestion| {
+                    let span = suggestion.offset..(suggestion.offset + suggestion.length);
+                    Label::secondary(file_id, span).with_message(suggestion.to_string())
+                }))
+                .collect();
+            let notes: Vec<_> = e
+                .errors
+                .first()
+                .and_then(|(offset, _)| origin_note(&source_map, *offset))
+                .into_iter()
+                .collect();
+
+            let diagnostic = Diagnostic::error()
+                .with_message(msg)
+                .with_code(e.error_code().to_string())
+                .with_labels(labels)
+                .with_notes(notes);
+
+            emit(&files, &diagnostic, Some(&source_map))?;
+            exit(1);
+        }
+    };
+
+    let parent = AbsoluteLocation::<()>::default();
+    let program = program.map_location(&parent);
+
+    debug!(entrypoint = %entrypoint, "Building computer");
+    let endianness = opt
+        .endianness
+        .or(config.machine.endianness)
+        .unwrap_or_default();
+    let arch = opt
+        .word_bits
+        .or(config.machine.word_bits)
+        .unwrap_or_default();
+    let scheme = MemoryScheme {
+        endianness,
+        arch,
+        ..MemoryScheme::default()
+    };
+    let (mut computer, debug_info) = match compile_with(program.inner, entrypoint, &scheme) {
+        Ok(p) => p,
+        Err(e) => {
+            // TODO: some cleanup needed
+            let mut last_error = &e as &dyn std::error::Error;
+            for error in anyhow::Chain::new(&e) {
+                // TODO: get the location of individual errors
+                error!("{}", error);
+                last_error = error;
             }
-        };
-        let source = source.as_str();
-
-        let mut files = SimpleFiles::new();
-        let file_id = files.add("preprocessed", source);
-
-        debug!("Parsing program");
-        let program = match parse(source) {
-            Ok(p) => p,
-            Err(e) => {
-                let msg = format!("{e}");
-                let labels: Vec<_> = e
-                    .errors
-                    .iter()
-                    .map(|(location, kind)| {
-                        let message = match kind {
-                            nom::error::VerboseErrorKind::Context(s) => (*s).to_owned(),
-                            nom::error::VerboseErrorKind::Char(c) => format!("expected '{c}'"),
-                            nom::error::VerboseErrorKind::Nom(code) => format!("{code:?}"),
-                        };
-                        let offset = char_offset(source, location);
-
-                        Label::primary(file_id, offset..offset).with_message(message)
-                    })
+
+            let msg = format!("{last_error}");
+
+            let location = match &e {
+                CompilationError::MemoryLayout(e) => e.location(),
+                CompilationError::MemoryFill(e) => Some(e.location()),
+                CompilationError::UnknownEntrypoint(_e) => None,
+            };
+
+            if let Some(location) = location {
+                let mut labels = vec![Label::primary(file_id, location.span())];
+                // A register written without its `%` is parsed as a label
+                if let Some(register) = e.undefined_label().and_then(register_fix) {
+                    labels.push(
+                        Label::secondary(file_id, location.span()).with_message(format!(
+                            "registers are written with a `%`: `{register}`"
+                        )),
+                    );
+                }
+                if let CompilationError::MemoryLayout(e) = &e {
+                    labels.extend(e.previous_location().map(|previous| {
+                        Label::secondary(file_id, previous.span())
+                            .with_message("previously filled here")
+                    }));
+                    labels.extend(e.related_locations().into_iter().map(|(label, location)| {
+                        Label::secondary(file_id, location.span())
+                            .with_message(format!("{label} is also involved"))
+                    }));
+                }
+
+                let notes: Vec<_> = origin_note(&source_map, location.offset)
+                    .into_iter()
                     .collect();
+                let diagnostic = Diagnostic::error()
+                    .with_message(msg)
+                    .with_code(e.error_code().to_string())
+                    .with_labels(labels)
+                    .with_notes(notes);
 
-                let diagnostic = Diagnostic::error().with_message(msg).with_labels(labels);
+                emit(&files, &diagnostic, Some(&source_map))?;
+            } else {
+                let diagnostic = Diagnostic::error()
+                    .with_message(msg)
+                    .with_code(e.error_code().to_string());
+                emit(&files, &diagnostic, None)?;
+            }
+            exit(1);
+        }
+    };
 
-                let writer = StandardStream::stderr(ColorChoice::Auto);
-                let config = codespan_reporting::term::Config {
-                    before_label_lines: 3,
-                    after_label_lines: 3,
-                    ..Default::default()
-                };
+    let count = report_warnings(
+        &debug_info.warnings,
+        &files,
+        file_id,
+        &source_map,
+        preprocessor.sources(),
+    )?;
+    if opt.deny_warnings && count > 0 {
+        anyhow::bail!("compilation emitted {count} warning(s), denied by --deny-warnings");
+    }
+
+    let mut entry = EntryState {
+        sp: opt.sp,
+        flags: opt.flags,
+        ..EntryState::default()
+    };
+    for assignment in &opt.registers {
+        entry.set(assignment.reg, assignment.value)?;
+    }
 
-                codespan_reporting::term::emit(&mut writer.lock(), &config, &files, &diagnostic)?;
-                exit(1);
+    computer.memory.initialize(opt.init_memory);
+    let machine = &config.machine;
+    computer.set_config(MachineConfig {
+        max_steps: machine.max_steps,
+        max_cycles: machine.max_cycles,
+        trap_on_overflow: opt.trap_on_overflow || machine.trap_on_overflow,
+        arch,
+        endianness,
+        entry,
+        ..MachineConfig::default()
+    });
+    if let Some(period) = machine.timer {
+        computer.attach(TIMER_PORT, Timer::new(period))?;
+    }
+
+    if let Some(path) = &opt.emit {
+        info!(path = ?path, "Writing metadata");
+        let metadata = metadata(input, entrypoint, &debug_info, preprocessor.sources());
+        std::fs::write(path, serde_json::to_string_pretty(&metadata)?)?;
+    }
+
+    Ok((owned_source, computer, debug_info))
+}
+
+/// Run the program until it resets, going through the `brk` instructions
+pub(crate) fn run_to_reset(computer: &mut Computer) -> Result<(), ProcessorError> {
+    loop {
+        match computer.run() {
+            Err(ProcessorError::Breakpoint) => {
+                info!(address = computer.registers.pc - 1, "Ignoring a breakpoint");
             }
+            result => return result,
+        }
+    }
+}
+
+impl RunOpt {
+    pub fn exec(&self) -> anyhow::Result<()> {
+        let config = crate::config::config()?;
+        let input = config.input(self.input.as_deref())?;
+        let entrypoint = config.entrypoint(self.entrypoint.as_deref());
+        let (source, mut computer, debug_info) = build(&input, entrypoint, &self.build)?;
+        if !self.args.is_empty() {
+            computer.set_args(&self.args)?;
+        }
+
+        let heap = if let Some(region) = self.heap.or(config.machine.heap) {
+            let (heap, monitor) = Heap::new(region);
+            computer.attach(HEAP_PORT, heap)?;
+            Some(monitor)
+        } else {
+            None
         };
 
-        let parent = AbsoluteLocation::<()>::default();
-        let program = program.map_location(&parent);
-
-        debug!(entrypoint = %self.entrypoint, "Building computer");
-        let (mut computer, debug_info) = match compile(program.inner, &self.entrypoint) {
-            Ok(p) => p,
-            Err(e) => {
-                // TODO: some cleanup needed
-                let mut last_error = &e as &dyn std::error::Error;
-                for error in anyhow::Chain::new(&e) {
-                    // TODO: get the location of individual errors
-                    error!("{}", error);
-                    last_error = error;
-                }
+        // The devices attached from the configuration are only known once the program is built
+        if self.detect_loops && !LoopDetector::supports(&computer) {
+            anyhow::bail!("--detect-loops can not be used with devices changed by the host");
+        }
 
-                let msg = format!("{last_error}");
+        // Kept to point at the instruction or operand which faulted
+        let locations = debug_info.locations.clone();
+        let operands = debug_info.operands.clone();
+        let labels = self.core.as_ref().map(|_| debug_info.labels.clone());
+        let symbols = self.symbols(&debug_info);
+        if self.shadow {
+            let shadow = Shadow::new(&computer, locations.keys().copied());
+            computer.set_shadow(match &heap {
+                Some(monitor) => shadow.with_heap(monitor.clone()),
+                None => shadow,
+            });
+        }
 
-                let location = match &e {
-                    CompilationError::MemoryLayout(e) => e.location(),
-                    CompilationError::MemoryFill(e) => Some(e.location()),
-                    CompilationError::UnknownEntrypoint(_e) => None,
-                };
+        let mut interrupts = if let Some(path) = &self.replay {
+            info!(path = ?path, "Replaying interrupts");
+            std::fs::read_to_string(path)?.parse()?
+        } else {
+            InterruptLog::new()
+        };
 
-                if let Some(location) = location {
-                    let label = Label::primary(
-                        file_id,
-                        location.offset..(location.offset + location.length),
-                    );
+        info!("Running program");
+        let result = self.run(&mut computer, &source, debug_info, &mut interrupts);
 
-                    let diagnostic = Diagnostic::error()
-                        .with_message(msg)
-                        .with_labels(vec![label]);
+        // Save the interrupts even if the program failed, to be able to reproduce the failure
+        if let Some(path) = &self.record {
+            info!(path = ?path, "Recording interrupts");
+            std::fs::write(path, interrupts.to_string())?;
+        }
 
-                    let writer = StandardStream::stderr(ColorChoice::Auto);
-                    let config = codespan_reporting::term::Config {
-                        before_label_lines: 3,
-                        after_label_lines: 3,
-                        ..Default::default()
-                    };
+        // Point at the operand which faulted, or else the whole instruction
+        if let Err(e) = &result {
+            if let Some(ProcessorError::Fault { inner, context }) = e.downcast_ref() {
+                let operand = context
+                    .operand
+                    .and_then(|index| operands.get(&context.address)?.get(index));
+                if let Some(location) = operand.or_else(|| locations.get(&context.address)) {
+                    let mut files = SimpleFiles::new();
+                    let file_id = files.add("preprocessed", source.as_str());
+                    let mut diagnostic = Diagnostic::error()
+                        .with_message(inner.to_string())
+                        .with_labels(vec![Label::primary(file_id, location.span())]);
+                    if let Some(symbol) = symbols.lookup(context.address) {
+                        diagnostic = diagnostic.with_notes(vec![format!(
+                            "the instruction is at address {} <{symbol}>",
+                            context.address
+                        )]);
+                    }
+                    diagnostic.code = inner.error_code().map(|code| code.to_string());
+                    emit(&files, &diagnostic, None)?;
+                }
 
-                    codespan_reporting::term::emit(
-                        &mut writer.lock(),
-                        &config,
-                        &files,
-                        &diagnostic,
-                    )?;
+                if let (Some(path), Some(labels)) = (&self.core, labels) {
+                    let line = locations.get(&context.address).map(|location| {
+                        let number = LineIndex::new(&source).line_column(location.offset).line;
+                        let line = source.lines().nth(number - 1).unwrap_or_default();
+                        (number, line.to_owned())
+                    });
+                    let fault = FaultReport {
+                        message: e.to_string(),
+                        code: inner.error_code().map(|code| code.to_string()),
+                        address: context.address,
+                        line,
+                    };
+                    let watches = self.watch.iter().map(|w| w.source().to_owned()).collect();
+                    let snapshot = Snapshot::capture(&computer);
+                    SessionFile::new(labels, BTreeSet::new(), watches, snapshot)
+                        .with_fault(fault)
+                        .write(path)?;
                 }
-                exit(1);
             }
-        };
+        }
+        result?;
 
-        info!("Running program");
-        if self.interactive {
-            run_interactive(&mut computer, debug_info)?;
+        info!(registers = %computer.registers, "End of program");
+
+        if let Some(monitor) = heap {
+            let errors = monitor.errors();
+            for e in &errors {
+                error!("{e}");
+            }
+            if !errors.is_empty() {
+                anyhow::bail!("the program misused the heap {} time(s)", errors.len());
+            }
+        }
+
+        if self.exit_code {
+            let code = computer
+                .exit_code()
+                .ok_or_else(|| anyhow::anyhow!("%a does not hold an exit code"))?;
+            exit(code.into());
+        }
+
+        Ok(())
+    }
+
+    /// The labels naming the addresses, unless disabled
+    fn symbols(&self, debug_info: &DebugInfo) -> Symbols {
+        if self.no_symbols {
+            Symbols::default()
         } else {
-            computer.run()?;
+            debug_info.symbols()
         }
+    }
 
-        info!(registers = %computer.registers, "End of program");
+    #[allow(clippy::too_many_lines)]
+    fn run(
+        &self,
+        computer: &mut Computer,
+        source: &str,
+        debug_info: DebugInfo,
+        interrupts: &mut InterruptLog,
+    ) -> anyhow::Result<()> {
+        if self.interactive {
+            let symbols = self.symbols(&debug_info);
+            run_interactive(
+                computer,
+                debug_info,
+                self.watch.clone(),
+                symbols,
+                interrupts,
+            )?;
+        } else if self.watch.is_empty()
+            && self.trace.is_none()
+            && !self.detect_loops
+            && !self.check_calls
+            && self.chrome_trace.is_none()
+            && self.vcd.is_none()
+            && self.timer.is_none()
+            && self.replay.is_none()
+            && !self.scheduler_metrics
+            && self.switch_at.is_none()
+        {
+            run_to_reset(computer).map_err(|e| e.with_symbols(&self.symbols(&debug_info)))?;
+        } else {
+            let mut tracer = self.trace.map(|format| {
+                let mut tracer = Tracer::new(format, source, &debug_info);
+                if self.no_symbols {
+                    tracer = tracer.without_symbols();
+                }
+                match self.trace_last {
+                    Some(capacity) => tracer.keep_last(capacity),
+                    None => tracer,
+                }
+            });
+            let mut detector = self.detect_loops.then(LoopDetector::new);
+            let mut checker = self
+                .check_calls
+                .then(|| ConventionChecker::new(&debug_info.labels));
+            let mut chrome = self
+                .chrome_trace
+                .as_ref()
+                .map(|_| ChromeTrace::new(&debug_info.labels, computer.registers.pc));
+            let mut vcd = self.vcd.as_ref().map(|_| VcdTrace::new(computer));
+            let mut metrics = if let Some(label) = &self.switch_at {
+                let address = debug_info
+                    .labels
+                    .get(label)
+                    .ok_or_else(|| anyhow::anyhow!("unknown label {label:?}"))?;
+                Some(SchedulerMetrics::new(Some(*address)))
+            } else {
+                self.scheduler_metrics.then(|| SchedulerMetrics::new(None))
+            };
+            let timer = self.timer.map(Duration::from_millis);
+            let mut last_tick = Instant::now();
+            let mut watches = self.watch.clone();
+            display_watches(&mut watches, computer, &debug_info.labels);
+            let result = loop {
+                if self.replay.is_some() {
+                    interrupts.replay(computer)?;
+                } else if let Some(period) = timer {
+                    if last_tick.elapsed() >= period {
+                        last_tick = Instant::now();
+                        if computer.interrupts_enabled() {
+                            interrupts.deliver(computer, InterruptSource::Timer)?;
+                        }
+                    }
+                }
+
+                if let Some(violation) = checker.as_mut().and_then(|c| c.check(computer)) {
+                    break Err(anyhow::anyhow!("{violation}"));
+                }
+
+                let transfer = Transfer::next(computer);
+                let result = if let Some(tracer) = tracer.as_mut() {
+                    tracer.step(computer)
+                } else {
+                    computer.step()
+                };
+
+                match result {
+                    Ok(()) => {
+                        display_watches(&mut watches, computer, &debug_info.labels);
+                        if let Some(chrome) = chrome.as_mut() {
+                            chrome.record(transfer, computer);
+                        }
+                        if let Some(vcd) = vcd.as_mut() {
+                            vcd.record(computer);
+                        }
+                        if let Some(metrics) = metrics.as_mut() {
+                            metrics.check(computer);
+                        }
+                    }
+                    Err(ProcessorError::Reset | ProcessorError::Halt) => break Ok(()),
+                    Err(ProcessorError::Breakpoint) => {
+                        info!(address = computer.registers.pc - 1, "Ignoring a breakpoint");
+                    }
+                    Err(e) => break Err(e.with_symbols(&self.symbols(&debug_info)).into()),
+                }
+
+                if let Some(found) = detector.as_mut().and_then(|d| d.check(computer)) {
+                    println!("Loop body:");
+                    for address in &found.body {
+                        if let Ok(cell) = computer.memory.get(*address) {
+                            println!("  {address}: {}", cell.display(computer.config().arch));
+                        }
+                    }
+                    break Err(anyhow::anyhow!("{found}"));
+                }
+            };
+
+            // Export the traces even if the program failed, to see how it got there
+            if let Some(tracer) = tracer {
+                tracer.finish();
+            }
+            if let (Some(path), Some(chrome)) = (&self.chrome_trace, chrome) {
+                info!(path = ?path, "Writing Chrome trace");
+                std::fs::write(path, chrome.finish(computer))?;
+            }
+            if let (Some(path), Some(vcd)) = (&self.vcd, vcd) {
+                info!(path = ?path, "Writing VCD waveform");
+                std::fs::write(path, vcd.finish())?;
+            }
+            if let Some(metrics) = metrics {
+                let report = metrics.finish(computer);
+                print!("{report}");
+                if let Some(path) = &self.gantt {
+                    info!(path = ?path, "Writing Gantt timeline");
+                    std::fs::write(path, report.gantt())?;
+                }
+            }
+            result?;
+        }
 
         Ok(())
     }
@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, ValueEnum, ValueHint};
+use serde_json::json;
+use tracing::{debug, info, warn};
+use z33_emulator::{
+    compiler::stats::{statistics, Statistics},
+    parse,
+    parser::location::{AbsoluteLocation, MapLocation},
+};
+
+/// Format of the statistics report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum StatsFormat {
+    /// One `file,category,name,count` row per count, the totals having an empty file
+    Csv,
+
+    /// An object with the statistics of each file and the totals
+    Json,
+}
+
+#[derive(Parser, Debug)]
+pub struct StatsOpt {
+    /// Input files
+    #[clap(value_parser, required = true, value_hint = ValueHint::FilePath)]
+    inputs: Vec<PathBuf>,
+
+    /// Format of the report
+    #[clap(long, value_enum, default_value = "csv", value_name = "FORMAT")]
+    report: StatsFormat,
+}
+
+/// Write the rows of a file statistics in CSV
+fn write_csv(out: &mut impl Write, file: &str, stats: &Statistics) -> std::io::Result<()> {
+    // Paths are quoted, in case they contain commas
+    let file = if file.is_empty() {
+        String::new()
+    } else {
+        format!("\"{}\"", file.replace('"', "\"\""))
+    };
+
+    writeln!(out, "{file},program,instructions,{}", stats.instructions)?;
+    writeln!(out, "{file},program,size,{}", stats.size)?;
+    for (category, counts) in [
+        ("mnemonic", &stats.mnemonics),
+        ("addressing", &stats.addressing_modes),
+        ("directive", &stats.directives),
+    ] {
+        for (name, count) in counts {
+            writeln!(out, "{file},{category},{name},{count}")?;
+        }
+    }
+
+    Ok(())
+}
+
+impl StatsOpt {
+    /// Compute the statistics of a single file
+    fn file(path: &Path) -> anyhow::Result<Statistics> {
+        info!(path = ?path, "Reading program");
+        let preprocessor = crate::config::preprocessor()?.and_load(path);
+        let source = preprocessor.preprocess(path)?;
+
+        debug!("Parsing program");
+        let program = parse(&source)?;
+        let program = program.map_location(&AbsoluteLocation::<()>::default());
+
+        Ok(statistics(&program.inner)?)
+    }
+
+    pub fn exec(&self) -> anyhow::Result<()> {
+        let mut files = BTreeMap::new();
+        let mut total = Statistics::default();
+
+        // Invalid programs are skipped, so a single one does not prevent analyzing the others
+        for path in &self.inputs {
+            match Self::file(path) {
+                Ok(stats) => {
+                    total.merge(&stats);
+                    files.insert(path.display().to_string(), stats);
+                }
+                Err(error) => warn!(path = ?path, error = %error, "Skipping invalid program"),
+            }
+        }
+
+        let mut stdout = std::io::stdout().lock();
+        match self.report {
+            StatsFormat::Csv => {
+                writeln!(stdout, "file,category,name,count")?;
+                for (file, stats) in &files {
+                    write_csv(&mut stdout, file, stats)?;
+                }
+                write_csv(&mut stdout, "", &total)?;
+            }
+            StatsFormat::Json => {
+                let report = json!({ "files": files, "total": total });
+                serde_json::to_writer_pretty(&mut stdout, &report)?;
+                writeln!(stdout)?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{Parser, ValueHint};
+use tracing::{error, info};
+use z33_emulator::runtime::MachineConfig;
+
+use crate::grading::{Outcome, Script};
+
+#[derive(Parser, Debug)]
+pub struct TestOpt {
+    /// Test script. Defaults to the tests of the project
+    #[clap(value_parser, value_hint = ValueHint::FilePath)]
+    script: Option<PathBuf>,
+
+    /// Program to test. Defaults to the main program of the project
+    #[clap(long, value_parser, value_hint = ValueHint::FilePath)]
+    input: Option<PathBuf>,
+}
+
+impl TestOpt {
+    pub fn exec(&self) -> anyhow::Result<()> {
+        let config = crate::config::config()?;
+        let script_path = config.tests(self.script.as_deref())?;
+        let input = config.input(self.input.as_deref())?;
+
+        info!(path = ?script_path, "Reading test script");
+        let script: Script = std::fs::read_to_string(&script_path)?.parse()?;
+
+        info!(path = ?input, "Reading program");
+        let source = config.preprocessor()?.and_load(&input).preprocess(&input)?;
+
+        let machine = MachineConfig {
+            max_steps: Some(config.machine.max_steps.unwrap_or(1_000_000)),
+            max_cycles: config.machine.max_cycles,
+            timeout: Some(Duration::from_secs(10)),
+            max_output_bytes: Some(1 << 20),
+            trap_on_overflow: config.machine.trap_on_overflow,
+            arch: config.machine.word_bits.unwrap_or_default(),
+            endianness: config.machine.endianness.unwrap_or_default(),
+            ..MachineConfig::default()
+        };
+
+        let mut failed = 0;
+        for (test, outcome) in script.tests.iter().zip(script.run(&source, machine)) {
+            match outcome {
+                Outcome::Passed => info!("{}: pass", test.name),
+                outcome => {
+                    failed += 1;
+                    error!(
+                        "{}: {}: {}",
+                        test.name,
+                        outcome.status(),
+                        outcome.message().unwrap_or_default()
+                    );
+                }
+            }
+        }
+
+        let total = script.tests.len();
+        if failed > 0 {
+            anyhow::bail!("{failed} of {total} test(s) failed");
+        }
+
+        info!("{total} test(s) passed");
+        Ok(())
+    }
+}
@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+
+use clap::{ArgAction, Parser, ValueHint};
+use z33_emulator::compiler::Symbols;
+
+use super::run::{build, BuildOpt};
+use crate::tui::run_tui;
+
+#[derive(Parser, Debug)]
+pub struct TuiOpt {
+    /// Input file. Defaults to the main program of the project
+    #[clap(value_parser, value_hint = ValueHint::FilePath)]
+    input: Option<PathBuf>,
+
+    /// Start label. Defaults to the entrypoint of the project, or `main`
+    #[clap(value_parser)]
+    entrypoint: Option<String>,
+
+    /// Show the addresses of the memory alone, without the label before them
+    #[clap(long, action = ArgAction::SetTrue)]
+    no_symbols: bool,
+
+    #[clap(flatten)]
+    build: BuildOpt,
+}
+
+impl TuiOpt {
+    pub fn exec(&self) -> anyhow::Result<()> {
+        let config = crate::config::config()?;
+        let input = config.input(self.input.as_deref())?;
+        let entrypoint = config.entrypoint(self.entrypoint.as_deref());
+        let (source, computer, debug_info) = build(&input, entrypoint, &self.build)?;
+        let symbols = if self.no_symbols {
+            Symbols::default()
+        } else {
+            debug_info.symbols()
+        };
+        run_tui(&source, computer, debug_info, symbols)
+    }
+}
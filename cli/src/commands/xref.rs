@@ -0,0 +1,60 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueHint};
+use tracing::{debug, info};
+use z33_emulator::{
+    compiler::{layout, DebugInfo},
+    parse,
+    parser::location::{AbsoluteLocation, MapLocation},
+    preprocessor::SourceMap,
+};
+
+#[derive(Parser, Debug)]
+pub struct XrefOpt {
+    /// Input file
+    #[clap(value_parser, value_hint = ValueHint::FilePath)]
+    input: PathBuf,
+}
+
+/// Format a location of the preprocessed source as `file:line`
+fn origin(map: &SourceMap, location: &AbsoluteLocation) -> String {
+    map.origin(location.offset)
+        .map_or_else(|| "?".to_owned(), ToString::to_string)
+}
+
+impl XrefOpt {
+    pub fn exec(&self) -> anyhow::Result<()> {
+        info!(path = ?self.input, "Reading program");
+        let preprocessor = crate::config::preprocessor()?.and_load(&self.input);
+
+        let (source, map) = preprocessor.preprocess_with_map(&self.input)?;
+
+        debug!("Parsing program");
+        let program = parse(&source)?;
+        let program = program.map_location(&AbsoluteLocation::<()>::default());
+
+        debug!("Laying out memory");
+        let debug_info: DebugInfo = layout(program.inner)?.into();
+
+        let mut labels: Vec<_> = debug_info.labels.iter().collect();
+        labels.sort_by_key(|&(label, address)| (*address, label));
+
+        for (label, address) in labels {
+            let definition = debug_info
+                .definitions
+                .get(label)
+                .map_or_else(|| "?".to_owned(), |location| origin(&map, location));
+            println!("{label} ({address}) defined at {definition}");
+
+            for reference in debug_info.references.iter().filter(|r| &r.label == label) {
+                println!(
+                    "  {:>5}  {}",
+                    reference.address,
+                    origin(&map, &reference.location)
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
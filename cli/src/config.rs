@@ -0,0 +1,188 @@
+//! Project configuration, read from a `z33.toml` file
+//!
+//! The file is searched for in the current directory, then in its parents. It gives defaults to
+//! the options of the subcommands, which still take precedence when given:
+//!
+//! ```toml
+//! main = "main.S"
+//! entrypoint = "main"
+//! include = ["lib"]
+//! tests = "tests.txt"
+//!
+//! [defines]
+//! SIZE = "10"
+//!
+//! [machine]
+//! trap_on_overflow = true
+//! word_bits = 16
+//! endianness = "big"
+//! max_steps = 100000
+//! timer = 500
+//! heap = "5000:1000"
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+use tracing::debug;
+use z33_emulator::preprocessor::{Filesystem, NativeFilesystem, Preprocessor};
+use z33_emulator::runtime::{Arch, Endianness, HeapRegion};
+
+/// Name of the configuration file
+pub const CONFIG_FILE: &str = "z33.toml";
+
+/// Options of the machine running the programs
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MachineSection {
+    /// Raise an exception on signed arithmetic overflows
+    #[serde(default)]
+    pub trap_on_overflow: bool,
+
+    /// Width of the words, in bits, to emulate a simplified variant of the machine
+    #[serde(default, deserialize_with = "parse_arch")]
+    pub word_bits: Option<Arch>,
+
+    /// Order of the cells of `.long` directives and of the bytes packed by `.byte` directives
+    #[serde(default, deserialize_with = "parse_endianness")]
+    pub endianness: Option<Endianness>,
+
+    /// Maximum number of instructions executed by a program
+    pub max_steps: Option<usize>,
+
+    /// Maximum number of cycles taken by a program
+    pub max_cycles: Option<usize>,
+
+    /// Period, in cycles, of a timer device attached on its port
+    pub timer: Option<usize>,
+
+    /// Region managed by the allocator of `<std/heap.s>`, as `START:SIZE`
+    #[serde(default, deserialize_with = "parse_heap")]
+    pub heap: Option<HeapRegion>,
+}
+
+fn parse_heap<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<HeapRegion>, D::Error> {
+    let region = String::deserialize(deserializer)?;
+    region.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+fn parse_arch<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<Arch>, D::Error> {
+    let bits = u32::deserialize(deserializer)?;
+    Arch::new(bits).map(Some).ok_or_else(|| {
+        serde::de::Error::custom(format!("invalid word width {bits}, expected 8 to 64 bits"))
+    })
+}
+
+fn parse_endianness<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Endianness>, D::Error> {
+    let order = String::deserialize(deserializer)?;
+    order.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+/// Content of a `z33.toml` file
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Directory holding the configuration file, which relative paths are resolved from
+    #[serde(skip)]
+    pub root: PathBuf,
+
+    /// Program built by default
+    pub main: Option<PathBuf>,
+
+    /// Label where programs start by default
+    pub entrypoint: Option<String>,
+
+    /// Directories searched for the files included between angle brackets
+    #[serde(default)]
+    pub include: Vec<PathBuf>,
+
+    /// Macros defined before preprocessing
+    #[serde(default)]
+    pub defines: BTreeMap<String, String>,
+
+    #[serde(default)]
+    pub machine: MachineSection,
+
+    /// Test script run by the `test` subcommand
+    pub tests: Option<PathBuf>,
+}
+
+impl Config {
+    /// Find and read the configuration file of the project of a directory
+    fn discover(start: &Path) -> anyhow::Result<Self> {
+        let Some(root) = start
+            .ancestors()
+            .find(|dir| dir.join(CONFIG_FILE).is_file())
+        else {
+            return Ok(Self::default());
+        };
+
+        let path = root.join(CONFIG_FILE);
+        debug!(path = ?path, "Reading project configuration");
+        let content = std::fs::read_to_string(&path)?;
+        let mut config: Self = toml::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("invalid {}: {e}", path.display()))?;
+        config.root = root.to_path_buf();
+        Ok(config)
+    }
+
+    /// Resolve a path of the configuration file
+    fn resolve(&self, path: &Path) -> PathBuf {
+        self.root.join(path)
+    }
+
+    /// The program to build, given on the command line or in the configuration
+    pub fn input(&self, input: Option<&Path>) -> anyhow::Result<PathBuf> {
+        input
+            .map(ToOwned::to_owned)
+            .or_else(|| self.main.as_deref().map(|main| self.resolve(main)))
+            .ok_or_else(|| anyhow::anyhow!("no input file given, and no main in {CONFIG_FILE}"))
+    }
+
+    /// The label to start from, given on the command line, in the configuration, or `main`
+    pub fn entrypoint<'a>(&'a self, entrypoint: Option<&'a str>) -> &'a str {
+        entrypoint.or(self.entrypoint.as_deref()).unwrap_or("main")
+    }
+
+    /// The test script, given on the command line or in the configuration
+    pub fn tests(&self, tests: Option<&Path>) -> anyhow::Result<PathBuf> {
+        tests
+            .map(ToOwned::to_owned)
+            .or_else(|| self.tests.as_deref().map(|tests| self.resolve(tests)))
+            .ok_or_else(|| anyhow::anyhow!("no test script given, and no tests in {CONFIG_FILE}"))
+    }
+
+    /// A preprocessor with the include directories and macros of the project
+    pub fn preprocessor(&self) -> std::io::Result<Preprocessor<NativeFilesystem>> {
+        let include = self.include.iter().map(|dir| self.resolve(dir)).collect();
+        let fs = crate::platform::filesystem()?.with_include_dirs(include);
+        let mut preprocessor = Preprocessor::new(fs);
+        for (key, content) in &self.defines {
+            preprocessor.define(key.clone(), Some(content.clone()));
+        }
+        Ok(preprocessor)
+    }
+}
+
+/// Configuration of the project the CLI runs in, read once
+pub fn config() -> anyhow::Result<&'static Config> {
+    static CONFIG: OnceLock<Config> = OnceLock::new();
+    if let Some(config) = CONFIG.get() {
+        return Ok(config);
+    }
+
+    let start = crate::platform::filesystem()?.root();
+    let config = Config::discover(&start)?;
+    Ok(CONFIG.get_or_init(|| config))
+}
+
+/// A preprocessor with the include directories and macros of the current project
+pub fn preprocessor() -> anyhow::Result<Preprocessor<NativeFilesystem>> {
+    Ok(config()?.preprocessor()?)
+}
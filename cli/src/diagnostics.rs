@@ -0,0 +1,271 @@
+//! Reporting of errors and warnings on the terminal
+//!
+//! With `--message-format json`, each diagnostic is instead printed on the standard output as a
+//! JSON record on its own line, with its severity, code, message, spans, notes and the text it
+//! would have been rendered as.
+
+use std::sync::OnceLock;
+use std::{collections::HashMap, path::PathBuf};
+
+use clap::ValueEnum;
+use codespan_reporting::{
+    diagnostic::{Diagnostic, Label, LabelStyle, Severity},
+    files::Files,
+    term::termcolor::{ColorChoice, NoColor, StandardStream},
+};
+use serde_json::{json, Value};
+use z33_emulator::{
+    codes::{explain, ErrorCode},
+    compiler::{
+        warning::{allowed_on, Warning},
+        CompilationError,
+    },
+    parser::{
+        location::{AbsoluteLocation, LineIndex, DEFAULT_TAB_WIDTH},
+        SyntaxError,
+    },
+    preprocessor::{PreprocessorError, SourceMap},
+    runtime::ProcessorError,
+};
+
+/// How the diagnostics are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub(crate) enum MessageFormat {
+    /// Rendered with the source around them, on stderr
+    #[default]
+    Human,
+
+    /// As newline-delimited JSON records, on stdout
+    Json,
+}
+
+static MESSAGE_FORMAT: OnceLock<MessageFormat> = OnceLock::new();
+
+/// Choose how the diagnostics are printed, once at startup
+pub(crate) fn set_message_format(format: MessageFormat) {
+    let _ = MESSAGE_FORMAT.set(format);
+}
+
+fn message_format() -> MessageFormat {
+    MESSAGE_FORMAT.get().copied().unwrap_or_default()
+}
+
+static TAB_WIDTH: OnceLock<usize> = OnceLock::new();
+
+/// Choose how many columns a tab takes in the sources, once at startup
+pub(crate) fn set_tab_width(tab_width: usize) {
+    let _ = TAB_WIDTH.set(tab_width.max(1));
+}
+
+fn tab_width() -> usize {
+    TAB_WIDTH.get().copied().unwrap_or(DEFAULT_TAB_WIDTH)
+}
+
+fn term_config() -> codespan_reporting::term::Config {
+    codespan_reporting::term::Config {
+        before_label_lines: 3,
+        after_label_lines: 3,
+        tab_width: tab_width(),
+        ..Default::default()
+    }
+}
+
+const fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Bug => "bug",
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+        Severity::Help => "help",
+    }
+}
+
+/// Describe a diagnostic as a JSON record
+///
+/// Each span has its offsets and position in its file, and when the file is the preprocessed
+/// source, the original line it comes from. The `column` counts characters, and the
+/// `display_column` expands the tabs before it, as editors show them.
+fn record<'a, F>(
+    files: &'a F,
+    diagnostic: &Diagnostic<F::FileId>,
+    map: Option<&SourceMap>,
+) -> anyhow::Result<Value>
+where
+    F: Files<'a>,
+{
+    let mut rendered = NoColor::new(Vec::new());
+    codespan_reporting::term::emit(&mut rendered, &term_config(), files, diagnostic)?;
+
+    let spans: Vec<Value> = diagnostic
+        .labels
+        .iter()
+        .map(|label| {
+            let location = files.location(label.file_id, label.range.start).ok();
+            let display = files.source(label.file_id).ok().map(|source| {
+                LineIndex::new(source.as_ref())
+                    .with_tab_width(tab_width())
+                    .line_column(label.range.start)
+            });
+            let origin = map.and_then(|map| map.origin(label.range.start));
+            json!({
+                "file": files.name(label.file_id).ok().map(|name| name.to_string()),
+                "start": label.range.start,
+                "end": label.range.end,
+                "line": location.as_ref().map(|location| location.line_number),
+                "column": location.as_ref().map(|location| location.column_number),
+                "display_column": display.map(|position| position.column),
+                "primary": label.style == LabelStyle::Primary,
+                "label": (!label.message.is_empty()).then_some(&label.message),
+                "origin": origin.map(|origin| json!({ "file": origin.file, "line": origin.line })),
+            })
+        })
+        .collect();
+
+    Ok(json!({
+        "severity": severity_name(diagnostic.severity),
+        "code": diagnostic.code,
+        "message": diagnostic.message,
+        "spans": spans,
+        "notes": diagnostic.notes,
+        "rendered": String::from_utf8_lossy(&rendered.into_inner()),
+    }))
+}
+
+/// Print a diagnostic on stderr, with a few lines of context around the labels
+///
+/// The source map, when the files hold the preprocessed source, gives the original location of
+/// the spans in JSON records.
+pub(crate) fn emit<'a, F>(
+    files: &'a F,
+    diagnostic: &Diagnostic<F::FileId>,
+    map: Option<&SourceMap>,
+) -> anyhow::Result<()>
+where
+    F: Files<'a>,
+{
+    // Point at the explanation of the error
+    let explained = diagnostic
+        .code
+        .as_deref()
+        .and_then(|code| code.parse().ok())
+        .and_then(explain);
+    let mut diagnostic = diagnostic.clone();
+    if let Some(explanation) = explained {
+        diagnostic.notes.push(explain_note(explanation.code));
+    }
+    let diagnostic = &diagnostic;
+
+    match message_format() {
+        MessageFormat::Human => {
+            let writer = StandardStream::stderr(ColorChoice::Auto);
+            codespan_reporting::term::emit(&mut writer.lock(), &term_config(), files, diagnostic)?;
+        }
+        MessageFormat::Json => println!("{}", record(files, diagnostic, map)?),
+    }
+    Ok(())
+}
+
+fn explain_note(code: ErrorCode) -> String {
+    format!("run `z33-cli explain {code}` for more information about this error")
+}
+
+/// Find the code of an error, from the errors of the emulator it may wrap
+fn error_code(error: &anyhow::Error) -> Option<ErrorCode> {
+    error.chain().find_map(|error| {
+        if let Some(e) = error.downcast_ref::<ProcessorError>() {
+            e.error_code()
+        } else if let Some(e) = error.downcast_ref::<SyntaxError>() {
+            Some(e.error_code())
+        } else if let Some(e) = error.downcast_ref::<CompilationError<AbsoluteLocation>>() {
+            Some(e.error_code())
+        } else {
+            error
+                .downcast_ref::<PreprocessorError<AbsoluteLocation<PathBuf>>>()
+                .map(PreprocessorError::error_code)
+        }
+    })
+}
+
+/// Report an error which stopped a subcommand
+pub(crate) fn report_error(error: &anyhow::Error) {
+    let code = error_code(error);
+    match message_format() {
+        MessageFormat::Human => {
+            if let Some(code) = code {
+                tracing::error!("[{code}] {error} ({})", explain_note(code));
+            } else {
+                tracing::error!("{}", error);
+            }
+        }
+        MessageFormat::Json => println!(
+            "{}",
+            json!({
+                "severity": "error",
+                "code": code.map(|code| code.to_string()),
+                "message": error.to_string(),
+                "spans": [],
+                "notes": error.chain().skip(1).map(ToString::to_string).collect::<Vec<_>>(),
+                "rendered": match code {
+                    Some(code) => format!("error[{code}]: {error:#}\n"),
+                    None => format!("error: {error:#}\n"),
+                },
+            })
+        ),
+    }
+}
+
+/// Tell which original line an offset of the preprocessed source comes from
+pub(crate) fn origin_note(map: &SourceMap, offset: usize) -> Option<String> {
+    map.origin(offset).map(|origin| format!("from {origin}"))
+}
+
+/// Check if a warning was allowed by a comment on its original source line
+fn is_allowed(
+    warning: &Warning<AbsoluteLocation>,
+    map: &SourceMap,
+    sources: &HashMap<PathBuf, String>,
+) -> bool {
+    map.origin(warning.location().offset)
+        .and_then(|origin| {
+            let source = sources.get(&origin.file)?;
+            source.lines().nth(origin.line.checked_sub(1)?)
+        })
+        .is_some_and(|line| allowed_on(line).contains(&warning.kind()))
+}
+
+/// Print the warnings which were not allowed in the source, returning how many were printed
+pub(crate) fn report_warnings<'a, F>(
+    warnings: &[Warning<AbsoluteLocation>],
+    files: &'a F,
+    file_id: F::FileId,
+    map: &SourceMap,
+    sources: &HashMap<PathBuf, String>,
+) -> anyhow::Result<usize>
+where
+    F: Files<'a>,
+{
+    let mut count = 0;
+    for warning in warnings {
+        if is_allowed(warning, map, sources) {
+            continue;
+        }
+
+        let diagnostic = Diagnostic::warning()
+            .with_message(warning.to_string())
+            .with_code(warning.kind().to_string())
+            .with_labels(vec![Label::primary(file_id, warning.location().span())])
+            .with_notes(
+                origin_note(map, warning.location().offset)
+                    .into_iter()
+                    .chain([format!(
+                        "add `// allow({})` on the line to silence this warning",
+                        warning.kind()
+                    )])
+                    .collect(),
+            );
+        emit(files, &diagnostic, Some(map))?;
+        count += 1;
+    }
+
+    Ok(count)
+}
@@ -0,0 +1,262 @@
+//! Execution traces exported to the formats of existing visualizers
+//!
+//! The time of each event is the number of cycles executed so far, counted as microseconds in the
+//! Chrome trace and as nanoseconds in the waveform.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use serde_json::{json, Value};
+use z33_emulator::{
+    constants as C,
+    runtime::{Cell, Computer, Reg},
+};
+
+/// How an instruction moves between routines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transfer {
+    Call,
+    Return,
+    None,
+}
+
+impl Transfer {
+    /// Kind of the instruction about to be executed
+    pub fn next(computer: &Computer) -> Self {
+        let instruction = computer
+            .memory
+            .get(computer.registers.pc)
+            .ok()
+            .and_then(|cell| cell.extract_instruction().ok());
+        match instruction {
+            Some(i) if i.is_call() => Self::Call,
+            Some(i) if i.is_return() => Self::Return,
+            _ => Self::None,
+        }
+    }
+}
+
+/// Routines as slices in Chrome's trace event format, to open in Perfetto or `chrome://tracing`
+pub struct ChromeTrace {
+    names: BTreeMap<C::Address, String>,
+    events: Vec<Value>,
+    open: Vec<String>,
+}
+
+impl ChromeTrace {
+    /// Start a trace with a slice for the entrypoint
+    pub fn new(labels: &HashMap<String, C::Address>, entry: C::Address) -> Self {
+        let mut names: BTreeMap<C::Address, String> = BTreeMap::new();
+        for (label, &address) in labels {
+            let name = names.entry(address).or_insert_with(|| label.clone());
+            if label < name {
+                name.clone_from(label);
+            }
+        }
+
+        let mut trace = Self {
+            names,
+            events: Vec::new(),
+            open: Vec::new(),
+        };
+        trace.begin(entry, 0);
+        trace
+    }
+
+    fn begin(&mut self, routine: C::Address, time: usize) {
+        let name = self
+            .names
+            .get(&routine)
+            .cloned()
+            .unwrap_or_else(|| routine.to_string());
+        self.events.push(json!({
+            "name": name,
+            "ph": "B",
+            "ts": time,
+            "pid": 1,
+            "tid": 1,
+            "args": { "address": routine },
+        }));
+        self.open.push(name);
+    }
+
+    fn end(&mut self, time: usize) {
+        if let Some(name) = self.open.pop() {
+            self.events.push(json!({
+                "name": name,
+                "ph": "E",
+                "ts": time,
+                "pid": 1,
+                "tid": 1,
+            }));
+        }
+    }
+
+    /// Record an executed instruction
+    pub fn record(&mut self, transfer: Transfer, computer: &Computer) {
+        match transfer {
+            Transfer::Call => self.begin(computer.registers.pc, computer.cycles),
+            // The entrypoint slice stays open until the end of the program
+            Transfer::Return if self.open.len() > 1 => self.end(computer.cycles),
+            Transfer::Return | Transfer::None => {}
+        }
+    }
+
+    /// Close the slices still open, and produce the JSON document
+    pub fn finish(mut self, computer: &Computer) -> String {
+        while !self.open.is_empty() {
+            self.end(computer.cycles);
+        }
+        json!({ "traceEvents": self.events }).to_string()
+    }
+}
+
+const VCD_REGISTERS: [(Reg, char); 5] = [
+    (Reg::A, '!'),
+    (Reg::B, '"'),
+    (Reg::PC, '#'),
+    (Reg::SP, '$'),
+    (Reg::SR, '%'),
+];
+
+/// Value of a register as a VCD binary vector
+fn vcd_value(cell: &Cell) -> String {
+    match cell {
+        Cell::Word(word) => format!("b{:b}", word.cast_unsigned()),
+        Cell::Char(c) => format!("b{:b}", u32::from(*c)),
+        Cell::Empty | Cell::Instruction(_) => "bx".to_owned(),
+    }
+}
+
+/// Registers over time as a VCD waveform, to open in `GTKWave`
+pub struct VcdTrace {
+    output: String,
+    values: Vec<String>,
+}
+
+impl VcdTrace {
+    /// Start a waveform with the initial value of the registers
+    pub fn new(computer: &Computer) -> Self {
+        let mut output = String::from("$version z33-emulator $end\n$timescale 1ns $end\n");
+        output.push_str("$scope module z33 $end\n");
+        for (reg, id) in VCD_REGISTERS {
+            let _ = writeln!(
+                output,
+                "$var wire 64 {id} {} $end",
+                reg.to_string().trim_start_matches('%')
+            );
+        }
+        output.push_str("$upscope $end\n$enddefinitions $end\n#0\n$dumpvars\n");
+
+        let values: Vec<String> = VCD_REGISTERS
+            .iter()
+            .map(|(reg, _)| vcd_value(&computer.registers.get(reg)))
+            .collect();
+        for ((_, id), value) in VCD_REGISTERS.iter().zip(&values) {
+            let _ = writeln!(output, "{value} {id}");
+        }
+        output.push_str("$end\n");
+
+        Self { output, values }
+    }
+
+    /// Record the registers which changed since the last step
+    pub fn record(&mut self, computer: &Computer) {
+        let mut changes = String::new();
+        for ((reg, id), previous) in VCD_REGISTERS.iter().zip(&mut self.values) {
+            let value = vcd_value(&computer.registers.get(reg));
+            if value != *previous {
+                let _ = writeln!(changes, "{value} {id}");
+                *previous = value;
+            }
+        }
+
+        if !changes.is_empty() {
+            let _ = writeln!(self.output, "#{}", computer.cycles);
+            self.output.push_str(&changes);
+        }
+    }
+
+    /// Produce the VCD document
+    pub fn finish(self) -> String {
+        self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use z33_emulator::{compile, parse, runtime::ProcessorError};
+
+    use super::*;
+
+    /// Compile a program, with its labels
+    fn compiled(source: &str) -> (Computer, HashMap<String, C::Address>) {
+        let program = parse(source).unwrap();
+        let (computer, debug_info) = compile(program.inner, "main").unwrap();
+        (computer, debug_info.labels)
+    }
+
+    /// Run a program until it resets, calling `record` after each step
+    fn run(computer: &mut Computer, mut record: impl FnMut(Transfer, &Computer)) {
+        loop {
+            let transfer = Transfer::next(computer);
+            match computer.step() {
+                Ok(()) => record(transfer, computer),
+                Err(ProcessorError::Reset) => return,
+                Err(e) => panic!("unexpected error {e}"),
+            }
+        }
+    }
+
+    #[test]
+    fn chrome_trace_test() {
+        let (mut computer, labels) = compiled("main: call f\n    reset\nf: rtn\n");
+        let mut trace = ChromeTrace::new(&labels, computer.registers.pc);
+        run(&mut computer, |transfer, computer| {
+            trace.record(transfer, computer);
+        });
+        let expected = concat!(
+            r#"{"traceEvents":["#,
+            r#"{"args":{"address":1000},"name":"main","ph":"B","pid":1,"tid":1,"ts":0},"#,
+            r#"{"args":{"address":1002},"name":"f","ph":"B","pid":1,"tid":1,"ts":1},"#,
+            r#"{"name":"f","ph":"E","pid":1,"tid":1,"ts":2},"#,
+            r#"{"name":"main","ph":"E","pid":1,"tid":1,"ts":2}"#,
+            "]}",
+        );
+        assert_eq!(trace.finish(&computer), expected);
+    }
+
+    #[test]
+    fn vcd_trace_test() {
+        let (mut computer, _) = compiled("main: ld 5, %a\n    add %a, %b\n    reset\n");
+        let mut trace = VcdTrace::new(&computer);
+        run(&mut computer, |_, computer| trace.record(computer));
+        let expected = "\
+$version z33-emulator $end
+$timescale 1ns $end
+$scope module z33 $end
+$var wire 64 ! a $end
+$var wire 64 \" b $end
+$var wire 64 # pc $end
+$var wire 64 $ sp $end
+$var wire 64 % sr $end
+$upscope $end
+$enddefinitions $end
+#0
+$dumpvars
+bx !
+bx \"
+b1111101000 #
+b10011100010000 $
+b0 %
+$end
+#1
+b101 !
+b1111101001 #
+#2
+b101 \"
+b1111101010 #
+";
+        assert_eq!(trace.finish(), expected);
+    }
+}
@@ -0,0 +1,447 @@
+//! Test scripts, run against students submissions to grade them
+//!
+//! A script is a list of tests, each one starting with a `test NAME` line and followed by its
+//! setup and checks:
+//!
+//! ```text
+//! # Comments start with a '#'
+//! test factorial
+//!     entrypoint main
+//!     args 5
+//!     expect %a = 120
+//!     expect [result] = 120
+//!     output "120\n"
+//!     exit 0
+//! ```
+//!
+//! The submission is compiled once for each entrypoint, and each test runs on its own copy of the
+//! compiled computer, so tests do not share any state.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use rayon::prelude::*;
+use thiserror::Error;
+use z33_emulator::{
+    compiler::{compile_with, DebugInfo, MemoryScheme},
+    constants::Word,
+    parse,
+    parser::location::{AbsoluteLocation, MapLocation},
+    runtime::{Cell, Channel, Computer, Console, MachineConfig, ProcessorError},
+};
+
+use crate::interactive::WatchExpression;
+
+/// A check done once the program reset
+#[derive(Debug)]
+pub enum Check {
+    /// The value of a register or a memory cell, like `expect %a = 120`
+    Value {
+        source: String,
+        expression: WatchExpression,
+        expected: Cell,
+    },
+
+    /// The text written by the program on the console
+    Output(String),
+
+    /// The exit code, which is the low byte of `%a`
+    ExitCode(u8),
+}
+
+/// A single test of a script
+#[derive(Debug)]
+pub struct Test {
+    pub name: String,
+    entrypoint: String,
+    args: Vec<String>,
+    checks: Vec<Check>,
+}
+
+/// A list of tests
+#[derive(Debug)]
+pub struct Script {
+    pub tests: Vec<Test>,
+}
+
+#[derive(Debug, Error)]
+#[error("line {line}: {message}")]
+pub struct ScriptError {
+    line: usize,
+    message: String,
+}
+
+/// Parse an expected cell value: a number, in decimal or hexadecimal, or a quoted character
+fn parse_value(s: &str) -> Option<Cell> {
+    let s = s.trim();
+    if let Some(quoted) = s.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+        let unescaped = unescape(quoted)?;
+        let mut chars = unescaped.chars();
+        let c = chars.next()?;
+        return chars.next().is_none().then_some(Cell::Char(c));
+    }
+
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s),
+    };
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => Word::from_str_radix(hex, 16).ok()?,
+        None => digits.parse().ok()?,
+    };
+    Some(Cell::Word(if negative { -value } else { value }))
+}
+
+/// Replace the `\n`, `\t`, `\0`, `\\`, `\"` and `\'` escape sequences
+fn unescape(s: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        unescaped.push(match chars.next()? {
+            'n' => '\n',
+            't' => '\t',
+            '0' => '\0',
+            c @ ('\\' | '"' | '\'') => c,
+            _ => return None,
+        });
+    }
+    Some(unescaped)
+}
+
+impl FromStr for Script {
+    type Err = ScriptError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tests: Vec<Test> = Vec::new();
+
+        for (index, line) in s.lines().enumerate() {
+            let error = |message: &str| ScriptError {
+                line: index + 1,
+                message: message.to_owned(),
+            };
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+
+            if keyword == "test" {
+                if rest.is_empty() {
+                    return Err(error("missing test name"));
+                }
+
+                tests.push(Test {
+                    name: rest.to_owned(),
+                    entrypoint: "main".to_owned(),
+                    args: Vec::new(),
+                    checks: Vec::new(),
+                });
+                continue;
+            }
+
+            let test = tests
+                .last_mut()
+                .ok_or_else(|| error("expected a \"test NAME\" line first"))?;
+
+            match keyword {
+                "entrypoint" => rest.clone_into(&mut test.entrypoint),
+                "args" => {
+                    test.args = shell_words::split(rest).map_err(|e| error(&e.to_string()))?;
+                }
+                "expect" => {
+                    let (source, expected) = rest
+                        .rsplit_once('=')
+                        .ok_or_else(|| error("expected \"expect EXPRESSION = VALUE\""))?;
+                    let expression = source.parse().map_err(|e| error(&format!("{e}")))?;
+                    let expected = parse_value(expected)
+                        .ok_or_else(|| error("expected a number or a character"))?;
+                    test.checks.push(Check::Value {
+                        source: source.trim().to_owned(),
+                        expression,
+                        expected,
+                    });
+                }
+                "output" => {
+                    let expected = rest
+                        .strip_prefix('"')
+                        .and_then(|rest| rest.strip_suffix('"'))
+                        .and_then(unescape)
+                        .ok_or_else(|| error("expected a quoted string"))?;
+                    test.checks.push(Check::Output(expected));
+                }
+                "exit" => {
+                    let code = rest
+                        .parse()
+                        .map_err(|_| error("expected an exit code between 0 and 255"))?;
+                    test.checks.push(Check::ExitCode(code));
+                }
+                _ => return Err(error(&format!("unknown keyword {keyword:?}"))),
+            }
+        }
+
+        Ok(Self { tests })
+    }
+}
+
+/// Result of a test
+#[derive(Debug)]
+pub enum Outcome {
+    Passed,
+
+    /// The program ran, but a check failed or it did not reset properly
+    Failed(String),
+
+    /// The program could not be compiled or loaded
+    Error(String),
+}
+
+impl Outcome {
+    /// Short name of the outcome, used in reports
+    pub fn status(&self) -> &'static str {
+        match self {
+            Self::Passed => "pass",
+            Self::Failed(_) => "fail",
+            Self::Error(_) => "error",
+        }
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        match self {
+            Self::Passed => None,
+            Self::Failed(message) | Self::Error(message) => Some(message),
+        }
+    }
+}
+
+/// Compare cells, empty cells being equal to zero
+fn same_value(actual: &Cell, expected: &Cell) -> bool {
+    match (actual, expected) {
+        (Cell::Empty, Cell::Word(0)) => true,
+        (actual, expected) => actual == expected,
+    }
+}
+
+/// A submission compiled from an entrypoint, or why it could not be
+type Compiled = Result<(Computer, DebugInfo), String>;
+
+/// Compile the preprocessed source of a submission, starting from an entrypoint, for a machine
+/// with the given words
+fn compile_submission(source: &str, entrypoint: &str, config: &MachineConfig) -> Compiled {
+    let program = match parse(source) {
+        Ok(program) => program.map_location(&AbsoluteLocation::<()>::default()),
+        Err(e) => return Err(format!("could not parse the program: {e}")),
+    };
+
+    let scheme = MemoryScheme {
+        endianness: config.endianness,
+        arch: config.arch,
+        ..MemoryScheme::default()
+    };
+    compile_with(program.inner, entrypoint, &scheme).map_err(|e| {
+        let e = anyhow::Error::from(e).context("could not compile the program");
+        format!("{e:#}")
+    })
+}
+
+impl Script {
+    /// Run the tests against the preprocessed source of a submission, in parallel
+    pub fn run(&self, source: &str, config: MachineConfig) -> Vec<Outcome> {
+        let mut compiled: HashMap<&str, Compiled> = HashMap::new();
+        for test in &self.tests {
+            compiled
+                .entry(test.entrypoint.as_str())
+                .or_insert_with(|| compile_submission(source, &test.entrypoint, &config));
+        }
+
+        self.tests
+            .par_iter()
+            .map(|test| match &compiled[test.entrypoint.as_str()] {
+                Ok((computer, debug_info)) => test.run(computer.clone(), debug_info, config),
+                Err(e) => Outcome::Error(e.clone()),
+            })
+            .collect()
+    }
+}
+
+impl Test {
+    /// Run the test on a computer holding the compiled submission
+    fn run(
+        &self,
+        mut computer: Computer,
+        debug_info: &DebugInfo,
+        config: MachineConfig,
+    ) -> Outcome {
+        if !self.args.is_empty() {
+            if let Err(e) = computer.set_args(&self.args) {
+                return Outcome::Error(e.to_string());
+            }
+        }
+
+        let (console, output) = Console::new();
+        if let Err(e) = computer.attach(0, console) {
+            return Outcome::Error(e.to_string());
+        }
+
+        computer.set_config(config);
+        let mut result = computer.run();
+        // Breakpoints left in the submission are ignored
+        while matches!(result, Err(ProcessorError::Breakpoint)) {
+            result = computer.run();
+        }
+        match result {
+            Ok(()) => {}
+            Err(ProcessorError::LimitExceeded(e)) => return Outcome::Failed(e.to_string()),
+            Err(e) => return Outcome::Failed(format!("runtime error: {e}")),
+        }
+
+        let stdout = output.take(Channel::Stdout);
+        let mut failures = Vec::new();
+        for check in &self.checks {
+            match check {
+                Check::Value {
+                    source,
+                    expression,
+                    expected,
+                } => match expression.evaluate(&computer, &debug_info.labels) {
+                    Ok(actual) if same_value(&actual, expected) => {}
+                    Ok(actual) => {
+                        failures.push(format!("expected {source} = {expected}, got {actual}"));
+                    }
+                    Err(e) => failures.push(format!("could not evaluate {source}: {e}")),
+                },
+                Check::Output(expected) => {
+                    if &stdout != expected {
+                        failures.push(format!("expected output {expected:?}, got {stdout:?}"));
+                    }
+                }
+                Check::ExitCode(expected) => {
+                    let actual = computer.exit_code();
+                    if actual != Some(*expected) {
+                        failures.push(format!(
+                            "expected exit code {expected}, got {}",
+                            actual.map_or_else(|| "none".to_owned(), |code| code.to_string())
+                        ));
+                    }
+                }
+            }
+        }
+
+        if failures.is_empty() {
+            Outcome::Passed
+        } else {
+            Outcome::Failed(failures.join("; "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(script: &str) -> String {
+        script.parse::<Script>().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parse_script_test() {
+        let script: Script = r#"
+            # Comments and blank lines are skipped
+
+            test factorial
+                entrypoint fact
+                args 5 "two words"
+                expect %a = 120
+                expect [result] = -0x10
+                expect [letter] = '\n'
+                output "120\n"
+                exit 0
+            test defaults
+        "#
+        .parse()
+        .unwrap();
+
+        let [factorial, defaults] = &script.tests[..] else {
+            panic!("expected two tests, got {:?}", script.tests);
+        };
+        assert_eq!(factorial.name, "factorial");
+        assert_eq!(factorial.entrypoint, "fact");
+        assert_eq!(factorial.args, ["5", "two words"]);
+        let expected: Vec<_> = factorial
+            .checks
+            .iter()
+            .filter_map(|check| match check {
+                Check::Value {
+                    source, expected, ..
+                } => Some((source.as_str(), expected.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            expected,
+            [
+                ("%a", Cell::Word(120)),
+                ("[result]", Cell::Word(-16)),
+                ("[letter]", Cell::Char('\n')),
+            ]
+        );
+        assert!(matches!(&factorial.checks[3], Check::Output(output) if output == "120\n"));
+        assert!(matches!(factorial.checks[4], Check::ExitCode(0)));
+
+        assert_eq!(defaults.entrypoint, "main");
+        assert!(defaults.args.is_empty() && defaults.checks.is_empty());
+    }
+
+    #[test]
+    fn parse_script_errors_test() {
+        assert_eq!(
+            error("entrypoint main"),
+            "line 1: expected a \"test NAME\" line first"
+        );
+        assert_eq!(error("test"), "line 1: missing test name");
+        assert_eq!(error("test a\n\n  run"), "line 3: unknown keyword \"run\"");
+        assert_eq!(
+            error("test a\nexpect %a"),
+            "line 2: expected \"expect EXPRESSION = VALUE\""
+        );
+        assert_eq!(
+            error("test a\nexpect %a = 'ab'"),
+            "line 2: expected a number or a character"
+        );
+        assert_eq!(
+            error("test a\nexpect %a = 0xg"),
+            "line 2: expected a number or a character"
+        );
+        assert!(error("test a\nexpect %z = 1").starts_with("line 2: "));
+        assert_eq!(
+            error("test a\noutput \"\\q\""),
+            "line 2: expected a quoted string"
+        );
+        assert_eq!(
+            error("test a\noutput unquoted"),
+            "line 2: expected a quoted string"
+        );
+        assert_eq!(
+            error("test a\nexit 256"),
+            "line 2: expected an exit code between 0 and 255"
+        );
+        assert!(error("test a\nargs \"unterminated").starts_with("line 2: "));
+    }
+
+    #[test]
+    fn parse_value_test() {
+        assert_eq!(parse_value(" 42 "), Some(Cell::Word(42)));
+        assert_eq!(parse_value("-0x2a"), Some(Cell::Word(-42)));
+        assert_eq!(parse_value("'\\''"), Some(Cell::Char('\'')));
+        assert_eq!(parse_value("''"), None);
+        assert_eq!(parse_value("'\\x'"), None);
+        assert_eq!(parse_value("twelve"), None);
+    }
+}
@@ -1,29 +1,39 @@
 //! This module implements the TTY interactive interface.
 //!
 //! It is mainly based on two crates:
-//!   - rustyline, to handle the line-editting logic
+//!   - rustyline, to handle the line-editting logic (a plain reader of the standard input is used
+//!     on WASI instead)
 //!   - clap, to handle the parsing of those interactive commands
 //!
 //! Using Parser to do this is a bit of a hack, and requires some weird options to have it working
 //! but works nonetheless.
 
 use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
 use clap::Parser;
-use rustyline::Behavior;
-use rustyline::{CompletionType, Config, EditMode, Editor};
-use tracing::{debug, info, warn};
+use tracing::{debug, error, info, warn};
 
-use z33_emulator::compiler::DebugInfo;
+use z33_emulator::compiler::{DebugInfo, Symbols};
 use z33_emulator::constants as C;
-use z33_emulator::runtime::{Computer, Exception, Reg};
+use z33_emulator::runtime::{
+    help, Cell, Computer, InterruptLog, InterruptSource, ProcessorError, Reg, SearchPattern,
+    Snapshot,
+};
 
+#[cfg(not(target_os = "wasi"))]
 mod helper;
 mod parse;
-use self::helper::RunHelper;
+mod save;
+mod watch;
+pub(crate) use self::parse::Address;
+use self::parse::{ExamineFormat, Unit};
+pub(crate) use self::save::{FaultReport, SessionFile};
+pub(crate) use self::watch::{display_watches, Watch, WatchExpression};
+use crate::platform::LineEditor;
 
 static HELP: &str = r#"
-Run "help [command]" for command-specific help.
+Run "help [command]" for command-specific help, or "help [instruction]" to document an instruction.
 An empty line re-runs the last valid command."#;
 
 #[derive(Parser, Clone, Debug)]
@@ -38,10 +48,15 @@ An empty line re-runs the last valid command."#;
 /// Interactive mode commands
 enum Command {
     /// Execute the next instructions
+    #[clap(alias = "s")]
     Step {
         /// Number of steps to execute
         #[clap(value_parser, default_value = "1")]
         number: u64,
+
+        /// Show the registers and memory cells changed by the steps
+        #[clap(long)]
+        diff: bool,
     },
 
     /// Exit the emulator
@@ -65,6 +80,36 @@ enum Command {
         number: i32,
     },
 
+    /// Examine the memory, like "x/16w 9980": a number of cells, shown as words ("w"), as a
+    /// hexdump ("x"), as characters ("c") or as instructions ("i")
+    #[clap(name = "x")]
+    Examine {
+        /// The format, also given right after the command, like "x/16w"
+        #[clap(long, value_parser, default_value = "1w")]
+        format: ExamineFormat,
+
+        /// The first address to show
+        #[clap(value_parser)]
+        address: parse::Address,
+    },
+
+    /// Show the digest of a block in memory, to check it with a `.final_assert` directive
+    Digest {
+        /// The first address of the block
+        #[clap(value_parser)]
+        address: parse::Address,
+
+        /// Number of memory cells in the block
+        #[clap(value_parser, default_value = "1")]
+        length: u32,
+    },
+
+    /// Search the memory for a value, a string or an instruction
+    Search {
+        #[clap(subcommand)]
+        pattern: SearchCommand,
+    },
+
     /// Trigger a hardware interrupt
     Interrupt,
 
@@ -92,11 +137,79 @@ enum Command {
     /// Continue the program until the next breakpoint or reset
     Continue,
 
+    /// Display the value of an expression each time it changes after a step
+    Display {
+        /// A register ("%a"), a memory cell ("[counter]", "[%sp + 1]") or an expression over
+        /// them ("[counter] * 2 + %a")
+        #[clap(value_parser)]
+        expression: Watch,
+    },
+
+    /// Stop displaying an expression
+    Undisplay {
+        /// The expression, as it was given to the "display" command
+        #[clap(value_parser)]
+        expression: String,
+    },
+
     /// Show informations about the current debugging session
     Info {
         #[clap(subcommand)]
         sub: Option<InfoCommand>,
     },
+
+    /// Save the debugging session to a file, or resume one
+    Session {
+        #[clap(subcommand)]
+        sub: SessionCommand,
+    },
+}
+
+#[derive(Parser, Clone, Debug)]
+enum SearchCommand {
+    /// Find the cells holding a word
+    Word {
+        #[clap(value_parser, allow_negative_numbers = true)]
+        value: C::Word,
+    },
+
+    /// Find the strings, held by consecutive cells as characters or as their codes
+    String {
+        #[clap(value_parser)]
+        text: String,
+    },
+
+    /// Find the instructions matching a pattern, like "ld *, %a": "*" matches any operand, and
+    /// the operands left out match anything
+    Instruction {
+        #[clap(value_parser, required = true, trailing_var_arg = true)]
+        pattern: Vec<String>,
+    },
+}
+
+impl From<&SearchCommand> for SearchPattern {
+    fn from(command: &SearchCommand) -> Self {
+        match command {
+            SearchCommand::Word { value } => Self::Word(*value),
+            SearchCommand::String { text } => Self::String(text.clone()),
+            SearchCommand::Instruction { pattern } => Self::Instruction(pattern.join(" ")),
+        }
+    }
+}
+
+#[derive(Parser, Clone, Debug)]
+enum SessionCommand {
+    /// Save the program, breakpoints, displayed expressions and state of the machine
+    Save {
+        #[clap(value_parser)]
+        path: PathBuf,
+    },
+
+    /// Replace the current session with one saved to a file
+    Load {
+        #[clap(value_parser)]
+        path: PathBuf,
+    },
 }
 
 #[derive(Parser, Clone, Debug)]
@@ -122,16 +235,46 @@ struct Session {
 
     /// Current address for the `list` command
     list_address: Option<C::Address>,
+
+    /// Expressions displayed after each step
+    watches: Vec<Watch>,
+
+    /// Labels naming the addresses of the memory dumps
+    symbols: Symbols,
 }
 
 impl Session {
-    fn from_debug_info(info: DebugInfo) -> Session {
+    fn from_debug_info(info: DebugInfo, watches: Vec<Watch>, symbols: Symbols) -> Session {
         Session {
             labels: info.labels,
+            watches,
+            symbols,
             ..Default::default()
         }
     }
 
+    /// Add an expression to display after each step
+    fn add_watch(&mut self, computer: &Computer, mut watch: Watch) {
+        if let Some(value) = watch.update(computer, &self.labels) {
+            info!("{} = {}", watch.source(), value);
+        }
+        self.watches.push(watch);
+    }
+
+    /// Remove a displayed expression
+    fn remove_watch(&mut self, source: &str) {
+        let count = self.watches.len();
+        self.watches.retain(|w| w.source() != source.trim());
+        if self.watches.len() == count {
+            warn!(expression = source, "This expression was not displayed");
+        }
+    }
+
+    /// Display the watched expressions which changed
+    fn update_watches(&mut self, computer: &Computer) {
+        display_watches(&mut self.watches, computer, &self.labels);
+    }
+
     /// Add a breakpoint
     fn add_breakpoint(&mut self, address: C::Address) {
         if self.breakpoints.insert(address) {
@@ -218,6 +361,59 @@ impl Session {
         }
     }
 
+    /// Display the cells at the given addresses, except the empty ones
+    fn display_cells(
+        &self,
+        computer: &Computer,
+        addresses: impl Iterator<Item = C::Address>,
+    ) -> anyhow::Result<()> {
+        for address in addresses {
+            let cell = computer.memory.get(address)?;
+            if *cell != Cell::Empty {
+                let value = cell.display(computer.config().arch);
+                info!(address = %self.symbols.describe(address), value = %value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Display cells of the memory in the given format
+    fn examine(
+        &self,
+        computer: &Computer,
+        address: C::Address,
+        format: ExamineFormat,
+    ) -> anyhow::Result<()> {
+        let range = address..address.saturating_add(format.count);
+        match format.unit {
+            Unit::Word => self.display_cells(computer, range)?,
+            Unit::Hex => {
+                for line in computer.hexdump(range).lines() {
+                    info!("{line}");
+                }
+            }
+            Unit::Char => {
+                let text: String = range
+                    .map(|address| match computer.memory.get(address) {
+                        Ok(Cell::Char(c)) => *c,
+                        Ok(Cell::Word(word)) => u32::try_from(*word)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .unwrap_or('.'),
+                        _ => '.',
+                    })
+                    .collect();
+                info!(address = %self.symbols.describe(address), text = ?text);
+            }
+            Unit::Instruction => {
+                for address in range {
+                    self.display_instruction(computer, address);
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Display the list of labels
     fn display_labels(&self) {
         match self.labels.len() {
@@ -235,14 +431,57 @@ impl Session {
     fn display_cycles(computer: &Computer) {
         info!("Cycles: {}", computer.cycles);
     }
+
+    /// Save the session, along with the state of the computer, to a file
+    fn save(&self, computer: &Computer, path: &Path) -> anyhow::Result<()> {
+        let watches = self.watches.iter().map(|w| w.source().to_owned()).collect();
+        SessionFile::new(
+            self.labels.clone(),
+            self.breakpoints.iter().copied().collect(),
+            watches,
+            Snapshot::capture(computer),
+        )
+        .write(path)
+    }
+
+    /// Replace the session and the state of the computer with the ones saved to a file
+    fn load(&mut self, computer: &mut Computer, path: &Path) -> anyhow::Result<()> {
+        let file = SessionFile::read(path)?;
+        let watches = file
+            .watches
+            .iter()
+            .map(|source| source.parse())
+            .collect::<Result<Vec<Watch>, _>>()?;
+        file.snapshot.restore(computer)?;
+        if let Some(fault) = &file.fault {
+            error!("The program stopped on a fault: {fault}");
+        }
+
+        *self = Session {
+            breakpoints: file.breakpoints.into_iter().collect(),
+            symbols: Symbols::new(&file.labels),
+            labels: file.labels,
+            list_address: None,
+            watches,
+        };
+        info!(registers = %computer.registers, "Session loaded");
+        self.update_watches(computer);
+        Ok(())
+    }
 }
 
-#[allow(clippy::too_many_lines)]
-pub(crate) fn run_interactive(
-    computer: &mut Computer,
-    debug_info: DebugInfo,
-) -> anyhow::Result<()> {
-    info!("Running in interactive mode. Type \"help\" to list available commands.");
+#[cfg(not(target_os = "wasi"))]
+impl<H: rustyline::Helper, I: rustyline::history::History> LineEditor for rustyline::Editor<H, I> {
+    fn readline(&mut self, prompt: &str) -> anyhow::Result<String> {
+        Ok(rustyline::Editor::readline(self, prompt)?)
+    }
+}
+
+/// Create the line editor, with completion and history
+#[cfg(not(target_os = "wasi"))]
+fn editor() -> anyhow::Result<Box<dyn LineEditor>> {
+    use rustyline::{Behavior, CompletionType, Config, EditMode, Editor};
+
     let config = Config::builder()
         .history_ignore_space(true)
         .completion_type(CompletionType::List)
@@ -251,11 +490,49 @@ pub(crate) fn run_interactive(
         .auto_add_history(true)
         .build();
 
-    let mut session = Session::from_debug_info(debug_info);
-
-    let h: RunHelper<Command> = RunHelper::new();
+    let h: self::helper::RunHelper<Command> = self::helper::RunHelper::new();
     let mut rl = Editor::with_config(config)?;
     rl.set_helper(Some(h));
+    Ok(Box::new(rl))
+}
+
+#[cfg(target_os = "wasi")]
+fn editor() -> anyhow::Result<Box<dyn LineEditor>> {
+    Ok(Box::new(crate::platform::StdinEditor))
+}
+
+pub(crate) fn run_interactive(
+    computer: &mut Computer,
+    debug_info: DebugInfo,
+    watches: Vec<Watch>,
+    symbols: Symbols,
+    interrupts: &mut InterruptLog,
+) -> anyhow::Result<()> {
+    let session = Session::from_debug_info(debug_info, watches, symbols);
+    interact(computer, session, interrupts)
+}
+
+/// Resume a debugging session saved with "session save"
+pub(crate) fn resume_interactive(
+    computer: &mut Computer,
+    path: &Path,
+    interrupts: &mut InterruptLog,
+) -> anyhow::Result<()> {
+    let mut session = Session::default();
+    session.load(computer, path)?;
+    interact(computer, session, interrupts)
+}
+
+#[allow(clippy::too_many_lines)]
+fn interact(
+    computer: &mut Computer,
+    mut session: Session,
+    interrupts: &mut InterruptLog,
+) -> anyhow::Result<()> {
+    info!("Running in interactive mode. Type \"help\" to list available commands.");
+    session.update_watches(computer);
+
+    let mut rl = editor()?;
 
     let mut last_command = None;
 
@@ -270,7 +547,22 @@ pub(crate) fn run_interactive(
                 continue;
             }
         } else {
-            let words = shell_words::split(readline.as_str())?;
+            let mut words = shell_words::split(readline.as_str())?;
+
+            // The format of "x" is given right after it, like "x/16w"
+            if let Some(format) = words.first().and_then(|first| first.strip_prefix("x/")) {
+                let format = format.to_owned();
+                words.splice(0..1, ["x".to_owned(), "--format".to_owned(), format]);
+            }
+
+            // Instructions are documented by "help MNEMONIC", other topics are handled by clap
+            if let [first, topic] = words.as_slice() {
+                if let Some(text) = (first == "help").then(|| help(topic)).flatten() {
+                    println!("{}: {text}", topic.to_lowercase());
+                    continue;
+                }
+            }
+
             match Command::try_parse_from(words) {
                 Ok(c) => c,
                 Err(e) => {
@@ -284,49 +576,92 @@ pub(crate) fn run_interactive(
 
         match &command {
             Command::Exit => break,
-            Command::Step { number } => {
+            Command::Step { number, diff } => {
+                let before = diff.then(|| Snapshot::capture(computer));
+
                 // TODO: recover from errors
                 for _ in 0..*number {
-                    computer.step()?;
+                    match computer.step() {
+                        Ok(()) => {}
+                        Err(ProcessorError::Breakpoint) => {
+                            session.update_watches(computer);
+                            info!(address = computer.registers.pc - 1, "Stopped at a brk");
+                            break;
+                        }
+                        Err(e) => return Err(e.with_symbols(&session.symbols).into()),
+                    }
+                    session.update_watches(computer);
+                }
+
+                if let Some(before) = before {
+                    let changes = before.diff(&Snapshot::capture(computer));
+                    if changes.is_empty() {
+                        info!("Nothing changed");
+                    }
+                    print!("{changes}");
                 }
 
                 session.reset_list();
             }
             Command::Registers { register } => {
+                let arch = computer.config().arch;
                 if let Some(reg) = register {
                     match reg {
                         Reg::SR => {
-                            info!("Register %sr = {:?}", computer.registers.sr);
+                            info!(
+                                "Register %sr = {:?} (flags {})",
+                                computer.registers.sr,
+                                computer.registers.flags()
+                            );
                         }
                         reg => {
                             let cell = computer.registers.get(reg);
-                            info!("Register {} = {}", reg, cell);
+                            info!("Register {} = {}", reg, cell.display(arch));
                         }
                     }
                 } else {
-                    info!("Registers: {}", computer.registers);
+                    info!("Registers: {}", computer.registers.display(arch));
+                    let depth = computer.interrupt_depth();
+                    if depth > 0 {
+                        info!(depth, "Inside an interrupt handler");
+                    }
                 }
             }
             Command::Memory { address, number } => {
                 let address = address.clone().evaluate(computer, &session.labels)?;
-
+                let count = number.unsigned_abs();
                 if number.is_positive() {
-                    for i in 0..(number.unsigned_abs() as C::Address) {
-                        let address = address + i;
-                        let cell = computer.memory.get(address)?;
-                        info!(address, value = %cell);
-                    }
-                } else {
-                    for i in 0..(number.unsigned_abs() as C::Address) {
-                        let address = address - i;
-                        let cell = computer.memory.get(address)?;
-                        info!(address, value = %cell);
-                    }
+                    session.display_cells(computer, address..address.saturating_add(count))?;
+                } else if count > 0 {
+                    let start = address.saturating_sub(count - 1);
+                    session.display_cells(computer, (start..=address).rev())?;
                 }
             }
 
+            Command::Search { pattern } => {
+                let found = computer.search(&pattern.into());
+                match found.len() {
+                    0 => info!("Not found"),
+                    1 => info!("Found once:"),
+                    x => info!("Found {} times:", x),
+                }
+                session.display_cells(computer, found.into_iter())?;
+            }
+
+            Command::Digest { address, length } => {
+                let address = address.clone().evaluate(computer, &session.labels)?;
+                let digest = computer.memory_digest(address..address.saturating_add(*length));
+                info!("Digest: {digest:#018x}");
+                info!(".final_assert {address}, {length}, {digest:#018x}");
+            }
+
+            Command::Examine { format, address } => {
+                let address = address.clone().evaluate(computer, &session.labels)?;
+                session.examine(computer, address, *format)?;
+            }
+
             Command::Interrupt => {
-                computer.recover_from_exception(&Exception::HardwareInterrupt)?;
+                interrupts.deliver(computer, InterruptSource::Keyboard)?;
                 session.reset_list();
             }
 
@@ -351,12 +686,29 @@ pub(crate) fn run_interactive(
             Command::Continue => {
                 loop {
                     // TODO: recover from error
-                    computer.step()?;
+                    match computer.step() {
+                        Ok(()) => {}
+                        Err(ProcessorError::Breakpoint) => {
+                            session.update_watches(computer);
+                            info!(address = computer.registers.pc - 1, "Stopped at a brk");
+                            break;
+                        }
+                        Err(e) => return Err(e.with_symbols(&session.symbols).into()),
+                    }
+                    session.update_watches(computer);
                     if session.has_breakpoint(computer.registers.pc) {
+                        info!(address = computer.registers.pc, "Stopped at a breakpoint");
                         break;
                     }
                 }
-                info!(address = computer.registers.pc, "Stopped at a breakpoint");
+            }
+
+            Command::Display { expression } => {
+                session.add_watch(computer, expression.clone());
+            }
+
+            Command::Undisplay { expression } => {
+                session.remove_watch(expression);
             }
 
             Command::Info { sub } => match sub {
@@ -377,6 +729,17 @@ pub(crate) fn run_interactive(
                     Session::display_cycles(computer);
                 }
             },
+
+            // A missing or invalid file should not end the session
+            Command::Session { sub } => {
+                let result = match sub {
+                    SessionCommand::Save { path } => session.save(computer, path),
+                    SessionCommand::Load { path } => session.load(computer, path),
+                };
+                if let Err(e) = result {
+                    warn!("{e:#}");
+                }
+            }
         };
 
         last_command = Some(command);
@@ -98,3 +98,59 @@ fn parse_address(input: &str) -> Result<Address, VerboseError<&str>> {
     let (_, ret) = all_consuming(parse_address_inner)(input).finish()?;
     Ok(ret)
 }
+
+/// How the "x" command shows the cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    /// One cell per line, with its value
+    Word,
+
+    /// A hexdump, with the characters on the right
+    Hex,
+
+    /// The characters held by the cells, as a string
+    Char,
+
+    /// The instructions held by the cells
+    Instruction,
+}
+
+/// Format of the "x" command, like `16w`: a number of cells, and how to show them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExamineFormat {
+    pub count: C::Address,
+    pub unit: Unit,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid format {0:?}, expected a number of cells followed by w, x, c or i, like \"16w\"")]
+pub struct ParseFormatError(String);
+
+impl FromStr for ExamineFormat {
+    type Err = ParseFormatError;
+
+    /// Parse a format, with or without its leading slash. The count defaults to 1, and the unit
+    /// to words
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseFormatError(s.to_owned());
+        let format = s.trim().trim_start_matches('/');
+        let digits = format
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(format.len());
+        let (count, unit) = format.split_at(digits);
+
+        let count = if count.is_empty() {
+            1
+        } else {
+            count.parse().map_err(|_| error())?
+        };
+        let unit = match unit {
+            "" | "w" => Unit::Word,
+            "x" => Unit::Hex,
+            "c" => Unit::Char,
+            "i" => Unit::Instruction,
+            _ => return Err(error()),
+        };
+        Ok(Self { count, unit })
+    }
+}
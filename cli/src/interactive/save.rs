@@ -0,0 +1,105 @@
+//! Debugging sessions saved to a file, to resume them later or share them
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use z33_emulator::constants as C;
+use z33_emulator::runtime::Snapshot;
+
+/// Version of the session file format
+const SESSION_VERSION: u32 = 1;
+
+/// Everything needed to resume a debugging session: the program and the state of the machine,
+/// along with the labels, breakpoints and displayed expressions
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionFile {
+    pub version: u32,
+    pub labels: HashMap<String, C::Address>,
+    pub breakpoints: BTreeSet<C::Address>,
+
+    /// Displayed expressions, as they were given to the "display" command
+    pub watches: Vec<String>,
+
+    /// The compiled program is part of the memory saved in the snapshot
+    pub snapshot: Snapshot,
+
+    /// The error which stopped the program, in core files written by "run --core"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fault: Option<FaultReport>,
+}
+
+/// An unhandled fault, as reported when the program stopped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaultReport {
+    /// The error, with the state of the processor when it happened
+    pub message: String,
+
+    /// Code of the error, like `E0021`
+    pub code: Option<String>,
+
+    /// Address of the instruction which faulted
+    pub address: C::Address,
+
+    /// Line of the preprocessed source holding the instruction, with its number
+    pub line: Option<(usize, String)>,
+}
+
+impl fmt::Display for FaultReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(code) = &self.code {
+            write!(f, "[{code}] ")?;
+        }
+        write!(f, "{}", self.message)?;
+        if let Some((number, line)) = &self.line {
+            write!(f, "\n  {number} | {}", line.trim())?;
+        }
+        Ok(())
+    }
+}
+
+impl SessionFile {
+    pub fn new(
+        labels: HashMap<String, C::Address>,
+        breakpoints: BTreeSet<C::Address>,
+        watches: Vec<String>,
+        snapshot: Snapshot,
+    ) -> Self {
+        Self {
+            version: SESSION_VERSION,
+            labels,
+            breakpoints,
+            watches,
+            snapshot,
+            fault: None,
+        }
+    }
+
+    /// Attach the fault which stopped the program, making a core file
+    pub fn with_fault(mut self, fault: FaultReport) -> Self {
+        self.fault = Some(fault);
+        self
+    }
+
+    /// Read a session from a file
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        info!(path = ?path, "Loading session");
+        let file = std::fs::File::open(path)?;
+        let session: Self = serde_json::from_reader(std::io::BufReader::new(file))?;
+        if session.version != SESSION_VERSION {
+            anyhow::bail!("unsupported session version {}", session.version);
+        }
+        Ok(session)
+    }
+
+    /// Write the session to a file
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        info!(path = ?path, "Saving session");
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer(std::io::BufWriter::new(file), self)?;
+        Ok(())
+    }
+}
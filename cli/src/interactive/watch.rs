@@ -0,0 +1,141 @@
+//! Watch expressions, evaluated against the state of the computer after each step
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use nom::{combinator::all_consuming, error::VerboseError, Finish};
+use thiserror::Error;
+use tracing::info;
+
+use z33_emulator::{
+    constants as C,
+    parser::{parse_register, parse_runtime_expression, ExpressionNode},
+    runtime::{Cell, Computer, MachineContext, Reg},
+};
+
+use super::parse::{Address, ParseAddressError};
+
+/// What a watch expression looks at
+#[derive(Debug, Clone)]
+pub enum WatchExpression {
+    /// A register, like `%a`
+    Register(Reg),
+
+    /// A memory cell, like `[counter]` or `[%sp + 1]`
+    Memory(Address),
+
+    /// Any other expression over the registers and memory, like `[counter] * 2 + %a`
+    Expression(ExpressionNode),
+}
+
+#[derive(Debug, Error)]
+pub enum ParseWatchError {
+    #[error(transparent)]
+    Address(#[from] ParseAddressError),
+
+    #[error("expected a register (\"%a\"), a memory cell (\"[address]\") or an expression")]
+    Invalid,
+}
+
+impl FromStr for WatchExpression {
+    type Err = ParseWatchError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(address) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            if let Ok(address) = address.trim().parse() {
+                return Ok(Self::Memory(address));
+            }
+        }
+
+        if let Ok((_, reg)) = all_consuming(parse_register::<VerboseError<&str>>)(s).finish() {
+            return Ok(Self::Register(reg));
+        }
+
+        let (_, node) = all_consuming(parse_runtime_expression::<VerboseError<&str>>)(s)
+            .finish()
+            .map_err(|_| ParseWatchError::Invalid)?;
+        Ok(Self::Expression(node))
+    }
+}
+
+impl WatchExpression {
+    /// Evaluate the expression against the computer state, with the labels of its program
+    pub fn evaluate(
+        &self,
+        computer: &Computer,
+        labels: &HashMap<String, C::Address>,
+    ) -> anyhow::Result<Cell> {
+        match self {
+            Self::Register(reg) => Ok(computer.registers.get(reg)),
+            Self::Memory(address) => {
+                let address = address.clone().evaluate(computer, labels)?;
+                Ok(computer.memory.get(address)?.clone())
+            }
+            Self::Expression(node) => {
+                let context = MachineContext::new(computer, labels);
+                let word: C::Word = node.evaluate(&context)?;
+                Ok(Cell::Word(word))
+            }
+        }
+    }
+}
+
+/// A watch expression along with its last displayed value
+#[derive(Debug, Clone)]
+pub struct Watch {
+    source: String,
+    expression: WatchExpression,
+    last: Option<String>,
+}
+
+impl FromStr for Watch {
+    type Err = ParseWatchError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            source: s.trim().to_owned(),
+            expression: s.parse()?,
+            last: None,
+        })
+    }
+}
+
+impl Watch {
+    /// The expression, as it was written
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Evaluate the expression, returning its new value if it changed since the last evaluation
+    pub fn update(
+        &mut self,
+        computer: &Computer,
+        labels: &HashMap<String, C::Address>,
+    ) -> Option<String> {
+        let value = match self.expression.evaluate(computer, labels) {
+            Ok(cell) => cell.to_string(),
+            Err(e) => format!("<{e}>"),
+        };
+
+        if self.last.as_ref() == Some(&value) {
+            None
+        } else {
+            self.last = Some(value.clone());
+            Some(value)
+        }
+    }
+}
+
+/// Evaluate all the watch expressions and print the ones which changed
+pub fn display_watches(
+    watches: &mut [Watch],
+    computer: &Computer,
+    labels: &HashMap<String, C::Address>,
+) {
+    for watch in watches {
+        if let Some(value) = watch.update(computer, labels) {
+            info!("{} = {}", watch.source(), value);
+        }
+    }
+}
@@ -5,17 +5,33 @@
 use std::process::exit;
 
 use clap::{ArgAction, ArgGroup, Parser};
-use tracing::error;
 use tracing_subscriber::filter::EnvFilter;
 use tracing_subscriber::prelude::*;
+use z33_emulator::parser::location::DEFAULT_TAB_WIDTH;
 
 mod commands;
+mod config;
+mod diagnostics;
+mod export;
+mod grading;
 mod interactive;
+mod metadata;
+mod platform;
+mod trace;
+#[cfg(not(target_os = "wasi"))]
+mod tui;
 
 use crate::commands::Subcommand;
+use crate::diagnostics::MessageFormat;
 
 #[derive(Parser)]
-#[clap(version, author, about, group = ArgGroup::new("format"))]
+#[clap(
+    version,
+    author,
+    about,
+    group = ArgGroup::new("format"),
+    after_help = "Run \"z33-cli help <COMMAND>\" to see examples of a command."
+)]
 struct Opt {
     /// Increase the level of verbosity. Can be used multiple times.
     #[clap(short, long, action = ArgAction::Count, global(true))]
@@ -33,6 +49,25 @@ struct Opt {
     #[clap(short, long, action = ArgAction::SetTrue, global(true), group = "format")]
     json: bool,
 
+    /// How errors and warnings are printed: rendered for humans, or as JSON records on stdout
+    #[clap(
+        long,
+        value_enum,
+        global(true),
+        default_value = "human",
+        value_name = "FORMAT"
+    )]
+    message_format: MessageFormat,
+
+    /// Number of columns a tab takes when showing and locating errors in the sources
+    #[clap(long, global(true), default_value_t = DEFAULT_TAB_WIDTH, value_name = "WIDTH")]
+    tab_width: usize,
+
+    /// Number of threads used to assemble files and grade submissions. Defaults to the number of
+    /// CPUs
+    #[clap(long, global(true), value_name = "N")]
+    jobs: Option<usize>,
+
     #[clap(subcommand)]
     command: Subcommand,
 }
@@ -54,7 +89,7 @@ impl Opt {
         } else if self.no_color {
             false
         } else {
-            atty::is(atty::Stream::Stdout)
+            platform::is_terminal()
         }
     }
 
@@ -85,10 +120,20 @@ fn main() {
         registry.with(fmt_layer).init();
     }
 
+    diagnostics::set_message_format(opt.message_format);
+    diagnostics::set_tab_width(opt.tab_width);
+
+    if let Some(jobs) = opt.jobs {
+        // This only fails when the pool was already built, which it is not at this point
+        let _ = rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global();
+    }
+
     // And run the command
     let res = opt.command.exec();
     if let Err(e) = res {
-        error!("{}", e);
+        diagnostics::report_error(&e);
         exit(1);
     }
 }
@@ -0,0 +1,73 @@
+//! Machine-readable metadata about a compiled program, for build tools
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use z33_emulator::compiler::DebugInfo;
+
+/// Hex-encoded SHA-256 of some content
+fn sha256(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// Build the metadata of a program
+///
+/// It holds the entrypoint, the label table, the range of each section and the list of files
+/// read by the preprocessor with the hash of their content.
+pub fn metadata(
+    input: &Path,
+    entrypoint: &str,
+    debug_info: &DebugInfo,
+    sources: &HashMap<PathBuf, String>,
+) -> Value {
+    let labels: BTreeMap<_, _> = debug_info.labels.iter().collect();
+
+    let mut regions: Vec<_> = debug_info
+        .regions
+        .iter()
+        .filter(|region| !region.range.is_empty())
+        .collect();
+    regions.sort_by_key(|region| region.range.start);
+    let sections: Vec<_> = regions
+        .into_iter()
+        .map(|region| {
+            json!({
+                "section": region.section.map(|s| s.to_string()),
+                "start": region.range.start,
+                "end": region.range.end,
+            })
+        })
+        .collect();
+
+    let sources: BTreeMap<_, _> = sources.iter().collect();
+    let includes: Vec<_> = sources
+        .into_iter()
+        .map(|(path, content)| {
+            json!({
+                "path": path,
+                "sha256": sha256(content),
+            })
+        })
+        .collect();
+
+    json!({
+        "input": input,
+        "entrypoint": {
+            "label": entrypoint,
+            "address": debug_info.labels.get(entrypoint),
+        },
+        "labels": labels,
+        "sections": sections,
+        "includes": includes,
+    })
+}
@@ -0,0 +1,58 @@
+//! Parts of the CLI depending on the host platform
+//!
+//! The CLI also builds for WASI, where there is no terminal to query and no current directory.
+//! Everything which differs between the two goes through here.
+
+use z33_emulator::preprocessor::NativeFilesystem;
+
+/// Check if the standard output is a terminal, to decide whether to use colors
+#[cfg(not(target_os = "wasi"))]
+pub(crate) fn is_terminal() -> bool {
+    atty::is(atty::Stream::Stdout)
+}
+
+/// Check if the standard output is a terminal, to decide whether to use colors
+///
+/// WASI can not tell, so colors have to be enabled explicitly with `--color`.
+#[cfg(target_os = "wasi")]
+pub(crate) fn is_terminal() -> bool {
+    false
+}
+
+/// Filesystem used to load programs, relative to the current directory
+pub(crate) fn filesystem() -> std::io::Result<NativeFilesystem> {
+    #[cfg(not(target_os = "wasi"))]
+    return NativeFilesystem::from_env();
+
+    // Paths are resolved against the directories preopened by the runtime
+    #[cfg(target_os = "wasi")]
+    return Ok(NativeFilesystem::new(".".into()));
+}
+
+/// Reads the commands typed in interactive mode
+pub(crate) trait LineEditor {
+    /// Read a line, without its trailing newline
+    fn readline(&mut self, prompt: &str) -> anyhow::Result<String>;
+}
+
+/// Line editor without completion nor history, reading from the standard input
+#[cfg(target_os = "wasi")]
+pub(crate) struct StdinEditor;
+
+#[cfg(target_os = "wasi")]
+impl LineEditor for StdinEditor {
+    fn readline(&mut self, prompt: &str) -> anyhow::Result<String> {
+        use std::io::Write;
+
+        let mut stdout = std::io::stdout();
+        write!(stdout, "{prompt}")?;
+        stdout.flush()?;
+
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            anyhow::bail!("end of input");
+        }
+
+        Ok(line.trim_end_matches(['\r', '\n']).to_owned())
+    }
+}
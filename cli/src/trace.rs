@@ -0,0 +1,195 @@
+//! Execution traces, printed while the program runs
+
+use ansi_term::{Colour, Style};
+use clap::ValueEnum;
+use serde_json::{json, Map, Value};
+use z33_emulator::{
+    compiler::{DebugInfo, Symbols},
+    constants as C,
+    runtime::{Computer, ProcessorError, Reg, Registers, RingBuffer},
+};
+
+/// Format of the execution trace
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TraceFormat {
+    /// One line per instruction, with its address
+    Plain,
+
+    /// Annotated with the source line and the changed registers, indented by call depth
+    Pretty,
+
+    /// One JSON object per instruction, with its address, its structure and the registers after
+    /// it was executed
+    Json,
+}
+
+const REGISTERS: [Reg; 5] = [Reg::A, Reg::B, Reg::PC, Reg::SP, Reg::SR];
+
+/// Prints each executed instruction
+pub struct Tracer<'a> {
+    format: TraceFormat,
+    source: &'a str,
+    debug_info: &'a DebugInfo,
+    symbols: Symbols,
+    colors: bool,
+    depth: usize,
+
+    /// The last lines, printed when the program stops instead of as it runs
+    last: Option<RingBuffer<String>>,
+}
+
+impl<'a> Tracer<'a> {
+    pub fn new(format: TraceFormat, source: &'a str, debug_info: &'a DebugInfo) -> Self {
+        Self {
+            format,
+            source,
+            debug_info,
+            symbols: debug_info.symbols(),
+            colors: crate::platform::is_terminal(),
+            depth: 0,
+            last: None,
+        }
+    }
+
+    /// Only keep the last lines, to print them when the program stops
+    pub fn keep_last(mut self, capacity: usize) -> Self {
+        self.last = Some(RingBuffer::new(capacity));
+        self
+    }
+
+    /// Show the addresses alone, without the label before them
+    pub fn without_symbols(mut self) -> Self {
+        self.symbols = Symbols::default();
+        self
+    }
+
+    fn print(&mut self, line: String) {
+        match self.last.as_mut() {
+            Some(last) => last.push(line),
+            None => println!("{line}"),
+        }
+    }
+
+    /// Print the lines kept until now, telling how many were left out
+    pub fn finish(self) {
+        if let Some(last) = self.last {
+            let (lines, dropped) = last.into_parts();
+            if dropped > 0 {
+                println!("({dropped} earlier instructions not shown)");
+            }
+            for line in lines {
+                println!("{line}");
+            }
+        }
+    }
+
+    fn paint(&self, style: Style, text: &str) -> String {
+        if self.colors {
+            style.paint(text).to_string()
+        } else {
+            text.to_owned()
+        }
+    }
+
+    /// The source line of the instruction at the given address
+    fn source_line(&self, address: C::Address) -> Option<&'a str> {
+        let location = self.debug_info.locations.get(&address)?;
+        let source = self.source;
+        let start = source[..location.offset].rfind('\n').map_or(0, |i| i + 1);
+        let end = source[location.offset..]
+            .find('\n')
+            .map_or(source.len(), |i| location.offset + i);
+        Some(source[start..end].trim())
+    }
+
+    /// Execute one step of the computer, printing the instruction
+    pub fn step(&mut self, computer: &mut Computer) -> Result<(), ProcessorError> {
+        let address = computer.registers.pc;
+        let instruction = computer
+            .memory
+            .get(address)
+            .ok()
+            .and_then(|c| c.extract_instruction().ok())
+            .cloned();
+        let before = computer.registers.clone();
+
+        let result = computer.step();
+
+        let text = instruction
+            .as_ref()
+            .map_or_else(|| "–".to_owned(), ToString::to_string);
+
+        let line = match self.format {
+            TraceFormat::Plain => format!("{:>5}  {text}", self.symbols.describe(address)),
+            TraceFormat::Pretty => self.pretty(address, &text, &before, computer),
+            TraceFormat::Json => {
+                let registers: Map<String, Value> = REGISTERS
+                    .iter()
+                    .map(|reg| {
+                        let value = computer.registers.get(reg);
+                        (reg.to_string(), json!(value))
+                    })
+                    .collect();
+                let step = json!({
+                    "address": address,
+                    "symbol": self.symbols.lookup(address).map(|symbol| symbol.to_string()),
+                    "text": text,
+                    "instruction": instruction,
+                    "registers": registers,
+                });
+                step.to_string()
+            }
+        };
+        self.print(line);
+
+        // A faulting call or return does not change the call depth
+        if let (Some(instruction), Ok(())) = (instruction, &result) {
+            if instruction.is_call() {
+                self.depth += 1;
+            } else if instruction.is_return() {
+                self.depth = self.depth.saturating_sub(1);
+            }
+        }
+
+        result
+    }
+
+    fn pretty(
+        &self,
+        address: C::Address,
+        text: &str,
+        before: &Registers,
+        computer: &Computer,
+    ) -> String {
+        let (after, arch) = (&computer.registers, computer.config().arch);
+        let indent = "  ".repeat(self.depth);
+        let instruction = format!("{indent}{text:<24}");
+        let source = self
+            .source_line(address)
+            .filter(|line| *line != text)
+            .map(|line| format!("; {line}"))
+            .unwrap_or_default();
+
+        let changes: Vec<_> = REGISTERS
+            .iter()
+            .filter(|&reg| *reg != Reg::PC && before.get(reg) != after.get(reg))
+            .map(|reg| {
+                let change = match reg {
+                    Reg::SR => format!("{reg} = {:?} ({})", after.sr, after.flags()),
+                    reg => format!("{reg} = {}", after.get(reg).display(arch)),
+                };
+                self.paint(Colour::Yellow.bold(), &change)
+            })
+            .collect();
+
+        format!(
+            "{}  {instruction} {} {}",
+            self.paint(
+                Colour::Blue.normal(),
+                &format!("{:>5}", self.symbols.describe(address))
+            ),
+            self.paint(Style::new().dimmed(), &format!("{source:<32}")),
+            changes.join(", ")
+        )
+    }
+}
@@ -0,0 +1,462 @@
+//! Full-screen terminal interface
+//!
+//! The screen is split in panes showing the source (with the current line highlighted), the
+//! registers, the stack and a hexdump of the memory. Commands are typed in the bar at the bottom:
+//!
+//!   - `step [n]` (or `s`, F10): execute the next instructions
+//!   - `continue` (or `c`, F5): run until the next breakpoint or reset
+//!   - `break <address>` (or `b`) / `unbreak <address>` (or `u`): set or remove a breakpoint
+//!   - `memory <address>` (or `m`): move the memory view
+//!   - `interrupt` (or `i`): trigger a hardware interrupt
+//!   - `quit` (or `q`, Esc): exit
+//!
+//! An empty command re-runs the last one.
+
+use std::collections::HashSet;
+use std::io::Stdout;
+
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Paragraph},
+    Frame, Terminal,
+};
+use z33_emulator::{
+    compiler::{DebugInfo, Symbols},
+    constants as C,
+    runtime::{Cell, Computer, Exception, ProcessorError, Reg, HEXDUMP_WIDTH},
+};
+
+use crate::interactive::Address;
+
+/// Maximum number of steps for the `continue` command, to avoid hanging on infinite loops
+const MAX_CONTINUE_STEPS: usize = 1_000_000;
+
+/// Number of memory cells scrolled with the page up/down keys
+const MEMORY_PAGE: C::Address = 16;
+
+struct App<'a> {
+    computer: Computer,
+    debug_info: DebugInfo,
+
+    /// Labels naming the addresses of the stack pane and of the errors
+    symbols: Symbols,
+
+    /// Lines of the preprocessed source, with the offset they start at
+    lines: Vec<(usize, &'a str)>,
+
+    breakpoints: HashSet<C::Address>,
+
+    /// First address shown in the memory pane
+    memory_address: C::Address,
+
+    /// Content of the command bar
+    command: String,
+
+    /// Last command executed, re-run when an empty command is submitted
+    last_command: Option<String>,
+
+    /// Message shown below the command bar
+    message: String,
+
+    /// Set once the computer was reset
+    finished: bool,
+}
+
+impl<'a> App<'a> {
+    fn new(source: &'a str, computer: Computer, debug_info: DebugInfo, symbols: Symbols) -> Self {
+        let mut offset = 0;
+        let lines = source
+            .split('\n')
+            .map(|line| {
+                let start = offset;
+                offset += line.len() + 1;
+                (start, line)
+            })
+            .collect();
+
+        Self {
+            memory_address: C::PROGRAM_START,
+            computer,
+            debug_info,
+            symbols,
+            lines,
+            breakpoints: HashSet::new(),
+            command: String::new(),
+            last_command: None,
+            message: String::from("Type \"step\", \"continue\" or \"quit\""),
+            finished: false,
+        }
+    }
+
+    /// Find the source line of the instruction at the given address
+    fn line_of(&self, address: C::Address) -> Option<usize> {
+        let location = self.debug_info.locations.get(&address)?;
+        let index = self
+            .lines
+            .partition_point(|(start, _)| *start <= location.offset);
+        index.checked_sub(1)
+    }
+
+    fn step(&mut self) -> bool {
+        if self.finished {
+            self.message = String::from("The program has ended");
+            return false;
+        }
+
+        match self.computer.step() {
+            Ok(()) => true,
+            Err(ProcessorError::Reset) => {
+                self.finished = true;
+                self.message = String::from("Program reset");
+                false
+            }
+            Err(ProcessorError::Halt) => {
+                self.finished = true;
+                self.message = String::from("Program halted");
+                false
+            }
+            Err(ProcessorError::Breakpoint) => {
+                self.message = format!("Stopped at brk {}", self.computer.registers.pc - 1);
+                false
+            }
+            Err(e) => {
+                self.message = e.with_symbols(&self.symbols).to_string();
+                false
+            }
+        }
+    }
+
+    fn run_until_breakpoint(&mut self) {
+        for _ in 0..MAX_CONTINUE_STEPS {
+            if !self.step() {
+                return;
+            }
+
+            if self.breakpoints.contains(&self.computer.registers.pc) {
+                self.message = format!("Stopped at breakpoint {}", self.computer.registers.pc);
+                return;
+            }
+        }
+
+        self.message = format!("Stopped after {MAX_CONTINUE_STEPS} steps");
+    }
+
+    fn evaluate_address(&self, address: Option<&str>) -> anyhow::Result<C::Address> {
+        let address: Address = address
+            .ok_or_else(|| anyhow::anyhow!("missing address"))?
+            .parse()?;
+        address.evaluate(&self.computer, &self.debug_info.labels)
+    }
+
+    /// Run a command from the command bar. Returns `false` if the interface should exit.
+    fn execute(&mut self, command: &str) -> bool {
+        let mut words = command.split_whitespace();
+        let Some(name) = words.next() else {
+            return true;
+        };
+        let argument = words.next();
+
+        let result = match name {
+            "q" | "quit" => return false,
+            "s" | "step" => argument
+                .map_or(Ok(1), str::parse::<usize>)
+                .map_err(anyhow::Error::from)
+                .map(|count| {
+                    self.message.clear();
+                    for _ in 0..count {
+                        if !self.step() {
+                            break;
+                        }
+                    }
+                }),
+            "c" | "continue" => {
+                self.message.clear();
+                self.run_until_breakpoint();
+                Ok(())
+            }
+            "b" | "break" => self.evaluate_address(argument).map(|address| {
+                self.breakpoints.insert(address);
+                self.message = format!("Breakpoint set at {address}");
+            }),
+            "u" | "unbreak" => self.evaluate_address(argument).map(|address| {
+                self.breakpoints.remove(&address);
+                self.message = format!("Breakpoint removed at {address}");
+            }),
+            "m" | "memory" => self.evaluate_address(argument).map(|address| {
+                self.memory_address = address;
+                self.message.clear();
+            }),
+            "i" | "interrupt" => self
+                .computer
+                .recover_from_exception(&Exception::HardwareInterrupt)
+                .map_err(anyhow::Error::from)
+                .map(|()| self.message = String::from("Hardware interrupt")),
+            _ => Err(anyhow::anyhow!("unknown command {name:?}")),
+        };
+
+        if let Err(e) = result {
+            self.message = e.to_string();
+        }
+
+        true
+    }
+
+    /// Handle a terminal event. Returns `false` if the interface should exit.
+    fn handle(&mut self, event: &Event) -> bool {
+        let Event::Key(key) = event else { return true };
+        if key.kind == KeyEventKind::Release {
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::F(10) => return self.execute("step"),
+            KeyCode::F(5) => return self.execute("continue"),
+            KeyCode::PageUp => {
+                self.memory_address = self.memory_address.saturating_sub(MEMORY_PAGE);
+            }
+            KeyCode::PageDown => {
+                self.memory_address = (self.memory_address + MEMORY_PAGE).min(C::MEMORY_SIZE - 1);
+            }
+            KeyCode::Char(c) => self.command.push(c),
+            KeyCode::Backspace => {
+                self.command.pop();
+            }
+            KeyCode::Enter => {
+                let command = std::mem::take(&mut self.command);
+                let command = if command.trim().is_empty() {
+                    self.last_command.clone().unwrap_or_default()
+                } else {
+                    command
+                };
+                let keep_going = self.execute(&command);
+                self.last_command = Some(command);
+                return keep_going;
+            }
+            _ => {}
+        }
+
+        true
+    }
+
+    fn draw<B: Backend>(&self, f: &mut Frame<'_, B>) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Min(5),
+                    Constraint::Length(3),
+                    Constraint::Length(1),
+                ]
+                .as_ref(),
+            )
+            .split(f.size());
+
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+            .split(rows[0]);
+
+        let panes = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Length(7),
+                    Constraint::Percentage(50),
+                    Constraint::Percentage(50),
+                ]
+                .as_ref(),
+            )
+            .split(columns[1]);
+
+        self.draw_source(f, columns[0]);
+        self.draw_registers(f, panes[0]);
+        self.draw_stack(f, panes[1]);
+        self.draw_memory(f, panes[2]);
+
+        let command = Paragraph::new(format!("> {}", self.command))
+            .block(Block::default().borders(Borders::ALL).title("Command"));
+        f.render_widget(command, rows[1]);
+        #[allow(clippy::cast_possible_truncation)]
+        f.set_cursor(rows[1].x + 3 + self.command.len() as u16, rows[1].y + 1);
+
+        let message = Paragraph::new(self.message.as_str())
+            .style(Style::default().add_modifier(Modifier::ITALIC));
+        f.render_widget(message, rows[2]);
+    }
+
+    fn draw_source<B: Backend>(&self, f: &mut Frame<'_, B>, area: Rect) {
+        let current = self.line_of(self.computer.registers.pc);
+        let breakpoints: HashSet<_> = self
+            .breakpoints
+            .iter()
+            .filter_map(|&address| self.line_of(address))
+            .collect();
+
+        let lines: Vec<_> = self
+            .lines
+            .iter()
+            .enumerate()
+            .map(|(index, (_, line))| {
+                let gutter = match (breakpoints.contains(&index), current == Some(index)) {
+                    (true, true) => "B>",
+                    (true, false) => "B ",
+                    (false, true) => " >",
+                    (false, false) => "  ",
+                };
+                let text = format!("{gutter} {:>4} {line}", index + 1);
+                if current == Some(index) {
+                    Spans::from(Span::styled(
+                        text,
+                        Style::default().bg(Color::Blue).fg(Color::White),
+                    ))
+                } else {
+                    Spans::from(text)
+                }
+            })
+            .collect();
+
+        // Keep the current line in the middle of the pane
+        let height = usize::from(area.height.saturating_sub(2));
+        let scroll = current.unwrap_or(0).saturating_sub(height / 2);
+        let scroll = u16::try_from(scroll).unwrap_or(u16::MAX);
+
+        let source = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title("Source"))
+            .scroll((scroll, 0));
+        f.render_widget(source, area);
+    }
+
+    fn draw_registers<B: Backend>(&self, f: &mut Frame<'_, B>, area: Rect) {
+        let registers = &self.computer.registers;
+        let arch = self.computer.config().arch;
+        let lines: Vec<_> = [Reg::A, Reg::B, Reg::PC, Reg::SP]
+            .iter()
+            .map(|reg| {
+                let cell = registers.get(reg);
+                Spans::from(format!("{reg:<3} = {}", cell.display(arch)))
+            })
+            .chain([
+                Spans::from(format!("%sr = {:?}", registers.sr)),
+                Spans::from(format!("flags {}", registers.flags())),
+                Spans::from(format!("handlers {}", self.computer.interrupt_depth())),
+            ])
+            .collect();
+
+        let title = format!("Registers ({} cycles)", self.computer.cycles);
+        let registers =
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+        f.render_widget(registers, area);
+    }
+
+    /// Render the cells from `start` to `end`, one per line, highlighting the one at `highlight`
+    fn cells(
+        &self,
+        start: C::Address,
+        end: C::Address,
+        highlight: C::Address,
+    ) -> Vec<Spans<'static>> {
+        (start..end)
+            .map(|address| {
+                let cell = self.computer.memory.get(address);
+                let value = match cell {
+                    Ok(Cell::Empty) => String::from("·"),
+                    Ok(cell) => cell.display(self.computer.config().arch).to_string(),
+                    Err(_) => String::from("–"),
+                };
+                let text = format!("{:>5}  {value}", self.symbols.describe(address));
+                if address == highlight {
+                    Spans::from(Span::styled(
+                        text,
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ))
+                } else {
+                    Spans::from(text)
+                }
+            })
+            .collect()
+    }
+
+    fn draw_stack<B: Backend>(&self, f: &mut Frame<'_, B>, area: Rect) {
+        let sp = self.computer.registers.sp;
+        let height = C::Address::from(area.height.saturating_sub(2));
+        let end = sp.saturating_add(height).min(C::STACK_START);
+        let stack = Paragraph::new(self.cells(sp, end, sp))
+            .block(Block::default().borders(Borders::ALL).title("Stack"));
+        f.render_widget(stack, area);
+    }
+
+    /// Show a hexdump of the memory, with as many lines as the pane can hold
+    fn draw_memory<B: Backend>(&self, f: &mut Frame<'_, B>, area: Rect) {
+        let start = self.memory_address;
+        let height = C::Address::from(area.height.saturating_sub(2));
+        #[allow(clippy::cast_possible_truncation)]
+        let end = start.saturating_add(height * HEXDUMP_WIDTH as C::Address);
+        let lines: Vec<_> = self
+            .computer
+            .hexdump(start..end)
+            .lines()
+            .map(|line| Spans::from(line.to_owned()))
+            .collect();
+        let memory =
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Memory"));
+        f.render_widget(memory, area);
+    }
+}
+
+fn event_loop(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    app: &mut App,
+) -> anyhow::Result<()> {
+    loop {
+        terminal.draw(|f| app.draw(f))?;
+        if !app.handle(&event::read()?) {
+            return Ok(());
+        }
+    }
+}
+
+/// Run the full-screen interface until the user exits
+pub(crate) fn run_tui(
+    source: &str,
+    computer: Computer,
+    debug_info: DebugInfo,
+    symbols: Symbols,
+) -> anyhow::Result<()> {
+    let mut app = App::new(source, computer, debug_info, symbols);
+
+    // Restore the terminal before the panic message is printed, or it would be lost in the
+    // alternate screen and leave the terminal in raw mode
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = disable_raw_mode();
+        let _ = execute!(std::io::stdout(), LeaveAlternateScreen);
+        default_hook(info);
+    }));
+
+    enable_raw_mode()?;
+    let mut stdout = std::io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+    let result = event_loop(&mut terminal, &mut app);
+
+    // Always restore the terminal, even if the loop failed
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+
+    // Put back the default hook, since the terminal is already restored
+    let _ = std::panic::take_hook();
+
+    result
+}
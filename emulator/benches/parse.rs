@@ -0,0 +1,54 @@
+use std::fmt::Write;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use z33_emulator::{
+    compile, parse,
+    parser::location::{AbsoluteLocation, MapLocation},
+};
+
+/// Number of generated lines, like a program produced by a compiler
+const LINES: usize = 10000;
+
+/// Number of generated lines of compiled programs, which must fit in memory
+const COMPILED_LINES: usize = 2000;
+
+/// A long generated program, mixing labels, instructions and directives
+fn program(lines: usize) -> String {
+    let mut source = String::from("main:\n");
+    for i in 0..lines {
+        match i % 5 {
+            0 => writeln!(source, "label{i}:  ld [%sp + 1], %a").unwrap(),
+            1 => writeln!(source, "    add 3 * (label{} + 2), %a", i - 1).unwrap(),
+            2 => writeln!(source, "    st %a, [%b]").unwrap(),
+            3 => writeln!(source, "    .string \"line {i}\"").unwrap(),
+            _ => writeln!(source, "    jne label{}", i - 4).unwrap(),
+        }
+    }
+    source.push_str("    reset\n");
+    source
+}
+
+fn parse_program(c: &mut Criterion) {
+    let source = program(LINES);
+
+    c.bench_function("parse generated program", |b| {
+        b.iter(|| parse(&source).unwrap());
+    });
+}
+
+fn compile_program(c: &mut Criterion) {
+    let source = program(COMPILED_LINES);
+    let program = parse(&source).unwrap();
+    let program = program.map_location(&AbsoluteLocation::<()>::default());
+
+    c.bench_function("compile generated program", |b| {
+        b.iter_batched(
+            || program.inner.clone(),
+            |program| compile(program, "main").unwrap(),
+            BatchSize::LargeInput,
+        );
+    });
+}
+
+criterion_group!(benches, parse_program, compile_program);
+criterion_main!(benches);
@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use z33_emulator::preprocessor::{InMemoryFilesystem, Preprocessor};
+
+/// Number of generated instructions, like a program produced by a compiler
+const LINES: usize = 5000;
+
+/// A long generated program, with a few definitions and an inclusion
+fn program() -> String {
+    let mut source =
+        String::from("#define STEP 3\n#define COUNTER %a\n#include \"lib.S\"\nmain:\n");
+    for i in 0..LINES {
+        match i % 4 {
+            0 => writeln!(source, "label{i}:  // a label").unwrap(),
+            1 => writeln!(source, "    add STEP, COUNTER").unwrap(),
+            2 => writeln!(source, "    push %b").unwrap(),
+            _ => writeln!(source, "    pop %b").unwrap(),
+        }
+    }
+    source.push_str("    reset\n");
+    source
+}
+
+fn preprocessor() -> Preprocessor<InMemoryFilesystem> {
+    let files = HashMap::from([
+        (PathBuf::from("/main.S"), program()),
+        (PathBuf::from("/lib.S"), "lib:\n    rtn\n".to_owned()),
+    ]);
+    Preprocessor::new(InMemoryFilesystem::new(files)).and_load(Path::new("/main.S"))
+}
+
+fn preprocess(c: &mut Criterion) {
+    let preprocessor = preprocessor();
+    let path = Path::new("/main.S");
+
+    c.bench_function("preprocess generated program", |b| {
+        b.iter(|| preprocessor.preprocess(path).unwrap());
+    });
+
+    c.bench_function("preprocess generated program with map", |b| {
+        b.iter(|| preprocessor.preprocess_with_map(path).unwrap());
+    });
+}
+
+criterion_group!(benches, preprocess);
+criterion_main!(benches);
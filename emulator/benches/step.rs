@@ -0,0 +1,30 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use z33_emulator::{compile, parse, runtime::Computer};
+
+/// A tight loop counting up to 10000, which mostly stresses the step loop
+const PROGRAM: &str = "
+main:
+    ld   0, %a
+loop:
+    add  1, %a
+    push %a
+    pop  %b
+    cmp  10000, %a
+    jne  loop
+    reset
+";
+
+fn build() -> Computer {
+    let program = parse(PROGRAM).unwrap();
+    let (computer, _) = compile(program.inner, "main").unwrap();
+    computer
+}
+
+fn step_loop(c: &mut Criterion) {
+    c.bench_function("run counting loop", |b| {
+        b.iter_batched(build, |mut computer| computer.run().unwrap(), BatchSize::SmallInput);
+    });
+}
+
+criterion_group!(benches, step_loop);
+criterion_main!(benches);
@@ -0,0 +1,82 @@
+//! Typed arenas, storing nodes in a single vector and referring to them by index
+//!
+//! Nodes are allocated together instead of one by one, and indices are cheap to copy around
+//! where references would tie the holder to the lifetime of the nodes.
+
+use std::marker::PhantomData;
+
+/// Index of a node in an [`Arena`]
+pub(crate) struct Id<T> {
+    index: u32,
+    marker: PhantomData<fn() -> T>,
+}
+
+// Implemented by hand, since deriving them would require `T` to implement them as well
+impl<T> Clone for Id<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Id<T> {}
+
+impl<T> PartialEq for Id<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for Id<T> {}
+
+impl<T> std::fmt::Debug for Id<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.index)
+    }
+}
+
+/// Nodes of the same type, which live as long as the arena
+#[derive(Debug, Clone)]
+pub(crate) struct Arena<T> {
+    nodes: Vec<T>,
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self { nodes: Vec::new() }
+    }
+}
+
+impl<T> Arena<T> {
+    /// Move a node in the arena
+    pub(crate) fn alloc(&mut self, node: T) -> Id<T> {
+        let index = u32::try_from(self.nodes.len()).expect("too many nodes in the arena");
+        self.nodes.push(node);
+        Id {
+            index,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> std::ops::Index<Id<T>> for Arena<T> {
+    type Output = T;
+
+    fn index(&self, id: Id<T>) -> &T {
+        &self.nodes[id.index as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arena_test() {
+        let mut arena = Arena::default();
+        let first = arena.alloc("first".to_owned());
+        let second = arena.alloc("second".to_owned());
+        assert_ne!(first, second);
+        assert_eq!(arena[first], "first");
+        assert_eq!(arena[second], "second");
+    }
+}
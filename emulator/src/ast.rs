@@ -1,5 +1,7 @@
 //! Utility AST manipulation, mainly for reporting
 
+use serde::Serialize;
+
 use crate::parser::location::{Located, MapLocation};
 
 pub trait AstNode<L> {
@@ -43,7 +45,7 @@ impl<N: AstNode<L>, L: Clone> Located<N, L> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub enum NodeKind {
     Program,
     Line,
@@ -52,17 +54,24 @@ pub enum NodeKind {
     Symbol,
     Instruction,
     Directive,
+    Section,
     Comment,
 
     // Children of LineContent
     InstructionKind,
     DirectiveKind,
+    SectionKind,
 
     // Children of InstructionArgument
     Register,
     Direct,
     Indirect,
+    PostIncrement,
+    PreDecrement,
     Indexed,
+    Scaled,
+    Relative,
+    RelativeDirect,
 
     // Children of DirectiveArgument
     StringLiteral,
@@ -78,11 +87,16 @@ pub enum NodeKind {
     ExpressionInvert,
     ExpressionLiteral,
     ExpressionVariable,
+    ExpressionMemory,
+    ExpressionList,
 }
 
+#[derive(Serialize)]
 pub struct Node<L> {
     pub(crate) kind: NodeKind,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub(crate) children: Vec<Node<L>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) content: Option<String>,
     pub(crate) location: L,
 }
@@ -0,0 +1,276 @@
+//! Stable codes identifying the errors, with an extended explanation of each one
+//!
+//! Codes are never reused: when an error goes away, its code is left unassigned.
+
+use std::fmt;
+
+use crate::compiler::{
+    layout::MemoryLayoutError, memory::InstructionCompilationError, memory::MemoryFillError,
+    CompilationError,
+};
+use crate::parser::expression::EvaluationError;
+use crate::parser::{SyntaxError, SyntaxErrorKind};
+use crate::preprocessor::{GetFileError, PreprocessorError};
+use crate::runtime::arguments::ExtractError;
+use crate::runtime::{CellError, Exception, ProcessorError};
+
+/// Code of an error, shown as `E0007`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ErrorCode(pub u16);
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "E{:04}", self.0)
+    }
+}
+
+impl std::str::FromStr for ErrorCode {
+    type Err = std::num::ParseIntError;
+
+    /// Parse a code, with or without its `E` prefix
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let digits = s.strip_prefix(['E', 'e']).unwrap_or(s);
+        digits.parse().map(Self)
+    }
+}
+
+/// Extended explanation of an error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Explanation {
+    pub code: ErrorCode,
+
+    /// What the error is about, in a few words
+    pub title: &'static str,
+
+    /// What causes the error, with an erroneous example and how to fix it
+    pub text: &'static str,
+}
+
+macro_rules! explanations {
+    ($($name:ident = $code:literal => $title:literal,)*) => {
+        &[$(Explanation {
+            code: ErrorCode($code),
+            title: $title,
+            text: include_str!(concat!("codes/", stringify!($name), ".md")),
+        },)*]
+    };
+}
+
+/// All the explanations, by code
+pub const EXPLANATIONS: &[Explanation] = explanations! {
+    E0001 = 1 => "included file not found",
+    E0002 = 2 => "#error directive",
+    E0003 = 3 => "misnested conditional directive",
+    E0004 = 4 => "invalid preprocessor condition",
+    E0005 = 5 => "syntax error",
+    E0006 = 6 => "unknown instruction",
+    E0007 = 7 => "invalid instruction argument syntax",
+    E0008 = 8 => "duplicate label",
+    E0009 = 9 => "invalid directive argument",
+    E0010 = 10 => "overlapping memory",
+    E0011 = 11 => "mismatched .global or .extern",
+    E0012 = 12 => "circular dependency between labels",
+    E0013 = 13 => "undefined label",
+    E0014 = 14 => "expression evaluation failed",
+    E0015 = 15 => "wrong number of arguments",
+    E0016 = 16 => "invalid argument for the instruction",
+    E0017 = 17 => "unknown entrypoint",
+    E0018 = 18 => "division by zero",
+    E0019 = 19 => "invalid instruction",
+    E0020 = 20 => "privileged instruction in user mode",
+    E0021 = 21 => "invalid memory access",
+    E0022 = 22 => "arithmetic overflow",
+    E0023 = 23 => "double fault",
+    E0024 = 24 => "limit exceeded",
+    E0025 = 25 => "shadow memory violation",
+    E0026 = 26 => "unexpected cell type",
+    E0027 = 27 => "invalid source encoding",
+    E0028 = 28 => "non-ASCII character outside of a string",
+    E0029 = 29 => "final assertion failed",
+    E0030 = 30 => "past the end of the address space",
+};
+
+/// Find the explanation of an error
+#[must_use]
+pub fn explain(code: ErrorCode) -> Option<&'static Explanation> {
+    EXPLANATIONS
+        .iter()
+        .find(|explanation| explanation.code == code)
+}
+
+impl<L> PreprocessorError<L> {
+    #[must_use]
+    pub const fn error_code(&self) -> ErrorCode {
+        match self {
+            Self::GetFile {
+                inner: GetFileError::Encoding(_),
+                ..
+            } => ErrorCode(27),
+            Self::GetFile { .. } => ErrorCode(1),
+            Self::UserError { .. } => ErrorCode(2),
+            Self::Misnested { .. } => ErrorCode(3),
+            Self::ConditionParse { .. } | Self::ConditionEvaluation(_) => ErrorCode(4),
+        }
+    }
+}
+
+impl SyntaxError {
+    #[must_use]
+    pub fn error_code(&self) -> ErrorCode {
+        match self.diagnosis() {
+            Some(SyntaxErrorKind::UnknownInstruction { .. }) => ErrorCode(6),
+            Some(SyntaxErrorKind::InvalidArgument { .. }) => ErrorCode(7),
+            Some(SyntaxErrorKind::NonAsciiCharacter(_)) => ErrorCode(28),
+            _ => ErrorCode(5),
+        }
+    }
+}
+
+impl<L> EvaluationError<L> {
+    const fn error_code(&self) -> ErrorCode {
+        match self {
+            Self::UndefinedVariable { .. } => ErrorCode(13),
+            _ => ErrorCode(14),
+        }
+    }
+}
+
+impl<L> MemoryLayoutError<L> {
+    #[must_use]
+    pub const fn error_code(&self) -> ErrorCode {
+        match self {
+            Self::DuplicateLabel { .. } => ErrorCode(8),
+            Self::InvalidDirectiveArgument { .. } | Self::DirectiveArgumentEvaluation { .. } => {
+                ErrorCode(9)
+            }
+            Self::MemoryOverlap { .. } => ErrorCode(10),
+            Self::UndefinedGlobal { .. } | Self::DefinedExtern { .. } => ErrorCode(11),
+            Self::CircularDependency { .. } => ErrorCode(12),
+            Self::SectionOverflow { .. } | Self::AddressOverflow { .. } => ErrorCode(30),
+        }
+    }
+}
+
+impl<L> MemoryFillError<L> {
+    #[must_use]
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            Self::Evaluation { source, .. }
+            | Self::Compute {
+                source: crate::parser::value::ComputeError::Evaluation(source),
+                ..
+            } => source.innermost().error_code(),
+            Self::Compute { .. } => ErrorCode(16),
+            Self::InstructionCompilation { source, .. } => match source {
+                InstructionCompilationError::InvalidArgumentNumber { .. } => ErrorCode(15),
+                InstructionCompilationError::ArgumentConversion { .. } => ErrorCode(16),
+            },
+        }
+    }
+}
+
+impl<L> CompilationError<L> {
+    #[must_use]
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            Self::MemoryLayout(e) => e.error_code(),
+            Self::MemoryFill(e) => e.error_code(),
+            Self::UnknownEntrypoint(_) => ErrorCode(17),
+        }
+    }
+}
+
+impl Exception {
+    /// Code of the exception when it stops the processor. Interrupts and traps are not errors
+    #[must_use]
+    pub const fn error_code(&self) -> Option<ErrorCode> {
+        match self {
+            Self::HardwareInterrupt | Self::Trap => None,
+            Self::DivByZero => Some(ErrorCode(18)),
+            Self::InvalidInstruction => Some(ErrorCode(19)),
+            Self::PrivilegedInstruction => Some(ErrorCode(20)),
+            Self::InvalidMemoryAccess(_) => Some(ErrorCode(21)),
+            Self::Overflow => Some(ErrorCode(22)),
+            Self::DoubleFault { .. } => Some(ErrorCode(23)),
+        }
+    }
+}
+
+impl ProcessorError {
+    /// Code of the error, if it is one: resets, halts and breakpoints are not
+    #[must_use]
+    pub fn error_code(&self) -> Option<ErrorCode> {
+        match self.cause() {
+            Self::Exception(exception) => exception.error_code(),
+            Self::InvalidAddress { .. }
+            | Self::CellError(CellError::InvalidAddress { .. })
+            | Self::Extract(
+                ExtractError::MemoryError(_)
+                | ExtractError::InvalidAddress(_)
+                | ExtractError::CellError(CellError::InvalidAddress { .. }),
+            ) => Some(ErrorCode(21)),
+            Self::LimitExceeded(_) => Some(ErrorCode(24)),
+            Self::FinalAssertion(_) => Some(ErrorCode(29)),
+            Self::Shadow(_) | Self::Extract(ExtractError::Shadow(_)) => Some(ErrorCode(25)),
+            Self::CellError(_) | Self::Extract(_) | Self::InvalidRegister { .. } => {
+                Some(ErrorCode(26))
+            }
+            Self::Reset
+            | Self::Halt
+            | Self::Breakpoint
+            | Self::Fault { .. }
+            | Self::Operand { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile, parse};
+
+    #[test]
+    fn explanations_test() {
+        for (index, explanation) in EXPLANATIONS.iter().enumerate() {
+            assert_eq!(usize::from(explanation.code.0), index + 1);
+            assert!(explanation.text.contains("Erroneous example"));
+        }
+
+        assert_eq!(ErrorCode(7).to_string(), "E0007");
+        assert_eq!("E0007".parse(), Ok(ErrorCode(7)));
+        assert_eq!("7".parse(), Ok(ErrorCode(7)));
+        assert_eq!(explain(ErrorCode(8)).unwrap().title, "duplicate label");
+        assert!(explain(ErrorCode(0)).is_none());
+    }
+
+    #[test]
+    fn error_code_test() {
+        let code = |source: &str| {
+            let program = parse(source).map_err(|e| e.error_code())?;
+            compile(program.inner, "main")
+                .map(|_| ())
+                .map_err(|e| e.error_code())
+        };
+
+        assert_eq!(code("main: lda 5, %a\n"), Err(ErrorCode(6)));
+        assert_eq!(code("café: reset\n"), Err(ErrorCode(28)));
+        assert_eq!(code("main: reset\nmain: reset\n"), Err(ErrorCode(8)));
+        assert_eq!(code("main: jmp nowhere\n"), Err(ErrorCode(13)));
+        assert_eq!(code("main: add %a\n"), Err(ErrorCode(15)));
+        assert_eq!(code("start: reset\n"), Err(ErrorCode(17)));
+        assert_eq!(code("main: .space 4294967295\n"), Err(ErrorCode(30)));
+
+        let run = |source: &str| {
+            let program = parse(source).unwrap();
+            let (mut computer, _) = compile(program.inner, "main").unwrap();
+            computer.run().unwrap_err().error_code()
+        };
+
+        assert_eq!(run("main: ld 0, %b\n div %b, %a\n"), Some(ErrorCode(18)));
+        assert_eq!(
+            run("main: div 0, %a\n.addr 200\nhandler: div 0, %a\n"),
+            Some(ErrorCode(23))
+        );
+    }
+}
@@ -0,0 +1,407 @@
+//! Compilation artifacts: program listing, label map, memory image, memory map diagram and
+//! control flow graph
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::hash::BuildHasher;
+
+use crate::{
+    constants::Address,
+    runtime::{Cell, Computer},
+};
+
+use super::{
+    dataflow::Graph,
+    layout::{AreaKind, MemoryArea},
+    DebugInfo,
+};
+
+/// Magic bytes at the start of a memory image
+pub const IMAGE_MAGIC: &[u8; 4] = b"Z33I";
+
+/// Find the source line containing the given byte offset
+fn source_line(source: &str, offset: usize) -> &str {
+    let offset = offset.min(source.len());
+    let start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let end = source[offset..]
+        .find('\n')
+        .map_or(source.len(), |i| offset + i);
+    source[start..end].trim()
+}
+
+/// Generate a listing of the compiled program
+///
+/// Each non-empty memory cell gets a line with its address, its content and the source line it
+/// was compiled from, preceded by the labels pointing to it.
+#[must_use]
+pub fn listing(source: &str, computer: &Computer, debug_info: &DebugInfo) -> String {
+    let mut labels: Vec<_> = debug_info.labels.iter().collect();
+    labels.sort_by_key(|(name, &address)| (address, *name));
+
+    let mut output = String::new();
+    for (address, cell) in computer.memory.occupied() {
+        for (label, _) in labels.iter().filter(|(_, &a)| a == address) {
+            let _ = writeln!(output, "{label}:");
+        }
+
+        let source = debug_info
+            .locations
+            .get(&address)
+            .map(|location| source_line(source, location.offset))
+            .unwrap_or_default();
+        let _ = writeln!(output, "{address:>5}  {:<24} {source}", cell.to_string());
+    }
+    output
+}
+
+/// Generate the map of labels, sorted by address
+#[must_use]
+pub fn map<L>(debug_info: &DebugInfo<L>) -> String {
+    let mut labels: Vec<_> = debug_info.labels.iter().collect();
+    labels.sort_by_key(|(name, &address)| (address, *name));
+
+    let width = labels.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    let mut output = String::new();
+    for (label, address) in labels {
+        let _ = writeln!(output, "{label:<width$}  {address:>5}");
+    }
+    output
+}
+
+/// Generate a binary image of the memory
+///
+/// The image starts with [`IMAGE_MAGIC`], followed by an entry for each non-empty cell: its
+/// address as a little-endian `u32`, a tag byte and the content of the cell:
+///
+///   - `0`: an instruction, in its JSON serialization like `{"add":[{"imm":5},"a"]}`, prefixed
+///     by its length as a little-endian `u16`
+///   - `1`: a word, as a little-endian `i64`
+///   - `2`: a char, as a little-endian `u32`
+#[must_use]
+pub fn image(computer: &Computer) -> Vec<u8> {
+    let mut image = IMAGE_MAGIC.to_vec();
+    for (address, cell) in computer.memory.occupied() {
+        image.extend(Address::to_le_bytes(address));
+        match cell {
+            Cell::Instruction(instruction) => {
+                // Serializing an instruction to JSON can not fail, and never gets near 64 KiB
+                let json = serde_json::to_vec(instruction).unwrap_or_default();
+                let length = u16::try_from(json.len()).unwrap_or(u16::MAX);
+                image.push(0);
+                image.extend(length.to_le_bytes());
+                image.extend(&json[..usize::from(length)]);
+            }
+            Cell::Word(word) => {
+                image.push(1);
+                image.extend(word.to_le_bytes());
+            }
+            Cell::Char(c) => {
+                image.push(2);
+                image.extend(u32::from(*c).to_le_bytes());
+            }
+            Cell::Empty => {}
+        }
+    }
+    image
+}
+
+/// Fill color of each kind of area in the memory map diagram
+fn area_color(kind: AreaKind) -> &'static str {
+    match kind {
+        AreaKind::Code => "#8ecae6",
+        AreaKind::Data => "#ffb703",
+        AreaKind::String => "#ffd6a5",
+        AreaKind::Reserved => "#e0e0e0",
+        AreaKind::Stack => "#cdb4db",
+        AreaKind::Vectors => "#f4978e",
+    }
+}
+
+/// Draw the memory map as an SVG diagram, high addresses at the top
+///
+/// Areas are not drawn to scale: their height grows with the logarithm of their size, so that
+/// single cells stay readable next to the stack. Unused memory between areas is drawn as a gap.
+#[must_use]
+pub fn svg_memory_map(areas: &[MemoryArea]) -> String {
+    const LINE: u32 = 14;
+    const GAP: u32 = 18;
+
+    let mut body = String::new();
+    let mut y = 10;
+    let mut previous: Option<&MemoryArea> = None;
+    for area in areas.iter().rev() {
+        if let Some(free) = previous.and_then(|p| p.range.start.checked_sub(area.range.end)) {
+            if free > 0 {
+                let _ = writeln!(
+                    body,
+                    r##"<text x="170" y="{}" text-anchor="middle" font-style="italic" fill="#888">{free} free cells</text>"##,
+                    y + GAP - 5,
+                );
+                y += GAP;
+            }
+        }
+
+        let size = area.range.len();
+        let labels = u32::try_from(area.labels.len()).unwrap_or(u32::MAX);
+        let height = (24 + 8 * size.max(1).ilog2()).max(8 + LINE * labels);
+        let _ = writeln!(
+            body,
+            r##"<rect x="70" y="{y}" width="200" height="{height}" fill="{}" stroke="#333"/>"##,
+            area_color(area.kind),
+        );
+        let _ = writeln!(
+            body,
+            r#"<text x="170" y="{}" text-anchor="middle">{} ({size})</text>"#,
+            y + height / 2 + 4,
+            area.kind,
+        );
+        let _ = writeln!(
+            body,
+            r#"<text x="64" y="{}" text-anchor="end">{}</text>"#,
+            y + 11,
+            area.range.end - 1,
+        );
+        let _ = writeln!(
+            body,
+            r#"<text x="64" y="{}" text-anchor="end">{}</text>"#,
+            y + height - 3,
+            area.range.start,
+        );
+
+        // Labels are listed next to the area, the highest address first
+        for (index, (label, address)) in (1..).zip(area.labels.iter().rev()) {
+            let _ = writeln!(
+                body,
+                r#"<text x="280" y="{}">{label} ({address})</text>"#,
+                y + LINE * index,
+            );
+        }
+
+        y += height;
+        previous = Some(area);
+    }
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="480" height="{}" font-family="monospace" font-size="12">"#,
+        y + 10,
+    );
+    svg.push_str(&body);
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Escape a string to be used in a quoted Graphviz label
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Draw the control flow graph of the program in the Graphviz DOT format
+///
+/// Each basic block is a node named after the label pointing to its first instruction, with
+/// edges for the jumps and the instructions following each other, and dashed edges for the calls.
+/// Given the number of times each address was executed, the nodes show how many times the block
+/// ran, and the blocks which never ran are greyed out.
+#[must_use]
+pub fn dot_control_flow<L: Clone, S: BuildHasher>(
+    computer: &Computer,
+    debug_info: &DebugInfo<L>,
+    counts: Option<&HashMap<Address, usize, S>>,
+) -> String {
+    let graph = Graph::explore(computer, debug_info, &mut Vec::new());
+
+    let mut names: BTreeMap<Address, &str> = BTreeMap::new();
+    for (label, &address) in &debug_info.labels {
+        let name = names.entry(address).or_insert(label);
+        if label.as_str() < *name {
+            *name = label;
+        }
+    }
+
+    // Blocks start at the entries, the labels and the targets of jumps, and after branches
+    let count = |address: Address| counts.map(|counts| counts.get(&address).copied().unwrap_or(0));
+    let mut leaders: Vec<Address> = graph
+        .instructions()
+        .filter(|address| graph.is_entry(*address) || names.contains_key(address))
+        .collect();
+    for address in graph.instructions() {
+        let successors = graph.successors(address);
+        if successors != [address + 1] {
+            leaders.extend(successors);
+        }
+        leaders.extend(graph.call(address));
+    }
+    leaders.sort_unstable();
+    leaders.dedup();
+
+    let name = |address: Address| {
+        names
+            .get(&address)
+            .map_or_else(|| address.to_string(), |name| (*name).to_owned())
+    };
+
+    let mut output = String::from("digraph cfg {\n    node [shape=box, fontname=\"monospace\"];\n");
+    let mut edges = String::new();
+    for &leader in &leaders {
+        let mut text = name(leader);
+        if let Some(count) = count(leader) {
+            let _ = write!(text, " ({count} times)");
+        }
+        text.push('\n');
+
+        let mut address = leader;
+        loop {
+            if let Ok(cell) = computer.memory.get(address) {
+                let _ = writeln!(text, "{address:>5}  {cell}");
+            }
+            if let Some(routine) = graph.call(address) {
+                let _ = writeln!(
+                    edges,
+                    "    \"{leader}\" -> \"{routine}\" [style=dashed, label=\"call\"];"
+                );
+            }
+
+            let successors = graph.successors(address);
+            if successors == [address + 1] && leaders.binary_search(&(address + 1)).is_err() {
+                address += 1;
+                continue;
+            }
+            for next in successors {
+                let _ = writeln!(edges, "    \"{leader}\" -> \"{next}\";");
+            }
+            break;
+        }
+
+        let style = if count(leader) == Some(0) {
+            ", style=filled, fillcolor=\"#dddddd\", fontcolor=\"#777777\""
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            output,
+            "    \"{leader}\" [label=\"{}\"{style}];",
+            dot_escape(&text).replace('\n', "\\l")
+        );
+    }
+
+    output.push_str(&edges);
+    output.push_str("}\n");
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+    use crate::{
+        compile, parse,
+        parser::location::{AbsoluteLocation, MapLocation},
+    };
+
+    const PROGRAM: &str = indoc! {"
+        main:
+            ld 5, %a
+        end:
+            reset
+        value: .word 42
+        text: .string \"a\"
+    "};
+
+    fn build() -> (Computer, DebugInfo) {
+        let program = parse(PROGRAM).unwrap();
+        let program = program.map_location(&AbsoluteLocation::default());
+        compile(program.inner, "main").unwrap()
+    }
+
+    #[test]
+    fn listing_test() {
+        let (computer, debug_info) = build();
+        let listing = listing(PROGRAM, &computer, &debug_info);
+        let lines: Vec<_> = listing.lines().map(str::trim_end).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "main:",
+                " 1000  ld   5, %a               ld 5, %a",
+                "end:",
+                " 1001  reset                    reset",
+                "value:",
+                " 1002  42 (0x2a)                value: .word 42",
+                "text:",
+                " 1003  'a'                      text: .string \"a\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn map_test() {
+        let (_, debug_info) = build();
+        assert_eq!(
+            map(&debug_info),
+            "main    1000\nend     1001\nvalue   1002\ntext    1003\n"
+        );
+    }
+
+    #[test]
+    fn image_test() {
+        let (computer, _) = build();
+        let image = image(&computer);
+        assert_eq!(&image[..4], IMAGE_MAGIC);
+
+        // The first instruction is at address 1000, and can be deserialized back
+        let json = br#"{"ld":[{"imm":5},"a"]}"#;
+        let length = u16::try_from(json.len()).unwrap().to_le_bytes();
+        let instruction = [&1000u32.to_le_bytes()[..], &[0], &length, json].concat();
+        assert_eq!(&image[4..4 + instruction.len()], instruction);
+        let decoded: crate::runtime::Instruction = serde_json::from_slice(json).unwrap();
+        assert_eq!(
+            computer.memory.get(1000).unwrap(),
+            &Cell::Instruction(Box::new(decoded))
+        );
+
+        // The word cell is at address 1002
+        let word = [&1002u32.to_le_bytes()[..], &[1], &42i64.to_le_bytes()].concat();
+        assert!(image.windows(word.len()).any(|w| w == word));
+    }
+
+    #[test]
+    fn svg_memory_map_test() {
+        let program = parse(PROGRAM).unwrap();
+        let program = program.map_location(&AbsoluteLocation::<()>::default());
+        let layout = crate::compiler::layout(program.inner).unwrap();
+
+        let svg = svg_memory_map(&layout.memory_map());
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<rect ").count(), 5);
+        assert!(svg.contains(">code (2)<"));
+        assert!(svg.contains(">main (1000)<"));
+        assert!(svg.contains(">897 free cells<"));
+    }
+
+    #[test]
+    fn dot_control_flow_test() {
+        let source = indoc! {"
+            main: ld 3, %a
+            loop: sub 1, %a
+                jne loop
+                call done
+                reset
+            done: rtn
+        "};
+        let program = parse(source).unwrap();
+        let program = program.map_location(&AbsoluteLocation::<()>::default());
+        let (computer, debug_info) = compile(program.inner, "main").unwrap();
+        let counts = HashMap::from([(1000, 1), (1001, 3), (1003, 1)]);
+        let dot = dot_control_flow(&computer, &debug_info, Some(&counts));
+
+        assert!(dot.contains("\"1000\" [label=\"main (1 times)\\l 1000  ld   3, %a\\l\"];"));
+        assert!(dot.contains("\"1000\" -> \"1001\";"));
+        assert!(dot.contains("\"1001\" -> \"1001\";"));
+        assert!(dot.contains("\"1001\" -> \"1003\";"));
+        assert!(dot.contains("\"1003\" -> \"1005\" [style=dashed, label=\"call\"];"));
+        assert!(dot.contains("\"1005\" [label=\"done (0 times)\\l 1005  rtn\\l\", style=filled"));
+    }
+}
@@ -0,0 +1,496 @@
+//! Dataflow analysis of the registers used by a compiled program
+//!
+//! The instructions reachable from the entrypoint, the called routines and the interrupt handler
+//! form a control flow graph. A forward pass finds the general purpose registers which may be read
+//! before being written, and a backward liveness pass finds the values loaded in a register and
+//! never read. Routines and the interrupt handler start with all registers written, since their
+//! caller sets them.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::constants::{Address, Word, INTERRUPT_HANDLER};
+use crate::runtime::arguments::{DirIndIdx, ImmReg, ImmRegDirIndIdx, RegDirIndIdx};
+use crate::runtime::{Computer, Instruction, Reg};
+
+use super::warning::Warning;
+use super::DebugInfo;
+
+/// Set of general purpose registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct Regs(u8);
+
+impl Regs {
+    const NONE: Self = Self(0);
+    const ALL: Self = Self(0b11);
+
+    fn of(reg: Reg) -> Self {
+        match reg {
+            Reg::A => Self(0b01),
+            Reg::B => Self(0b10),
+            _ => Self::NONE,
+        }
+    }
+
+    fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    fn without(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    fn iter(self) -> impl Iterator<Item = Reg> {
+        [Reg::A, Reg::B]
+            .into_iter()
+            .filter(move |reg| self.intersection(Self::of(*reg)) != Self::NONE)
+    }
+}
+
+/// Registers read by an argument, and the ones it updates with a post-increment or pre-decrement
+trait Uses {
+    fn uses(&self) -> (Regs, Regs);
+}
+
+impl Uses for ImmRegDirIndIdx {
+    fn uses(&self) -> (Regs, Regs) {
+        match self {
+            Self::Imm(_) | Self::Dir(_) | Self::Rel(_) => (Regs::NONE, Regs::NONE),
+            Self::Reg(reg) => (Regs::of(*reg), Regs::NONE),
+            Self::Ind(ind) => (Regs::of(ind.0), Regs::NONE),
+            Self::Idx(idx) => (Regs::of(idx.0), Regs::NONE),
+            Self::Sca(sca) => (Regs::of(sca.0).union(Regs::of(sca.1)), Regs::NONE),
+            Self::Inc(inc) => (Regs::of(inc.0), Regs::of(inc.0)),
+            Self::Dec(dec) => (Regs::of(dec.0), Regs::of(dec.0)),
+        }
+    }
+}
+
+impl Uses for DirIndIdx {
+    fn uses(&self) -> (Regs, Regs) {
+        ImmRegDirIndIdx::from(self.clone()).uses()
+    }
+}
+
+impl Uses for ImmReg {
+    fn uses(&self) -> (Regs, Regs) {
+        ImmRegDirIndIdx::from(self.clone()).uses()
+    }
+}
+
+impl Uses for RegDirIndIdx {
+    fn uses(&self) -> (Regs, Regs) {
+        ImmRegDirIndIdx::from(self.clone()).uses()
+    }
+}
+
+/// Where the execution goes after an instruction
+enum Flow {
+    /// To the next instruction
+    Next,
+
+    /// To the next instruction, or to the target of a conditional jump
+    Branch(Option<Address>),
+
+    /// To the target of a jump, unknown for indirect jumps
+    Jump(Option<Address>),
+
+    /// Out of the analyzed code, with the registers still needed
+    Exit(Regs),
+}
+
+/// Effect of an instruction on the registers
+struct Effect {
+    reads: Regs,
+    writes: Regs,
+
+    /// Registers whose value may be used by the code this instruction hands over to
+    needs: Regs,
+
+    /// Register in which the instruction only loads a value
+    load: Option<Reg>,
+    flow: Flow,
+
+    /// Routine called by the instruction
+    call: Option<Address>,
+}
+
+/// Target of a jump, if known statically
+fn target(arg: &ImmRegDirIndIdx, address: Address) -> Option<Address> {
+    let target = match arg {
+        ImmRegDirIndIdx::Imm(imm) => imm.0,
+        ImmRegDirIndIdx::Rel(rel) => Word::from(address) + 1 + rel.0,
+        _ => return None,
+    };
+    Address::try_from(target).ok()
+}
+
+fn effect(instruction: &Instruction, address: Address) -> Effect {
+    use Instruction as I;
+
+    let effect = |(reads, updates): (Regs, Regs), writes: Regs| Effect {
+        reads,
+        writes: writes.union(updates),
+        needs: Regs::NONE,
+        load: None,
+        flow: Flow::Next,
+        call: None,
+    };
+
+    match instruction {
+        I::Add(arg, reg)
+        | I::And(arg, reg)
+        | I::Div(arg, reg)
+        | I::Mod(arg, reg)
+        | I::Mul(arg, reg)
+        | I::Or(arg, reg)
+        | I::Rol(arg, reg)
+        | I::Ror(arg, reg)
+        | I::Shl(arg, reg)
+        | I::Shr(arg, reg)
+        | I::Sub(arg, reg)
+        | I::Xor(arg, reg) => {
+            let (reads, updates) = arg.uses();
+            effect((reads.union(Regs::of(*reg)), updates), Regs::of(*reg))
+        }
+        I::Cmp(arg, reg) => {
+            let (reads, updates) = arg.uses();
+            effect((reads.union(Regs::of(*reg)), updates), Regs::NONE)
+        }
+        I::Ld(arg, reg) => Effect {
+            load: Some(*reg),
+            ..effect(arg.uses(), Regs::of(*reg))
+        },
+        // `cas` only writes %a when the comparison fails
+        I::Cas(arg, reg) => {
+            let (reads, updates) = arg.uses();
+            let reads = reads.union(Regs::of(*reg)).union(Regs::of(Reg::A));
+            effect((reads, updates), Regs::NONE)
+        }
+        I::Fas(arg, reg) | I::In(arg, reg) => effect(arg.uses(), Regs::of(*reg)),
+        I::Out(value, arg) => {
+            let (reads, updates) = arg.uses();
+            effect((reads.union(value.uses().0), updates), Regs::NONE)
+        }
+        I::St(reg, arg) => {
+            let (reads, updates) = arg.uses();
+            effect((reads.union(Regs::of(*reg)), updates), Regs::NONE)
+        }
+        I::Swap(arg, reg) => {
+            let (reads, updates) = arg.uses();
+            let written = match arg {
+                RegDirIndIdx::Reg(other) => Regs::of(*other),
+                _ => Regs::NONE,
+            };
+            effect(
+                (reads.union(Regs::of(*reg)), updates),
+                written.union(Regs::of(*reg)),
+            )
+        }
+        I::Neg(reg) | I::Not(reg) => effect((Regs::of(*reg), Regs::NONE), Regs::of(*reg)),
+        I::Pop(reg) => effect((Regs::NONE, Regs::NONE), Regs::of(*reg)),
+        I::Push(value) => effect(value.uses(), Regs::NONE),
+        I::Jmp(arg) => Effect {
+            flow: Flow::Jump(target(arg, address)),
+            ..effect(arg.uses(), Regs::NONE)
+        },
+        I::Jeq(arg)
+        | I::Jne(arg)
+        | I::Jle(arg)
+        | I::Jlt(arg)
+        | I::Jge(arg)
+        | I::Jgt(arg)
+        | I::Jleu(arg)
+        | I::Jltu(arg)
+        | I::Jgeu(arg)
+        | I::Jgtu(arg) => Effect {
+            flow: Flow::Branch(target(arg, address)),
+            ..effect(arg.uses(), Regs::NONE)
+        },
+        // Routines may take arguments in any register, and return their result in %a
+        I::Call(arg) => {
+            let (reads, updates) = arg.uses();
+            Effect {
+                needs: Regs::ALL,
+                call: target(arg, address),
+                ..effect((reads, updates), Regs::of(Reg::A))
+            }
+        }
+        I::Rtn | I::Rti => Effect {
+            flow: Flow::Exit(Regs::ALL),
+            ..effect((Regs::NONE, Regs::NONE), Regs::NONE)
+        },
+        // %a holds the exit code of the program
+        I::Reset | I::Halt => Effect {
+            flow: Flow::Exit(Regs::of(Reg::A)),
+            ..effect((Regs::NONE, Regs::NONE), Regs::NONE)
+        },
+        I::Trap => Effect {
+            needs: Regs::ALL,
+            ..effect((Regs::NONE, Regs::NONE), Regs::NONE)
+        },
+        I::Brk | I::Nop | I::DebugReg => effect((Regs::NONE, Regs::NONE), Regs::NONE),
+    }
+}
+
+/// Reachable instructions, with their effect and the instructions which may follow them
+#[derive(Default)]
+pub(crate) struct Graph {
+    /// Entries of the code, with the registers already written there
+    entries: BTreeMap<Address, Regs>,
+    effects: BTreeMap<Address, Effect>,
+    successors: HashMap<Address, Vec<Address>>,
+}
+
+impl Graph {
+    /// Explore the code from the entrypoint, reporting the jumps to cells without an instruction
+    pub(crate) fn explore<L: Clone>(
+        computer: &Computer,
+        debug_info: &DebugInfo<L>,
+        warnings: &mut Vec<Warning<L>>,
+    ) -> Self {
+        let instruction = |address: Address| {
+            computer
+                .memory
+                .get(address)
+                .ok()
+                .and_then(|cell| cell.extract_instruction().ok())
+        };
+
+        let mut graph = Self::default();
+        graph.entries.insert(computer.registers.pc, Regs::NONE);
+        if instruction(INTERRUPT_HANDLER).is_some() {
+            graph.entries.insert(INTERRUPT_HANDLER, Regs::ALL);
+        }
+
+        let mut pending: Vec<Address> = graph.entries.keys().copied().collect();
+        while let Some(address) = pending.pop() {
+            if graph.effects.contains_key(&address) {
+                continue;
+            }
+            let Some(instr) = instruction(address) else {
+                continue;
+            };
+
+            let effect = effect(instr, address);
+            let mut next = Vec::new();
+            let target = match effect.flow {
+                Flow::Next => {
+                    next.push(address + 1);
+                    None
+                }
+                Flow::Branch(target) => {
+                    next.push(address + 1);
+                    target
+                }
+                Flow::Jump(target) => target,
+                Flow::Exit(_) => None,
+            };
+
+            for target in target.into_iter().chain(effect.call) {
+                if instruction(target).is_none() {
+                    if let Some(location) = debug_info.locations.get(&address) {
+                        warnings.push(Warning::JumpIntoData {
+                            target,
+                            location: location.clone(),
+                        });
+                    }
+                } else if Some(target) == effect.call {
+                    graph.entries.insert(target, Regs::ALL);
+                    pending.push(target);
+                } else {
+                    next.push(target);
+                }
+            }
+
+            next.retain(|next| instruction(*next).is_some());
+            pending.extend(&next);
+            graph.successors.insert(address, next);
+            graph.effects.insert(address, effect);
+        }
+
+        graph
+    }
+
+    /// Addresses of the reachable instructions, in order
+    pub(crate) fn instructions(&self) -> impl Iterator<Item = Address> + '_ {
+        self.effects.keys().copied()
+    }
+
+    /// Instructions which may be executed after the one at the given address
+    pub(crate) fn successors(&self, address: Address) -> &[Address] {
+        self.successors.get(&address).map_or(&[], Vec::as_slice)
+    }
+
+    /// Routine called by the instruction at the given address
+    pub(crate) fn call(&self, address: Address) -> Option<Address> {
+        self.effects.get(&address).and_then(|effect| effect.call)
+    }
+
+    /// Check if the code can be entered at this address, other than by following instructions
+    pub(crate) fn is_entry(&self, address: Address) -> bool {
+        self.entries.contains_key(&address)
+    }
+
+    /// Registers written on every path reaching each instruction
+    fn written(&self) -> HashMap<Address, Regs> {
+        let mut written: HashMap<Address, Regs> = self.entries.clone().into_iter().collect();
+        let mut pending: BTreeSet<Address> = self.entries.keys().copied().collect();
+        while let Some(address) = pending.pop_first() {
+            let Some(effect) = self.effects.get(&address) else {
+                continue;
+            };
+            let after = written[&address].union(effect.writes);
+            for next in &self.successors[&address] {
+                let mut merged = written.get(next).map_or(after, |w| w.intersection(after));
+                if let Some(entry) = self.entries.get(next) {
+                    merged = merged.intersection(*entry);
+                }
+                if written.get(next) != Some(&merged) {
+                    written.insert(*next, merged);
+                    pending.insert(*next);
+                }
+            }
+        }
+        written
+    }
+
+    /// Registers whose value is read later, after each instruction
+    fn live(&self) -> HashMap<Address, Regs> {
+        let mut live: HashMap<Address, Regs> = HashMap::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (address, effect) in self.effects.iter().rev() {
+                let out = match effect.flow {
+                    Flow::Exit(needed) => needed,
+                    Flow::Jump(None) => Regs::ALL,
+                    _ => self.successors[address]
+                        .iter()
+                        .fold(Regs::NONE, |out, next| {
+                            let effect = &self.effects[next];
+                            let live_in = live
+                                .get(next)
+                                .map_or(Regs::NONE, |out| out.without(effect.writes))
+                                .union(effect.reads)
+                                .union(effect.needs);
+                            out.union(live_in)
+                        }),
+                };
+                if live.get(address) != Some(&out) {
+                    live.insert(*address, out);
+                    changed = true;
+                }
+            }
+        }
+        live
+    }
+}
+
+/// Check how a compiled program uses its registers and where it jumps
+///
+/// The analysis starts at the current `%pc` of the computer, its entrypoint.
+#[must_use]
+pub fn check_registers<L: Clone>(
+    computer: &Computer,
+    debug_info: &DebugInfo<L>,
+) -> Vec<Warning<L>> {
+    let mut warnings = Vec::new();
+    let graph = Graph::explore(computer, debug_info, &mut warnings);
+    let written = graph.written();
+    let live = graph.live();
+
+    for (address, effect) in &graph.effects {
+        let Some(location) = debug_info.locations.get(address) else {
+            continue;
+        };
+
+        for reg in effect.reads.without(written[address]).iter() {
+            warnings.push(Warning::UninitializedRegister {
+                reg,
+                location: location.clone(),
+            });
+        }
+
+        if let Some(reg) = effect.load {
+            if Regs::of(reg).without(live[address]) != Regs::NONE {
+                warnings.push(Warning::DeadStore {
+                    reg,
+                    location: location.clone(),
+                });
+            }
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::warning::WarningKind;
+    use crate::parser::location::{AbsoluteLocation, MapLocation};
+    use crate::{compile, parse};
+
+    fn check(source: &str) -> Vec<(WarningKind, usize)> {
+        let program = parse(source).unwrap();
+        let program = program.map_location(&AbsoluteLocation::<()>::default());
+        let (computer, debug_info) = compile(program.inner, "main").unwrap();
+        let mut warnings: Vec<_> = check_registers(&computer, &debug_info)
+            .iter()
+            .map(|warning| {
+                let line = source[..warning.location().offset].matches('\n').count() + 1;
+                (warning.kind(), line)
+            })
+            .collect();
+        warnings.sort_by_key(|&(_, line)| line);
+        warnings
+    }
+
+    #[test]
+    fn clean_program_test() {
+        assert_eq!(
+            check(
+                "main: ld 10, %a\n\
+                 \x20   ld 0, %b\n\
+                 loop: add %a, %b\n\
+                 \x20   sub 1, %a\n\
+                 \x20   jne loop\n\
+                 \x20   push %b\n\
+                 \x20   call double\n\
+                 \x20   add 1, %sp\n\
+                 \x20   reset\n\
+                 double: push %b\n\
+                 \x20   ld [%sp+2], %a\n\
+                 \x20   add %a, %a\n\
+                 \x20   pop %b\n\
+                 \x20   rtn\n"
+            ),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn register_warnings_test() {
+        assert_eq!(
+            check(
+                "main: ld 1, %a\n\
+                 \x20   ld 2, %a\n\
+                 \x20   cmp 0, %a\n\
+                 \x20   jeq skip\n\
+                 \x20   ld 3, %b\n\
+                 skip: add %b, %a\n\
+                 \x20   jmp value\n\
+                 value: .word 4\n"
+            ),
+            vec![
+                (WarningKind::DeadStore, 1),
+                (WarningKind::UninitializedRegister, 6),
+                (WarningKind::JumpIntoData, 7),
+            ]
+        );
+    }
+}
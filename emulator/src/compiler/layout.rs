@@ -1,22 +1,32 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
 
 use parse_display::Display;
+use serde::Serialize;
 use thiserror::Error;
 use tracing::{debug, trace};
 
 use crate::parser::{
     expression::{
-        Context as ExpressionContext, EmptyContext as EmptyExpressionContext,
-        EvaluationError as ExpressionEvaluationError,
+        Context as ExpressionContext, EmptyContext, EvaluationError as ExpressionEvaluationError,
+        Node,
     },
     line::{Line, LineContent},
-    value::{DirectiveArgument, DirectiveKind},
+    value::{DirectiveArgument, DirectiveKind, SectionKind},
 };
 use crate::{
-    constants::{Address, PROGRAM_START},
-    parser::location::Located,
+    arena::{Arena, Id},
+    constants::{
+        Address, INTERRUPT_EXCEPTION, INTERRUPT_PC_SAVE, MEMORY_SIZE, PROGRAM_START, STACK_START,
+    },
+    parser::{expression::Value, location::Located},
+    runtime::{Arch, Endianness},
 };
 
+use super::{warning::Warning, Reference};
+
 pub(crate) type Labels = HashMap<String, Address>;
 
 impl ExpressionContext for Labels {
@@ -25,62 +35,334 @@ impl ExpressionContext for Labels {
     }
 }
 
-#[derive(Display)]
+/// What to do with a new definition of a label
+#[derive(Debug, PartialEq, Eq)]
+enum Definition {
+    /// The label now points to this definition
+    Bind,
+
+    /// The label keeps its previous definition
+    Ignore,
+
+    /// The label was already defined
+    Duplicate,
+}
+
+/// Labels declared with the `.weak` directive
+///
+/// The definition following a `.weak label` directive is a default one, used only if the label
+/// is not defined elsewhere in the program.
+#[derive(Default)]
+pub(crate) struct WeakLabels {
+    /// Labels whose next definition is a default one
+    declared: HashSet<String>,
+
+    /// Labels currently bound to their default definition
+    defaults: HashSet<String>,
+}
+
+impl WeakLabels {
+    fn declare(&mut self, label: &str) {
+        self.declared.insert(label.to_owned());
+    }
+
+    fn define(&mut self, label: &str, defined: bool) -> Definition {
+        let weak = self.declared.remove(label);
+        if !defined {
+            if weak {
+                self.defaults.insert(label.to_owned());
+            }
+            Definition::Bind
+        } else if weak {
+            Definition::Ignore
+        } else if self.defaults.remove(label) {
+            Definition::Bind
+        } else {
+            Definition::Duplicate
+        }
+    }
+}
+
 pub(crate) enum Placement<L> {
     /// A memory cell filled by .space
-    #[display("SPACE")]
     Reserved,
 
     /// A memory cell filled by .string
-    #[display("{0:?}")]
     Char(char),
 
-    /// A instruction or a .word directive
-    #[display("{0}")]
-    Line(LineContent<L>),
+    /// A instruction or a .word directive, stored in the contents of the layout
+    Line(Id<LineContent<L>>),
+}
+
+/// Kind of content of an area of the memory map
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Serialize)]
+#[display(style = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum AreaKind {
+    /// Instructions
+    Code,
+
+    /// Cells filled by .word directives
+    Data,
+
+    /// Chars of .string directives
+    String,
+
+    /// Cells reserved by .space directives
+    Reserved,
+
+    /// Free memory below the top of the stack, which grows down into it
+    Stack,
+
+    /// Cells where the processor saves its state on interrupts
+    Vectors,
+}
+
+/// Contiguous cells of the same kind, with the labels pointing into them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MemoryArea {
+    pub range: Range<Address>,
+    pub kind: AreaKind,
+
+    /// Labels pointing into the area, sorted by address
+    pub labels: Vec<(String, Address)>,
+}
+
+/// Where the sections get placed in memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryScheme {
+    /// Start of the `.text` section
+    pub text: Address,
+
+    /// Start of the `.data` section, right after `.text` if not set
+    pub data: Option<Address>,
+
+    /// Start of the `.bss` section, right after `.data` if not set
+    pub bss: Option<Address>,
+
+    /// Order of the cells of `.long` directives, and of the bytes packed by `.byte` directives
+    pub endianness: Endianness,
+
+    /// Width of the words, which the values of `.long` and `.byte` directives are split in
+    pub arch: Arch,
+}
+
+impl Default for MemoryScheme {
+    fn default() -> Self {
+        Self {
+            text: PROGRAM_START,
+            data: None,
+            bss: None,
+            endianness: Endianness::default(),
+            arch: Arch::default(),
+        }
+    }
+}
+
+impl MemoryScheme {
+    /// Compute the start of each section, given their sizes
+    ///
+    /// Fails with the first section which ends past the end of the address space.
+    fn starts(
+        &self,
+        sizes: &HashMap<SectionKind, Address>,
+    ) -> Result<HashMap<SectionKind, Address>, SectionKind> {
+        let end = |kind, start: Address| {
+            let size = sizes.get(&kind).copied().unwrap_or(0);
+            start.checked_add(size).ok_or(kind)
+        };
+        let text = self.text;
+        let data = self.data.unwrap_or(end(SectionKind::Text, text)?);
+        let bss = self.bss.unwrap_or(end(SectionKind::Data, data)?);
+        end(SectionKind::Bss, bss)?;
+        Ok(HashMap::from([
+            (SectionKind::Text, text),
+            (SectionKind::Data, data),
+            (SectionKind::Bss, bss),
+        ]))
+    }
+}
+
+/// A contiguous range of laid out memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub range: Range<Address>,
+
+    /// The section the region belongs to, or `None` if it was started by a .addr directive
+    pub section: Option<SectionKind>,
 }
 
 #[derive(Default)]
 pub struct Layout<L> {
     pub labels: Labels,
     pub(crate) memory: HashMap<Address, Placement<L>>,
+
+    /// The instructions and .word directives placed in memory, moved out of the program
+    pub(crate) contents: Arena<LineContent<L>>,
+
+    /// Location of the line content placed at each address
+    pub(crate) locations: HashMap<Address, L>,
+
+    /// Location where each label is defined
+    pub(crate) definitions: HashMap<String, L>,
+
+    /// Labels referenced by the placed instructions and directives
+    pub(crate) references: Vec<Reference<L>>,
+
+    /// Non-fatal issues found while laying out the memory
+    pub(crate) warnings: Vec<Warning<L>>,
+
+    /// Contiguous ranges of laid out memory, started by sections and .addr directives
+    pub(crate) regions: Vec<Region>,
+
+    /// Index of the region each label is defined in
+    pub(crate) label_regions: HashMap<String, usize>,
+
+    /// Labels declared weak, and which of them still use their default definition
+    pub(crate) weak: WeakLabels,
+
+    /// Labels exported by `.global` directives, with the location of the directive
+    pub(crate) globals: HashMap<String, L>,
+
+    /// Labels declared by `.extern` directives, with the location of the directive
+    pub(crate) externs: HashMap<String, L>,
+
+    /// Start, length and digest of the `.final_assert` directives, evaluated with the labels
+    pub(crate) final_asserts: Vec<[Located<Node<L>, L>; 3]>,
+
+    /// Width of the words, which the placed values must fit in
+    pub(crate) arch: Arch,
 }
 
-impl<L> Layout<L> {
-    fn insert_placement(
+impl<L: Clone> Layout<L> {
+    pub(crate) fn insert_placement(
         &mut self,
         address: Address,
         placement: Placement<L>,
+        location: &L,
     ) -> Result<(), MemoryLayoutError<L>> {
-        if self.memory.contains_key(&address) {
-            return Err(MemoryLayoutError::MemoryOverlap { address });
+        if let Some(Placement::Reserved) = self.memory.get(&address) {
+            // Reserved cells hold nothing, the later line takes their place
+            self.warnings.push(Warning::OverlapOverwrite {
+                address,
+                location: location.clone(),
+                previous: self.locations[&address].clone(),
+            });
+        } else if self.memory.contains_key(&address) {
+            return Err(MemoryLayoutError::MemoryOverlap {
+                address,
+                location: location.clone(),
+                previous: self.locations[&address].clone(),
+            });
         }
 
         self.memory.insert(address, placement);
+        self.locations.insert(address, location.clone());
         Ok(())
     }
 
+    /// Define a label, returning whether it points to this definition
     fn insert_label(
         &mut self,
         label: Located<String, L>,
         address: Address,
-    ) -> Result<(), MemoryLayoutError<L>> {
-        if self.labels.contains_key(&label.inner) {
-            return Err(MemoryLayoutError::DuplicateLabel {
+    ) -> Result<bool, MemoryLayoutError<L>> {
+        let defined = self.labels.contains_key(&label.inner);
+        match self.weak.define(&label.inner, defined) {
+            Definition::Bind => {
+                self.labels.insert(label.inner.clone(), address);
+                self.definitions.insert(label.inner, label.location);
+                Ok(true)
+            }
+            Definition::Ignore => Ok(false),
+            Definition::Duplicate => Err(MemoryLayoutError::DuplicateLabel {
                 label: label.inner,
                 location: label.location,
+            }),
+        }
+    }
+
+    /// Group the laid out cells in areas of the same kind, to draw a map of the memory
+    pub fn memory_map(&self) -> Vec<MemoryArea> {
+        let mut cells: Vec<_> = self
+            .memory
+            .iter()
+            .map(|(address, placement)| {
+                let kind = match placement {
+                    Placement::Line(id) => match self.contents[*id] {
+                        LineContent::Instruction { .. } => AreaKind::Code,
+                        _ => AreaKind::Data,
+                    },
+                    Placement::Char(_) => AreaKind::String,
+                    Placement::Reserved => AreaKind::Reserved,
+                };
+                (*address, kind)
+            })
+            .collect();
+        cells.sort_by_key(|&(address, _)| address);
+
+        let mut areas: Vec<MemoryArea> = Vec::new();
+        for (address, kind) in cells {
+            match areas.last_mut() {
+                Some(area) if area.kind == kind && area.range.end == address => {
+                    area.range.end += 1;
+                }
+                _ => areas.push(MemoryArea {
+                    range: address..address + 1,
+                    kind,
+                    labels: Vec::new(),
+                }),
+            }
+        }
+
+        let vectors = INTERRUPT_PC_SAVE..INTERRUPT_EXCEPTION + 1;
+        if !vectors
+            .clone()
+            .any(|address| self.memory.contains_key(&address))
+        {
+            areas.push(MemoryArea {
+                range: vectors,
+                kind: AreaKind::Vectors,
+                labels: Vec::new(),
             });
         }
 
-        self.labels.insert(label.inner, address);
-        Ok(())
+        let end = areas.iter().map(|area| area.range.end).max();
+        let stack = end.unwrap_or(PROGRAM_START).max(PROGRAM_START)..STACK_START;
+        if !stack.is_empty() {
+            areas.push(MemoryArea {
+                range: stack,
+                kind: AreaKind::Stack,
+                labels: Vec::new(),
+            });
+        }
+        areas.sort_by_key(|area| area.range.start);
+
+        let mut labels: Vec<_> = self.labels.iter().collect();
+        labels.sort_by_key(|&(name, address)| (*address, name));
+        for (name, address) in labels {
+            if let Some(area) = areas.iter_mut().find(|area| area.range.contains(address)) {
+                area.labels.push((name.clone(), *address));
+            }
+        }
+
+        areas
+    }
+
+    /// Describe what a placement fills its cell with
+    fn describe(&self, placement: &Placement<L>) -> String {
+        match placement {
+            Placement::Reserved => "SPACE".to_owned(),
+            Placement::Char(c) => format!("{c:?}"),
+            Placement::Line(id) => self.contents[*id].to_string(),
+        }
     }
 
     pub fn memory_report(&self) -> Vec<(Address, String)> {
         let mut v: Vec<_> = self
             .memory
             .iter()
-            .map(|(k, v)| (*k, format!("{v}")))
+            .map(|(address, placement)| (*address, self.describe(placement)))
             .collect();
         v.sort_by_key(|&(k, _)| k);
         v
@@ -102,16 +384,65 @@ pub enum MemoryLayoutError<L> {
     },
 
     #[error("address {address} is already filled")]
-    MemoryOverlap { address: Address },
+    MemoryOverlap {
+        address: Address,
+        location: L,
+        previous: L,
+    },
+
+    #[error("label {label} is declared global but never defined")]
+    UndefinedGlobal { label: String, location: L },
+
+    #[error("label {label} is declared external but defined in this program")]
+    DefinedExtern { label: String, location: L },
+
+    #[error("circular dependency between the labels {}", join(labels))]
+    CircularDependency { labels: Vec<Located<String, L>> },
+
+    #[error("the .{section} section holds {size} cells, past the end of the address space")]
+    SectionOverflow { section: SectionKind, size: Address },
+
+    #[error("this line goes past the end of the address space")]
+    AddressOverflow { location: L },
+}
+
+fn join<L>(labels: &[Located<String, L>]) -> String {
+    let names: Vec<_> = labels.iter().map(|l| l.inner.as_str()).collect();
+    names.join(", ")
 }
 
 impl<L> MemoryLayoutError<L> {
     pub fn location(&self) -> Option<&L> {
         match self {
             MemoryLayoutError::DuplicateLabel { location, .. }
-            | MemoryLayoutError::InvalidDirectiveArgument { location, .. } => Some(location),
+            | MemoryLayoutError::InvalidDirectiveArgument { location, .. }
+            | MemoryLayoutError::UndefinedGlobal { location, .. }
+            | MemoryLayoutError::DefinedExtern { location, .. }
+            | MemoryLayoutError::AddressOverflow { location }
+            | MemoryLayoutError::MemoryOverlap { location, .. } => Some(location),
+            MemoryLayoutError::CircularDependency { labels } => labels.first().map(|l| &l.location),
             MemoryLayoutError::DirectiveArgumentEvaluation { .. }
-            | MemoryLayoutError::MemoryOverlap { .. } => None,
+            | MemoryLayoutError::SectionOverflow { .. } => None,
+        }
+    }
+
+    /// Location of the line which filled the memory first, for overlap errors
+    pub fn previous_location(&self) -> Option<&L> {
+        match self {
+            MemoryLayoutError::MemoryOverlap { previous, .. } => Some(previous),
+            _ => None,
+        }
+    }
+
+    /// Definitions of the other labels involved, for circular dependency errors
+    pub fn related_locations(&self) -> Vec<(&str, &L)> {
+        match self {
+            MemoryLayoutError::CircularDependency { labels } => labels
+                .iter()
+                .skip(1)
+                .map(|l| (l.inner.as_str(), &l.location))
+                .collect(),
+            _ => Vec::new(),
         }
     }
 }
@@ -119,32 +450,77 @@ impl<L> MemoryLayoutError<L> {
 /// Lays out the memory
 ///
 /// It places the labels & prepare a hashmap of cells to be filled.
-#[tracing::instrument(skip(program))]
 pub(crate) fn layout_memory<L: Clone + Default>(
-    program: &[Line<L>],
+    program: Vec<Line<L>>,
 ) -> Result<Layout<L>, MemoryLayoutError<L>> {
-    use DirectiveKind::{Addr, Space, String, Word};
-    use MemoryLayoutError::{DirectiveArgumentEvaluation, InvalidDirectiveArgument};
+    layout_memory_with(program, &MemoryScheme::default())
+}
+
+/// Lays out the memory, placing the sections according to the given scheme
+#[tracing::instrument(skip(program))]
+#[allow(clippy::too_many_lines)]
+pub(crate) fn layout_memory_with<L: Clone + Default>(
+    program: Vec<Line<L>>,
+    scheme: &MemoryScheme,
+) -> Result<Layout<L>, MemoryLayoutError<L>> {
+    use DirectiveKind::{Addr, Byte, Extern, FinalAssert, Global, Long, Space, String, Weak, Word};
+    use MemoryLayoutError::{
+        AddressOverflow, DirectiveArgumentEvaluation, InvalidDirectiveArgument,
+    };
 
     debug!(lines = program.len(), "Laying out memory");
-    let mut layout: Layout<L> = Layout::default();
-    let mut position = PROGRAM_START;
+    let mut layout: Layout<L> = Layout {
+        arch: scheme.arch,
+        ..Layout::default()
+    };
+
+    // Programs start in the .text section. Each section keeps its own position, and the
+    // .addr directive leaves them to place lines at a fixed address.
+    let estimate = estimate(&program, scheme)
+        .map_err(|labels| MemoryLayoutError::CircularDependency { labels })?;
+    let mut positions =
+        scheme
+            .starts(&estimate.sizes)
+            .map_err(|section| MemoryLayoutError::SectionOverflow {
+                section,
+                size: estimate.sizes[&section],
+            })?;
+    let mut section = Some(SectionKind::Text);
+    let mut position = positions[&SectionKind::Text];
+    let mut region = Region {
+        range: position..position,
+        section,
+    };
 
     for line in program {
-        for key in line.symbols.clone() {
+        for key in line.symbols {
             trace!(key = %key.inner, position, "Inserting label");
-            layout.insert_label(key, position)?;
+            let name = key.inner.clone();
+            let region = layout.regions.len();
+            if layout.insert_label(key, position)? {
+                layout.label_regions.insert(name, region);
+            }
         }
 
-        if let Some(ref content) = line.content {
+        if let Some(content) = line.content {
             match &content.inner {
                 LineContent::Directive {
                     kind: Located { inner: Word, .. },
                     ..
                 }
                 | LineContent::Instruction { .. } => {
-                    layout.insert_placement(position, Placement::Line(content.inner.clone()))?;
+                    for label in content.inner.references() {
+                        layout.references.push(Reference {
+                            label: label.to_owned(),
+                            address: position,
+                            location: content.location.clone(),
+                        });
+                    }
                     trace!(position, content = %content.inner, "Inserting line");
+
+                    // The content is moved in the layout instead of being copied
+                    let id = layout.contents.alloc(content.inner);
+                    layout.insert_placement(position, Placement::Line(id), &content.location)?;
                     position += 1; // Instructions and word directives take one memory cell
                 }
 
@@ -156,19 +532,41 @@ pub(crate) fn layout_memory<L: Clone + Default>(
                             ..
                         },
                 } => {
-                    let size = e.evaluate(&EmptyExpressionContext).map_err(|source| {
-                        DirectiveArgumentEvaluation {
-                            kind: Space,
-                            source,
-                        }
-                    })?;
+                    // Labels can be used, like `.space end - start`
+                    let context = Resolver {
+                        labels: &layout.labels,
+                        estimates: &estimate.labels,
+                    };
+                    let size =
+                        e.evaluate(&context)
+                            .map_err(|source| DirectiveArgumentEvaluation {
+                                kind: Space,
+                                source,
+                            })?;
 
                     trace!(size, position, "Reserving space");
 
-                    for _ in 0..size {
-                        layout.insert_placement(position, Placement::Reserved)?;
-                        position += 1;
+                    // Running off the end of memory is likely a mistake, reserving more than the
+                    // whole memory can not be one
+                    let end = position
+                        .checked_add(size)
+                        .filter(|_| size <= MEMORY_SIZE)
+                        .ok_or_else(|| AddressOverflow {
+                            location: content.location.clone(),
+                        })?;
+                    if end > MEMORY_SIZE {
+                        layout.warnings.push(Warning::OversizedSpace {
+                            start: position,
+                            size,
+                            location: content.location.clone(),
+                        });
+                    }
+
+                    // Cells past the end of memory are reported by the warning above
+                    for address in position..end.min(MEMORY_SIZE) {
+                        layout.insert_placement(address, Placement::Reserved, &content.location)?;
                     }
+                    position = end;
                 }
 
                 LineContent::Directive {
@@ -179,16 +577,63 @@ pub(crate) fn layout_memory<L: Clone + Default>(
                             ..
                         },
                 } => {
+                    let context = Resolver {
+                        labels: &layout.labels,
+                        estimates: &estimate.labels,
+                    };
                     let addr = e
-                        .evaluate(&EmptyExpressionContext)
+                        .evaluate(&context)
                         .map_err(|source| DirectiveArgumentEvaluation { kind: Addr, source })?;
 
                     debug!(addr, "Changing address");
 
+                    if let Some(section) = section.take() {
+                        positions.insert(section, position);
+                    }
+
+                    region.range.end = position;
+                    let previous = std::mem::replace(
+                        &mut region,
+                        Region {
+                            range: addr..addr,
+                            section: None,
+                        },
+                    );
+                    layout.regions.push(previous);
+
+                    // Moving back into memory already laid out is most likely a mistake
+                    if layout.regions.iter().any(|r| r.range.contains(&addr)) {
+                        layout.warnings.push(Warning::AddrBackwards {
+                            from: position,
+                            to: addr,
+                            location: content.location.clone(),
+                        });
+                    }
+
                     // The ".addr N" directive changes the current address to N
                     position = addr;
                 }
 
+                LineContent::Section { kind } => {
+                    debug!(section = %kind.inner, "Changing section");
+
+                    if let Some(section) = section {
+                        positions.insert(section, position);
+                    }
+                    section = Some(kind.inner);
+
+                    region.range.end = position;
+                    position = positions[&kind.inner];
+                    let previous = std::mem::replace(
+                        &mut region,
+                        Region {
+                            range: position..position,
+                            section,
+                        },
+                    );
+                    layout.regions.push(previous);
+                }
+
                 LineContent::Directive {
                     kind: Located { inner: String, .. },
                     argument:
@@ -200,7 +645,90 @@ pub(crate) fn layout_memory<L: Clone + Default>(
                     trace!(position, string = string.as_str(), "Inserting string");
                     // Fill the memory with the chars of the string
                     for c in string.chars() {
-                        layout.insert_placement(position, Placement::Char(c))?;
+                        layout.insert_placement(position, Placement::Char(c), &content.location)?;
+                        position += 1;
+                    }
+                }
+
+                LineContent::Directive {
+                    kind: Located { inner: Weak, .. },
+                    argument:
+                        Located {
+                            inner: DirectiveArgument::Expression(Node::Variable(label)),
+                            ..
+                        },
+                } => {
+                    trace!(label, "Declaring weak label");
+                    layout.weak.declare(label);
+                }
+
+                LineContent::Directive {
+                    kind:
+                        Located {
+                            inner: kind @ (Global | Extern),
+                            location,
+                        },
+                    argument:
+                        Located {
+                            inner: DirectiveArgument::Expression(Node::Variable(label)),
+                            ..
+                        },
+                } => {
+                    trace!(label, %kind, "Declaring label visibility");
+                    let declarations = if *kind == Global {
+                        &mut layout.globals
+                    } else {
+                        &mut layout.externs
+                    };
+                    declarations.insert(label.clone(), location.clone());
+                }
+
+                LineContent::Directive {
+                    kind:
+                        Located {
+                            inner: FinalAssert,
+                            location,
+                        },
+                    argument:
+                        Located {
+                            inner: DirectiveArgument::List(arguments),
+                            ..
+                        },
+                } => {
+                    let arguments = <[_; 3]>::try_from(arguments.clone()).map_err(|_| {
+                        InvalidDirectiveArgument {
+                            kind: FinalAssert,
+                            location: location.clone(),
+                        }
+                    })?;
+                    trace!("Declaring final assertion");
+                    layout.final_asserts.push(arguments);
+                }
+
+                LineContent::Directive { kind, argument } if matches!(kind.inner, Long | Byte) => {
+                    let words = word_directives(kind, argument, scheme).ok_or_else(|| {
+                        InvalidDirectiveArgument {
+                            kind: kind.inner,
+                            location: kind.location.clone(),
+                        }
+                    })?;
+
+                    trace!(position, content = %content.inner, "Inserting words");
+                    for word in words {
+                        for label in word.references() {
+                            layout.references.push(Reference {
+                                label: label.to_owned(),
+                                address: position,
+                                location: content.location.clone(),
+                            });
+                        }
+
+                        let id = layout.contents.alloc(word);
+                        layout.insert_placement(
+                            position,
+                            Placement::Line(id),
+                            &content.location,
+                        )?;
                         position += 1;
                     }
                 }
@@ -215,21 +743,253 @@ pub(crate) fn layout_memory<L: Clone + Default>(
         }
     }
 
+    region.range.end = position;
+    layout.regions.push(region);
+
+    if let Some((label, location)) = layout
+        .globals
+        .iter()
+        .filter(|(label, _)| !layout.labels.contains_key(*label))
+        .min_by_key(|(label, _)| *label)
+    {
+        return Err(MemoryLayoutError::UndefinedGlobal {
+            label: label.clone(),
+            location: location.clone(),
+        });
+    }
+
+    if let Some((label, location)) = layout
+        .externs
+        .iter()
+        .filter(|(label, _)| layout.labels.contains_key(*label))
+        .min_by_key(|(label, _)| *label)
+    {
+        return Err(MemoryLayoutError::DefinedExtern {
+            label: label.clone(),
+            location: location.clone(),
+        });
+    }
+
     Ok(layout)
 }
 
+/// The `.word` directives standing for a `.long` or `.byte` directive, in memory order
+///
+/// A `.long` directive holds a value of two words, and a `.byte` one the bytes of a string packed
+/// in words, both at the width of the words of the scheme. Returns `None` if the argument does not
+/// fit the directive.
+fn word_directives<L: Clone>(
+    kind: &Located<DirectiveKind, L>,
+    argument: &Located<DirectiveArgument<L>, L>,
+    scheme: &MemoryScheme,
+) -> Option<Vec<LineContent<L>>> {
+    let MemoryScheme {
+        endianness, arch, ..
+    } = *scheme;
+    let located = |node: Node<L>| Located {
+        inner: Box::new(node),
+        location: argument.location.clone(),
+    };
+
+    let words: Vec<Node<L>> = match (kind.inner, &argument.inner) {
+        (DirectiveKind::Long, DirectiveArgument::Expression(value)) => {
+            // The low word keeps the low bits of the value, and the high one its sign
+            let literal = |bits: u32| located(Node::Literal(bits.into()));
+            let shift = Value::BITS - arch.word_bits();
+            let low = Node::RightShift(
+                located(Node::LeftShift(located(value.clone()), literal(shift))),
+                literal(shift),
+            );
+            let high = Node::RightShift(located(value.clone()), literal(arch.word_bits()));
+            endianness.order([low, high]).into()
+        }
+        (DirectiveKind::Byte, DirectiveArgument::StringLiteral(string)) => endianness
+            .pack(string.as_bytes(), arch)
+            .into_iter()
+            .map(|word| Node::Literal(word.into()))
+            .collect(),
+        (DirectiveKind::Byte, DirectiveArgument::List(values)) => {
+            // The bytes are packed while laying out the memory, before the labels are known
+            let bytes = values
+                .iter()
+                .map(|value| value.inner.evaluate(&EmptyContext).ok())
+                .collect::<Option<Vec<u8>>>()?;
+            endianness
+                .pack(&bytes, arch)
+                .into_iter()
+                .map(|word| Node::Literal(word.into()))
+                .collect()
+        }
+        _ => return None,
+    };
+
+    let words = words
+        .into_iter()
+        .map(|word| LineContent::Directive {
+            kind: Located {
+                inner: DirectiveKind::Word,
+                location: kind.location.clone(),
+            },
+            argument: Located {
+                inner: DirectiveArgument::Expression(word),
+                location: argument.location.clone(),
+            },
+        })
+        .collect();
+    Some(words)
+}
+
+/// Label positions and section sizes, as estimated by a pass over the program
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Estimate {
+    labels: Labels,
+    sizes: HashMap<SectionKind, Address>,
+}
+
+/// Resolves labels defined so far, falling back to the estimated position of the others
+struct Resolver<'a> {
+    labels: &'a Labels,
+    estimates: &'a Labels,
+}
+
+impl ExpressionContext for Resolver<'_> {
+    fn resolve_variable(&self, variable: &str) -> Option<i128> {
+        self.labels
+            .get(variable)
+            .or_else(|| self.estimates.get(variable))
+            .map(|v| i128::from(*v))
+    }
+}
+
+/// Maximum number of passes to settle the position of the labels
+const MAX_ESTIMATE_PASSES: usize = 64;
+
+/// Settle the position of the labels and the size of the sections
+///
+/// Directive arguments may reference labels defined later in the program, so the estimation is
+/// repeated until it stops changing. If it never does, the labels which kept moving are returned.
+fn estimate<L: Clone>(
+    program: &[Line<L>],
+    scheme: &MemoryScheme,
+) -> Result<Estimate, Vec<Located<String, L>>> {
+    let mut previous = Estimate::default();
+
+    for pass in 0..MAX_ESTIMATE_PASSES {
+        let next = estimate_pass(program, scheme, &previous);
+        if next == previous {
+            trace!(passes = pass + 1, "Label positions settled");
+            return Ok(next);
+        }
+        previous = next;
+    }
+
+    let last = estimate_pass(program, scheme, &previous);
+    let moving = program
+        .iter()
+        .flat_map(|line| &line.symbols)
+        .filter(|symbol| last.labels.get(&symbol.inner) != previous.labels.get(&symbol.inner))
+        .cloned()
+        .collect();
+    Err(moving)
+}
+
+/// Estimate the position of the labels and the size of the sections, from a previous estimate
+///
+/// Arguments which can not be evaluated yet count as zero, errors are reported when laying out
+/// the memory.
+fn estimate_pass<L: Clone>(
+    program: &[Line<L>],
+    scheme: &MemoryScheme,
+    previous: &Estimate,
+) -> Estimate {
+    let mut estimate = Estimate::default();
+    let mut weak = WeakLabels::default();
+    // Sections which do not fit in the address space are reported when laying out the memory
+    let Ok(mut positions) = scheme.starts(&previous.sizes) else {
+        return previous.clone();
+    };
+    let mut section = Some(SectionKind::Text);
+    let mut position = positions[&SectionKind::Text];
+
+    for line in program {
+        for symbol in &line.symbols {
+            let defined = estimate.labels.contains_key(&symbol.inner);
+            if weak.define(&symbol.inner, defined) == Definition::Bind {
+                estimate.labels.insert(symbol.inner.clone(), position);
+            }
+        }
+
+        let Some(content) = &line.content else {
+            continue;
+        };
+
+        let resolver = Resolver {
+            labels: &estimate.labels,
+            estimates: &previous.labels,
+        };
+        let size = match &content.inner {
+            LineContent::Section { kind } => {
+                if let Some(section) = section {
+                    positions.insert(section, position);
+                }
+                section = Some(kind.inner);
+                position = positions[&kind.inner];
+                continue;
+            }
+            LineContent::Instruction { .. } => 1,
+            LineContent::Directive { kind, argument } => match (kind.inner, &argument.inner) {
+                (DirectiveKind::Addr, DirectiveArgument::Expression(e)) => {
+                    if let Some(section) = section.take() {
+                        positions.insert(section, position);
+                    }
+                    position = e.evaluate(&resolver).unwrap_or(0);
+                    continue;
+                }
+                (DirectiveKind::Weak, DirectiveArgument::Expression(Node::Variable(label))) => {
+                    weak.declare(label);
+                    continue;
+                }
+                (DirectiveKind::Word, _) => 1,
+                (DirectiveKind::Long, _) => 2,
+                (DirectiveKind::Byte, DirectiveArgument::StringLiteral(string)) => {
+                    Address::try_from(string.len().div_ceil(scheme.arch.word_bytes())).unwrap_or(0)
+                }
+                (DirectiveKind::Byte, DirectiveArgument::List(values)) => {
+                    Address::try_from(values.len().div_ceil(scheme.arch.word_bytes())).unwrap_or(0)
+                }
+                (DirectiveKind::Space, DirectiveArgument::Expression(e)) => {
+                    e.evaluate(&resolver).unwrap_or(0)
+                }
+                (DirectiveKind::String, DirectiveArgument::StringLiteral(string)) => {
+                    Address::try_from(string.chars().count()).unwrap_or(0)
+                }
+                _ => 0,
+            },
+        };
+
+        position = position.saturating_add(size);
+        if let Some(section) = section {
+            let total = estimate.sizes.entry(section).or_default();
+            *total = total.saturating_add(size);
+        }
+    }
+
+    estimate
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::compiler::warning::WarningKind;
     use crate::parser::{
         expression::Node,
         line::Line,
-        location::RelativeLocation,
+        location::{AbsoluteLocation, Locatable, MapLocation, RelativeLocation},
         value::{InstructionArgument, InstructionKind},
     };
     use crate::runtime::Reg;
 
-    use InstructionKind::{Add, Jmp};
+    use InstructionKind::{Add, Jmp, Ld};
 
     #[test]
     fn place_labels_simple_test() {
@@ -247,7 +1007,7 @@ mod tests {
             ),
         ];
 
-        let labels = layout_memory(&program).unwrap().labels;
+        let labels = layout_memory(program).unwrap().labels;
         let expected = {
             let mut h = HashMap::new();
             h.insert(String::from("main"), PROGRAM_START);
@@ -257,6 +1017,43 @@ mod tests {
         assert_eq!(labels, expected);
     }
 
+    #[test]
+    fn label_references_test() {
+        let program: Vec<Line<RelativeLocation>> = vec![
+            Line::default().symbol("main").instruction(
+                Ld,
+                vec![
+                    InstructionArgument::Direct(Node::Variable("value".into()).with_location(())),
+                    InstructionArgument::Register(Reg::A),
+                ],
+            ),
+            Line::default().symbol("loop").instruction(
+                Jmp,
+                vec![InstructionArgument::Value(Node::Variable("loop".into()))],
+            ),
+            Line::default().symbol("value").directive(
+                DirectiveKind::Word,
+                DirectiveArgument::Expression(Node::Variable("main".into())),
+            ),
+        ];
+
+        let layout = layout_memory(program).unwrap();
+        let references: Vec<_> = layout
+            .references
+            .iter()
+            .map(|r| (r.label.as_str(), r.address))
+            .collect();
+        assert_eq!(
+            references,
+            vec![
+                ("value", PROGRAM_START),
+                ("loop", PROGRAM_START + 1),
+                ("main", PROGRAM_START + 2),
+            ]
+        );
+        assert_eq!(layout.definitions.len(), 3);
+    }
+
     #[test]
     fn place_labels_addr_test() {
         let program: Vec<Line<RelativeLocation>> = vec![
@@ -267,7 +1064,7 @@ mod tests {
             ),
         ];
 
-        let labels = layout_memory(&program).unwrap().labels;
+        let labels = layout_memory(program).unwrap().labels;
         let expected = {
             let mut h = HashMap::new();
             h.insert(String::from("main"), 10);
@@ -291,7 +1088,7 @@ mod tests {
             ),
         ];
 
-        let labels = layout_memory(&program).unwrap().labels;
+        let labels = layout_memory(program).unwrap().labels;
         let expected = {
             let mut h = HashMap::new();
             h.insert(String::from("first"), PROGRAM_START);
@@ -318,7 +1115,7 @@ mod tests {
             ),
         ];
 
-        let labels = layout_memory(&program).unwrap().labels;
+        let labels = layout_memory(program).unwrap().labels;
         let expected = {
             let mut h = HashMap::new();
             h.insert(String::from("first"), PROGRAM_START);
@@ -345,7 +1142,7 @@ mod tests {
             ),
         ];
 
-        let labels = layout_memory(&program).unwrap().labels;
+        let labels = layout_memory(program).unwrap().labels;
         let expected = {
             let mut h = HashMap::new();
             h.insert(String::from("first"), PROGRAM_START);
@@ -365,7 +1162,7 @@ mod tests {
         ];
 
         assert_eq!(
-            layout_memory(&program).err(),
+            layout_memory(program).err(),
             Some(MemoryLayoutError::DuplicateLabel {
                 label: "hello".into(),
                 location: RelativeLocation::default(),
@@ -379,7 +1176,7 @@ mod tests {
             vec![Line::default().directive(DirectiveKind::String, 3)];
 
         assert_eq!(
-            layout_memory(&program).err(),
+            layout_memory(program).err(),
             Some(MemoryLayoutError::InvalidDirectiveArgument {
                 kind: DirectiveKind::String,
                 location: RelativeLocation::default(),
@@ -391,7 +1188,7 @@ mod tests {
             vec![Line::default().directive(DirectiveKind::Space, "hello")];
 
         assert_eq!(
-            layout_memory(&program).err(),
+            layout_memory(program).err(),
             Some(MemoryLayoutError::InvalidDirectiveArgument {
                 kind: DirectiveKind::Space,
                 location: RelativeLocation::default(),
@@ -403,7 +1200,7 @@ mod tests {
             vec![Line::default().directive(DirectiveKind::Addr, "hello")];
 
         assert_eq!(
-            layout_memory(&program).err(),
+            layout_memory(program).err(),
             Some(MemoryLayoutError::InvalidDirectiveArgument {
                 kind: DirectiveKind::Addr,
                 location: RelativeLocation::default(),
@@ -412,6 +1209,95 @@ mod tests {
         );
     }
 
+    #[test]
+    fn warnings_test() {
+        let program: Vec<Line<RelativeLocation>> = vec![
+            Line::default().directive(DirectiveKind::Word, 0),
+            Line::default().directive(DirectiveKind::Addr, 100), // Nothing was placed there yet
+            Line::default().directive(DirectiveKind::Word, 0),
+            Line::default().directive(DirectiveKind::Word, 0),
+            Line::default().directive(DirectiveKind::Addr, 101),
+            Line::default().directive(DirectiveKind::Addr, 9990),
+            Line::default().directive(DirectiveKind::Space, 20),
+        ];
+
+        let warnings = layout_memory(program).unwrap().warnings;
+        assert_eq!(
+            warnings,
+            vec![
+                Warning::AddrBackwards {
+                    from: 102,
+                    to: 101,
+                    location: RelativeLocation::default(),
+                },
+                Warning::OversizedSpace {
+                    start: 9990,
+                    size: 20,
+                    location: RelativeLocation::default(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn space_test() {
+        // The cells past the end of memory are not laid out one by one
+        let program: Vec<Line<RelativeLocation>> = vec![
+            Line::default().directive(DirectiveKind::Addr, 9990),
+            Line::default().directive(DirectiveKind::Space, i128::from(MEMORY_SIZE)),
+            Line::default()
+                .symbol("end")
+                .directive(DirectiveKind::Word, 0),
+        ];
+        let layout = layout_memory(program).unwrap();
+        let reserved = layout
+            .memory
+            .values()
+            .filter(|p| matches!(p, Placement::Reserved));
+        assert_eq!(reserved.count(), 10);
+        assert_eq!(layout.labels["end"], 9990 + MEMORY_SIZE);
+
+        // Reserving more than the whole memory is an error
+        for size in [i128::from(MEMORY_SIZE) + 1, 4_000_000_000, 4_294_967_295] {
+            let program: Vec<Line<RelativeLocation>> = vec![
+                Line::default().directive(DirectiveKind::Addr, 9990),
+                Line::default().directive(DirectiveKind::Space, size),
+            ];
+            assert_eq!(
+                layout_memory(program).err(),
+                Some(MemoryLayoutError::AddressOverflow {
+                    location: RelativeLocation::default(),
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn overlap_overwrite_test() {
+        let source = "buffer: .space 4\n.addr buffer + 2\nvalue: .word 5\n";
+        let program = crate::parse(source).unwrap();
+        let program = program.map_location(&AbsoluteLocation::<()>::default());
+        let lines: Vec<_> = program.inner.lines.into_iter().map(|l| l.inner).collect();
+
+        let layout = layout_memory(lines).unwrap();
+        let Some(Warning::OverlapOverwrite {
+            address,
+            location,
+            previous,
+        }) = layout.warnings.last()
+        else {
+            panic!("unexpected warnings {:?}", layout.warnings);
+        };
+        assert_eq!(layout.warnings[0].kind(), WarningKind::AddrBackwards);
+        assert_eq!(*address, PROGRAM_START + 2);
+        assert_eq!(&source[location.span()], ".word 5");
+        assert_eq!(&source[previous.span()], ".space 4");
+        assert!(matches!(
+            layout.memory[&(PROGRAM_START + 2)],
+            Placement::Line(_)
+        ));
+    }
+
     #[test]
     fn memory_overlap_test() {
         let program: Vec<Line<RelativeLocation>> = vec![
@@ -422,8 +1308,354 @@ mod tests {
         ];
 
         assert_eq!(
-            layout_memory(&program).err(),
-            Some(MemoryLayoutError::MemoryOverlap { address: 14 })
+            layout_memory(program).err(),
+            Some(MemoryLayoutError::MemoryOverlap {
+                address: 14,
+                location: RelativeLocation::default(),
+                previous: RelativeLocation::default(),
+            })
+        );
+    }
+
+    #[test]
+    fn memory_overlap_locations_test() {
+        let source = ".addr 10\n.string \"hello\"\n.addr 14\n.word 0\n";
+        let program = crate::parse(source).unwrap();
+        let program = program.map_location(&AbsoluteLocation::<()>::default());
+        let lines: Vec<_> = program.inner.lines.into_iter().map(|l| l.inner).collect();
+
+        let error = layout_memory(lines).err().unwrap();
+        let span = |location: &AbsoluteLocation| &source[location.span()];
+        assert_eq!(span(error.location().unwrap()), ".word 0");
+        assert_eq!(
+            span(error.previous_location().unwrap()),
+            ".string \"hello\""
+        );
+    }
+
+    #[test]
+    fn sections_test() {
+        let source = indoc::indoc! {"
+            .data
+            value: .word 5
+            .text
+            main: ld [value], %a
+                reset
+            .bss
+            buf: .space 10
+            .addr 200
+            handler: rtn
+            .data
+            other: .word 1
+            .text
+            after: reset
+        "};
+        let program = crate::parse(source).unwrap().inner;
+        let lines: Vec<_> = program.lines.into_iter().map(|l| l.inner).collect();
+
+        let labels = layout_memory(lines.clone()).unwrap().labels;
+        let expected = HashMap::from([
+            ("main".to_owned(), PROGRAM_START),
+            ("after".to_owned(), PROGRAM_START + 2),
+            ("value".to_owned(), PROGRAM_START + 3),
+            ("other".to_owned(), PROGRAM_START + 4),
+            ("buf".to_owned(), PROGRAM_START + 5),
+            ("handler".to_owned(), 200),
+        ]);
+        assert_eq!(labels, expected);
+
+        let scheme = MemoryScheme {
+            data: Some(5000),
+            ..MemoryScheme::default()
+        };
+        let labels = layout_memory_with(lines, &scheme).unwrap().labels;
+        assert_eq!(labels["value"], 5000);
+        assert_eq!(labels["buf"], 5002);
+    }
+
+    #[test]
+    fn section_overflow_test() {
+        let layout = |source: &str, scheme: &MemoryScheme| {
+            let program = crate::parse(source).unwrap().inner;
+            let lines: Vec<_> = program.lines.into_iter().map(|l| l.inner).collect();
+            layout_memory_with(lines, scheme).err()
+        };
+
+        assert_eq!(
+            layout("main: reset\n.space 4294967295\n", &MemoryScheme::default()),
+            Some(MemoryLayoutError::SectionOverflow {
+                section: SectionKind::Text,
+                size: Address::MAX,
+            })
+        );
+
+        let scheme = MemoryScheme {
+            bss: Some(Address::MAX - 1),
+            ..MemoryScheme::default()
+        };
+        assert_eq!(
+            layout("main: reset\n.bss\n.space 2\n", &scheme),
+            Some(MemoryLayoutError::SectionOverflow {
+                section: SectionKind::Bss,
+                size: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn label_arithmetic_test() {
+        let source = indoc::indoc! {"
+            .data
+            table: .word 1
+                .word 2
+                .word 3
+            table_end:
+            copy: .space table_end - table
+            .text
+            main: reset
+            .addr main + 100
+            handler: rtn
+        "};
+        let program = crate::parse(source).unwrap().inner;
+        let lines: Vec<_> = program.lines.into_iter().map(|l| l.inner).collect();
+
+        let layout = layout_memory(lines.clone()).unwrap();
+        assert_eq!(layout.labels["table"], PROGRAM_START + 1);
+        assert_eq!(layout.labels["copy"], PROGRAM_START + 4);
+        assert_eq!(layout.labels["handler"], PROGRAM_START + 100);
+        assert_eq!(layout.memory.len(), 8);
+
+        // The data section size accounts for the reserved space
+        let estimate = estimate(&lines, &MemoryScheme::default()).unwrap();
+        assert_eq!(estimate.sizes[&SectionKind::Data], 6);
+    }
+
+    #[test]
+    fn multi_cell_directive_test() {
+        use crate::runtime::{Arch, Cell, MachineConfig};
+
+        let source = indoc::indoc! {r#"
+            main: reset
+            big: .long 0x500000000 * 0x100000000 + end
+            small: .long -2
+            text: .byte "ABCDEFGHIJ"
+            end:
+        "#};
+
+        for endianness in [Endianness::Little, Endianness::Big] {
+            let scheme = MemoryScheme {
+                endianness,
+                ..MemoryScheme::default()
+            };
+            let program = crate::parse(source).unwrap().inner;
+            let (mut computer, debug_info) =
+                crate::compiler::compile_with(program, "main", &scheme).unwrap();
+            computer.set_config(MachineConfig {
+                endianness,
+                ..MachineConfig::default()
+            });
+
+            let labels = &debug_info.labels;
+            assert_eq!(labels["text"], labels["big"] + 4);
+            assert_eq!(labels["end"], labels["text"] + 2);
+
+            let end = labels["end"];
+            let low = computer.memory.get(labels["big"]).unwrap();
+            let expected = match endianness {
+                Endianness::Little => Cell::Word(end.into()),
+                Endianness::Big => Cell::Word(5),
+            };
+            assert_eq!(low, &expected);
+            assert_eq!(
+                computer.read_long(labels["big"]).unwrap(),
+                (5 << 64) + i128::from(end)
+            );
+            assert_eq!(computer.read_long(labels["small"]).unwrap(), -2);
+            assert_eq!(
+                computer.read_bytes(labels["text"], 10).unwrap(),
+                b"ABCDEFGHIJ"
+            );
+        }
+
+        // Literals can be wider than a word
+        let program = crate::parse("main: reset\nbig: .long 0x10000000000000002\n").unwrap();
+        let (computer, debug_info) = crate::compile(program.inner, "main").unwrap();
+        let big = debug_info.labels["big"];
+        assert_eq!(computer.read_long(big).unwrap(), (1 << 64) + 2);
+
+        // Narrower words hold fewer bytes, and longs of twice their width
+        let arch = Arch::new(16).unwrap();
+        let scheme = MemoryScheme {
+            arch,
+            ..MemoryScheme::default()
+        };
+        let source = "main: reset\nbig: .long 0x123456\ntext: .byte \"ABC\"\nend:\n";
+        let program = crate::parse(source).unwrap().inner;
+        let (mut computer, debug_info) =
+            crate::compiler::compile_with(program, "main", &scheme).unwrap();
+        computer.set_config(MachineConfig {
+            arch,
+            ..MachineConfig::default()
+        });
+        let labels = &debug_info.labels;
+        assert_eq!(labels["end"], labels["text"] + 2);
+        assert_eq!(computer.read_long(labels["big"]).unwrap(), 0x12_3456);
+        assert_eq!(computer.read_bytes(labels["text"], 3).unwrap(), b"ABC");
+
+        // Bytes can also be given as values, like the ones of the files included by `.incbin`
+        let source = "main: reset\nbytes: .byte 1, 2 * 8, 0xff\nend:\n";
+        let program = crate::parse(source).unwrap().inner;
+        let (mut computer, debug_info) =
+            crate::compiler::compile_with(program, "main", &scheme).unwrap();
+        computer.set_config(MachineConfig {
+            arch,
+            ..MachineConfig::default()
+        });
+        let labels = &debug_info.labels;
+        assert_eq!(labels["end"], labels["bytes"] + 2);
+        assert_eq!(
+            computer.read_bytes(labels["bytes"], 3).unwrap(),
+            [1, 16, 255]
+        );
+        assert_eq!(
+            computer.memory.get(labels["bytes"]).unwrap(),
+            &Cell::Word(0x1001)
+        );
+
+        // The values must be bytes, known before the labels are placed
+        for (source, expected) in [
+            (".long \"AB\"\n", DirectiveKind::Long),
+            (".byte 1, 256\n", DirectiveKind::Byte),
+            (".byte main\nmain: reset\n", DirectiveKind::Byte),
+        ] {
+            let program = crate::parse(source).unwrap().inner;
+            let lines: Vec<_> = program.lines.into_iter().map(|l| l.inner).collect();
+            assert!(matches!(
+                layout_memory(lines),
+                Err(MemoryLayoutError::InvalidDirectiveArgument { kind, .. }) if kind == expected
+            ));
+        }
+    }
+
+    #[test]
+    fn forward_reference_test() {
+        let source = indoc::indoc! {"
+            main: ld [copy], %a
+                reset
+            copy: .space table_end - table
+            .addr handler + 10
+            table: .word 1
+                .word 2
+            table_end:
+            .addr 2000
+            handler: rtn
+        "};
+        let program = crate::parse(source).unwrap().inner;
+        let lines: Vec<_> = program.lines.into_iter().map(|l| l.inner).collect();
+
+        let layout = layout_memory(lines).unwrap();
+        assert_eq!(layout.labels["copy"], PROGRAM_START + 2);
+        assert_eq!(layout.labels["table"], 2010);
+        assert_eq!(layout.labels["table_end"], 2012);
+        assert_eq!(layout.memory.len(), 7);
+    }
+
+    #[test]
+    fn circular_dependency_test() {
+        let source = indoc::indoc! {"
+            start: .space end - start + 1
+            end: reset
+        "};
+        let program = crate::parse(source).unwrap();
+        let program = program.map_location(&AbsoluteLocation::<()>::default());
+        let lines: Vec<_> = program.inner.lines.into_iter().map(|l| l.inner).collect();
+
+        let error = layout_memory(lines).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "circular dependency between the labels end"
+        );
+        let span = |location: &AbsoluteLocation| &source[location.span()];
+        assert_eq!(span(error.location().unwrap()), "end:");
+
+        // Labels which are not defined anywhere are still reported as evaluation errors
+        let program: Vec<Line<RelativeLocation>> = vec![Line::default().directive(
+            DirectiveKind::Space,
+            DirectiveArgument::Expression(Node::Variable("nowhere".into())),
+        )];
+        assert!(matches!(
+            layout_memory(program),
+            Err(MemoryLayoutError::DirectiveArgumentEvaluation { .. })
+        ));
+    }
+
+    #[test]
+    fn weak_label_test() {
+        let library = indoc::indoc! {"
+            .weak handler
+            handler: reset
+        "};
+
+        // The default definition is used when there is no other one
+        let program = crate::parse(&format!("main: jmp handler\n{library}"))
+            .unwrap()
+            .inner;
+        let lines: Vec<_> = program.lines.into_iter().map(|l| l.inner).collect();
+        let layout = layout_memory(lines).unwrap();
+        assert_eq!(layout.labels["handler"], PROGRAM_START + 1);
+
+        // Other definitions override it, wherever they are
+        for source in [
+            format!("{library}handler: rtn\n"),
+            format!("handler: rtn\n{library}"),
+        ] {
+            let program = crate::parse(&source).unwrap().inner;
+            let lines: Vec<_> = program.lines.into_iter().map(|l| l.inner).collect();
+            let layout = layout_memory(lines).unwrap();
+            let address = layout.labels["handler"];
+            assert_eq!(layout.describe(&layout.memory[&address]), "rtn");
+        }
+
+        // Two definitions without a .weak directive still conflict
+        let program = crate::parse(&format!("{library}handler: rtn\nhandler: rtn\n"))
+            .unwrap()
+            .inner;
+        let lines: Vec<_> = program.lines.into_iter().map(|l| l.inner).collect();
+        assert!(matches!(
+            layout_memory(lines),
+            Err(MemoryLayoutError::DuplicateLabel { .. })
+        ));
+    }
+
+    #[test]
+    fn memory_map_test() {
+        let source = indoc::indoc! {"
+            main: ld [value], %a
+                reset
+            value: .word 1
+            name: .string \"ab\"
+            buf: .space 2
+        "};
+        let program = crate::parse(source).unwrap().inner;
+        let lines: Vec<_> = program.lines.into_iter().map(|l| l.inner).collect();
+
+        let map = layout_memory(lines).unwrap().memory_map();
+        let areas: Vec<_> = map
+            .iter()
+            .map(|area| (area.kind, area.range.clone()))
+            .collect();
+        assert_eq!(
+            areas,
+            vec![
+                (AreaKind::Vectors, 100..103),
+                (AreaKind::Code, 1000..1002),
+                (AreaKind::Data, 1002..1003),
+                (AreaKind::String, 1003..1005),
+                (AreaKind::Reserved, 1005..1007),
+                (AreaKind::Stack, 1007..STACK_START),
+            ]
         );
+        assert_eq!(map[1].labels, vec![("main".to_owned(), 1000)]);
+        assert_eq!(map[4].labels, vec![("buf".to_owned(), 1005)]);
     }
 }
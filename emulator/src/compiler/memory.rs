@@ -5,16 +5,18 @@ use thiserror::Error;
 use tracing::{debug, span, trace, Level};
 
 use crate::{
+    arena::Arena,
     constants as C,
     parser::expression::EvaluationError as ExpressionEvaluationError,
     parser::line::LineContent,
     parser::{
+        expression::{Node, Value},
         location::Located,
-        value::{ComputeError, DirectiveArgument, DirectiveKind, InstructionKind},
+        value::{check_range, ComputeError, DirectiveArgument, DirectiveKind, InstructionKind},
     },
     runtime::{
         arguments::{ArgConversionError, ImmRegDirIndIdx},
-        Cell, Instruction, Memory,
+        Arch, Cell, FinalAssertion, Instruction, Memory,
     },
 };
 
@@ -28,7 +30,7 @@ pub enum MemoryFillError<L> {
         source: ExpressionEvaluationError<L>,
     },
 
-    #[error("could not compute instruction argument")]
+    #[error("could not compute argument")]
     Compute {
         location: L,
         source: ComputeError<L>,
@@ -42,11 +44,14 @@ pub enum MemoryFillError<L> {
 }
 
 impl<L> MemoryFillError<L> {
+    /// Location of the error, narrowed down to the faulty sub-expression or argument if known
     pub fn location(&self) -> &L {
         match self {
-            MemoryFillError::Evaluation { location, .. }
-            | MemoryFillError::Compute { location, .. }
-            | MemoryFillError::InstructionCompilation { location, .. } => location,
+            MemoryFillError::Evaluation { location, source } => {
+                source.location().unwrap_or(location)
+            }
+            MemoryFillError::Compute { location, source } => source.location().unwrap_or(location),
+            MemoryFillError::InstructionCompilation { location, .. } => location,
         }
     }
 }
@@ -56,22 +61,45 @@ pub enum InstructionCompilationError {
     #[error("invalid number of arguments: expected {expected}, got {got}")]
     InvalidArgumentNumber { expected: usize, got: usize },
 
-    #[error(transparent)]
-    ArgumentConversion(#[from] ArgConversionError),
+    #[error("invalid argument {}", .index + 1)]
+    ArgumentConversion {
+        /// Index of the argument, starting at 0
+        index: usize,
+        source: ArgConversionError,
+    },
 }
 
-impl From<std::convert::Infallible> for InstructionCompilationError {
-    fn from(_: std::convert::Infallible) -> Self {
-        unreachable!()
+impl InstructionCompilationError {
+    /// Index of the argument causing the error, if any
+    #[must_use]
+    pub fn argument(&self) -> Option<usize> {
+        match self {
+            Self::ArgumentConversion { index, .. } => Some(*index),
+            Self::InvalidArgumentNumber { .. } => None,
+        }
     }
 }
 
-fn get_tuple<X, Y>(args: Vec<ImmRegDirIndIdx>) -> Result<(X, Y), InstructionCompilationError>
+/// Convert the argument at the given index
+fn convert<X>(index: usize, arg: ImmRegDirIndIdx) -> Result<X, InstructionCompilationError>
+where
+    X: TryFrom<ImmRegDirIndIdx>,
+    X::Error: Into<ArgConversionError>,
+{
+    X::try_from(arg).map_err(|source| InstructionCompilationError::ArgumentConversion {
+        index,
+        source: source.into(),
+    })
+}
+
+pub(crate) fn get_tuple<X, Y>(
+    args: Vec<ImmRegDirIndIdx>,
+) -> Result<(X, Y), InstructionCompilationError>
 where
     X: TryFrom<ImmRegDirIndIdx>,
     Y: TryFrom<ImmRegDirIndIdx>,
-    X::Error: Into<InstructionCompilationError>,
-    Y::Error: Into<InstructionCompilationError>,
+    X::Error: Into<ArgConversionError>,
+    Y::Error: Into<ArgConversionError>,
 {
     let [x, y]: [ImmRegDirIndIdx; 2] = args.try_into().map_err(|args: Vec<_>| {
         InstructionCompilationError::InvalidArgumentNumber {
@@ -80,16 +108,13 @@ where
         }
     })?;
 
-    Ok((
-        X::try_from(x).map_err(Into::into)?,
-        Y::try_from(y).map_err(Into::into)?,
-    ))
+    Ok((convert(0, x)?, convert(1, y)?))
 }
 
-fn get_singleton<X>(args: Vec<ImmRegDirIndIdx>) -> Result<X, InstructionCompilationError>
+pub(crate) fn get_singleton<X>(args: Vec<ImmRegDirIndIdx>) -> Result<X, InstructionCompilationError>
 where
     X: TryFrom<ImmRegDirIndIdx>,
-    X::Error: Into<InstructionCompilationError>,
+    X::Error: Into<ArgConversionError>,
 {
     let [x]: [ImmRegDirIndIdx; 1] = args.try_into().map_err(|args: Vec<_>| {
         InstructionCompilationError::InvalidArgumentNumber {
@@ -98,11 +123,11 @@ where
         }
     })?;
 
-    X::try_from(x).map_err(Into::into)
+    convert(0, x)
 }
 
 #[allow(clippy::needless_pass_by_value)]
-fn get_none(args: Vec<ImmRegDirIndIdx>) -> Result<(), InstructionCompilationError> {
+pub(crate) fn get_none(args: Vec<ImmRegDirIndIdx>) -> Result<(), InstructionCompilationError> {
     if !args.is_empty() {
         return Err(InstructionCompilationError::InvalidArgumentNumber {
             expected: 0,
@@ -115,200 +140,34 @@ fn get_none(args: Vec<ImmRegDirIndIdx>) -> Result<(), InstructionCompilationErro
 
 #[tracing::instrument]
 fn compile_instruction(
-    kind: &InstructionKind,
+    kind: InstructionKind,
     arguments: Vec<ImmRegDirIndIdx>,
 ) -> Result<Instruction, InstructionCompilationError> {
-    use InstructionKind as K;
-
-    match kind {
-        K::Add => {
-            let (a, b) = get_tuple(arguments)?;
-            Ok(Instruction::Add(a, b))
-        }
-
-        K::And => {
-            let (a, b) = get_tuple(arguments)?;
-            Ok(Instruction::And(a, b))
-        }
-
-        K::Call => {
-            let a = get_singleton(arguments)?;
-            Ok(Instruction::Call(a))
-        }
-
-        K::Cmp => {
-            let (a, b) = get_tuple(arguments)?;
-            Ok(Instruction::Cmp(a, b))
-        }
-
-        K::Div => {
-            let (a, b) = get_tuple(arguments)?;
-            Ok(Instruction::Div(a, b))
-        }
-
-        K::Fas => {
-            let (a, b) = get_tuple(arguments)?;
-            Ok(Instruction::Fas(a, b))
-        }
-
-        K::In => {
-            let (a, b) = get_tuple(arguments)?;
-            Ok(Instruction::In(a, b))
-        }
-
-        K::Jmp => {
-            let a = get_singleton(arguments)?;
-            Ok(Instruction::Jmp(a))
-        }
-
-        K::Jeq => {
-            let a = get_singleton(arguments)?;
-            Ok(Instruction::Jeq(a))
-        }
-
-        K::Jne => {
-            let a = get_singleton(arguments)?;
-            Ok(Instruction::Jne(a))
-        }
-
-        K::Jle => {
-            let a = get_singleton(arguments)?;
-            Ok(Instruction::Jle(a))
-        }
-
-        K::Jlt => {
-            let a = get_singleton(arguments)?;
-            Ok(Instruction::Jlt(a))
-        }
-
-        K::Jge => {
-            let a = get_singleton(arguments)?;
-            Ok(Instruction::Jge(a))
-        }
-
-        K::Jgt => {
-            let a = get_singleton(arguments)?;
-            Ok(Instruction::Jgt(a))
-        }
-
-        K::Ld => {
-            let (a, b) = get_tuple(arguments)?;
-            Ok(Instruction::Ld(a, b))
-        }
-
-        K::Mul => {
-            let (a, b) = get_tuple(arguments)?;
-            Ok(Instruction::Mul(a, b))
-        }
-
-        K::Neg => {
-            let a = get_singleton(arguments)?;
-            Ok(Instruction::Neg(a))
-        }
-
-        K::Nop => {
-            get_none(arguments)?;
-            Ok(Instruction::Nop)
-        }
-
-        K::Not => {
-            let a = get_singleton(arguments)?;
-            Ok(Instruction::Not(a))
-        }
-
-        K::Or => {
-            let (a, b) = get_tuple(arguments)?;
-            Ok(Instruction::Or(a, b))
-        }
-
-        K::Out => {
-            let (a, b) = get_tuple(arguments)?;
-            Ok(Instruction::Out(a, b))
-        }
-
-        K::Pop => {
-            let a = get_singleton(arguments)?;
-            Ok(Instruction::Pop(a))
-        }
-
-        K::Push => {
-            let a = get_singleton(arguments)?;
-            Ok(Instruction::Push(a))
-        }
-
-        K::Reset => {
-            get_none(arguments)?;
-            Ok(Instruction::Reset)
-        }
-
-        K::Rti => {
-            get_none(arguments)?;
-            Ok(Instruction::Rti)
-        }
-
-        K::Rtn => {
-            get_none(arguments)?;
-            Ok(Instruction::Rtn)
-        }
-
-        K::Shl => {
-            let (a, b) = get_tuple(arguments)?;
-            Ok(Instruction::Shl(a, b))
-        }
-
-        K::Shr => {
-            let (a, b) = get_tuple(arguments)?;
-            Ok(Instruction::Shr(a, b))
-        }
-
-        K::St => {
-            let (a, b) = get_tuple(arguments)?;
-            Ok(Instruction::St(a, b))
-        }
-
-        K::Sub => {
-            let (a, b) = get_tuple(arguments)?;
-            Ok(Instruction::Sub(a, b))
-        }
-
-        K::Swap => {
-            let (a, b) = get_tuple(arguments)?;
-            Ok(Instruction::Swap(a, b))
-        }
-
-        K::Trap => {
-            get_none(arguments)?;
-            Ok(Instruction::Trap)
-        }
-
-        K::Xor => {
-            let (a, b) = get_tuple(arguments)?;
-            Ok(Instruction::Xor(a, b))
-        }
-
-        K::DebugReg => {
-            get_none(arguments)?;
-            Ok(Instruction::DebugReg)
-        }
-    }
+    Instruction::from_operands(kind, arguments)
 }
 
-#[tracing::instrument(skip(placement, labels))]
-fn compile_placement<L: Clone>(
+/// Compile a placement into a cell, checking that its values fit in the words of the machine
+#[tracing::instrument(skip(placement, contents, labels))]
+pub(crate) fn compile_placement<L: Clone>(
     labels: &Labels,
+    contents: &Arena<LineContent<L>>,
+    address: C::Address,
     placement: &Placement<L>,
+    arch: Arch,
 ) -> Result<Cell, MemoryFillError<L>> {
-    use Placement as P;
-
-    match placement {
+    let content = match placement {
         // Reserved placements are created by .space directives
-        P::Reserved => Ok(Cell::Empty),
+        Placement::Reserved => return Ok(Cell::Empty),
 
         // Char placements are create by .string directives
-        P::Char(c) => Ok(Cell::Char(*c)),
+        Placement::Char(c) => return Ok(Cell::Char(*c)),
 
+        Placement::Line(id) => &contents[*id],
+    };
+
+    match content {
         // A .word directive (don't mind the weird destructuring)
-        P::Line(LineContent::Directive {
+        LineContent::Directive {
             kind:
                 Located {
                     inner: DirectiveKind::Word,
@@ -319,26 +178,34 @@ fn compile_placement<L: Clone>(
                     inner: DirectiveArgument::Expression(expression),
                     location,
                 },
-        }) => {
+        } => {
             debug!(%expression, "Evaluating directive");
-            let value =
+            let value: Value =
                 expression
                     .evaluate(labels)
                     .map_err(|source| MemoryFillError::Evaluation {
                         source,
                         location: location.clone(),
                     })?;
+            let value =
+                check_range("a word", value, arch.min(), arch.max(), None).map_err(|source| {
+                    MemoryFillError::Compute {
+                        location: location.clone(),
+                        source,
+                    }
+                })?;
             Ok(Cell::Word(value))
         }
 
         // We should not have any other directives other than "word" at this point
-        P::Line(LineContent::Directive { .. }) => {
+        LineContent::Directive { .. } | LineContent::Section { .. } => {
             unreachable!();
         }
 
-        P::Line(LineContent::Instruction { kind, arguments }) => {
+        LineContent::Instruction { kind, arguments } => {
             let span = span!(Level::TRACE, "line", %kind);
             let _guard = span.enter();
+            let located_arguments = arguments;
             let arguments: Result<Vec<_>, _> = arguments
                 .iter()
                 .enumerate()
@@ -346,7 +213,7 @@ fn compile_placement<L: Clone>(
                     trace!("argument {} evaluation: {}", index, argument);
                     argument
                         .inner
-                        .evaluate(labels)
+                        .evaluate(labels, address, arch)
                         .map_err(|source| MemoryFillError::Compute {
                             location: argument.location.clone(),
                             source,
@@ -354,9 +221,14 @@ fn compile_placement<L: Clone>(
                 })
                 .collect();
             let arguments = arguments?;
-            let instruction = compile_instruction(&kind.inner, arguments).map_err(|source| {
+            let instruction = compile_instruction(kind.inner, arguments).map_err(|source| {
+                // Point at the faulty argument if possible, else at the whole instruction
+                let location = source
+                    .argument()
+                    .and_then(|index| located_arguments.get(index))
+                    .map_or(&kind.location, |argument| &argument.location);
                 MemoryFillError::InstructionCompilation {
-                    location: kind.location.clone(),
+                    location: location.clone(),
                     source,
                 }
             })?;
@@ -365,8 +237,14 @@ fn compile_placement<L: Clone>(
     }
 }
 
+/// Compile the placements of a layout into memory cells
+///
+/// With the `parallel` feature, the placements are compiled on the thread pool of `rayon`, since
+/// they only depend on the labels.
 #[tracing::instrument(skip(layout))]
-pub(crate) fn fill_memory<L: Clone>(layout: &Layout<L>) -> Result<Memory, MemoryFillError<L>> {
+pub(crate) fn fill_memory<L: Clone + Send + Sync>(
+    layout: &Layout<L>,
+) -> Result<Memory, MemoryFillError<L>> {
     debug!(
         placements = layout.memory.len(),
         labels = ?layout.labels,
@@ -374,16 +252,27 @@ pub(crate) fn fill_memory<L: Clone>(layout: &Layout<L>) -> Result<Memory, Memory
     );
     let mut memory = Memory::default();
 
-    let cells: Result<HashMap<C::Address, Cell>, MemoryFillError<L>> = layout
-        .memory
-        .iter()
-        .map(|(index, placement)| {
-            let span = span!(Level::TRACE, "placement", index);
-            let _guard = span.enter();
-            let cell = compile_placement(&layout.labels, placement)?;
-            Ok((*index, cell))
-        })
-        .collect();
+    let compile = |(index, placement): (&C::Address, &Placement<L>)| {
+        let span = span!(Level::TRACE, "placement", index);
+        let _guard = span.enter();
+        let cell = compile_placement(
+            &layout.labels,
+            &layout.contents,
+            *index,
+            placement,
+            layout.arch,
+        )?;
+        Ok((*index, cell))
+    };
+
+    #[cfg(feature = "parallel")]
+    let cells: Result<HashMap<C::Address, Cell>, MemoryFillError<L>> = {
+        use rayon::prelude::*;
+        layout.memory.par_iter().map(compile).collect()
+    };
+    #[cfg(not(feature = "parallel"))]
+    let cells: Result<HashMap<C::Address, Cell>, MemoryFillError<L>> =
+        layout.memory.iter().map(compile).collect();
 
     for (address, content) in cells? {
         trace!(address, content = %content, "Filling cell");
@@ -393,3 +282,165 @@ pub(crate) fn fill_memory<L: Clone>(layout: &Layout<L>) -> Result<Memory, Memory
 
     Ok(memory)
 }
+
+/// Evaluate an argument of a directive with the labels of the layout
+fn evaluate_argument<L: Clone, V: TryFrom<Value>>(
+    labels: &Labels,
+    argument: &Located<Node<L>, L>,
+) -> Result<V, MemoryFillError<L>> {
+    argument
+        .inner
+        .evaluate(labels)
+        .map_err(|source| MemoryFillError::Evaluation {
+            source,
+            location: argument.location.clone(),
+        })
+}
+
+/// The ranges of memory and their digests checked by the `.final_assert` directives
+pub(crate) fn final_assertions<L: Clone>(
+    layout: &Layout<L>,
+) -> Result<Vec<FinalAssertion>, MemoryFillError<L>> {
+    layout
+        .final_asserts
+        .iter()
+        .map(|[start, length, digest]| {
+            let start: C::Address = evaluate_argument(&layout.labels, start)?;
+            let length: C::Address = evaluate_argument(&layout.labels, length)?;
+            Ok(FinalAssertion {
+                range: start..start.saturating_add(length),
+                digest: evaluate_argument(&layout.labels, digest)?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        compile,
+        compiler::{compile_with, CompilationError, MemoryScheme},
+        parse,
+        parser::location::{AbsoluteLocation, MapLocation},
+        runtime::{Arch, Cell},
+    };
+
+    /// Compile a program, returning the source text the error points at
+    fn error_span(source: &str) -> &str {
+        let program = parse(source).unwrap();
+        let program = program.map_location(&AbsoluteLocation::<()>::default());
+        let Err(CompilationError::MemoryFill(error)) = compile(program.inner, "main") else {
+            panic!("expected a memory fill error");
+        };
+        &source[error.location().span()]
+    }
+
+    #[test]
+    fn sub_expression_location_test() {
+        assert_eq!(error_span("main: ld [1 + (2 / 0)], %a\n"), "(2 / 0)");
+        assert_eq!(error_span("main: ld [missing + 1], %a\n"), "missing");
+    }
+
+    #[test]
+    fn out_of_range_test() {
+        let source = "main: ld [5000 * 3], %a\n";
+        let program = parse(source).unwrap();
+        let program = program.map_location(&AbsoluteLocation::<()>::default());
+        let Err(CompilationError::MemoryFill(error)) = compile(program.inner, "main") else {
+            panic!("expected a memory fill error");
+        };
+
+        assert_eq!(&source[error.location().span()], "5000 * 3");
+        let message = std::error::Error::source(&error).unwrap().to_string();
+        assert_eq!(
+            message,
+            "15000 is out of range for a memory address, expected a value between 0 and 9999"
+        );
+        assert_eq!(error_span("main: ld [%a + (1 << 70)], %b\n"), "+ (1 << 70)");
+    }
+
+    #[test]
+    fn word_width_test() {
+        let scheme = MemoryScheme {
+            arch: Arch::new(16).unwrap(),
+            ..MemoryScheme::default()
+        };
+        let compile_narrow = |source: &str| {
+            let program = parse(source).unwrap();
+            let program = program.map_location(&AbsoluteLocation::<()>::default());
+            compile_with(program.inner, "main", &scheme)
+        };
+
+        let source = "main: ld -32768, %a\nvalue: .word 32767\n";
+        let (computer, debug_info) = compile_narrow(source).unwrap();
+        let value = debug_info.labels["value"];
+        assert_eq!(computer.memory.get(value).unwrap(), &Cell::Word(32767));
+
+        for (source, span) in [
+            ("main: ld 40000, %a\n", "40000"),
+            ("main: reset\n    .word 40000\n", "40000"),
+            ("main: ld [%a - 32769], %b\n", "- 32769"),
+        ] {
+            let Err(CompilationError::MemoryFill(error)) = compile_narrow(source) else {
+                panic!("expected a memory fill error");
+            };
+            assert_eq!(&source[error.location().span()], span);
+            let message = std::error::Error::source(&error).unwrap().to_string();
+            assert!(message.contains("expected a value between -32768 and 32767"));
+        }
+    }
+
+    #[test]
+    fn argument_location_test() {
+        assert_eq!(error_span("main: add %a, 5\n"), "5");
+
+        // Errors name the addressing modes
+        let source = "main: push [%a]\n";
+        let program = parse(source).unwrap();
+        let program = program.map_location(&AbsoluteLocation::<()>::default());
+        let Err(CompilationError::MemoryFill(error)) = compile(program.inner, "main") else {
+            panic!("expected a memory fill error");
+        };
+        let mut source = std::error::Error::source(&error);
+        let mut messages = Vec::new();
+        while let Some(error) = source {
+            messages.push(error.to_string());
+            source = error.source();
+        }
+        assert_eq!(
+            messages.last().unwrap(),
+            "invalid argument type 'indirect', expected 'immediate/register'"
+        );
+    }
+
+    #[test]
+    fn relative_test() {
+        let compile_at = |origin: u32| {
+            let source = format!(
+                ".addr {origin}\nmain: ld [value(%pc)], %a\n    call double(%pc)\n    reset\n\
+                 double: add %a, %a\n    rtn\nvalue: .word 21\n"
+            );
+            let program = parse(&source).unwrap();
+            let program = program.map_location(&AbsoluteLocation::<()>::default());
+            compile(program.inner, "main").unwrap().0
+        };
+
+        // The same code runs wherever it is placed
+        let mut low = compile_at(1000);
+        let mut high = compile_at(3000);
+        for offset in 0..6 {
+            assert_eq!(
+                low.memory.get(1000 + offset).unwrap(),
+                high.memory.get(3000 + offset).unwrap()
+            );
+        }
+        assert_eq!(low.memory.get(1001).unwrap().to_string(), "call %pc+1");
+
+        low.run().unwrap();
+        high.run().unwrap();
+        assert_eq!(low.registers.a, Cell::Word(42));
+        assert_eq!(high.registers.a, Cell::Word(42));
+
+        assert_eq!(error_span("main: jmp 20000(%pc)\n"), "20000");
+    }
+}
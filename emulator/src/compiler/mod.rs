@@ -3,19 +3,107 @@ use std::collections::HashMap;
 use thiserror::Error;
 use tracing::debug;
 
-use crate::{constants as C, parser::line::Program, runtime::Computer, runtime::Registers};
+use crate::{
+    constants as C,
+    parser::{
+        expression::EvaluationError,
+        line::{LineContent, Program},
+        location::AbsoluteLocation,
+        value::ComputeError,
+    },
+    runtime::Computer,
+    runtime::Registers,
+};
 
-use self::{layout::MemoryLayoutError, memory::MemoryFillError};
+use self::{layout::MemoryLayoutError, memory::MemoryFillError, warning::Warning};
 
+pub mod artifacts;
+pub mod dataflow;
 pub(crate) mod layout;
 pub(crate) mod memory;
+pub mod object;
+pub mod stats;
+mod symbols;
+pub mod warning;
+
+pub use self::layout::{AreaKind, MemoryArea, MemoryScheme, Region};
+pub use self::symbols::{Symbol, SymbolicAddress, Symbols};
+pub use crate::parser::value::SectionKind;
 
 type Labels = HashMap<String, C::Address>;
 
 /// Holds informations about the compilation
-pub struct DebugInfo {
+pub struct DebugInfo<L = AbsoluteLocation> {
     /// Map of labels to addresses
     pub labels: Labels,
+
+    /// Map of addresses to the location of the instruction or directive placed there
+    pub locations: HashMap<C::Address, L>,
+
+    /// Map of addresses to the location of each operand of the instruction placed there
+    pub operands: HashMap<C::Address, Vec<L>>,
+
+    /// Map of labels to the location of their definition
+    pub definitions: HashMap<String, L>,
+
+    /// Every reference to a label from an instruction or directive
+    pub references: Vec<Reference<L>>,
+
+    /// Warnings emitted during the compilation
+    pub warnings: Vec<Warning<L>>,
+
+    /// Ranges of memory laid out by each section and .addr directive
+    pub regions: Vec<Region>,
+}
+
+impl<L: Clone> From<layout::Layout<L>> for DebugInfo<L> {
+    fn from(layout: layout::Layout<L>) -> Self {
+        let operands = layout
+            .memory
+            .iter()
+            .filter_map(|(address, placement)| match placement {
+                layout::Placement::Line(id) => match &layout.contents[*id] {
+                    LineContent::Instruction { arguments, .. } => {
+                        let locations = arguments.iter().map(|a| a.location.clone()).collect();
+                        Some((*address, locations))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+
+        Self {
+            labels: layout.labels,
+            locations: layout.locations,
+            operands,
+            definitions: layout.definitions,
+            references: layout.references,
+            warnings: layout.warnings,
+            regions: layout.regions,
+        }
+    }
+}
+
+impl<L> DebugInfo<L> {
+    /// The labels, to name the addresses laid out by the program after them
+    #[must_use]
+    pub fn symbols(&self) -> Symbols {
+        Symbols::new(&self.labels).within(self.regions.iter().map(|region| region.range.clone()))
+    }
+}
+
+/// A reference to a label
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference<L> {
+    /// Name of the referenced label
+    pub label: String,
+
+    /// Address of the instruction or directive referencing the label
+    pub address: C::Address,
+
+    /// Location of the instruction or directive referencing the label
+    pub location: L,
 }
 
 #[derive(Debug, Error)]
@@ -30,21 +118,52 @@ pub enum CompilationError<L> {
     UnknownEntrypoint(String),
 }
 
+impl<L> CompilationError<L> {
+    /// The label which is not defined, when the error comes from one
+    pub fn undefined_label(&self) -> Option<&str> {
+        let Self::MemoryFill(
+            MemoryFillError::Evaluation { source, .. }
+            | MemoryFillError::Compute {
+                source: ComputeError::Evaluation(source),
+                ..
+            },
+        ) = self
+        else {
+            return None;
+        };
+
+        match source.innermost() {
+            EvaluationError::UndefinedVariable { variable } => Some(variable),
+            _ => None,
+        }
+    }
+}
+
 pub fn layout<L: Clone + Default>(
     program: Program<L>,
 ) -> Result<layout::Layout<L>, MemoryLayoutError<L>> {
     let lines: Vec<_> = program.lines.into_iter().map(|l| l.inner).collect();
-    self::layout::layout_memory(&lines)
+    self::layout::layout_memory(lines)
 }
 
+pub fn compile<L: Clone + Default + std::fmt::Debug + Send + Sync>(
+    program: Program<L>,
+    entrypoint: &str,
+) -> Result<(Computer, DebugInfo<L>), CompilationError<L>> {
+    compile_with(program, entrypoint, &MemoryScheme::default())
+}
+
+/// Compile a program, placing its sections according to the given scheme
 #[tracing::instrument(skip(program))]
-pub fn compile<L: Clone + Default + std::fmt::Debug>(
+pub fn compile_with<L: Clone + Default + std::fmt::Debug + Send + Sync>(
     program: Program<L>,
     entrypoint: &str,
-) -> Result<(Computer, DebugInfo), CompilationError<L>> {
+    scheme: &MemoryScheme,
+) -> Result<(Computer, DebugInfo<L>), CompilationError<L>> {
     let lines: Vec<_> = program.lines.into_iter().map(|l| l.inner).collect();
-    let layout = self::layout::layout_memory(&lines)?;
+    let layout = self::layout::layout_memory_with(lines, scheme)?;
     let memory = self::memory::fill_memory(&layout)?;
+    let final_assertions = self::memory::final_assertions(&layout)?;
 
     // Lookup the entrypoint
     let pc = *layout
@@ -60,16 +179,9 @@ pub fn compile<L: Clone + Default + std::fmt::Debug>(
             sp: C::STACK_START,
             ..Default::default()
         },
+        final_assertions,
         ..Default::default()
     };
 
-    let debug_info = DebugInfo {
-        labels: layout
-            .labels
-            .iter()
-            .map(|(key, value)| (key.to_string(), *value))
-            .collect(),
-    };
-
-    Ok((computer, debug_info))
+    Ok((computer, layout.into()))
 }
@@ -0,0 +1,516 @@
+//! Relocatable object files, and the linker combining them into a program
+//!
+//! An object holds the memory laid out from a single compilation unit. Instructions and `.word`
+//! directives are kept as source text, so that labels defined in other units only get resolved
+//! when linking.
+//!
+//! Each `.addr` directive starts a new section at a fixed address. Other sections, like the ones
+//! started by `.text` or `.data`, are relocatable: the linker places those one after the other,
+//! starting at [`PROGRAM_START`].
+//!
+//! Labels listed by `.global` directives are visible from the other units, the remaining ones are
+//! local to their unit. Units without any `.global` directive export all their labels.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::{
+    constants::{Address, PROGRAM_START, STACK_START},
+    parser::{line::Program, location::MapLocation},
+    runtime::{Computer, Memory, Registers},
+};
+
+use super::{
+    layout::Labels,
+    layout::{layout_memory, Layout, MemoryLayoutError, Placement},
+    memory::{compile_placement, MemoryFillError},
+    DebugInfo,
+};
+
+/// Version of the object file format
+pub const OBJECT_VERSION: u32 = 2;
+
+/// The content of a memory cell in an object
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum ObjectCell {
+    /// An instruction or a `.word` directive, as source text
+    Line(String),
+
+    /// A character of a `.string` directive
+    Char(char),
+
+    /// A cell reserved by a `.space` directive
+    Reserved,
+}
+
+/// A contiguous range of memory cells
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Section {
+    /// Address of the section, or `None` if the linker can place it anywhere
+    pub origin: Option<Address>,
+    pub cells: Vec<ObjectCell>,
+}
+
+/// A label defined in an object
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Symbol {
+    pub name: String,
+
+    /// Index of the section the label points to
+    pub section: usize,
+
+    /// Offset of the label from the start of its section
+    pub offset: Address,
+
+    /// Whether the label is visible from other objects
+    pub global: bool,
+}
+
+/// A cell referencing a label defined in another object
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExternalReference {
+    pub label: String,
+
+    /// Index of the section of the referencing cell
+    pub section: usize,
+
+    /// Offset of the referencing cell from the start of its section
+    pub offset: Address,
+}
+
+/// A compiled, but not yet linked, compilation unit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Object {
+    pub version: u32,
+
+    /// Name of the compilation unit, used in error messages
+    pub name: String,
+    pub sections: Vec<Section>,
+    pub symbols: Vec<Symbol>,
+
+    /// References to labels defined elsewhere
+    pub undefined: Vec<ExternalReference>,
+}
+
+/// Convert a placement back to source text
+fn object_cell<L>(layout: &Layout<L>, placement: Option<&Placement<L>>) -> ObjectCell {
+    match placement {
+        Some(Placement::Line(id)) => ObjectCell::Line(layout.contents[*id].to_source()),
+        Some(Placement::Char(c)) => ObjectCell::Char(*c),
+        Some(Placement::Reserved) | None => ObjectCell::Reserved,
+    }
+}
+
+impl Object {
+    /// Compile a program to an object
+    pub fn assemble<L: Clone + Default>(
+        name: &str,
+        program: Program<L>,
+    ) -> Result<Self, MemoryLayoutError<L>> {
+        let lines: Vec<_> = program.lines.into_iter().map(|l| l.inner).collect();
+        let layout = layout_memory(lines)?;
+
+        let sections = layout
+            .regions
+            .iter()
+            .map(|region| Section {
+                origin: region.section.is_none().then_some(region.range.start),
+                cells: region
+                    .range
+                    .clone()
+                    .map(|address| object_cell(&layout, layout.memory.get(&address)))
+                    .collect(),
+            })
+            .collect();
+
+        let export_all = layout.globals.is_empty();
+        let mut symbols: Vec<_> = layout
+            .labels
+            .iter()
+            .map(|(name, address)| {
+                let section = layout.label_regions[name];
+                Symbol {
+                    name: name.clone(),
+                    section,
+                    offset: address - layout.regions[section].range.start,
+                    global: export_all || layout.globals.contains_key(name),
+                }
+            })
+            .collect();
+        symbols.sort_by(|a, b| (a.section, a.offset, &a.name).cmp(&(b.section, b.offset, &b.name)));
+
+        let mut undefined: Vec<_> = layout
+            .references
+            .iter()
+            .filter(|reference| !layout.labels.contains_key(&reference.label))
+            .map(|reference| {
+                let section = layout
+                    .regions
+                    .iter()
+                    .position(|region| region.range.contains(&reference.address))
+                    .unwrap_or_default();
+                ExternalReference {
+                    label: reference.label.clone(),
+                    section,
+                    offset: reference.address - layout.regions[section].range.start,
+                }
+            })
+            .collect();
+        undefined
+            .sort_by(|a, b| (&a.label, a.section, a.offset).cmp(&(&b.label, b.section, b.offset)));
+        undefined.dedup();
+
+        Ok(Self {
+            version: OBJECT_VERSION,
+            name: name.to_owned(),
+            sections,
+            symbols,
+            undefined,
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum LinkError {
+    #[error("unsupported version {version} for object {object}")]
+    UnsupportedVersion { object: String, version: u32 },
+
+    #[error("label {label} is defined in both {first} and {second}")]
+    DuplicateDefinition {
+        label: String,
+        first: String,
+        second: String,
+    },
+
+    #[error("undefined label {label}, referenced in {}", join(sites))]
+    UndefinedLabel {
+        label: String,
+        sites: Vec<ReferenceSite>,
+    },
+
+    #[error("invalid line {line:?} in object {object}")]
+    InvalidLine { object: String, line: String },
+
+    #[error("could not layout memory")]
+    MemoryLayout(#[from] MemoryLayoutError<()>),
+
+    #[error("could not fill memory")]
+    MemoryFill(#[from] MemoryFillError<()>),
+
+    #[error("unknown entrypoint: {0}")]
+    UnknownEntrypoint(String),
+}
+
+/// A cell referencing a label, once linked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReferenceSite {
+    pub object: String,
+    pub address: Address,
+}
+
+impl std::fmt::Display for ReferenceSite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}", self.object, self.address)
+    }
+}
+
+fn join(sites: &[ReferenceSite]) -> String {
+    let sites: Vec<_> = sites.iter().map(ToString::to_string).collect();
+    sites.join(", ")
+}
+
+/// Parse back a line stored in an object, moving its content in the layout
+fn parse_cell(
+    layout: &mut Layout<()>,
+    object: &Object,
+    cell: &ObjectCell,
+) -> Result<Placement<()>, LinkError> {
+    let line = match cell {
+        ObjectCell::Line(line) => line,
+        ObjectCell::Char(c) => return Ok(Placement::Char(*c)),
+        ObjectCell::Reserved => return Ok(Placement::Reserved),
+    };
+
+    let invalid = || LinkError::InvalidLine {
+        object: object.name.clone(),
+        line: line.clone(),
+    };
+    let program = crate::parse(line).map_err(|_| invalid())?;
+    let program = program.inner.map_location(&());
+    program
+        .lines
+        .into_iter()
+        .next()
+        .and_then(|line| line.inner.content)
+        .map(|content| Placement::Line(layout.contents.alloc(content.inner)))
+        .ok_or_else(invalid)
+}
+
+/// Link objects together, placing their relocatable sections one after the other
+///
+/// Local labels are resolved within their object, and shadow the global ones.
+pub fn link(objects: &[Object], entrypoint: &str) -> Result<(Computer, DebugInfo<()>), LinkError> {
+    let mut layout: Layout<()> = Layout::default();
+    let mut globals = Labels::new();
+    let mut defined_in: HashMap<&str, &str> = HashMap::new();
+    let mut position = PROGRAM_START;
+
+    // Addresses of the cells and of the sections of each object
+    let mut cells = Vec::with_capacity(objects.len());
+    let mut starts = Vec::with_capacity(objects.len());
+    let mut locals = Vec::with_capacity(objects.len());
+
+    for object in objects {
+        if object.version != OBJECT_VERSION {
+            return Err(LinkError::UnsupportedVersion {
+                object: object.name.clone(),
+                version: object.version,
+            });
+        }
+
+        // Place the sections
+        let mut addresses = Vec::new();
+        let mut object_starts = Vec::with_capacity(object.sections.len());
+        for section in &object.sections {
+            let start = section.origin.unwrap_or(position);
+            debug!(object = %object.name, start, "Placing section");
+            for (address, cell) in (start..).zip(&section.cells) {
+                let placement = parse_cell(&mut layout, object, cell)?;
+                layout.insert_placement(address, placement, &())?;
+                addresses.push(address);
+            }
+
+            if section.origin.is_none() {
+                position = start + Address::try_from(section.cells.len()).unwrap_or(Address::MAX);
+            }
+            object_starts.push(start);
+        }
+
+        // Then resolve its labels
+        let mut object_locals = Labels::new();
+        for symbol in &object.symbols {
+            let address = object_starts[symbol.section] + symbol.offset;
+            layout.labels.entry(symbol.name.clone()).or_insert(address);
+
+            if !symbol.global {
+                object_locals.insert(symbol.name.clone(), address);
+                continue;
+            }
+
+            if let Some(first) = defined_in.get(symbol.name.as_str()) {
+                return Err(LinkError::DuplicateDefinition {
+                    label: symbol.name.clone(),
+                    first: (*first).to_owned(),
+                    second: object.name.clone(),
+                });
+            }
+            defined_in.insert(&symbol.name, &object.name);
+            globals.insert(symbol.name.clone(), address);
+        }
+
+        cells.push(addresses);
+        starts.push(object_starts);
+        locals.push(object_locals);
+    }
+
+    // Report the first unresolved label, with every cell referencing it
+    let mut unresolved: BTreeMap<&str, Vec<ReferenceSite>> = BTreeMap::new();
+    for (object, object_starts) in objects.iter().zip(&starts) {
+        for reference in &object.undefined {
+            if !globals.contains_key(&reference.label) {
+                unresolved
+                    .entry(&reference.label)
+                    .or_default()
+                    .push(ReferenceSite {
+                        object: object.name.clone(),
+                        address: object_starts[reference.section] + reference.offset,
+                    });
+            }
+        }
+    }
+    if let Some((label, sites)) = unresolved.into_iter().next() {
+        return Err(LinkError::UndefinedLabel {
+            label: label.to_owned(),
+            sites,
+        });
+    }
+
+    let mut memory = Memory::default();
+    for (addresses, object_locals) in cells.iter().zip(locals) {
+        let mut labels = globals.clone();
+        labels.extend(object_locals);
+        for address in addresses {
+            let placement = &layout.memory[address];
+            let cell =
+                compile_placement(&labels, &layout.contents, *address, placement, layout.arch)?;
+            *memory.get_mut(*address).unwrap() = cell;
+        }
+    }
+
+    let pc = *globals
+        .get(entrypoint)
+        .ok_or_else(|| LinkError::UnknownEntrypoint(entrypoint.to_owned()))?;
+
+    let computer = Computer {
+        memory,
+        registers: Registers {
+            pc,
+            sp: STACK_START,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    Ok((computer, layout.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Cell;
+
+    fn assemble(name: &str, source: &str) -> Object {
+        let program = crate::parse(source).unwrap().inner;
+        Object::assemble(name, program).unwrap()
+    }
+
+    #[test]
+    fn assemble_test() {
+        let object = assemble(
+            "main.S",
+            "main: ld [value], %a\n    reset\n.addr 200\nvalue: .word 2 * count\n",
+        );
+
+        assert_eq!(
+            object.sections,
+            vec![
+                Section {
+                    origin: None,
+                    cells: vec![
+                        ObjectCell::Line("ld [value], %a".into()),
+                        ObjectCell::Line("reset".into()),
+                    ],
+                },
+                Section {
+                    origin: Some(200),
+                    cells: vec![ObjectCell::Line(".word 2 * count".into())],
+                },
+            ]
+        );
+        assert_eq!(
+            object.symbols,
+            vec![
+                Symbol {
+                    name: "main".into(),
+                    section: 0,
+                    offset: 0,
+                    global: true,
+                },
+                Symbol {
+                    name: "value".into(),
+                    section: 1,
+                    offset: 0,
+                    global: true,
+                },
+            ]
+        );
+        assert_eq!(
+            object.undefined,
+            vec![ExternalReference {
+                label: "count".into(),
+                section: 1,
+                offset: 0,
+            }]
+        );
+    }
+
+    #[test]
+    fn link_test() {
+        let main = assemble("main.S", "main: ld [value], %a\n    add 1, %a\n    reset\n");
+        let data = assemble("data.S", "    .word 0\nvalue: .word 41\n");
+
+        let (mut computer, debug_info) = link(&[main, data], "main").unwrap();
+        assert_eq!(debug_info.labels["value"], PROGRAM_START + 4);
+
+        computer.run().unwrap();
+        assert_eq!(computer.registers.a, Cell::Word(42));
+    }
+
+    #[test]
+    fn link_nested_expressions_test() {
+        // The cells are written back as source, which must keep the parenthesis needed
+        let source = "main: reset\n    .word 10 - (3 - 2)\n    .word 8 / (4 / 2)\n    .word (1 << 2) << 3\n    .word 256 >> (2 >> 1)\n    .word -(-3) * (2 - 6)\n";
+        let program = crate::parse(source).unwrap().inner;
+        let (compiled, _) = crate::compile(program, "main").unwrap();
+        let (linked, _) = link(&[assemble("main.S", source)], "main").unwrap();
+
+        for (address, expected) in (PROGRAM_START + 1..).zip([9, 4, 32, 128, -12]) {
+            assert_eq!(compiled.memory.get(address).unwrap(), &Cell::Word(expected));
+            assert_eq!(linked.memory.get(address).unwrap(), &Cell::Word(expected));
+        }
+    }
+
+    #[test]
+    fn link_errors_test() {
+        let main = assemble("main.S", "main: jmp missing\n    jmp missing\n");
+        let other = assemble("other.S", "    .word missing\n");
+        let error = link(&[main.clone(), other], "main").err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "undefined label missing, referenced in main.S at 1000, main.S at 1001, other.S at 1002"
+        );
+
+        let other = assemble("other.S", "main: reset\n");
+        assert!(matches!(
+            link(&[main, other], "main"),
+            Err(LinkError::DuplicateDefinition { label, first, second })
+                if label == "main" && first == "main.S" && second == "other.S"
+        ));
+    }
+
+    #[test]
+    fn visibility_test() {
+        let main = assemble(
+            "main.S",
+            ".global main\n.extern add_one\nmain: ld [value], %a\n    call add_one\n    reset\nvalue: .word 41\n",
+        );
+        let library = assemble(
+            "lib.S",
+            ".global add_one\nadd_one: add [value], %a\n    rtn\nvalue: .word 1\n",
+        );
+        assert!(!main
+            .symbols
+            .iter()
+            .any(|symbol| symbol.name == "value" && symbol.global));
+
+        // Each unit uses its own local value label
+        let (mut computer, _) = link(&[main.clone(), library.clone()], "main").unwrap();
+        computer.run().unwrap();
+        assert_eq!(computer.registers.a, Cell::Word(42));
+
+        // Local labels are not visible from the other units
+        let caller = assemble("caller.S", "    ld [value], %a\n");
+        assert!(matches!(
+            link(&[main, library, caller], "main"),
+            Err(LinkError::UndefinedLabel { label, .. }) if label == "value"
+        ));
+
+        let program = crate::parse(".global missing\nmain: reset\n")
+            .unwrap()
+            .inner;
+        assert!(matches!(
+            Object::assemble("main.S", program),
+            Err(MemoryLayoutError::UndefinedGlobal { label, .. }) if label == "missing"
+        ));
+
+        let program = crate::parse(".extern main\nmain: reset\n").unwrap().inner;
+        assert!(matches!(
+            Object::assemble("main.S", program),
+            Err(MemoryLayoutError::DefinedExtern { label, .. }) if label == "main"
+        ));
+    }
+}
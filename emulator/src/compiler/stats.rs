@@ -0,0 +1,124 @@
+//! Static statistics about the instruction set usage of programs
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::parser::line::{LineContent, Program};
+
+use super::layout::{layout_memory, MemoryLayoutError};
+
+/// Counts of the mnemonics, addressing modes and directives used by a program
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Statistics {
+    /// Number of instructions
+    pub instructions: usize,
+
+    /// Number of memory cells laid out by the program
+    pub size: usize,
+
+    /// Number of uses of each mnemonic
+    pub mnemonics: BTreeMap<String, usize>,
+
+    /// Number of instruction arguments using each addressing mode
+    pub addressing_modes: BTreeMap<String, usize>,
+
+    /// Number of uses of each directive, including the section ones
+    pub directives: BTreeMap<String, usize>,
+}
+
+impl Statistics {
+    /// Add the counts of another program to these
+    pub fn merge(&mut self, other: &Self) {
+        self.instructions += other.instructions;
+        self.size += other.size;
+        for (counts, others) in [
+            (&mut self.mnemonics, &other.mnemonics),
+            (&mut self.addressing_modes, &other.addressing_modes),
+            (&mut self.directives, &other.directives),
+        ] {
+            for (name, count) in others {
+                *counts.entry(name.clone()).or_default() += count;
+            }
+        }
+    }
+}
+
+/// Count the mnemonics, addressing modes and directives used by a program
+///
+/// The memory is laid out to compute the size of the program, which fails if the program is
+/// invalid.
+pub fn statistics<L: Clone + Default>(
+    program: &Program<L>,
+) -> Result<Statistics, MemoryLayoutError<L>> {
+    let mut stats = Statistics::default();
+
+    for line in &program.lines {
+        let Some(content) = &line.inner.content else {
+            continue;
+        };
+
+        match &content.inner {
+            LineContent::Instruction { kind, arguments } => {
+                stats.instructions += 1;
+                *stats.mnemonics.entry(kind.inner.to_string()).or_default() += 1;
+                for argument in arguments {
+                    *stats
+                        .addressing_modes
+                        .entry(argument.inner.mode().to_string())
+                        .or_default() += 1;
+                }
+            }
+            LineContent::Directive { kind, .. } => {
+                *stats.directives.entry(kind.inner.to_string()).or_default() += 1;
+            }
+            LineContent::Section { kind } => {
+                *stats.directives.entry(kind.inner.to_string()).or_default() += 1;
+            }
+        }
+    }
+
+    let lines: Vec<_> = program.lines.iter().map(|l| l.inner.clone()).collect();
+    stats.size = layout_memory(lines)?.memory.len();
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn statistics_test() {
+        let program = parse(indoc! {"
+            main:
+                ld [value], %a
+                add 1, %a
+                st %a, [%sp-1]
+                reset
+            .data
+            value: .word 41
+            text: .string \"ab\"
+        "})
+        .unwrap();
+
+        let mut stats = statistics(&program.inner).unwrap();
+        assert_eq!(stats.instructions, 4);
+        assert_eq!(stats.size, 4 + 1 + 2);
+        assert_eq!(stats.mnemonics["ld"], 1);
+        assert_eq!(stats.addressing_modes["register"], 3);
+        assert_eq!(stats.addressing_modes["direct"], 1);
+        assert_eq!(stats.addressing_modes["immediate"], 1);
+        assert_eq!(stats.addressing_modes["indexed"], 1);
+        assert_eq!(stats.directives["data"], 1);
+        assert_eq!(stats.directives["word"], 1);
+
+        let other = stats.clone();
+        stats.merge(&other);
+        assert_eq!(stats.instructions, 8);
+        assert_eq!(stats.mnemonics["reset"], 2);
+    }
+}
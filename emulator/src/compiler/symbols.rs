@@ -0,0 +1,154 @@
+//! Symbolic names of the addresses, to show them relative to the labels of the program
+//!
+//! An address is named after the nearest label before it, with the offset from this label, like
+//! `buffer+3`. When the regions laid out by the program are known, the label must be in the same
+//! region as the address, so that the stack and the free memory are not named after the last
+//! label of the program.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+
+use crate::constants::Address;
+
+/// An address, relative to a label
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbol<'a> {
+    pub label: &'a str,
+    pub offset: Address,
+}
+
+impl fmt::Display for Symbol<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.offset == 0 {
+            write!(f, "{}", self.label)
+        } else {
+            write!(f, "{}+{}", self.label, self.offset)
+        }
+    }
+}
+
+/// An address followed by its symbol, if it has one, like `1003 <buffer+3>`
+///
+/// The width and alignment given when formatting it apply to the whole text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SymbolicAddress<'a> {
+    pub address: Address,
+    pub symbol: Option<Symbol<'a>>,
+}
+
+impl fmt::Display for SymbolicAddress<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.symbol {
+            Some(symbol) => f.pad(&format!("{} <{symbol}>", self.address)),
+            None => f.pad(&self.address.to_string()),
+        }
+    }
+}
+
+/// The labels of a program, sorted by address to name the addresses after them
+///
+/// The default value has no labels, and names no address.
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    /// Labels sorted by address, then by name
+    labels: Vec<(Address, String)>,
+
+    /// Ranges of memory laid out by the program, if known
+    ranges: Option<Vec<Range<Address>>>,
+}
+
+impl Symbols {
+    #[must_use]
+    pub fn new(labels: &HashMap<String, Address>) -> Self {
+        let mut labels: Vec<_> = labels
+            .iter()
+            .map(|(label, &address)| (address, label.clone()))
+            .collect();
+        labels.sort_unstable();
+        Self {
+            labels,
+            ranges: None,
+        }
+    }
+
+    /// Only name the addresses within these ranges, after labels of the same range
+    #[must_use]
+    pub fn within(mut self, ranges: impl IntoIterator<Item = Range<Address>>) -> Self {
+        self.ranges = Some(ranges.into_iter().collect());
+        self
+    }
+
+    /// Find the nearest label before an address
+    #[must_use]
+    pub fn lookup(&self, address: Address) -> Option<Symbol<'_>> {
+        let start = match &self.ranges {
+            Some(ranges) => ranges.iter().find(|range| range.contains(&address))?.start,
+            None => 0,
+        };
+
+        let index = self.labels.partition_point(|(label, _)| *label <= address);
+        let (found, _) = self.labels.get(index.checked_sub(1)?)?;
+        if *found < start {
+            return None;
+        }
+
+        // Labels at the same address are sorted by name, the first one is used
+        let (found, label) = &self.labels[self.labels.partition_point(|(a, _)| a < found)];
+        Some(Symbol {
+            label,
+            offset: address - found,
+        })
+    }
+
+    /// The address along with its symbol, to display them
+    #[must_use]
+    pub fn describe(&self, address: Address) -> SymbolicAddress<'_> {
+        SymbolicAddress {
+            address,
+            symbol: self.lookup(address),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile, parse};
+
+    #[test]
+    fn symbols_test() {
+        let source = "main: ld [buffer], %a\n    reset\nbuffer: .space 4\nend:\n\
+                      .addr 2000\nother: .word 1\n";
+        let program = parse(source).unwrap();
+        let (_, debug_info) = compile(program.inner, "main").unwrap();
+        let main = debug_info.labels["main"];
+        let symbols = debug_info.symbols();
+
+        assert_eq!(symbols.lookup(main).unwrap().to_string(), "main");
+        assert_eq!(symbols.lookup(main + 1).unwrap().to_string(), "main+1");
+        assert_eq!(
+            symbols.describe(main + 5).to_string(),
+            format!("{} <buffer+3>", main + 5)
+        );
+        assert_eq!(symbols.lookup(2000).unwrap().label, "other");
+
+        // Past the regions of the program, or before its first label
+        assert_eq!(symbols.lookup(1500), None);
+        assert_eq!(symbols.lookup(main - 1), None);
+        assert_eq!(format!("{:>5}", symbols.describe(900)), "  900");
+        assert_eq!(
+            format!("{:<16}|", symbols.describe(main + 1)),
+            format!("{:<16}|", format!("{} <main+1>", main + 1))
+        );
+
+        // Without the regions, the last label names everything after it
+        let unbounded = Symbols::new(&debug_info.labels);
+        let end = debug_info.labels["end"];
+        assert_eq!(
+            unbounded.lookup(1500).unwrap().to_string(),
+            format!("end+{}", 1500 - end)
+        );
+        assert_eq!(Symbols::default().lookup(main), None);
+    }
+}
@@ -0,0 +1,127 @@
+//! Non-fatal issues found while compiling a program
+
+use parse_display::{Display, FromStr};
+use thiserror::Error;
+
+use crate::constants::Address;
+use crate::runtime::Reg;
+
+/// Kind of warning, used to allow them individually
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, FromStr)]
+#[display(style = "kebab-case")]
+pub enum WarningKind {
+    /// A `.addr` directive moving the current address back before cells already laid out
+    AddrBackwards,
+
+    /// A `.space` directive reserving cells past the end of memory
+    OversizedSpace,
+
+    /// A cell reserved by a `.space` directive, overwritten by a later line
+    OverlapOverwrite,
+
+    /// A register which may be read before the program writes it
+    UninitializedRegister,
+
+    /// A value loaded in a register and never read
+    DeadStore,
+
+    /// A jump or call to a cell which does not hold an instruction
+    JumpIntoData,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Warning<L> {
+    #[error(".addr moves back to {to}, into memory already laid out (was at {from})")]
+    AddrBackwards {
+        from: Address,
+        to: Address,
+        location: L,
+    },
+
+    #[error(".space reserves {size} cells from address {start}, past the end of memory")]
+    OversizedSpace {
+        start: Address,
+        size: Address,
+        location: L,
+    },
+
+    #[error("address {address}, reserved by a .space directive, is overwritten")]
+    OverlapOverwrite {
+        address: Address,
+        location: L,
+        previous: L,
+    },
+
+    #[error("{reg} may be read before being written")]
+    UninitializedRegister { reg: Reg, location: L },
+
+    #[error("the value loaded in {reg} is never read")]
+    DeadStore { reg: Reg, location: L },
+
+    #[error("jump to address {target}, which does not hold an instruction")]
+    JumpIntoData { target: Address, location: L },
+}
+
+impl<L> Warning<L> {
+    #[must_use]
+    pub const fn kind(&self) -> WarningKind {
+        match self {
+            Warning::AddrBackwards { .. } => WarningKind::AddrBackwards,
+            Warning::OversizedSpace { .. } => WarningKind::OversizedSpace,
+            Warning::OverlapOverwrite { .. } => WarningKind::OverlapOverwrite,
+            Warning::UninitializedRegister { .. } => WarningKind::UninitializedRegister,
+            Warning::DeadStore { .. } => WarningKind::DeadStore,
+            Warning::JumpIntoData { .. } => WarningKind::JumpIntoData,
+        }
+    }
+
+    pub const fn location(&self) -> &L {
+        match self {
+            Warning::AddrBackwards { location, .. }
+            | Warning::OversizedSpace { location, .. }
+            | Warning::OverlapOverwrite { location, .. }
+            | Warning::UninitializedRegister { location, .. }
+            | Warning::DeadStore { location, .. }
+            | Warning::JumpIntoData { location, .. } => location,
+        }
+    }
+}
+
+/// Warnings allowed on a source line by an `// allow(kind, ...)` comment
+#[must_use]
+pub fn allowed_on(line: &str) -> Vec<WarningKind> {
+    let Some((_, comment)) = line.split_once("//") else {
+        return Vec::new();
+    };
+
+    comment
+        .trim()
+        .strip_prefix("allow(")
+        .and_then(|rest| rest.split_once(')'))
+        .map(|(kinds, _)| {
+            kinds
+                .split(',')
+                .filter_map(|kind| kind.trim().parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowed_on_test() {
+        assert_eq!(allowed_on(".addr 10"), vec![]);
+        assert_eq!(allowed_on(".addr 10 // some comment"), vec![]);
+        assert_eq!(
+            allowed_on(".addr 10 // allow(addr-backwards)"),
+            vec![WarningKind::AddrBackwards]
+        );
+        assert_eq!(
+            allowed_on(".space 99999 //allow(oversized-space, addr-backwards, unknown)"),
+            vec![WarningKind::OversizedSpace, WarningKind::AddrBackwards]
+        );
+    }
+}
@@ -1,7 +1,15 @@
 //! Various constants and type definitions used throughout the emulator
 
 pub type Address = u32;
+
+/// A memory cell or register value, stored in two's complement
+///
+/// Arithmetic instructions wrap around on overflow. Words are signed for `jlt`, `jle`, `jgt`, `jge`
+/// and the `OVERFLOW` flag, and unsigned for `jltu`, `jleu`, `jgtu`, `jgeu` and the `CARRY` flag.
 pub type Word = i64;
+
+/// Width of a [`Word`], in bits
+pub const WORD_BITS: u32 = Word::BITS;
 pub type Char = char;
 
 /// Total size of the computer memory
@@ -13,6 +21,21 @@ pub const STACK_START: Address = MEMORY_SIZE;
 /// Default place to store the beginning of the program
 pub const PROGRAM_START: Address = 1000;
 
+/// Address holding the number of program arguments
+pub const ARGS_COUNT: Address = 500;
+
+/// Address holding the address of the array of program arguments
+pub const ARGS_VECTOR: Address = 501;
+
+/// End of the region holding the program arguments
+pub const ARGS_END: Address = PROGRAM_START;
+
+/// First port of the allocator device, used by `<std/heap.s>`
+pub const HEAP_PORT: Address = 2;
+
+/// Port of the timer set up by the project configuration of the CLI, and for the bundled examples
+pub const TIMER_PORT: Address = 4;
+
 /// Address of the interrupt handler
 pub const INTERRUPT_HANDLER: Address = 200;
 
@@ -0,0 +1,268 @@
+//! High-level API to embed the emulator in other programs
+//!
+//! It wires the preprocessor, the parser and the compiler together, and attaches devices to the
+//! resulting computer:
+//!
+//! ```
+//! use z33_emulator::{runtime::Timer, Emulator};
+//!
+//! let mut emulator = Emulator::builder()
+//!     .source("main: ld VALUE, %a\n    reset\n")
+//!     .define("VALUE", "42")
+//!     .device(Timer::new(100))
+//!     .entrypoint("main")
+//!     .build()
+//!     .unwrap();
+//!
+//! emulator.run().unwrap();
+//! ```
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::compiler::{compile_with, CompilationError, DebugInfo, MemoryScheme};
+use crate::constants::Address;
+use crate::parser::location::{AbsoluteLocation, RelativeLocation};
+use crate::parser::{parse, SyntaxError};
+use crate::preprocessor::{InMemoryFilesystem, Preprocessor, PreprocessorError};
+use crate::runtime::{ArgsError, Computer, Device, PortConflict, ProcessorError};
+
+/// Name of the file holding the source given to [`EmulatorBuilder::source`]
+const MAIN_FILE: &str = "main.S";
+
+#[derive(Debug, Error)]
+pub enum BuildError {
+    #[error("no source given")]
+    MissingSource,
+
+    #[error("could not preprocess the source")]
+    Preprocess(#[from] PreprocessorError<AbsoluteLocation<PathBuf>>),
+
+    #[error("could not parse the source")]
+    Parse(#[from] SyntaxError),
+
+    #[error("could not compile the program")]
+    Compile(#[from] CompilationError<RelativeLocation>),
+
+    #[error("could not attach device")]
+    Device(#[from] PortConflict),
+
+    #[error("could not load the program arguments")]
+    Args(#[from] ArgsError),
+}
+
+/// Builder for an [`Emulator`], created with [`Emulator::builder`]
+pub struct EmulatorBuilder {
+    files: HashMap<PathBuf, String>,
+    main: Option<PathBuf>,
+    definitions: Vec<(String, Option<String>)>,
+    devices: Vec<(Option<Address>, Box<dyn Device>)>,
+    entrypoint: String,
+    scheme: MemoryScheme,
+    args: Vec<String>,
+}
+
+impl Default for EmulatorBuilder {
+    fn default() -> Self {
+        Self {
+            files: HashMap::new(),
+            main: None,
+            definitions: Vec::new(),
+            devices: Vec::new(),
+            entrypoint: "main".to_owned(),
+            scheme: MemoryScheme::default(),
+            args: Vec::new(),
+        }
+    }
+}
+
+impl EmulatorBuilder {
+    /// Set the source of the program
+    #[must_use]
+    pub fn source(self, source: impl Into<String>) -> Self {
+        self.main_file(MAIN_FILE, source)
+    }
+
+    /// Set the source of the program, with the path used to resolve its includes
+    #[must_use]
+    pub fn main_file(mut self, path: impl AsRef<Path>, source: impl Into<String>) -> Self {
+        let path = path.as_ref().to_path_buf();
+        self.files.insert(path.clone(), source.into());
+        self.main = Some(path);
+        self
+    }
+
+    /// Add a file which can be included by the program
+    #[must_use]
+    pub fn file(mut self, path: impl AsRef<Path>, source: impl Into<String>) -> Self {
+        self.files
+            .insert(path.as_ref().to_path_buf(), source.into());
+        self
+    }
+
+    /// Define a preprocessor macro
+    #[must_use]
+    pub fn define(mut self, key: impl Into<String>, content: impl Into<String>) -> Self {
+        self.definitions.push((key.into(), Some(content.into())));
+        self
+    }
+
+    /// Attach a device, on the ports following the previously attached ones
+    #[must_use]
+    pub fn device<D: Device + 'static>(mut self, device: D) -> Self {
+        self.devices.push((None, Box::new(device)));
+        self
+    }
+
+    /// Attach a device on ports starting at the given base address
+    #[must_use]
+    pub fn device_at<D: Device + 'static>(mut self, base: Address, device: D) -> Self {
+        self.devices.push((Some(base), Box::new(device)));
+        self
+    }
+
+    /// Set the label where the program starts. Defaults to `main`
+    #[must_use]
+    pub fn entrypoint(mut self, entrypoint: impl Into<String>) -> Self {
+        self.entrypoint = entrypoint.into();
+        self
+    }
+
+    /// Set where the `.text`, `.data` and `.bss` sections are placed
+    #[must_use]
+    pub fn memory_scheme(mut self, scheme: MemoryScheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    /// Set the arguments passed to the program
+    #[must_use]
+    pub fn args<S: Into<String>>(mut self, args: impl IntoIterator<Item = S>) -> Self {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Preprocess, parse and compile the program, and attach the devices
+    pub fn build(self) -> Result<Emulator, BuildError> {
+        let main = self.main.ok_or(BuildError::MissingSource)?;
+        let mut preprocessor = Preprocessor::new(InMemoryFilesystem::new(self.files));
+        for (key, content) in self.definitions {
+            preprocessor.define(key, content);
+        }
+        preprocessor.load(&main);
+        let source = preprocessor.preprocess(&main)?;
+
+        let program = parse(&source)?;
+        let (mut computer, debug_info) =
+            compile_with(program.inner, &self.entrypoint, &self.scheme)?;
+
+        if !self.args.is_empty() {
+            computer.set_args(&self.args)?;
+        }
+
+        for (base, device) in self.devices {
+            let base = base.unwrap_or_else(|| computer.devices.end());
+            computer.devices.attach(base, device)?;
+        }
+
+        Ok(Emulator {
+            source,
+            computer,
+            debug_info,
+        })
+    }
+}
+
+/// A compiled program, ready to run
+pub struct Emulator {
+    source: String,
+    computer: Computer,
+    debug_info: DebugInfo<RelativeLocation>,
+}
+
+impl Emulator {
+    #[must_use]
+    pub fn builder() -> EmulatorBuilder {
+        EmulatorBuilder::default()
+    }
+
+    /// The preprocessed source of the program
+    #[must_use]
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    #[must_use]
+    pub fn computer(&self) -> &Computer {
+        &self.computer
+    }
+
+    pub fn computer_mut(&mut self) -> &mut Computer {
+        &mut self.computer
+    }
+
+    #[must_use]
+    pub fn debug_info(&self) -> &DebugInfo<RelativeLocation> {
+        &self.debug_info
+    }
+
+    /// Get the address of a label
+    #[must_use]
+    pub fn label(&self, name: &str) -> Option<Address> {
+        self.debug_info.labels.get(name).copied()
+    }
+
+    /// Execute a single instruction
+    pub fn step(&mut self) -> Result<(), ProcessorError> {
+        self.computer.step()
+    }
+
+    /// Run the program until it resets
+    pub fn run(&mut self) -> Result<(), ProcessorError> {
+        self.computer.run()
+    }
+
+    #[must_use]
+    pub fn into_parts(self) -> (Computer, DebugInfo<RelativeLocation>) {
+        (self.computer, self.debug_info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{mailbox, Cell, Reg};
+
+    #[test]
+    fn builder_test() {
+        let (host, device) = mailbox(1);
+        let mut emulator = Emulator::builder()
+            .source("main: trap\n    in [PORT], %a\n    reset\n#include \"lib.S\"\n")
+            .file(
+                "lib.S",
+                ".addr 200\n    ld [101], %b\n    or 0x200, %b\n    st %b, [101]\n    rti\n",
+            )
+            .define("PORT", "2")
+            .device(crate::runtime::Timer::new(10))
+            .device(device)
+            .entrypoint("main")
+            .build()
+            .unwrap();
+
+        // The timer uses port 0, the mailbox starts at port 1, with its receive register on port 2
+        host.send(Cell::Word(42)).unwrap();
+        emulator.run().unwrap();
+        assert_eq!(emulator.computer().registers.get(&Reg::A), Cell::Word(42));
+        assert_eq!(emulator.label("main"), Some(1000));
+    }
+
+    #[test]
+    fn missing_source_test() {
+        assert!(matches!(
+            Emulator::builder().build(),
+            Err(BuildError::MissingSource)
+        ));
+    }
+}
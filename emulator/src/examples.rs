@@ -0,0 +1,99 @@
+//! Example programs bundled with the emulator, to try it without writing a program first
+//!
+//! The examples start at `main`. They run with a [`Timer`] attached on port [`TIMER_PORT`],
+//! stopped until the program sets its period, like the timer of the project configuration.
+
+use crate::constants::TIMER_PORT;
+use crate::emulator::EmulatorBuilder;
+use crate::runtime::Timer;
+use crate::Emulator;
+
+/// A bundled example program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Example {
+    pub name: &'static str,
+
+    /// What the example shows, in a single line
+    pub description: &'static str,
+    pub source: &'static str,
+}
+
+/// The bundled examples, from the simplest one
+pub const EXAMPLES: &[Example] = &[
+    Example {
+        name: "factorial",
+        description: "Recursive calls, following the stack convention of <std/stack.s>",
+        source: include_str!("examples/factorial.S"),
+    },
+    Example {
+        name: "reverse",
+        description: "Reversing a string in place, with pointers",
+        source: include_str!("examples/reverse.S"),
+    },
+    Example {
+        name: "counter",
+        description: "Counting the hardware interrupts raised by a timer",
+        source: include_str!("examples/counter.S"),
+    },
+    Example {
+        name: "scheduler",
+        description: "Two tasks sharing the processor, switched on timer interrupts",
+        source: include_str!("examples/scheduler.S"),
+    },
+];
+
+/// Find an example by its name
+#[must_use]
+pub fn example(name: &str) -> Option<&'static Example> {
+    EXAMPLES.iter().find(|example| example.name == name)
+}
+
+impl Example {
+    /// Name of the file holding the example, like `factorial.S`
+    #[must_use]
+    pub fn file_name(&self) -> String {
+        format!("{}.S", self.name)
+    }
+
+    /// Prepare an emulator running the example, which can be further configured
+    #[must_use]
+    pub fn builder(&self) -> EmulatorBuilder {
+        Emulator::builder()
+            .main_file(self.file_name(), self.source)
+            .device_at(TIMER_PORT, Timer::new(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{Cell, Reg};
+
+    #[test]
+    fn examples_test() {
+        let expected = [
+            ("factorial", Cell::Word(120), Cell::Empty),
+            // The codes of 'd' and 's', from "desserts"
+            ("reverse", Cell::Word(100), Cell::Word(115)),
+            ("counter", Cell::Word(5), Cell::Word(0)),
+        ];
+
+        for (name, a, b) in expected {
+            let mut emulator = example(name).unwrap().builder().build().unwrap();
+            emulator.run().unwrap();
+            let registers = &emulator.computer().registers;
+            assert_eq!(
+                (registers.get(&Reg::A), registers.get(&Reg::B)),
+                (a, b),
+                "{name}"
+            );
+        }
+
+        // Both tasks took turns
+        let mut emulator = example("scheduler").unwrap().builder().build().unwrap();
+        emulator.run().unwrap();
+        let registers = &emulator.computer().registers;
+        assert!(matches!(registers.a, Cell::Word(count) if count > 0));
+        assert!(matches!(registers.b, Cell::Word(count) if count >= 100));
+    }
+}
@@ -6,11 +6,17 @@
     clippy::missing_panics_doc
 )]
 
+mod arena;
 mod ast;
+pub mod codes;
 pub mod compiler;
 pub mod constants;
+pub mod emulator;
+pub mod examples;
 pub mod parser;
 pub mod preprocessor;
 pub mod runtime;
+#[cfg(test)]
+mod testing;
 
-pub use self::{compiler::compile, parser::parse};
+pub use self::{compiler::compile, emulator::Emulator, parser::parse};
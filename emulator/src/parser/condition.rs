@@ -9,10 +9,12 @@
 //! LogicalAnd       := LogicalExpr ('&&' LogicalExpr)*
 //! LogicalExpr      := Atom | '!' Atom
 //! Literal          := 'true' | 'false'
-//! Atom             := NumberComparison | '(' Condition ')' | 'defined(' Identifier ')' | Literal
+//! Atom             := NumberComparison | '(' Condition ')' | 'defined(' Identifier ')' | Literal | ConstExpr
 //! NumberComparison := ConstExpr ('==' | '!=' | '>=' | '>' | '<=' | '<') ConstExpr
 //! ```
 //!
+//! A lone `ConstExpr` is true if it does not evaluate to zero, like in C.
+//!
 //! Note: to simplify a bit, it might accept some weird conditions.
 //! For example, `!4 > 3` is evaluated like `!(4 > 3)`.
 
@@ -20,8 +22,9 @@ use nom::{
     branch::alt,
     bytes::complete::tag,
     bytes::complete::tag_no_case,
-    character::complete::{char, space0},
-    combinator::{cut, map, opt, value},
+    character::complete::{char, satisfy, space0},
+    combinator::{cut, map, not, opt, value},
+    sequence::terminated,
     IResult, Offset,
 };
 use thiserror::Error;
@@ -29,13 +32,13 @@ use thiserror::Error;
 use super::literal::parse_bool_literal;
 use super::{
     expression::{
-        parse_expression, Context as ExpressionContext, EmptyContext as EmptyExpressionContext,
+        parse_expression, Context as ExpressionContext,
         EvaluationError as ExpressionEvaluationError, Node as ENode,
     },
     location::{Locatable, Located, MapLocation, RelativeLocation},
     precedence::Precedence,
 };
-use super::{parse_identifier, ParseError};
+use super::{is_identifier_char, parse_identifier, ParseError};
 
 type ChildNode<L> = Located<Box<Node<L>>, L>;
 type ExpressionNode<L> = Located<ENode<L>, L>;
@@ -74,6 +77,9 @@ pub(crate) enum Node<L> {
 
     /// defined(N)
     Defined(Located<String, L>),
+
+    /// a, true if not zero
+    Expression(ExpressionNode<L>),
 }
 
 impl<P, L> MapLocation<P> for Node<L>
@@ -106,6 +112,7 @@ where
                 inner,
                 location: location.map_location(parent),
             }),
+            Node::Expression(a) => Node::Expression(a.map_location(parent)),
         }
     }
 }
@@ -164,6 +171,7 @@ impl<L> std::fmt::Display for Node<L> {
             Node::Not(a) => write!(f, "!{}", a.inner.with_parent(self)),
             Node::Literal(a) => write!(f, "{a}"),
             Node::Defined(a) => write!(f, "defined({})", a.inner),
+            Node::Expression(a) => write!(f, "{}", a.inner),
         }
     }
 }
@@ -182,13 +190,14 @@ impl Node<RelativeLocation> {
             Node::Not(a) => Node::Not(a.offset(offset)),
             Node::Literal(a) => Node::Literal(a),
             Node::Defined(a) => Node::Defined(a.offset(offset)),
+            Node::Expression(a) => Node::Expression(a.offset(offset)),
         }
     }
 }
 
 #[derive(Error, Debug, PartialEq)]
 pub enum EvaluationError<L> {
-    #[error("could not evaluate expression")]
+    #[error("{}", .source.innermost())]
     ExpressionEvaluation {
         location: L,
         source: ExpressionEvaluationError<L>,
@@ -196,9 +205,13 @@ pub enum EvaluationError<L> {
 }
 
 impl<L> EvaluationError<L> {
+    /// Location of the innermost sub-expression which failed to evaluate, or of the whole
+    /// condition
     pub fn location(&self) -> &L {
         match self {
-            EvaluationError::ExpressionEvaluation { location, .. } => location,
+            EvaluationError::ExpressionEvaluation { location, source } => {
+                source.location().unwrap_or(location)
+            }
         }
     }
 }
@@ -216,59 +229,49 @@ pub trait Context {
     fn get_expression_context(&self) -> &Self::ExpressionContext;
 }
 
-/// An empty context that has no variable defined
-pub(crate) struct EmptyContext;
-impl Context for EmptyContext {
-    type ExpressionContext = EmptyExpressionContext;
-
-    fn get_expression_context(&self) -> &Self::ExpressionContext {
-        &EmptyExpressionContext
-    }
-}
-
 impl<L: Clone> Node<L> {
     /// Evaluate a condition AST node with a given context
     pub fn evaluate<C: Context>(&self, context: &C) -> Result<bool, ExpressionEvaluationError<L>> {
         let value = match self {
             Node::Equal(a, b) => {
                 let context = context.get_expression_context();
-                let a: i128 = a.inner.evaluate(context)?;
-                let b: i128 = b.inner.evaluate(context)?;
+                let a: i128 = a.evaluate(context)?;
+                let b: i128 = b.evaluate(context)?;
                 a == b
             }
 
             Node::NotEqual(a, b) => {
                 let context = context.get_expression_context();
-                let a: i128 = a.inner.evaluate(context)?;
-                let b: i128 = b.inner.evaluate(context)?;
+                let a: i128 = a.evaluate(context)?;
+                let b: i128 = b.evaluate(context)?;
                 a != b
             }
 
             Node::GreaterOrEqual(a, b) => {
                 let context = context.get_expression_context();
-                let a: i128 = a.inner.evaluate(context)?;
-                let b: i128 = b.inner.evaluate(context)?;
+                let a: i128 = a.evaluate(context)?;
+                let b: i128 = b.evaluate(context)?;
                 a >= b
             }
 
             Node::GreaterThan(a, b) => {
                 let context = context.get_expression_context();
-                let a: i128 = a.inner.evaluate(context)?;
-                let b: i128 = b.inner.evaluate(context)?;
+                let a: i128 = a.evaluate(context)?;
+                let b: i128 = b.evaluate(context)?;
                 a > b
             }
 
             Node::LesserOrEqual(a, b) => {
                 let context = context.get_expression_context();
-                let a: i128 = a.inner.evaluate(context)?;
-                let b: i128 = b.inner.evaluate(context)?;
+                let a: i128 = a.evaluate(context)?;
+                let b: i128 = b.evaluate(context)?;
                 a <= b
             }
 
             Node::LesserThan(a, b) => {
                 let context = context.get_expression_context();
-                let a: i128 = a.inner.evaluate(context)?;
-                let b: i128 = b.inner.evaluate(context)?;
+                let a: i128 = a.evaluate(context)?;
+                let b: i128 = b.evaluate(context)?;
                 a < b
             }
 
@@ -292,6 +295,11 @@ impl<L: Clone> Node<L> {
             Node::Literal(l) => *l,
 
             Node::Defined(v) => context.is_defined(&v.inner),
+
+            Node::Expression(a) => {
+                let a: i128 = a.evaluate(context.get_expression_context())?;
+                a != 0
+            }
         };
 
         Ok(value)
@@ -417,15 +425,27 @@ fn parse_atom<'a, Error: ParseError<&'a str>>(
     let (input, _) = space0(input)?; // TODO: why does this eat leading spaces
 
     // Order is important here. Since in numerical expressions, opening parenthesis, bool literals
-    // and the `defined` keyword would get parsed, number comparison need to be last
+    // and the `defined` keyword would get parsed, number comparison need to be last, followed by
+    // lone expressions
     alt((
         parse_parenthesis,
         map(parse_defined, Node::Defined),
-        map(parse_bool_literal, Node::Literal),
+        map(
+            terminated(parse_bool_literal, not(satisfy(is_identifier_char))),
+            Node::Literal,
+        ),
         parse_number_comparison,
+        map(parse_lone_expression, Node::Expression),
     ))(input)
 }
 
+fn parse_lone_expression<'a, Error: ParseError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, ExpressionNode<RelativeLocation>, Error> {
+    let (rest, node) = parse_expression(input)?;
+    Ok((rest, node.with_location((0, input.offset(rest)))))
+}
+
 fn parse_number_comparison<'a, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Node<RelativeLocation>, Error> {
@@ -518,6 +538,17 @@ mod tests {
     use pretty_assertions::assert_eq;
 
     use super::*;
+    use crate::parser::expression::EmptyContext as EmptyExpressionContext;
+
+    /// An empty context that has no variable defined
+    struct EmptyContext;
+    impl Context for EmptyContext {
+        type ExpressionContext = EmptyExpressionContext;
+
+        fn get_expression_context(&self) -> &Self::ExpressionContext {
+            &EmptyExpressionContext
+        }
+    }
 
     type R<T> = nom::IResult<&'static str, T, ()>;
 
@@ -692,8 +723,11 @@ mod tests {
                 E::Literal(10).with_location(())
             )
             .evaluate(ctx),
-            Err(ExpressionEvaluationError::UndefinedVariable {
-                variable: "undefined".into(),
+            Err(ExpressionEvaluationError::Expression {
+                location: (),
+                inner: Box::new(ExpressionEvaluationError::UndefinedVariable {
+                    variable: "undefined".into(),
+                }),
             })
         );
     }
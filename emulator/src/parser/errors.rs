@@ -1,5 +1,13 @@
 use std::num::ParseIntError;
 
+use nom::{
+    error::{VerboseError, VerboseErrorKind},
+    Offset,
+};
+use thiserror::Error;
+
+use super::line::{diagnose_line, suggest_fixes};
+
 pub trait ParseError<I>:
     nom::error::ParseError<I>
     + nom::error::FromExternalError<I, ParseIntError>
@@ -95,3 +103,156 @@ impl<I: std::fmt::Debug> Error<I> {
         write!(f, "{self:#?}")
     }
 }
+
+/// Kind of a syntax error entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyntaxErrorKind {
+    /// The parser was in the given context
+    Context(&'static str),
+
+    /// A specific character was expected
+    Char(char),
+
+    /// An error from one of the underlying parsers
+    Nom(nom::error::ErrorKind),
+
+    /// The mnemonic of an instruction is not known, with the closest known one
+    UnknownInstruction {
+        mnemonic: String,
+        suggestion: Option<&'static str>,
+    },
+
+    /// An argument of an instruction could not be parsed, starting from 0
+    InvalidArgument {
+        mnemonic: &'static str,
+        index: usize,
+    },
+
+    /// A character outside of ASCII, which is only allowed in strings and comments
+    NonAsciiCharacter(char),
+}
+
+impl std::fmt::Display for SyntaxErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyntaxErrorKind::Context(ctx) => write!(f, "{ctx}"),
+            SyntaxErrorKind::Char(c) => write!(f, "expected '{c}'"),
+            SyntaxErrorKind::Nom(kind) => write!(f, "{kind:?}"),
+            SyntaxErrorKind::UnknownInstruction {
+                mnemonic,
+                suggestion: Some(suggestion),
+            } => write!(
+                f,
+                "unknown instruction \"{mnemonic}\", did you mean \"{suggestion}\"?"
+            ),
+            SyntaxErrorKind::UnknownInstruction {
+                mnemonic,
+                suggestion: None,
+            } => write!(f, "unknown instruction \"{mnemonic}\""),
+            SyntaxErrorKind::InvalidArgument { mnemonic, index } => {
+                write!(f, "invalid argument {} of \"{mnemonic}\"", index + 1)
+            }
+            SyntaxErrorKind::NonAsciiCharacter(c) => write!(
+                f,
+                "unexpected character '{c}' (U+{:04X}), only allowed in strings and comments",
+                u32::from(*c)
+            ),
+        }
+    }
+}
+
+impl From<VerboseErrorKind> for SyntaxErrorKind {
+    fn from(kind: VerboseErrorKind) -> Self {
+        match kind {
+            VerboseErrorKind::Context(ctx) => Self::Context(ctx),
+            VerboseErrorKind::Char(c) => Self::Char(c),
+            VerboseErrorKind::Nom(kind) => Self::Nom(kind),
+        }
+    }
+}
+
+/// A replacement fixing a common mistake on the line of a syntax error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    /// Byte offset of the replaced text in the input
+    pub offset: usize,
+    pub length: usize,
+    pub replacement: String,
+
+    /// What the mistake is, like "registers are written with a `%`"
+    pub message: &'static str,
+}
+
+impl std::fmt::Display for Suggestion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: `{}`", self.message, self.replacement)
+    }
+}
+
+/// A syntax error which does not borrow the parsed input
+///
+/// Each entry holds the byte offset in the input where the error happened, from the innermost to
+/// the outermost parser.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("syntax error{}", self.diagnosis().map(|kind| format!(": {kind}")).unwrap_or_default())]
+pub struct SyntaxError {
+    pub errors: Vec<(usize, SyntaxErrorKind)>,
+
+    /// Fixes for the mistakes recognized on the failing line
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl SyntaxError {
+    /// Detach a nom error from the input it was produced from
+    ///
+    /// When the error happened on an instruction, a more precise error is looked for on its line
+    /// and added as the innermost one, along with fixes for common mistakes.
+    pub(crate) fn from_verbose(input: &str, error: VerboseError<&str>) -> Self {
+        let mut errors: Vec<_> = error
+            .errors
+            .into_iter()
+            .map(|(location, kind)| (input.offset(location), kind.into()))
+            .collect();
+
+        let mut suggestions = Vec::new();
+        if let Some(&(offset, _)) = errors.first() {
+            let start = input[..offset].rfind('\n').map_or(0, |i| i + 1);
+            let end = input[offset..]
+                .find('\n')
+                .map_or(input.len(), |i| offset + i);
+            let line = &input[start..end];
+            if let Some((position, kind)) = diagnose_line(line) {
+                errors.insert(0, (start + position, kind));
+            }
+
+            suggestions = suggest_fixes(line);
+            for suggestion in &mut suggestions {
+                suggestion.offset += start;
+            }
+        }
+
+        Self {
+            errors,
+            suggestions,
+        }
+    }
+
+    /// The precise error found on the failing instruction, if any
+    #[must_use]
+    pub fn diagnosis(&self) -> Option<&SyntaxErrorKind> {
+        self.errors.first().map(|(_, kind)| kind).filter(|kind| {
+            matches!(
+                kind,
+                SyntaxErrorKind::UnknownInstruction { .. }
+                    | SyntaxErrorKind::InvalidArgument { .. }
+                    | SyntaxErrorKind::NonAsciiCharacter(_)
+            )
+        })
+    }
+
+    /// The offset of the innermost error
+    #[must_use]
+    pub fn offset(&self) -> Option<usize> {
+        self.errors.first().map(|(offset, _)| *offset)
+    }
+}
@@ -12,9 +12,12 @@
 //! Sum     := Mul ('+' Mul | '-' Mul)*
 //! Mul     := Unary ('*' Unary | '/' Unary)*
 //! Unary   := Expr | '-' Expr | '~' Expr
-//! Expr    := Literal | '(' ConstExpr ')'
+//! Expr    := Literal | Variable | '(' ConstExpr ')'
 //! ```
 //!
+//! Variables are labels in the assembly, and can also be registers (`%a`) or memory cells
+//! (`[counter + 1]`) in the expressions evaluated at runtime by the debugger.
+//!
 //! All the calculation is done with the [`Value`](type.Value.html) type, then converted down using the
 //! `TryFrom` trait.
 
@@ -24,19 +27,21 @@ use nom::{
     branch::alt,
     bytes::complete::tag,
     character::complete::{char, space0},
-    combinator::{cut, map, not, opt, value},
+    combinator::{cut, map, not, opt, recognize, value},
     error::context,
     IResult, Offset,
 };
 use thiserror::Error;
 
 use crate::ast::{AstNode, NodeKind};
+use crate::constants::Word;
 
 use super::{
     literal::parse_number_literal,
     location::{Locatable, Located, MapLocation, RelativeLocation},
     parse_identifier,
     precedence::Precedence,
+    value::parse_register,
     ParseError,
 };
 
@@ -79,6 +84,9 @@ pub enum Node<L = RelativeLocation> {
 
     /// A reference to a variable
     Variable(String),
+
+    /// [a], the memory cell at an address, in the expressions evaluated at runtime
+    Memory(ChildNode<L>),
 }
 
 impl<P, L> MapLocation<P> for Node<L>
@@ -109,6 +117,7 @@ where
             Node::BinaryNot(a) => Node::BinaryNot(a.map_location(parent)),
             Node::Literal(a) => Node::Literal(a),
             Node::Variable(a) => Node::Variable(a),
+            Node::Memory(a) => Node::Memory(a.map_location(parent)),
         }
     }
 }
@@ -128,6 +137,7 @@ impl<L: Clone> AstNode<L> for Node<L> {
             Node::BinaryNot(_) => NodeKind::ExpressionBinaryNot,
             Node::Literal(_) => NodeKind::ExpressionLiteral,
             Node::Variable(_) => NodeKind::ExpressionVariable,
+            Node::Memory(_) => NodeKind::ExpressionMemory,
         }
     }
 
@@ -141,7 +151,7 @@ impl<L: Clone> AstNode<L> for Node<L> {
             | Node::Substract(a, b)
             | Node::Multiply(a, b)
             | Node::Divide(a, b) => vec![a.to_node(), b.to_node()],
-            Node::Invert(a) | Node::BinaryNot(a) => vec![a.to_node()],
+            Node::Invert(a) | Node::BinaryNot(a) | Node::Memory(a) => vec![a.to_node()],
             Node::Variable(_) | Node::Literal(_) => Vec::new(),
         }
     }
@@ -155,13 +165,46 @@ impl<L: Clone> AstNode<L> for Node<L> {
     }
 }
 
+impl<L> Node<L> {
+    /// Names of the variables referenced in the expression
+    pub(crate) fn variables(&self) -> Vec<&str> {
+        match self {
+            Node::BinaryOr(a, b)
+            | Node::BinaryAnd(a, b)
+            | Node::LeftShift(a, b)
+            | Node::RightShift(a, b)
+            | Node::Sum(a, b)
+            | Node::Substract(a, b)
+            | Node::Multiply(a, b)
+            | Node::Divide(a, b) => {
+                let mut variables = a.inner.variables();
+                variables.extend(b.inner.variables());
+                variables
+            }
+            Node::Invert(a) | Node::BinaryNot(a) | Node::Memory(a) => a.inner.variables(),
+            Node::Literal(_) => Vec::new(),
+            Node::Variable(v) => vec![v.as_str()],
+        }
+    }
+}
+
 impl<L> std::fmt::Display for Node<L> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if f.sign_plus() {
-            // Special case for indexed arguments
+            // Special case for indexed arguments, where a leading minus replaces the plus. Being
+            // its own parent, the node is displayed without parenthesis
+            let expression = self.with_parent(self).to_string();
+            match expression.strip_prefix('-') {
+                Some(rest) => write!(f, "- {rest}"),
+                None => write!(f, "+ {expression}"),
+            }
+        } else if f.alternate() {
+            // Special case for the scales of indexed arguments, which are atoms
             match self {
-                Node::Invert(a) => write!(f, "- {}", a.inner),
-                n => write!(f, "+ {n}"),
+                Node::Literal(a) => write!(f, "{a}"),
+                Node::Variable(a) => write!(f, "{a}"),
+                Node::Memory(a) => write!(f, "[{}]", a.inner),
+                n => write!(f, "({n})"),
             }
         } else {
             match self {
@@ -169,54 +212,55 @@ impl<L> std::fmt::Display for Node<L> {
                     f,
                     "{} | {}",
                     a.inner.with_parent(self),
-                    b.inner.with_parent(self)
+                    b.inner.with_strict_parent(self)
                 ),
                 Node::BinaryAnd(a, b) => write!(
                     f,
                     "{} & {}",
                     a.inner.with_parent(self),
-                    b.inner.with_parent(self)
+                    b.inner.with_strict_parent(self)
                 ),
                 Node::LeftShift(a, b) => write!(
                     f,
                     "{} << {}",
-                    a.inner.with_parent(self),
-                    b.inner.with_parent(self)
+                    a.inner.with_strict_parent(self),
+                    b.inner.with_strict_parent(self)
                 ),
                 Node::RightShift(a, b) => write!(
                     f,
                     "{} >> {}",
-                    a.inner.with_parent(self),
-                    b.inner.with_parent(self)
+                    a.inner.with_strict_parent(self),
+                    b.inner.with_strict_parent(self)
                 ),
                 Node::Sum(a, b) => write!(
                     f,
                     "{} + {}",
                     a.inner.with_parent(self),
-                    b.inner.with_parent(self)
+                    b.inner.with_strict_parent(self)
                 ),
                 Node::Substract(a, b) => write!(
                     f,
                     "{} - {}",
                     a.inner.with_parent(self),
-                    b.inner.with_parent(self)
+                    b.inner.with_strict_parent(self)
                 ),
                 Node::Multiply(a, b) => write!(
                     f,
                     "{} * {}",
                     a.inner.with_parent(self),
-                    b.inner.with_parent(self)
+                    b.inner.with_strict_parent(self)
                 ),
                 Node::Divide(a, b) => write!(
                     f,
                     "{} / {}",
                     a.inner.with_parent(self),
-                    b.inner.with_parent(self)
+                    b.inner.with_strict_parent(self)
                 ),
-                Node::Invert(a) => write!(f, "-{}", a.inner.with_parent(self)),
-                Node::BinaryNot(a) => write!(f, "~{}", a.inner.with_parent(self)),
+                Node::Invert(a) => write!(f, "-{}", a.inner.with_strict_parent(self)),
+                Node::BinaryNot(a) => write!(f, "~{}", a.inner.with_strict_parent(self)),
                 Node::Literal(a) => write!(f, "{a}"),
                 Node::Variable(a) => write!(f, "{a}"),
+                Node::Memory(a) => write!(f, "[{}]", a.inner),
             }
         }
     }
@@ -237,6 +281,7 @@ impl Node<RelativeLocation> {
             Node::BinaryNot(a) => Node::BinaryNot(a.offset(offset)),
             Node::Literal(a) => Node::Literal(a),
             Node::Variable(a) => Node::Variable(a),
+            Node::Memory(a) => Node::Memory(a.offset(offset)),
         }
     }
 }
@@ -244,6 +289,11 @@ impl Node<RelativeLocation> {
 pub trait Context {
     // TODO: use something else than Value
     fn resolve_variable(&self, variable: &str) -> Option<Value>;
+
+    /// Value of the memory cell at an address, only known to the expressions evaluated at runtime
+    fn resolve_memory(&self, _address: Value) -> Option<Value> {
+        None
+    }
 }
 
 pub(crate) struct EmptyContext;
@@ -258,6 +308,9 @@ pub enum EvaluationError<L> {
     #[error("undefined variable {variable:?}")]
     UndefinedVariable { variable: String },
 
+    #[error("no word in memory at address {address}")]
+    InvalidMemory { address: Value },
+
     #[error("could not downcast value")]
     Downcast,
 
@@ -270,13 +323,31 @@ pub enum EvaluationError<L> {
     #[error("divide by zero")]
     DivByZero,
 
-    #[error("evaluation")]
+    #[error("{}", .inner.innermost())]
     Expression {
         location: L,
         inner: Box<EvaluationError<L>>,
     },
 }
 
+impl<L> EvaluationError<L> {
+    /// The error of the innermost sub-expression which failed to evaluate
+    pub fn innermost(&self) -> &Self {
+        match self {
+            Self::Expression { inner, .. } => inner.innermost(),
+            _ => self,
+        }
+    }
+
+    /// Location of the innermost sub-expression which failed to evaluate, if known
+    pub fn location(&self) -> Option<&L> {
+        match self {
+            Self::Expression { location, inner } => inner.location().or(Some(location)),
+            _ => None,
+        }
+    }
+}
+
 impl<L: Clone> Node<L> {
     pub fn evaluate<C: Context, V: TryFrom<Value>>(
         &self,
@@ -340,17 +411,14 @@ impl<L: Clone> Node<L> {
                 }
 
                 Node::BinaryNot(operand) => {
-                    let _operand: Value = operand.inner.evaluate(context)?;
-                    // TODO: bit inversion is tricky because we're not supposed to know the word length
-                    // here. It's a bit opiniated, but for now it tries casting down to u16 before
-                    // negating.
-
-                    /*
-                    u16::try_from(v) // try casting it down to u16
-                        .map(|v| !v) // invert the bits
-                        .map(|v| v as _) // cast it back up
-                    */
-                    todo!()
+                    let operand: Value = operand.evaluate(context)?;
+                    // The bits of the word are inverted, the operand being either signed or
+                    // unsigned. Words are sign-extended, so this is also right for the machines
+                    // with narrower words once the result is truncated to their width
+                    let word = Word::try_from(operand)
+                        .or_else(|_| u64::try_from(operand).map(u64::cast_signed))
+                        .map_err(|_| EvaluationError::Overflow)?;
+                    Value::from(!word)
                 }
 
                 Node::Literal(value) => *value,
@@ -360,6 +428,13 @@ impl<L: Clone> Node<L> {
                         variable: variable.clone(),
                     },
                 )?,
+
+                Node::Memory(address) => {
+                    let address: Value = address.evaluate(context)?;
+                    context
+                        .resolve_memory(address)
+                        .ok_or(EvaluationError::InvalidMemory { address })?
+                }
             };
 
         V::try_from(value).map_err(|_| EvaluationError::Downcast)
@@ -380,11 +455,25 @@ impl<L: Clone> ChildNode<L> {
     }
 }
 
+impl<L: Clone> Located<Node<L>, L> {
+    pub fn evaluate<C: Context, V: TryFrom<Value>>(
+        &self,
+        context: &C,
+    ) -> Result<V, EvaluationError<L>> {
+        self.inner
+            .evaluate(context)
+            .map_err(|source| EvaluationError::Expression {
+                location: self.location.clone(),
+                inner: Box::new(source),
+            })
+    }
+}
+
 /// The type of value used throughout the calculation
 pub type Value = i128;
 
 #[doc(hidden)]
-fn parse_or_rec<'a, Error: ParseError<&'a str>>(
+fn parse_or_rec<'a, V: Variables, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, ChildNode<RelativeLocation>, Error> {
     let (rest, _) = space0(input)?;
@@ -395,19 +484,19 @@ fn parse_or_rec<'a, Error: ParseError<&'a str>>(
 
     cut(move |rest: &'a str| {
         let start = rest;
-        let (rest, node) = parse_and(rest)?;
+        let (rest, node) = parse_and::<V, _>(rest)?;
         let node = Box::new(node).with_location((input.offset(start), start.offset(rest)));
         Ok((rest, node))
     })(rest)
 }
 
 /// Parse a bitwise "or" operation
-fn parse_or<'a, Error: ParseError<&'a str>>(
+fn parse_or<'a, V: Variables, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Node<RelativeLocation>, Error> {
-    let (mut cursor, mut node) = parse_and(input)?;
+    let (mut cursor, mut node) = parse_and::<V, _>(input)?;
 
-    while let (rest, Some(right)) = opt(parse_or_rec)(cursor)? {
+    while let (rest, Some(right)) = opt(parse_or_rec::<V, _>)(cursor)? {
         let offset = input.offset(cursor);
         // Wrap the "left" node with location information
         let left = Box::new(node).with_location((0, offset));
@@ -424,7 +513,7 @@ fn parse_or<'a, Error: ParseError<&'a str>>(
 }
 
 #[doc(hidden)]
-fn parse_and_rec<'a, Error: ParseError<&'a str>>(
+fn parse_and_rec<'a, V: Variables, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, ChildNode<RelativeLocation>, Error> {
     let (rest, _) = space0(input)?;
@@ -435,19 +524,19 @@ fn parse_and_rec<'a, Error: ParseError<&'a str>>(
 
     cut(move |rest: &'a str| {
         let start = rest;
-        let (rest, node) = parse_shift(rest)?;
+        let (rest, node) = parse_shift::<V, _>(rest)?;
         let node = Box::new(node).with_location((input.offset(start), start.offset(rest)));
         Ok((rest, node))
     })(rest)
 }
 
 /// Parse a bitwise "and" operation
-fn parse_and<'a, Error: ParseError<&'a str>>(
+fn parse_and<'a, V: Variables, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Node<RelativeLocation>, Error> {
-    let (mut cursor, mut node) = parse_shift(input)?;
+    let (mut cursor, mut node) = parse_shift::<V, _>(input)?;
 
-    while let (rest, Some(right)) = opt(parse_and_rec)(cursor)? {
+    while let (rest, Some(right)) = opt(parse_and_rec::<V, _>)(cursor)? {
         let offset = input.offset(cursor);
         // Wrap the "left" node with location information
         let left = Box::new(node).with_location((0, offset));
@@ -473,7 +562,7 @@ enum ShiftOp {
 }
 
 #[doc(hidden)]
-fn parse_shift_rec<'a, Error: ParseError<&'a str>>(
+fn parse_shift_rec<'a, V: Variables, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, (ShiftOp, ChildNode<RelativeLocation>), Error> {
     let (rest, _) = space0(input)?;
@@ -485,19 +574,19 @@ fn parse_shift_rec<'a, Error: ParseError<&'a str>>(
 
     cut(move |rest: &'a str| {
         let start = rest;
-        let (rest, node) = parse_sum(rest)?;
+        let (rest, node) = parse_sum::<V, _>(rest)?;
         let node = Box::new(node).with_location((input, start, rest));
         Ok((rest, (op, node)))
     })(rest)
 }
 
 /// Parse a bitshift operation
-fn parse_shift<'a, Error: ParseError<&'a str>>(
+fn parse_shift<'a, V: Variables, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Node<RelativeLocation>, Error> {
-    let (mut cursor, mut node) = parse_sum(input)?;
+    let (mut cursor, mut node) = parse_sum::<V, _>(input)?;
 
-    if let (rest, Some((op, right))) = opt(parse_shift_rec)(cursor)? {
+    if let (rest, Some((op, right))) = opt(parse_shift_rec::<V, _>)(cursor)? {
         let offset = input.offset(cursor);
         // Wrap the "left" node with location information
         let left = Box::new(node).with_location((0, offset));
@@ -525,7 +614,7 @@ enum SumOp {
 }
 
 #[doc(hidden)]
-fn parse_sum_rec<'a, Error: ParseError<&'a str>>(
+fn parse_sum_rec<'a, V: Variables, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, (SumOp, ChildNode<RelativeLocation>), Error> {
     let (rest, _) = space0(input)?;
@@ -537,19 +626,19 @@ fn parse_sum_rec<'a, Error: ParseError<&'a str>>(
 
     cut(move |rest: &'a str| {
         let start = rest;
-        let (rest, node) = parse_mul(rest)?;
+        let (rest, node) = parse_mul::<V, _>(rest)?;
         let node = Box::new(node).with_location((input, start, rest));
         Ok((rest, (op, node)))
     })(rest)
 }
 
 /// Parse a sum/sub operation
-fn parse_sum<'a, Error: ParseError<&'a str>>(
+fn parse_sum<'a, V: Variables, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Node<RelativeLocation>, Error> {
-    let (mut cursor, mut node) = parse_mul(input)?;
+    let (mut cursor, mut node) = parse_mul::<V, _>(input)?;
 
-    while let (rest, Some((op, right))) = opt(parse_sum_rec)(cursor)? {
+    while let (rest, Some((op, right))) = opt(parse_sum_rec::<V, _>)(cursor)? {
         let offset = input.offset(cursor);
         // Wrap the "left" node with location information
         let left = Box::new(node).with_location((0, offset));
@@ -577,7 +666,7 @@ enum MulOp {
 }
 
 #[doc(hidden)]
-fn parse_mul_rec<'a, Error: ParseError<&'a str>>(
+fn parse_mul_rec<'a, V: Variables, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, (MulOp, ChildNode<RelativeLocation>), Error> {
     let (rest, _) = space0(input)?;
@@ -589,19 +678,19 @@ fn parse_mul_rec<'a, Error: ParseError<&'a str>>(
 
     cut(move |rest: &'a str| {
         let start = rest;
-        let (rest, node) = parse_unary(rest)?;
+        let (rest, node) = parse_unary::<V, _>(rest)?;
         let node = Box::new(node).with_location((input, start, rest));
         Ok((rest, (op, node)))
     })(rest)
 }
 
 /// Parse a multiply/divide operation
-fn parse_mul<'a, Error: ParseError<&'a str>>(
+fn parse_mul<'a, V: Variables, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Node<RelativeLocation>, Error> {
-    let (mut cursor, mut node) = parse_unary(input)?;
+    let (mut cursor, mut node) = parse_unary::<V, _>(input)?;
 
-    while let (rest, Some((op, right))) = opt(parse_mul_rec)(cursor)? {
+    while let (rest, Some((op, right))) = opt(parse_mul_rec::<V, _>)(cursor)? {
         let offset = input.offset(cursor);
         // Wrap the "left" node with location information
         let left = Box::new(node).with_location((0, offset));
@@ -621,7 +710,7 @@ fn parse_mul<'a, Error: ParseError<&'a str>>(
     Ok((cursor, node))
 }
 
-fn parse_invert<'a, Error: ParseError<&'a str>>(
+fn parse_invert<'a, V: Variables, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Node<RelativeLocation>, Error> {
     let (rest, _) = char('-')(input)?;
@@ -629,13 +718,13 @@ fn parse_invert<'a, Error: ParseError<&'a str>>(
 
     cut(move |rest: &'a str| {
         let start = rest;
-        let (rest, node) = parse_atom(rest)?;
+        let (rest, node) = parse_atom_with::<V, _>(rest)?;
         let node = Box::new(node).with_location((input, start, rest));
         Ok((rest, Node::Invert(node)))
     })(rest)
 }
 
-fn parse_binary_not<'a, Error: ParseError<&'a str>>(
+fn parse_binary_not<'a, V: Variables, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Node<RelativeLocation>, Error> {
     let (rest, _) = char('~')(input)?;
@@ -643,38 +732,36 @@ fn parse_binary_not<'a, Error: ParseError<&'a str>>(
 
     cut(move |rest: &'a str| {
         let start = rest;
-        let (rest, node) = parse_atom(rest)?;
+        let (rest, node) = parse_atom_with::<V, _>(rest)?;
         let node = Box::new(node).with_location((input, start, rest));
         Ok((rest, Node::BinaryNot(node)))
     })(rest)
 }
 
 /// Parse unary operations (negation and bit inversion)
-fn parse_unary<'a, Error: ParseError<&'a str>>(
+fn parse_unary<'a, V: Variables, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Node<RelativeLocation>, Error> {
-    alt((parse_invert, parse_binary_not, parse_atom))(input)
+    alt((
+        parse_invert::<V, _>,
+        parse_binary_not::<V, _>,
+        parse_atom_with::<V, _>,
+    ))(input)
 }
 
 /// Parse an atom of an expression: either a literal or a full expression within parenthesis
-fn parse_atom<'a, Error: ParseError<&'a str>>(
+fn parse_atom_with<'a, V: Variables, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Node<RelativeLocation>, Error> {
     alt((
-        context(
-            "number literal",
-            map(parse_number_literal, |v| Node::Literal(Value::from(v))),
-        ),
-        context(
-            "identifier",
-            map(parse_identifier, |i| Node::Variable(i.into())),
-        ),
-        parse_parenthesis,
+        context("number literal", map(parse_number_literal, Node::Literal)),
+        context("identifier", V::parse_variable_node),
+        parse_parenthesis::<V, _>,
     ))(input)
 }
 
 /// Parse an expression surrounded by parenthesis
-fn parse_parenthesis<'a, Error: ParseError<&'a str>>(
+fn parse_parenthesis<'a, V: Variables, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Node<RelativeLocation>, Error> {
     let (rest, _) = char('(')(input)?;
@@ -682,7 +769,7 @@ fn parse_parenthesis<'a, Error: ParseError<&'a str>>(
 
     cut(move |rest: &'a str| {
         let offset = input.offset(rest);
-        let (rest, value) = parse_expression(rest)?;
+        let (rest, value) = parse_or::<V, _>(rest)?;
         // This offsets the child nodes location to compensate the parenthesis
         let value = value.offset(offset);
 
@@ -692,11 +779,91 @@ fn parse_parenthesis<'a, Error: ParseError<&'a str>>(
     })(rest)
 }
 
+/// Parse an atom of an expression of the assembly
+pub(crate) fn parse_atom<'a, Error: ParseError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, Node<RelativeLocation>, Error> {
+    parse_atom_with::<Identifiers, _>(input)
+}
+
 /// Parse an expression, returning its AST
 pub fn parse_expression<'a, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Node<RelativeLocation>, Error> {
-    parse_or(input)
+    parse_or::<Identifiers, _>(input)
+}
+
+/// Parse an expression evaluated against the state of a running computer
+///
+/// Besides labels, its variables are registers, like `%a`, and memory cells, like
+/// `[counter + 1]`. They are resolved by the context the expression is evaluated with.
+pub fn parse_runtime_expression<'a, Error: ParseError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, Node<RelativeLocation>, Error> {
+    parse_or::<MachineVariables, _>(input)
+}
+
+/// What the variables of an expression look like
+pub(crate) trait Variables {
+    fn parse_variable<'a, Error: ParseError<&'a str>>(
+        input: &'a str,
+    ) -> IResult<&'a str, String, Error>;
+
+    /// Parse a variable as a node of the expression
+    fn parse_variable_node<'a, Error: ParseError<&'a str>>(
+        input: &'a str,
+    ) -> IResult<&'a str, Node<RelativeLocation>, Error> {
+        map(Self::parse_variable, Node::Variable)(input)
+    }
+}
+
+/// Variables of the expressions of the assembly: labels and macros
+pub(crate) struct Identifiers;
+
+impl Variables for Identifiers {
+    fn parse_variable<'a, Error: ParseError<&'a str>>(
+        input: &'a str,
+    ) -> IResult<&'a str, String, Error> {
+        map(parse_identifier, Into::into)(input)
+    }
+}
+
+/// Variables of the expressions evaluated at runtime: labels, registers like `%a`, and memory
+/// cells like `[%sp + 1]`, whose address is parsed as a sub-expression
+pub(crate) struct MachineVariables;
+
+impl Variables for MachineVariables {
+    fn parse_variable<'a, Error: ParseError<&'a str>>(
+        input: &'a str,
+    ) -> IResult<&'a str, String, Error> {
+        map(
+            alt((parse_identifier, recognize(parse_register))),
+            Into::into,
+        )(input)
+    }
+
+    fn parse_variable_node<'a, Error: ParseError<&'a str>>(
+        input: &'a str,
+    ) -> IResult<&'a str, Node<RelativeLocation>, Error> {
+        alt((parse_memory, map(Self::parse_variable, Node::Variable)))(input)
+    }
+}
+
+/// Parse the memory cell at an address, like `[counter + 1]`
+fn parse_memory<'a, Error: ParseError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, Node<RelativeLocation>, Error> {
+    let (rest, _) = char('[')(input)?;
+    let (rest, _) = space0(rest)?;
+
+    cut(move |rest: &'a str| {
+        let start = rest;
+        let (rest, node) = parse_or::<MachineVariables, _>(rest)?;
+        let node = Box::new(node).with_location((input, start, rest));
+        let (rest, _) = space0(rest)?;
+        let (rest, _) = char(']')(rest)?;
+        Ok((rest, Node::Memory(node)))
+    })(rest)
 }
 
 #[cfg(test)]
@@ -723,5 +890,35 @@ mod tests {
         assert_eq!(evaluate(parse_expression("0xF0>> 4")), 0x0F);
         assert_eq!(evaluate(parse_expression("0xAF & 0xF0")), 0xA0);
         assert_eq!(evaluate(parse_expression("0x0F | 0xF0")), 0xFF);
+        assert_eq!(evaluate(parse_expression("~0")), -1);
+        assert_eq!(evaluate(parse_expression("~0xFF")), -0x100);
+        assert_eq!(evaluate(parse_expression("~0xFFFFFFFFFFFFFFFF")), 0);
+    }
+
+    #[test]
+    fn runtime_expression_test() {
+        let (rest, node) = parse_runtime_expression::<()>("[ [counter] + %a ] * 2").unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(node.to_string(), "[[counter] + %a] * 2");
+        assert_eq!(node.variables(), vec!["counter", "%a"]);
+
+        let Node::Multiply(memory, _) = &node else {
+            panic!("expected a product, got {node:?}");
+        };
+        let Node::Memory(address) = &*memory.inner else {
+            panic!("expected a memory cell, got {:?}", memory.inner);
+        };
+        assert!(matches!(&*address.inner, Node::Sum(_, _)));
+
+        // Memory cells are only known at runtime
+        assert!(parse_expression::<()>("[counter]").is_err());
+        assert_eq!(
+            node.evaluate::<_, i128>(&EmptyContext)
+                .unwrap_err()
+                .innermost(),
+            &EvaluationError::UndefinedVariable {
+                variable: "counter".into()
+            }
+        );
     }
 }
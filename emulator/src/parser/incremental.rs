@@ -0,0 +1,368 @@
+//! Incremental parsing, for editor integrations
+//!
+//! A [`Document`] keeps the source of a program along with the result of parsing each of its
+//! lines. When the source is edited, only the lines touched by the edit are parsed again, and the
+//! lines after it are kept as-is, with their offsets shifted.
+
+use std::ops::Range;
+
+use nom::{
+    character::complete::line_ending,
+    error::{ErrorKind, VerboseError},
+    Finish, Offset,
+};
+
+use super::{
+    line::{parse_single_line, split_line, Line, Program},
+    location::{Locatable, Located, RelativeLocation},
+    Suggestion, SyntaxError, SyntaxErrorKind,
+};
+
+/// A line of the document, with the result of its parsing
+#[derive(Debug, Clone)]
+struct ParsedLine {
+    /// Span of the line in the source, excluding the line ending
+    span: Range<usize>,
+
+    /// Offset of the next line, if any
+    next: Option<usize>,
+
+    /// The parsed line, or the error with offsets relative to the start of the line
+    result: Result<Line<RelativeLocation>, SyntaxError>,
+}
+
+impl ParsedLine {
+    /// Split and parse the line starting at `start` in the source
+    fn parse(source: &str, start: usize) -> Self {
+        let input = &source[start..];
+        // Splitting a line can not fail, empty lines are matched as well
+        let (rest, line) = split_line::<()>(input).unwrap_or((input, ""));
+        let span = start..(start + line.len());
+        let next = match line_ending::<_, ()>(rest) {
+            Ok((rest, _)) => Some(start + input.offset(rest)),
+            Err(_) if rest.is_empty() => None,
+            Err(_) => {
+                // The rest of the source could not be split, e.g. because of a stray carriage
+                // return, so it is reported as a single erroneous line
+                let kind = SyntaxErrorKind::Nom(ErrorKind::Eof);
+                return Self {
+                    span: start..source.len(),
+                    next: None,
+                    result: Err(SyntaxError {
+                        errors: vec![(line.len(), kind)],
+                        suggestions: Vec::new(),
+                    }),
+                };
+            }
+        };
+
+        let result = parse_single_line::<VerboseError<&str>>(line)
+            .finish()
+            .map(|(_, line)| line)
+            .map_err(|e| SyntaxError::from_verbose(line, e));
+
+        Self { span, next, result }
+    }
+
+    fn shift(&mut self, delta: isize) {
+        let shift = |offset: usize| offset.checked_add_signed(delta).unwrap();
+        self.span = shift(self.span.start)..shift(self.span.end);
+        self.next = self.next.map(shift);
+    }
+}
+
+/// A program source which can be edited and re-parsed incrementally
+#[derive(Debug, Clone)]
+pub struct Document {
+    source: String,
+    lines: Vec<ParsedLine>,
+}
+
+impl Document {
+    /// Parse a whole source
+    #[must_use]
+    pub fn new(source: String) -> Self {
+        let mut document = Self {
+            source,
+            lines: Vec::new(),
+        };
+        document.lines = document.parse_from(0, None);
+        document
+    }
+
+    /// The current source of the document
+    #[must_use]
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Number of lines in the document
+    #[must_use]
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Replace the given byte range of the source with some text
+    ///
+    /// Returns the range of lines which were parsed again.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds or does not lie on char boundaries.
+    pub fn edit(&mut self, range: Range<usize>, text: &str) -> Range<usize> {
+        // Find the first and last lines touched by the edit, including their line endings: an
+        // edit between a carriage return and a line feed changes how the line is split
+        let first = self
+            .lines
+            .iter()
+            .position(|l| l.next.is_none_or(|next| next > range.start))
+            .unwrap_or(self.lines.len() - 1);
+        let last = self
+            .lines
+            .iter()
+            .rposition(|l| l.span.start <= range.end)
+            .unwrap_or(first)
+            .max(first);
+
+        let start = self.lines[first].span.start;
+        #[allow(clippy::cast_possible_wrap)]
+        let delta = text.len() as isize - range.len() as isize;
+        let edit_end = range.start + text.len();
+
+        self.source.replace_range(range, text);
+
+        // Lines after the edit which start at the same (shifted) offset can be reused as-is
+        let mut tail = self.lines.split_off(last + 1);
+        let resync = tail.iter().map(|l| l.span.start).collect::<Vec<_>>();
+        let lines = self.parse_from(start, Some((edit_end, delta, &resync)));
+
+        // The rest of the source could not be split after a stray carriage return: the old tail is
+        // dropped, and the whole source parsed again so that it is reported as with `parse`
+        let unsplit = lines
+            .last()
+            .is_some_and(|l| l.next.is_none() && self.source[l.span.clone()].contains('\r'));
+        if unsplit {
+            self.lines = self.parse_from(0, None);
+            return 0..self.lines.len();
+        }
+
+        let reparsed = first..(first + lines.len());
+        self.lines.truncate(first);
+        self.lines.extend(lines);
+
+        if let Some(next) = self.lines.last().and_then(|l| l.next) {
+            let index = resync.partition_point(|&s| s.checked_add_signed(delta) < Some(next));
+            let mut tail = tail.split_off(index);
+            for line in &mut tail {
+                line.shift(delta);
+            }
+            self.lines.extend(tail);
+        }
+
+        reparsed
+    }
+
+    /// Parse lines starting at `start`, until the end of the source or until a line boundary
+    /// matches one of the `resync` offsets, shifted by `delta`, after the end of the edit
+    fn parse_from(
+        &self,
+        start: usize,
+        resync: Option<(usize, isize, &[usize])>,
+    ) -> Vec<ParsedLine> {
+        let mut lines = Vec::new();
+        let mut cursor = start;
+        loop {
+            let line = ParsedLine::parse(&self.source, cursor);
+            let next = line.next;
+            lines.push(line);
+
+            let Some(next) = next else { break };
+            cursor = next;
+
+            if let Some((edit_end, delta, resync)) = resync {
+                let synced = next >= edit_end
+                    && next
+                        .checked_add_signed(-delta)
+                        .is_some_and(|old| resync.binary_search(&old).is_ok());
+                if synced {
+                    break;
+                }
+            }
+        }
+
+        lines
+    }
+
+    /// The syntax errors in the document, with offsets relative to the whole source
+    pub fn errors(&self) -> impl Iterator<Item = SyntaxError> + '_ {
+        self.lines.iter().filter_map(|line| {
+            let error = line.result.as_ref().err()?;
+            let errors = error
+                .errors
+                .iter()
+                .map(|(offset, kind)| (line.span.start + offset, kind.clone()))
+                .collect();
+            let suggestions = error
+                .suggestions
+                .iter()
+                .map(|suggestion| Suggestion {
+                    offset: line.span.start + suggestion.offset,
+                    ..suggestion.clone()
+                })
+                .collect();
+            Some(SyntaxError {
+                errors,
+                suggestions,
+            })
+        })
+    }
+
+    /// Build the program from the parsed lines
+    ///
+    /// This returns the first syntax error if any line failed to parse.
+    pub fn program(
+        &self,
+    ) -> Result<Located<Program<RelativeLocation>, RelativeLocation>, SyntaxError> {
+        if let Some(error) = self.errors().next() {
+            return Err(error);
+        }
+
+        let lines = self
+            .lines
+            .iter()
+            .filter_map(|line| {
+                let inner = line.result.as_ref().ok()?.clone();
+                Some(inner.with_location((line.span.start, line.span.len())))
+            })
+            .collect();
+
+        Ok(Program { lines }.with_location((0, self.source.len())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::testing::XorShift;
+
+    const SOURCE: &str = "main:\n    add %a, %b\n    ld [%a], %b\n\n    reset\n";
+
+    #[test]
+    fn initial_parse_test() {
+        let document = Document::new(SOURCE.to_owned());
+        assert_eq!(document.line_count(), 6);
+        assert_eq!(document.program().unwrap(), parse(SOURCE).unwrap());
+    }
+
+    #[test]
+    fn edit_single_line_test() {
+        let mut document = Document::new(SOURCE.to_owned());
+        let start = SOURCE.find("add").unwrap();
+        let reparsed = document.edit(start..(start + 3), "sub");
+
+        assert_eq!(reparsed, 1..2);
+        let expected = SOURCE.replace("add", "sub");
+        assert_eq!(document.source(), expected);
+        assert_eq!(document.program().unwrap(), parse(&expected).unwrap());
+    }
+
+    #[test]
+    fn edit_across_lines_test() {
+        let mut document = Document::new(SOURCE.to_owned());
+
+        // Insert a line, shifting all the following ones
+        let start = SOURCE.find("    ld").unwrap();
+        let reparsed = document.edit(start..start, "    push %a\n    pop %b\n");
+        assert_eq!(reparsed, 2..5);
+        assert_eq!(document.line_count(), 8);
+        assert_eq!(
+            document.program().unwrap(),
+            parse(document.source()).unwrap()
+        );
+
+        // Remove them again, joining lines
+        let end = start + "    push %a\n    pop %b\n".len();
+        document.edit(start..end, "");
+        assert_eq!(document.source(), SOURCE);
+        assert_eq!(document.program().unwrap(), parse(SOURCE).unwrap());
+    }
+
+    #[test]
+    fn edit_errors_test() {
+        let mut document = Document::new(SOURCE.to_owned());
+        let start = SOURCE.find("[%a]").unwrap();
+        document.edit(start..(start + 4), "[%a");
+
+        let errors: Vec<_> = document.errors().collect();
+        assert_eq!(errors.len(), 1);
+        let offset = errors[0].offset().unwrap();
+        assert!(offset >= start && offset <= SOURCE.find("\n\n").unwrap());
+        assert!(document.program().is_err());
+
+        document.edit(start..(start + 3), "[%a]");
+        assert_eq!(document.errors().count(), 0);
+        assert_eq!(document.program().unwrap(), parse(SOURCE).unwrap());
+    }
+
+    #[test]
+    fn edit_line_ending_test() {
+        // Inserting between a carriage return and a line feed leaves a stray carriage return, and
+        // the rest of the source is a single erroneous line
+        let mut document = Document::new("é\r\n.wor3".to_owned());
+        assert_eq!(document.line_count(), 2);
+        document.edit(3..3, "\\main:");
+        assert_eq!(document.source(), "é\r\\main:\n.wor3");
+        assert_eq!(document.line_count(), 1);
+        assert!(parse(document.source()).is_err());
+        assert!(document.program().is_err());
+    }
+
+    #[test]
+    fn edit_fuzz_test() {
+        // Deterministic pseudo-random edits, with lone carriage returns and multibyte characters:
+        // the document always matches the one parsed from scratch
+        const PIECES: &[&str] = &[
+            "main", ":", " ", "\n", "\r\n", "\r", ",", "ld", "%a", "[", "]", "1", ".word", ".wor3",
+            "\"", "\\", "é", "#",
+        ];
+
+        let mut random = XorShift::new(0x2545_F491_4F6C_DD1D);
+        let mut text = |random: &mut XorShift, count: usize| {
+            let mut text = String::new();
+            for _ in 0..random.below(count) {
+                text.push_str(PIECES[random.below(PIECES.len())]);
+            }
+            text
+        };
+
+        for _ in 0..500 {
+            let mut document = Document::new(text(&mut random, 16));
+            for _ in 0..4 {
+                let source = document.source();
+                let boundaries: Vec<_> = (0..=source.len())
+                    .filter(|&offset| source.is_char_boundary(offset))
+                    .collect();
+                let start = boundaries[random.below(boundaries.len())];
+                let end = boundaries[random.below(boundaries.len())];
+                let range = start.min(end)..start.max(end);
+                let inserted = text(&mut random, 4);
+                let before = source.to_owned();
+                document.edit(range.clone(), &inserted);
+
+                let context = format!("{before:?} with {inserted:?} at {range:?}");
+                let expected = Document::new(document.source().to_owned());
+                assert_eq!(document.line_count(), expected.line_count(), "{context}");
+                assert_eq!(
+                    document.errors().collect::<Vec<_>>(),
+                    expected.errors().collect::<Vec<_>>(),
+                    "{context}"
+                );
+                match parse(document.source()) {
+                    Ok(program) => assert_eq!(document.program().ok(), Some(program), "{context}"),
+                    Err(_) => assert!(document.program().is_err(), "{context}"),
+                }
+            }
+        }
+    }
+}
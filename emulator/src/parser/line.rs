@@ -11,25 +11,25 @@
 use nom::{
     branch::alt,
     bytes::complete::escaped,
-    character::complete::{char, line_ending, none_of, one_of, space0, space1},
-    combinator::{all_consuming, cut, eof, map, opt, peek, value},
-    error::context,
-    multi::separated_list1,
-    sequence::delimited,
-    IResult,
+    character::complete::{alpha1, char, line_ending, none_of, one_of, satisfy, space0, space1},
+    combinator::{all_consuming, cut, eof, map, not, opt, peek, value},
+    error::{context, ErrorKind},
+    sequence::{delimited, terminated},
+    IResult, Offset,
 };
 
 use crate::ast::{AstNode, Node, NodeKind};
+use crate::runtime::mnemonics;
 
 use super::{
     location::{Locatable, Located, MapLocation, RelativeLocation},
     parse_identifier,
     value::{
-        parse_directive_argument, parse_directive_kind, parse_instruction_argument,
-        parse_instruction_kind, DirectiveArgument, DirectiveKind, InstructionArgument,
-        InstructionKind,
+        parse_byte_argument, parse_directive_argument, parse_directive_kind, parse_directive_list,
+        parse_instruction_argument, parse_instruction_kind, parse_register, parse_section_kind,
+        DirectiveArgument, DirectiveKind, InstructionArgument, InstructionKind, SectionKind,
     },
-    ParseError,
+    ParseError, Suggestion, SyntaxErrorKind,
 };
 
 /// Holds the content of a line
@@ -45,12 +45,59 @@ pub(crate) enum LineContent<L> {
         kind: Located<DirectiveKind, L>,
         argument: Located<DirectiveArgument<L>, L>,
     },
+    /// Represents a section directive, which takes no argument
+    Section { kind: Located<SectionKind, L> },
 }
 
 impl<L> LineContent<L> {
     /// Check if the line is a directive
     pub(crate) fn is_directive(&self) -> bool {
-        matches!(self, Self::Directive { .. })
+        matches!(self, Self::Directive { .. } | Self::Section { .. })
+    }
+
+    /// Format the content as it would be written in a source file
+    pub(crate) fn to_source(&self) -> String {
+        match self {
+            Self::Instruction { .. } => self.to_string().trim_end().to_owned(),
+            Self::Directive { kind, argument } => format!(".{} {}", kind.inner, argument.inner),
+            Self::Section { kind } => format!(".{}", kind.inner),
+        }
+    }
+
+    /// Names of the labels referenced by the arguments
+    pub(crate) fn references(&self) -> Vec<&str> {
+        match self {
+            Self::Instruction { arguments, .. } => arguments
+                .iter()
+                .flat_map(|argument| match &argument.inner {
+                    InstructionArgument::Value(value)
+                    | InstructionArgument::Direct(Located { inner: value, .. })
+                    | InstructionArgument::Relative(Located { inner: value, .. })
+                    | InstructionArgument::RelativeDirect(Located { inner: value, .. })
+                    | InstructionArgument::Indexed {
+                        value: Located { inner: value, .. },
+                        ..
+                    }
+                    | InstructionArgument::Scaled {
+                        scale: Located { inner: value, .. },
+                        ..
+                    } => value.variables(),
+                    InstructionArgument::Register(_)
+                    | InstructionArgument::Indirect(_)
+                    | InstructionArgument::PostIncrement(_)
+                    | InstructionArgument::PreDecrement(_) => Vec::new(),
+                })
+                .collect(),
+            Self::Directive { argument, .. } => match &argument.inner {
+                DirectiveArgument::Expression(value) => value.variables(),
+                DirectiveArgument::List(expressions) => expressions
+                    .iter()
+                    .flat_map(|expression| expression.inner.variables())
+                    .collect(),
+                DirectiveArgument::StringLiteral(_) => Vec::new(),
+            },
+            Self::Section { .. } => Vec::new(),
+        }
     }
 }
 
@@ -77,6 +124,10 @@ where
 
                 LineContent::Directive { kind, argument }
             }
+            LineContent::Section { kind } => {
+                let kind = kind.map_location_only(parent);
+                LineContent::Section { kind }
+            }
         }
     }
 }
@@ -86,6 +137,7 @@ impl<L: Clone> AstNode<L> for LineContent<L> {
         match self {
             LineContent::Instruction { .. } => NodeKind::Instruction,
             LineContent::Directive { .. } => NodeKind::Directive,
+            LineContent::Section { .. } => NodeKind::Section,
         }
     }
 
@@ -95,6 +147,7 @@ impl<L: Clone> AstNode<L> for LineContent<L> {
                 .chain(arguments.iter().map(Located::to_node))
                 .collect(),
             LineContent::Directive { kind, argument } => vec![kind.to_node(), argument.to_node()],
+            LineContent::Section { kind } => vec![kind.to_node()],
         }
     }
 }
@@ -120,6 +173,7 @@ impl<L> std::fmt::Display for LineContent<L> {
             LineContent::Directive { kind, argument } => {
                 write!(f, ".{}: {}", kind.inner, argument.inner)
             }
+            LineContent::Section { kind } => write!(f, ".{}", kind.inner),
         }
     }
 }
@@ -128,7 +182,7 @@ impl<L> std::fmt::Display for LineContent<L> {
 /// (if any).
 ///
 /// Note that the `Default::default()` implementation represents an empty line.
-#[derive(Debug, PartialEq, Default)]
+#[derive(Clone, Debug, PartialEq, Default)]
 pub(crate) struct Line<L> {
     pub symbols: Vec<Located<String, L>>,
     pub content: Option<Located<LineContent<L>, L>>,
@@ -234,7 +288,7 @@ where
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Program<L> {
     pub(crate) lines: Vec<Located<Line<L>, L>>,
 }
@@ -289,13 +343,32 @@ fn parse_directive_line<'a, Error: ParseError<&'a str>>(
         let (rest, _) = space1(rest)?;
 
         let start = rest;
-        let (rest, argument) = parse_directive_argument(rest)?;
+        let (rest, argument) = match kind.inner {
+            DirectiveKind::FinalAssert => parse_directive_list(rest)?,
+            DirectiveKind::Byte => parse_byte_argument(rest)?,
+            _ => parse_directive_argument(rest)?,
+        };
         let argument = argument.with_location((input, start, rest));
 
         Ok((rest, LineContent::Directive { kind, argument }))
     })(rest)
 }
 
+/// Parses a section directive
+fn parse_section_line<'a, Error: ParseError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, LineContent<RelativeLocation>, Error> {
+    let (rest, _) = char('.')(input)?;
+
+    let start = rest;
+    let (rest, kind) = parse_section_kind(rest)?;
+    // Make sure the whole word was consumed, to leave other directives to the next parser
+    let (rest, ()) = not(satisfy(|c| c.is_alphanumeric() || c == '_'))(rest)?;
+    let kind = kind.with_location((input, start, rest));
+
+    Ok((rest, LineContent::Section { kind }))
+}
+
 /// Parses an instruction
 fn parse_instruction_line<'a, Error: ParseError<&'a str>>(
     input: &'a str,
@@ -345,6 +418,7 @@ fn parse_line_content<'a, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, LineContent<RelativeLocation>, Error> {
     alt((
+        context("section", parse_section_line),
         context("directive", parse_directive_line),
         context("instruction", parse_instruction_line),
     ))(input)
@@ -388,39 +462,297 @@ fn parse_line<'a, Error: ParseError<&'a str>>(
     Ok((rest, Line { symbols, content }))
 }
 
-fn split_lines<'a, Error: ParseError<&'a str>>(
+/// Splits the first line off the input, without its line ending
+pub(crate) fn split_line<'a, Error: ParseError<&'a str>>(
     input: &'a str,
-) -> IResult<&'a str, Vec<&str>, Error> {
+) -> IResult<&'a str, &'a str, Error> {
     let line_parser = escaped(none_of("\\\r\n"), '\\', one_of("\\\r\nrnt\""));
-    let line_parser = alt((
+    alt((
         // either we have an escaped line
         line_parser,
         // or an EOF
         eof,
         // or an empty line (just peek for the line ending & make the result zero-length)
         map(peek(line_ending), |i: &str| &i[..0]),
-    ));
-    separated_list1(line_ending, line_parser)(input)
+    ))(input)
 }
 
-pub(crate) fn parse_program<'a, Error: ParseError<&'a str>>(
+/// Parses a single line, which must have been split with [`split_line`]
+pub(crate) fn parse_single_line<'a, Error: ParseError<&'a str>>(
     input: &'a str,
-) -> IResult<&'a str, Program<RelativeLocation>, Error> {
-    let (rest, lines) = split_lines(input)?;
-    // TODO: bubble up more detailed errors here
-    let lines: Result<_, _> = lines
+) -> IResult<&'a str, Line<RelativeLocation>, Error> {
+    context("line", all_consuming(parse_line))(input)
+}
+
+/// Checks that the input is at the end of a line, failing like [`all_consuming`] otherwise
+fn end_of_line<'a, Error: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, (), Error> {
+    if input.is_empty() || input.starts_with(['\n', '\r']) {
+        Ok((input, ()))
+    } else {
+        Err(nom::Err::Error(Error::from_error_kind(
+            input,
+            ErrorKind::Eof,
+        )))
+    }
+}
+
+/// Parses the line at the start of the input, stopping before its line ending
+///
+/// This parses the same lines as [`parse_single_line`] after [`split_line`], without going
+/// through the input twice.
+fn parse_next_line<'a, Error: ParseError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, Line<RelativeLocation>, Error> {
+    context("line", terminated(parse_line, end_of_line))(input)
+}
+
+/// Number of single-character edits to turn a word into another
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The known mnemonic closest to an unknown one, if it is close enough to be a typo
+fn closest_mnemonic(word: &str) -> Option<&'static str> {
+    let word = word.to_lowercase();
+    let threshold = 1.max(word.len() / 3);
+    // Ties are broken by preferring mnemonics with the same first letter, then the same length
+    mnemonics()
+        .map(|mnemonic| (edit_distance(&word, mnemonic), mnemonic))
+        .filter(|(distance, _)| *distance <= threshold)
+        .min_by_key(|(distance, mnemonic)| {
+            (
+                *distance,
+                word.chars().next() != mnemonic.chars().next(),
+                word.len().abs_diff(mnemonic.len()),
+            )
+        })
+        .map(|(_, mnemonic)| mnemonic)
+}
+
+/// Split instruction arguments on the commas which are not nested in brackets, parenthesis or
+/// quotes
+fn split_arguments(input: &str) -> Vec<&str> {
+    let mut arguments = Vec::new();
+    let mut depth = 0_usize;
+    let mut quote = None;
+    let mut escaped = false;
+    let mut start = 0;
+    for (index, c) in input.char_indices() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '(' | '[') => depth += 1,
+            (None, ')' | ']') => depth = depth.saturating_sub(1),
+            (None, ',') if depth == 0 => {
+                arguments.push(&input[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    arguments.push(&input[start..]);
+    arguments
+}
+
+/// Find the first non-ASCII character of a line which is not in a string or character literal,
+/// nor in its comment
+fn non_ascii_character(line: &str) -> Option<(usize, char)> {
+    let mut quote = None;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '/') if line[index..].starts_with("//") => return None,
+            (None, c) if !c.is_ascii() => return Some((index, c)),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Find the precise cause of a syntax error on a line
+///
+/// This is either a non-ASCII character outside of a string, an unknown mnemonic, with the
+/// closest known one, or the first argument which could not be parsed. The offset is relative to
+/// the start of the line.
+pub(crate) fn diagnose_line(line: &str) -> Option<(usize, SyntaxErrorKind)> {
+    if let Some((offset, c)) = non_ascii_character(line) {
+        return Some((offset, SyntaxErrorKind::NonAsciiCharacter(c)));
+    }
+
+    let (mut rest, _) = space0::<_, ()>(line).ok()?;
+    while let Ok((after, _)) = parse_symbol_definition::<()>(rest) {
+        (rest, _) = space0::<_, ()>(after).ok()?;
+    }
+
+    // Directives are left to the generic errors
+    let (arguments, word) = alpha1::<_, ()>(rest).ok()?;
+    let Some(mnemonic) = mnemonics().find(|m| m.eq_ignore_ascii_case(word)) else {
+        let kind = SyntaxErrorKind::UnknownInstruction {
+            mnemonic: word.to_owned(),
+            suggestion: closest_mnemonic(word),
+        };
+        return Some((line.offset(word), kind));
+    };
+
+    if arguments.trim().is_empty() {
+        return None;
+    }
+
+    split_arguments(arguments)
         .into_iter()
-        .map(|start| {
-            context("line", all_consuming(parse_line))(start)
-                .map(|(end, line)| line.with_location((input, start, end)))
+        .map(str::trim)
+        .enumerate()
+        .find(|(_, argument)| all_consuming(parse_instruction_argument::<()>)(argument).is_err())
+        .map(|(index, argument)| {
+            let kind = SyntaxErrorKind::InvalidArgument { mnemonic, index };
+            (line.offset(argument), kind)
         })
-        .collect();
-    let lines = lines?;
-    Ok((rest, Program { lines }))
+}
+
+/// The register a word would name with a `%` in front of it, like `%sp` for `sp`
+#[must_use]
+pub fn register_fix(word: &str) -> Option<String> {
+    let register = format!("%{word}");
+    let valid = all_consuming(parse_register::<()>)(register.as_str()).is_ok();
+    valid.then_some(register)
+}
+
+/// The hexadecimal literal a token was probably meant to be, like `0x1F` for `1Fh`, `$1F` or `1F`
+fn hexadecimal_fix(token: &str) -> Option<String> {
+    let digits = if let Some(digits) = token.strip_prefix('$') {
+        digits
+    } else if let Some(digits) = token.strip_suffix(['h', 'H']) {
+        digits
+            .starts_with(|c: char| c.is_ascii_digit())
+            .then_some(digits)?
+    } else {
+        // Without a prefix nor a suffix, only tokens which can not be decimal numbers
+        (token.starts_with(|c: char| c.is_ascii_digit())
+            && token.contains(|c: char| c.is_ascii_alphabetic()))
+        .then_some(token)?
+    };
+
+    (!digits.is_empty() && digits.chars().all(|c| c.is_ascii_hexdigit()))
+        .then(|| format!("0x{digits}"))
+}
+
+/// Find fixes for the arguments of an instruction which could not be parsed
+///
+/// Each argument is checked for a missing `%` before a register, a label followed by its colon,
+/// a missing comma before the next argument, and a hexadecimal literal written without `0x`.
+/// Offsets are relative to the start of the line.
+pub(crate) fn suggest_fixes(line: &str) -> Vec<Suggestion> {
+    let parses = |argument: &str| all_consuming(parse_instruction_argument::<()>)(argument).is_ok();
+    let Some(arguments) = instruction_arguments(line) else {
+        return Vec::new();
+    };
+
+    let mut suggestions = Vec::new();
+    for argument in split_arguments(arguments).into_iter().map(str::trim) {
+        if argument.is_empty() || parses(argument) {
+            continue;
+        }
+
+        let offset = line.offset(argument);
+        if let Some(label) = argument.strip_suffix(':').filter(|label| parses(label)) {
+            suggestions.push(Suggestion {
+                offset,
+                length: argument.len(),
+                replacement: label.to_owned(),
+                message: "labels are used without their colon",
+            });
+            continue;
+        }
+
+        // Two arguments separated by spaces only
+        let split = argument
+            .match_indices(' ')
+            .map(|(index, _)| (argument[..index].trim(), argument[index..].trim()))
+            .find(|(left, right)| !right.is_empty() && parses(left) && parses(right));
+        if let Some((left, right)) = split {
+            suggestions.push(Suggestion {
+                offset,
+                length: argument.len(),
+                replacement: format!("{left}, {right}"),
+                message: "arguments are separated by commas",
+            });
+            continue;
+        }
+
+        let tokens = argument
+            .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$' || c == '%'))
+            .filter(|token| !token.is_empty());
+        for token in tokens {
+            let fix = if let Some(register) = register_fix(token) {
+                Some((register, "registers are written with a `%`"))
+            } else {
+                hexadecimal_fix(token)
+                    .map(|literal| (literal, "hexadecimal literals are written with `0x`"))
+            };
+
+            if let Some((replacement, message)) = fix {
+                suggestions.push(Suggestion {
+                    offset: line.offset(token),
+                    length: token.len(),
+                    replacement,
+                    message,
+                });
+            }
+        }
+    }
+
+    suggestions
+}
+
+/// The arguments of the instruction on a line, after its labels and mnemonic
+fn instruction_arguments(line: &str) -> Option<&str> {
+    let (mut rest, _) = space0::<_, ()>(line).ok()?;
+    while let Ok((after, _)) = parse_symbol_definition::<()>(rest) {
+        (rest, _) = space0::<_, ()>(after).ok()?;
+    }
+
+    let (arguments, word) = alpha1::<_, ()>(rest).ok()?;
+    mnemonics()
+        .any(|m| m.eq_ignore_ascii_case(word))
+        .then_some(arguments)
+}
+
+/// Parses a whole program, in a single pass over the input
+pub(crate) fn parse_program<'a, Error: ParseError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, Program<RelativeLocation>, Error> {
+    let mut lines = Vec::new();
+    let mut start = input;
+    loop {
+        let (end, line) = parse_next_line(start)?;
+        lines.push(line.with_location((input, start, end)));
+
+        match line_ending::<_, Error>(end) {
+            Ok((next, _)) => start = next,
+            Err(_) => return Ok((end, Program { lines })),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use nom::multi::separated_list1;
     use pretty_assertions::assert_eq;
 
     use crate::runtime::Reg;
@@ -484,13 +816,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_section_line_test() {
+        let line = fully_parsed(parse_line("start: .data"));
+        assert_eq!(
+            line,
+            Line {
+                symbols: vec!["start".to_string().with_location((0, 6))],
+                content: Some(
+                    LineContent::Section {
+                        kind: SectionKind::Data.with_location((1, 4)),
+                    }
+                    .with_location((7, 5))
+                ),
+            }
+        );
+
+        // Directives starting like a section are not mistaken for one
+        assert!(parse_line::<()>(".textual 5").is_err());
+    }
+
     #[test]
     fn split_lines_test() {
         let input = r#"hello \
 world
 this is a new line
 this has escaped chars: \r \n \t \""#;
-        let lines = fully_parsed(split_lines(input));
+        let lines = fully_parsed(separated_list1(line_ending, split_line)(input));
         assert_eq!(
             lines,
             vec![
@@ -580,4 +932,84 @@ main:
             }
         );
     }
+
+    #[test]
+    fn diagnose_line_test() {
+        assert_eq!(
+            diagnose_line("main: ldd 5, %a"),
+            Some((
+                6,
+                SyntaxErrorKind::UnknownInstruction {
+                    mnemonic: "ldd".to_owned(),
+                    suggestion: Some("ld"),
+                }
+            ))
+        );
+        assert_eq!(
+            diagnose_line("    lea 1, %a"),
+            Some((
+                4,
+                SyntaxErrorKind::UnknownInstruction {
+                    mnemonic: "lea".to_owned(),
+                    suggestion: None,
+                }
+            ))
+        );
+        assert_eq!(
+            diagnose_line("    add 1, [%a+], %b"),
+            Some((
+                11,
+                SyntaxErrorKind::InvalidArgument {
+                    mnemonic: "add",
+                    index: 1,
+                }
+            ))
+        );
+        assert_eq!(
+            diagnose_line("café: reset"),
+            Some((3, SyntaxErrorKind::NonAsciiCharacter('é')))
+        );
+        assert_eq!(
+            diagnose_line("    add 1,\u{a0}%b"),
+            Some((10, SyntaxErrorKind::NonAsciiCharacter('\u{a0}')))
+        );
+        assert_eq!(diagnose_line("    .string \"café \\\"☃\\\"\""), None);
+        assert_eq!(
+            diagnose_line("main: ld 5 %a // café"),
+            Some((
+                9,
+                SyntaxErrorKind::InvalidArgument {
+                    mnemonic: "ld",
+                    index: 0,
+                }
+            ))
+        );
+        assert_eq!(
+            diagnose_line("    .string \"a // b\" é"),
+            Some((21, SyntaxErrorKind::NonAsciiCharacter('é')))
+        );
+        assert_eq!(diagnose_line("    st %a, [%sp - 1]"), None);
+        assert_eq!(diagnose_line("    .word 1"), None);
+    }
+
+    #[test]
+    fn suggest_fixes_test() {
+        let fixes = |line| {
+            suggest_fixes(line)
+                .into_iter()
+                .map(|s| (s.offset, s.length, s.replacement))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(fixes("    jmp loop:"), vec![(8, 5, "loop".to_owned())]);
+        assert_eq!(fixes("    ld 5 %a"), vec![(7, 4, "5, %a".to_owned())]);
+        assert_eq!(fixes("    ld [sp]+, %a"), vec![(8, 2, "%sp".to_owned())]);
+        assert_eq!(fixes("    ld 1Fh, %a"), vec![(7, 3, "0x1F".to_owned())]);
+        assert_eq!(fixes("    ld $ff, %a"), vec![(7, 3, "0xff".to_owned())]);
+        assert_eq!(fixes("    add 1, [%a+], %b"), vec![]);
+        assert_eq!(fixes("main: ld 5, %a"), vec![]);
+
+        assert_eq!(register_fix("sp"), Some("%sp".to_owned()));
+        assert_eq!(register_fix("loop"), None);
+    }
 }
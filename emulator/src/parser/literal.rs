@@ -1,7 +1,8 @@
 //! Parse number and string literals.
 //!
 //! It parses base 10, base 16 (prefixed by `0x`), base 8 (prefixed by `0o`) and base 2 (prefixed
-//! by `01`) number literals.
+//! by `01`) number literals. Number literals are parsed on 128 bits, like the expressions they
+//! are used in, so that `.long` directives can take values wider than a word.
 
 use std::{num::ParseIntError, str::FromStr};
 
@@ -43,11 +44,11 @@ where
 }
 
 /// Parse a decimal number
-fn from_decimal<I>(input: I) -> Result<u64, ParseIntError>
+fn from_decimal<I>(input: I) -> Result<i128, ParseIntError>
 where
     I: AsRef<str>,
 {
-    u64::from_str(input.as_ref())
+    i128::from_str(input.as_ref())
 }
 
 /// Check if character is a decimal digit
@@ -56,11 +57,11 @@ fn is_digit<C: AsChar>(c: C) -> bool {
 }
 
 /// Parse a hexadecimal number
-fn from_hexadecimal<I>(input: I) -> Result<u64, ParseIntError>
+fn from_hexadecimal<I>(input: I) -> Result<i128, ParseIntError>
 where
     I: AsRef<str>,
 {
-    u64::from_str_radix(input.as_ref(), 16)
+    i128::from_str_radix(input.as_ref(), 16)
 }
 
 /// Check if character is a hexadecimal digit
@@ -69,7 +70,7 @@ fn is_hex_digit<C: AsChar>(c: C) -> bool {
 }
 
 /// Extract a hexadecimal literal
-fn parse_hexadecimal_literal<I, Error>(input: I) -> IResult<I, u64, Error>
+fn parse_hexadecimal_literal<I, Error>(input: I) -> IResult<I, i128, Error>
 where
     I: InputTakeAtPosition + InputTake + Compare<&'static str> + AsRef<str> + Clone,
     <I as InputTakeAtPosition>::Item: AsChar,
@@ -80,11 +81,11 @@ where
 }
 
 /// Parse an octal number
-fn from_octal<I>(input: I) -> Result<u64, std::num::ParseIntError>
+fn from_octal<I>(input: I) -> Result<i128, std::num::ParseIntError>
 where
     I: AsRef<str>,
 {
-    u64::from_str_radix(input.as_ref(), 8)
+    i128::from_str_radix(input.as_ref(), 8)
 }
 
 /// Check if character is an octal digit
@@ -93,7 +94,7 @@ fn is_oct_digit<C: AsChar>(c: C) -> bool {
 }
 
 /// Extract an octal literal
-fn parse_octal_literal<I, Error>(input: I) -> IResult<I, u64, Error>
+fn parse_octal_literal<I, Error>(input: I) -> IResult<I, i128, Error>
 where
     I: InputTakeAtPosition + InputTake + Compare<&'static str> + AsRef<str> + Clone,
     <I as InputTakeAtPosition>::Item: AsChar,
@@ -104,11 +105,11 @@ where
 }
 
 /// Parse a binary number
-fn from_binary<I>(input: I) -> Result<u64, ParseIntError>
+fn from_binary<I>(input: I) -> Result<i128, ParseIntError>
 where
     I: AsRef<str>,
 {
-    u64::from_str_radix(input.as_ref(), 2)
+    i128::from_str_radix(input.as_ref(), 2)
 }
 
 /// Check if character is a binary digit
@@ -117,7 +118,7 @@ fn is_bin_digit<C: AsChar>(c: C) -> bool {
 }
 
 /// Extract a binary literal
-fn parse_binary_literal<I, Error>(input: I) -> IResult<I, u64, Error>
+fn parse_binary_literal<I, Error>(input: I) -> IResult<I, i128, Error>
 where
     I: InputTakeAtPosition + InputTake + Compare<&'static str> + AsRef<str> + Clone,
     <I as InputTakeAtPosition>::Item: AsChar,
@@ -128,7 +129,7 @@ where
 }
 
 /// Parse a number literal
-pub fn parse_number_literal<I, Error>(input: I) -> IResult<I, u64, Error>
+pub fn parse_number_literal<I, Error>(input: I) -> IResult<I, i128, Error>
 where
     I: InputTakeAtPosition + InputTake + Compare<&'static str> + AsRef<str> + Clone,
     <I as InputTakeAtPosition>::Item: AsChar,
@@ -185,7 +186,7 @@ mod tests {
 
     #[test]
     fn take_hexadecimal_literal_test() {
-        type R<'a> = IResult<&'a str, u64, ()>;
+        type R<'a> = IResult<&'a str, i128, ()>;
         assert_eq!(parse_hexadecimal_literal("0x4F"), R::Ok(("", 0x4F)));
         assert_eq!(parse_hexadecimal_literal("0X4f"), R::Ok(("", 0x4f)));
         assert_eq!(parse_hexadecimal_literal("0xffff"), R::Ok(("", 0xffff)));
@@ -221,7 +222,7 @@ mod tests {
 
     #[test]
     fn take_octal_literal_test() {
-        type R<'a> = IResult<&'a str, u64, ()>;
+        type R<'a> = IResult<&'a str, i128, ()>;
         assert_eq!(parse_octal_literal("0o77"), R::Ok(("", 0o77)));
         assert_eq!(parse_octal_literal("0O77"), R::Ok(("", 0o77)));
         assert_eq!(parse_octal_literal("0o177777"), R::Ok(("", 0o177_777)));
@@ -254,7 +255,7 @@ mod tests {
 
     #[test]
     fn take_binary_literal_test() {
-        type R<'a> = IResult<&'a str, u64, ()>;
+        type R<'a> = IResult<&'a str, i128, ()>;
         assert_eq!(parse_binary_literal("0b10"), R::Ok(("", 0b10)));
         assert_eq!(parse_binary_literal("0B10"), R::Ok(("", 0b10)));
         assert_eq!(
@@ -274,7 +275,7 @@ mod tests {
 
     #[test]
     fn parse_literal_test() {
-        type R<'a> = IResult<&'a str, u64, ()>;
+        type R<'a> = IResult<&'a str, i128, ()>;
         // Decimal
         assert_eq!(parse_number_literal("100"), R::Ok(("", 100)));
         assert_eq!(parse_number_literal("42"), R::Ok(("", 42)));
@@ -297,5 +298,12 @@ mod tests {
             parse_number_literal("0b1111111111111111"),
             R::Ok(("", 0xffff))
         ); // Upper bound
+
+        // Wider than a word
+        assert_eq!(
+            parse_number_literal("0x10000000000000002"),
+            R::Ok(("", (1 << 64) + 2))
+        );
+        assert!(parse_number_literal::<_, ()>("0x80000000000000000000000000000000").is_err());
     }
 }
@@ -1,5 +1,6 @@
 use nom::Offset;
 use parse_display::Display;
+use serde::Serialize;
 
 #[derive(Clone, Debug, PartialEq, Eq, Display)]
 #[display("{inner}", bound(T))]
@@ -8,6 +9,13 @@ pub struct Located<T, L> {
     pub(crate) location: L,
 }
 
+impl<T, L> Located<T, L> {
+    /// The location of the node
+    pub fn location(&self) -> &L {
+        &self.location
+    }
+}
+
 impl<T> Located<T, RelativeLocation> {
     pub(crate) fn offset(self, offset: usize) -> Self {
         Located {
@@ -31,7 +39,7 @@ pub trait Locatable: Sized {
 
 impl<T> Locatable for T {}
 
-#[derive(Clone, Debug, PartialEq, Eq, Default)]
+#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize)]
 pub struct RelativeLocation {
     offset: usize,
     length: usize,
@@ -61,6 +69,18 @@ where
 }
 
 impl RelativeLocation {
+    /// Offset in bytes, relative to the parent node
+    #[must_use]
+    pub const fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Length in bytes
+    #[must_use]
+    pub const fn length(&self) -> usize {
+        self.length
+    }
+
     #[must_use]
     pub fn into_absolute(self, parent: &AbsoluteLocation) -> AbsoluteLocation {
         self.to_absolute(parent)
@@ -96,7 +116,7 @@ impl<T> Located<T, RelativeLocation> {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Default)]
+#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize)]
 pub struct AbsoluteLocation<File = ()> {
     pub offset: usize,
     pub length: usize,
@@ -113,12 +133,118 @@ impl<F: Default> From<(usize, usize)> for AbsoluteLocation<F> {
     }
 }
 
+impl<F> AbsoluteLocation<F> {
+    /// The range of bytes covered in the source
+    #[must_use]
+    pub const fn span(&self) -> std::ops::Range<usize> {
+        self.offset..(self.offset + self.length)
+    }
+}
+
 impl std::fmt::Display for AbsoluteLocation {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}..{}", self.offset, self.offset + self.length)
     }
 }
 
+/// Width of a tab when computing columns, the one used when rendering diagnostics
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
+/// A position in a source, as shown to humans: both the line and the column start at 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct LineColumn {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for LineColumn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// The start and end positions of a span, the end being exclusive
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct LineColumnSpan {
+    pub start: LineColumn,
+    pub end: LineColumn,
+}
+
+/// Converts byte offsets in a source into lines and columns
+///
+/// Columns count characters, except tabs which move to the next multiple of the tab width, like
+/// editors display them.
+#[derive(Clone, Debug)]
+pub struct LineIndex<'a> {
+    source: &'a str,
+
+    /// Offset of the start of each line
+    line_starts: Vec<usize>,
+    tab_width: usize,
+}
+
+impl<'a> LineIndex<'a> {
+    #[must_use]
+    pub fn new(source: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+
+        Self {
+            source,
+            line_starts,
+            tab_width: DEFAULT_TAB_WIDTH,
+        }
+    }
+
+    /// Set the width of tabs, at least 1
+    #[must_use]
+    pub fn with_tab_width(mut self, tab_width: usize) -> Self {
+        self.tab_width = tab_width.max(1);
+        self
+    }
+
+    /// Number of lines in the source
+    #[must_use]
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Position of a byte offset, which is moved back to the start of its character if it falls
+    /// inside one, and to the end of the source if it is past it
+    #[must_use]
+    pub fn line_column(&self, offset: usize) -> LineColumn {
+        let mut offset = offset.min(self.source.len());
+        while !self.source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let start = self.line_starts[line - 1];
+        let column = self.source[start..offset].chars().fold(0, |column, c| {
+            if c == '\t' {
+                (column / self.tab_width + 1) * self.tab_width
+            } else {
+                column + 1
+            }
+        });
+
+        LineColumn {
+            line,
+            column: column + 1,
+        }
+    }
+
+    /// Positions of the start and end of a span
+    #[must_use]
+    pub fn span<F>(&self, location: &AbsoluteLocation<F>) -> LineColumnSpan {
+        LineColumnSpan {
+            start: self.line_column(location.offset),
+            end: self.line_column(location.offset + location.length),
+        }
+    }
+}
+
 pub trait MapLocation<Parent> {
     type Mapped;
 
@@ -212,3 +338,60 @@ impl<T, L> Located<T, L> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_index_test() {
+        let source = "main:\n\tld 1, %a\t// one\n  .string \"é\"\n";
+        let index = LineIndex::new(source);
+        assert_eq!(index.line_count(), 4);
+        assert_eq!(index.line_column(0), LineColumn { line: 1, column: 1 });
+        assert_eq!(index.line_column(5), LineColumn { line: 1, column: 6 });
+
+        // Tabs move to the next tab stop
+        let ld = source.find("ld").unwrap();
+        assert_eq!(index.line_column(ld), LineColumn { line: 2, column: 5 });
+        let comment = source.find("//").unwrap();
+        assert_eq!(
+            index.line_column(comment),
+            LineColumn {
+                line: 2,
+                column: 17
+            }
+        );
+        let wide = LineIndex::new(source).with_tab_width(8);
+        assert_eq!(wide.line_column(ld), LineColumn { line: 2, column: 9 });
+        assert_eq!(
+            wide.line_column(comment),
+            LineColumn {
+                line: 2,
+                column: 25
+            }
+        );
+
+        // Multibyte characters take a single column
+        let quote = source.rfind('"').unwrap();
+        assert_eq!(
+            index.line_column(quote),
+            LineColumn {
+                line: 3,
+                column: 13
+            }
+        );
+        assert_eq!(
+            index.line_column(quote - 1),
+            LineColumn {
+                line: 3,
+                column: 12
+            }
+        );
+        assert_eq!(index.line_column(1000), LineColumn { line: 4, column: 1 });
+
+        let span = index.span(&AbsoluteLocation::<()>::from((ld, 8)));
+        assert_eq!(span.start.to_string(), "2:5");
+        assert_eq!(span.end.to_string(), "2:13");
+    }
+}
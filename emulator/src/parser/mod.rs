@@ -4,7 +4,8 @@
 //! handled by the `nom` library.
 
 use nom::{
-    bytes::complete::take_while1, combinator::all_consuming, combinator::verify, Finish, IResult,
+    bytes::complete::take_while1, combinator::all_consuming, combinator::verify,
+    error::VerboseError, Finish, IResult,
 };
 
 use self::{
@@ -15,6 +16,7 @@ use self::{
 pub(crate) mod condition;
 mod errors;
 pub(crate) mod expression;
+pub mod incremental;
 pub(crate) mod line;
 pub(crate) mod literal;
 pub mod location;
@@ -22,8 +24,12 @@ mod precedence;
 pub(crate) mod preprocessor;
 pub(crate) mod value;
 
-pub use errors::{Error, ParseError};
-pub use expression::{parse_expression, Context as ExpressionContext, Node as ExpressionNode};
+pub use errors::{Error, ParseError, Suggestion, SyntaxError, SyntaxErrorKind};
+pub use expression::{
+    parse_expression, parse_runtime_expression, Context as ExpressionContext,
+    Node as ExpressionNode,
+};
+pub use line::register_fix;
 pub use value::parse_register;
 
 fn is_identifier_char(c: char) -> bool {
@@ -46,10 +52,18 @@ pub(crate) fn parse_identifier<'a, Error: ParseError<&'a str>>(
     })(input)
 }
 
+/// Parse a whole program
+///
+/// The resulting AST and error own their data and only refer to the input through byte offsets,
+/// so they can outlive the source buffer.
 pub fn parse(
     input: &str,
-) -> Result<Located<Program<RelativeLocation>, RelativeLocation>, nom::error::VerboseError<&str>> {
-    parse_new(input)
+) -> Result<Located<Program<RelativeLocation>, RelativeLocation>, SyntaxError> {
+    // Verbose errors are costly to build on every failed alternative, so they are only collected
+    // when parsing again a program known to be invalid
+    parse_new::<()>(input).or_else(|()| {
+        parse_new::<VerboseError<&str>>(input).map_err(|e| SyntaxError::from_verbose(input, e))
+    })
 }
 
 pub fn parse_new<'a, Error: ParseError<&'a str>>(
@@ -65,6 +79,16 @@ pub fn parse_new<'a, Error: ParseError<&'a str>>(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::XorShift;
+
+    #[test]
+    fn parse_error_offset_test() {
+        let input = "main:\n    add %a, %b\n    ld [%a, %b\n";
+        let error = parse(input).unwrap_err();
+        let offset = error.offset().unwrap();
+        assert!(offset > input.find("ld").unwrap());
+        assert!(offset <= input.len());
+    }
 
     #[test]
     fn parse_identifier_test() {
@@ -75,4 +99,27 @@ mod tests {
         assert_eq!(parse_identifier("abc_123"), R::Ok(("", "abc_123")));
         assert_eq!(parse_identifier("abc-123"), R::Ok(("-123", "abc")));
     }
+
+    #[test]
+    fn parse_fuzz_test() {
+        // Deterministic pseudo-random programs, made of pieces of valid ones and of multibyte
+        // characters: parsing never panics, and errors always point inside of the input
+        const PIECES: &[&str] = &[
+            "main", ":", " ", "\t", "\n", ",", "ld", "add", "%a", "[", "]", "+", "-", "1", "0x",
+            ".string", ".word", "\"", "\\", "'", "é", "☃", "\u{a0}", "\u{feff}", "𝄞",
+        ];
+
+        let mut random = XorShift::new(0x9E37_79B9_7F4A_7C15);
+        for _ in 0..2000 {
+            let mut input = String::new();
+            for _ in 0..random.below(24) {
+                input.push_str(PIECES[random.below(PIECES.len())]);
+            }
+
+            if let Err(error) = parse(&input) {
+                let offset = error.offset().unwrap_or_default();
+                assert!(input.is_char_boundary(offset), "{input:?}");
+            }
+        }
+    }
 }
@@ -9,6 +9,7 @@ use super::expression::Node as ExpressionNode;
 
 pub(crate) struct ChildTree<'a, T: Precedence> {
     parent_precedence: usize,
+    strict: bool,
     inner: &'a T,
 }
 
@@ -19,6 +20,17 @@ pub(crate) trait Precedence: Sized {
     fn with_parent<T: Precedence>(&self, parent: &T) -> ChildTree<Self> {
         ChildTree {
             parent_precedence: parent.precedence(),
+            strict: false,
+            inner: self,
+        }
+    }
+
+    /// Wrap the node in a [`ChildTree`] for display, as an operand which needs parenthesis even
+    /// with the same precedence as its parent, like the right-hand side of a subtraction
+    fn with_strict_parent<T: Precedence>(&self, parent: &T) -> ChildTree<Self> {
+        ChildTree {
+            parent_precedence: parent.precedence(),
+            strict: true,
             inner: self,
         }
     }
@@ -26,7 +38,11 @@ pub(crate) trait Precedence: Sized {
 
 impl<'a, T: Precedence> ChildTree<'a, T> {
     fn needs_parenthesis(&self) -> bool {
-        self.parent_precedence < self.inner.precedence()
+        if self.strict {
+            self.parent_precedence <= self.inner.precedence()
+        } else {
+            self.parent_precedence < self.inner.precedence()
+        }
     }
 }
 
@@ -45,7 +61,9 @@ impl<'a, T: std::fmt::Display + Precedence> std::fmt::Display for ChildTree<'a,
 impl<L> Precedence for ExpressionNode<L> {
     fn precedence(&self) -> usize {
         match self {
-            Self::Literal(_) | Self::Variable(_) => 0,
+            // Negative literals are written with a leading minus, like inverted values
+            Self::Literal(value) if *value < 0 => 2,
+            Self::Literal(_) | Self::Variable(_) | Self::Memory(_) => 0,
             Self::Invert(_) | Self::BinaryNot(_) => 2,
             Self::Multiply(_, _) | Self::Divide(_, _) => 3,
             Self::Sum(_, _) | Self::Substract(_, _) => 4,
@@ -59,7 +77,7 @@ impl<L> Precedence for ExpressionNode<L> {
 impl<L> Precedence for ConditionNode<L> {
     fn precedence(&self) -> usize {
         match self {
-            Self::Literal(_) | Self::Defined(_) => 0,
+            Self::Literal(_) | Self::Defined(_) | Self::Expression(_) => 0,
             Self::Not(_) => 2,
             Self::GreaterOrEqual(_, _)
             | Self::GreaterThan(_, _)
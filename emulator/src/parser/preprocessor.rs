@@ -1,9 +1,9 @@
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take_till},
-    character::complete::{char, line_ending, not_line_ending, space0, space1},
-    combinator::{map, not, opt},
-    sequence::preceded,
+    bytes::complete::{tag, take_till, take_till1},
+    character::complete::{char, digit1, line_ending, not_line_ending, space0, space1},
+    combinator::{map, map_res, not, opt},
+    sequence::{delimited, preceded},
     IResult, Offset,
 };
 
@@ -36,6 +36,7 @@ where
 
 #[derive(Debug, PartialEq)]
 pub(crate) enum Node<L> {
+    /// Consecutive lines of text, without their comments
     Raw {
         content: String,
     },
@@ -49,8 +50,15 @@ pub(crate) enum Node<L> {
         key: Located<String, L>,
         content: Option<Located<String, L>>,
     },
+    /// Includes a file, from the bundled library if its path is between angle brackets
     Inclusion {
         path: Located<String, L>,
+        library: bool,
+    },
+    /// Renames the lines which follow, like `#line 12 "main.c"`
+    Line {
+        line: usize,
+        file: Option<Located<String, L>>,
     },
     Condition {
         branches: Vec<ConditionBranch<L>>,
@@ -77,8 +85,13 @@ where
                 key: key.map_location_only(parent),
                 content: content.map(|c| c.map_location_only(parent)),
             },
-            Self::Inclusion { path } => Node::Inclusion {
+            Self::Inclusion { path, library } => Node::Inclusion {
                 path: path.map_location_only(parent),
+                library,
+            },
+            Self::Line { line, file } => Node::Line {
+                line,
+                file: file.map(|f| f.map_location_only(parent)),
             },
             Self::Condition { branches, fallback } => Node::Condition {
                 branches: branches.map_location(parent),
@@ -140,6 +153,12 @@ fn parse_directive_argument<'a, Error: ParseError<&'a str>>(
             break;
         }
 
+        // A single slash, like a division, is part of the argument
+        if let Some(rest) = rest.strip_prefix('/') {
+            cursor = rest;
+            continue;
+        }
+
         let (rest, _) =
             take_till(|c| c == ' ' || c == '\t' || c == '\r' || c == '\n' || c == '/')(rest)?;
         cursor = rest;
@@ -207,14 +226,43 @@ fn parse_inclusion<'a, Error: ParseError<&'a str>>(
     let (rest, _) = tag("include")(rest)?;
     let (rest, _) = space1(rest)?;
 
-    // Parse the argument
+    // Parse the argument, either "path" or <path>
     let start = rest;
-    let (rest, path) = parse_string_literal(rest)?;
+    let (rest, (path, library)) = alt((
+        map(parse_string_literal, |path| (path, false)),
+        map(
+            delimited(char('<'), take_till1(|c| c == '>' || c == '\n'), char('>')),
+            |path: &str| (path.to_owned(), true),
+        ),
+    ))(rest)?;
     let path = path.with_location((input, start, rest));
 
     let (rest, _) = eat_end_of_line(rest)?;
 
-    Ok((rest, Node::Inclusion { path }))
+    Ok((rest, Node::Inclusion { path, library }))
+}
+
+fn parse_line<'a, Error: ParseError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, Node<RelativeLocation>, Error> {
+    // Parse "#line"
+    let (rest, _) = char('#')(input)?;
+    let (rest, _) = space0(rest)?;
+    let (rest, _) = tag("line")(rest)?;
+    let (rest, _) = space1(rest)?;
+
+    // Parse the line number, then the optional file name
+    let (rest, line) = map_res(digit1, str::parse)(rest)?;
+    let (rest, file) = opt(|rest| {
+        let (rest, _) = space1(rest)?;
+        let start = rest;
+        let (rest, file) = parse_string_literal(rest)?;
+        Ok((rest, file.with_location((input, start, rest))))
+    })(rest)?;
+
+    let (rest, ()) = eat_end_of_line(rest)?;
+
+    Ok((rest, Node::Line { line, file }))
 }
 
 fn parse_error<'a, Error: ParseError<&'a str>>(
@@ -324,6 +372,85 @@ fn parse_condition<'a, Error: ParseError<&'a str>>(
     Ok((rest, Node::Condition { branches, fallback }))
 }
 
+/// How conditional directives are misnested
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MisnestingKind {
+    /// An `#else` or `#elif` after the `#else` of the same `#if`
+    AfterElse(&'static str),
+
+    /// An `#else`, `#elif` or `#endif` outside of any `#if`
+    Unopened(&'static str),
+
+    /// An `#if` without its `#endif`
+    Unterminated,
+}
+
+/// A conditional directive which is not properly nested
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Misnesting {
+    pub kind: MisnestingKind,
+
+    /// Offset of the faulty directive, or of the unterminated `#if`
+    pub offset: usize,
+
+    /// Length of the directive, up to the end of its line
+    pub length: usize,
+}
+
+/// Check that the `#if`, `#elif`, `#else` and `#endif` directives are properly nested
+///
+/// This is a line-based scan, used to explain why a file failed to parse, since the parser only
+/// tells it could not go further.
+pub(crate) fn check_nesting(input: &str) -> Result<(), Misnesting> {
+    // The open `#if` directives, with their location and whether their `#else` was seen
+    let mut open: Vec<(usize, usize, bool)> = Vec::new();
+
+    let mut offset = 0;
+    for line in input.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+
+        let Some(directive) = line.strip_prefix('#') else {
+            continue;
+        };
+        let directive = directive.trim_start();
+        let name: &str = &directive[..directive
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(directive.len())];
+        let length = line.trim_end().len();
+        let misnesting = |kind| Misnesting {
+            kind,
+            offset: start,
+            length,
+        };
+
+        match name {
+            "if" => open.push((start, length, false)),
+            "elif" | "else" => {
+                let name = if name == "else" { "else" } else { "elif" };
+                match open.last_mut() {
+                    None => return Err(misnesting(MisnestingKind::Unopened(name))),
+                    Some((_, _, true)) => return Err(misnesting(MisnestingKind::AfterElse(name))),
+                    Some((_, _, seen)) => *seen = name == "else",
+                }
+            }
+            "endif" if open.pop().is_none() => {
+                return Err(misnesting(MisnestingKind::Unopened("endif")));
+            }
+            _ => {}
+        }
+    }
+
+    match open.pop() {
+        Some((offset, length, _)) => Err(Misnesting {
+            kind: MisnestingKind::Unterminated,
+            offset,
+            length,
+        }),
+        None => Ok(()),
+    }
+}
+
 fn parse_raw<'a, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Node<RelativeLocation>, Error> {
@@ -346,6 +473,7 @@ fn parse_chunk<'a, Error: ParseError<&'a str>>(
         parse_definition, // #define X [Y]
         parse_undefine,   // #undefine X
         parse_inclusion,  // #include "X"
+        parse_line,       // #line N ["X"]
         parse_condition,  // #if X ... [#elif Y ...] [#else Z ...] #endif
         parse_error,      // #error "X"
         parse_raw,        // anything else
@@ -355,12 +483,32 @@ fn parse_chunk<'a, Error: ParseError<&'a str>>(
 pub(crate) fn parse<'a, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Children<RelativeLocation>, Error> {
-    let mut chunks = Vec::new();
+    let mut chunks: Children<RelativeLocation> = Vec::new();
     let mut cursor = input;
+    // Where the last chunk starts, if it is raw text
+    let mut raw_start = None;
 
     while let (rest, Some(chunk)) = opt(parse_chunk)(cursor)? {
-        let chunk = chunk.with_location((input, cursor, rest));
-        chunks.push(chunk);
+        // Consecutive raw lines are coalesced in a single chunk, since they are processed the same
+        // way, to avoid the overhead of one chunk per line on large files
+        match (chunk, chunks.last_mut(), raw_start) {
+            (
+                Node::Raw { content },
+                Some(Located {
+                    inner: Node::Raw { content: previous },
+                    location,
+                }),
+                Some(start),
+            ) => {
+                previous.push('\n');
+                previous.push_str(&content);
+                *location = (input, start, rest).into();
+            }
+            (chunk, _, _) => {
+                raw_start = matches!(chunk, Node::Raw { .. }).then_some(cursor);
+                chunks.push(chunk.with_location((input, cursor, rest)));
+            }
+        }
 
         if let Ok((rest, _)) = line_ending::<_, nom::error::Error<_>>(rest) {
             cursor = rest;
@@ -384,6 +532,9 @@ mod tests {
     fn parse_directive_argument_test() {
         let res = parse_directive_argument::<()>("foo").unwrap();
         assert_eq!(res, ("", "foo"));
+
+        let res = parse_directive_argument::<()>("4 / 2 // half").unwrap();
+        assert_eq!(res, (" // half", "4 / 2"));
     }
 
     #[test]
@@ -433,7 +584,49 @@ mod tests {
             (
                 "",
                 Node::Inclusion {
-                    path: "foo".to_string().with_location((9, 5))
+                    path: "foo".to_string().with_location((9, 5)),
+                    library: false,
+                }
+            )
+        );
+
+        let res = parse_inclusion::<()>("#include <std/stack.s> // frames").unwrap();
+        assert_eq!(
+            res,
+            (
+                "",
+                Node::Inclusion {
+                    path: "std/stack.s".to_string().with_location((9, 13)),
+                    library: true,
+                }
+            )
+        );
+
+        assert!(parse_inclusion::<()>("#include <std/stack.s").is_err());
+    }
+
+    #[test]
+    fn parse_line_test() {
+        let res = parse_line::<()>("#line 12 \"main.c\" // generated").unwrap();
+        assert_eq!(
+            res,
+            (
+                "",
+                Node::Line {
+                    line: 12,
+                    file: Some("main.c".to_string().with_location((9, 8)))
+                }
+            )
+        );
+
+        let res = parse_line::<()>("#line 3").unwrap();
+        assert_eq!(
+            res,
+            (
+                "",
+                Node::Line {
+                    line: 3,
+                    file: None
                 }
             )
         );
@@ -498,7 +691,8 @@ mod tests {
                 }
                 .with_location((0, 5)),
                 Inclusion {
-                    path: "foo".to_string().with_location((9, 5))
+                    path: "foo".to_string().with_location((9, 5)),
+                    library: false,
                 }
                 .with_location((6, 14)),
                 Definition {
@@ -556,7 +750,8 @@ mod tests {
                 }
                 .with_location((0, 5)),
                 Inclusion {
-                    path: "foo".to_string().with_location((13, 5))
+                    path: "foo".to_string().with_location((13, 5)),
+                    library: false,
                 }
                 .with_location((6, 27)),
                 Definition {
@@ -582,21 +777,44 @@ mod tests {
                 }
                 .with_location((125, 27)),
                 Raw {
-                    content: String::new()
+                    content: "\nempty line\n".to_string()
                 }
-                .with_location((153, 0)),
-                Raw {
-                    content: "empty line".to_string()
-                }
-                .with_location((154, 10)),
-                Raw {
-                    content: String::new()
-                }
-                .with_location((165, 0)),
+                .with_location((153, 12)),
             ]
         );
     }
 
+    #[test]
+    fn check_nesting_test() {
+        let misnesting = |input| check_nesting(input).unwrap_err();
+
+        assert_eq!(check_nesting("#if a\n#elif b\n#else\n#endif\n"), Ok(()));
+        assert_eq!(
+            misnesting("#if a\n#else\n#else // again\n#endif\n"),
+            Misnesting {
+                kind: MisnestingKind::AfterElse("else"),
+                offset: 12,
+                length: 14,
+            }
+        );
+        assert_eq!(
+            misnesting("#if a\n#else\n#elif b\n#endif\n").kind,
+            MisnestingKind::AfterElse("elif")
+        );
+        assert_eq!(
+            misnesting("foo\n#endif\n").kind,
+            MisnestingKind::Unopened("endif")
+        );
+        assert_eq!(
+            misnesting("#if a\n#if b\n#endif\n"),
+            Misnesting {
+                kind: MisnestingKind::Unterminated,
+                offset: 0,
+                length: 5,
+            }
+        );
+    }
+
     #[test]
     fn parse_condition_test() {
         use Node::{Condition, Raw};
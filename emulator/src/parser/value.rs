@@ -1,16 +1,17 @@
 use nom::{
     branch::alt,
     bytes::complete::tag_no_case,
-    character::complete::{char, space0},
-    combinator::{map, value},
+    character::complete::{alpha1, char, space0},
+    combinator::{map, opt, value},
     error::context,
+    sequence::{pair, tuple},
     Compare, IResult, InputTake,
 };
 use parse_display::{Display, FromStr};
 use thiserror::Error;
 
 use super::{
-    expression::{parse_expression, Context, EvaluationError, Node},
+    expression::{parse_atom, parse_expression, Context, EvaluationError, Node, Value},
     literal::parse_string_literal,
     location::Locatable,
     location::{Located, MapLocation, RelativeLocation},
@@ -18,9 +19,10 @@ use super::{
 };
 use crate::{
     ast::{AstNode, NodeKind},
+    constants::{Address, Word, MEMORY_SIZE},
     runtime::{
-        arguments::{Dir, Idx, Imm, ImmRegDirIndIdx, Ind},
-        Reg,
+        arguments::{AddressingMode, Dec, Dir, Idx, Imm, ImmRegDirIndIdx, Inc, Ind, Rel, Sca},
+        Arch, Reg,
     },
 };
 
@@ -29,10 +31,13 @@ use crate::{
 pub(crate) enum InstructionKind {
     Add,
     And,
+    Brk,
     Call,
+    Cas,
     Cmp,
     Div,
     Fas,
+    Halt,
     In,
     Jmp,
     Jeq,
@@ -41,7 +46,12 @@ pub(crate) enum InstructionKind {
     Jlt,
     Jge,
     Jgt,
+    Jleu,
+    Jltu,
+    Jgeu,
+    Jgtu,
     Ld,
+    Mod,
     Mul,
     Neg,
     Nop,
@@ -51,6 +61,8 @@ pub(crate) enum InstructionKind {
     Pop,
     Push,
     Reset,
+    Rol,
+    Ror,
     Rti,
     Rtn,
     Shl,
@@ -73,56 +85,85 @@ impl<L> AstNode<L> for InstructionKind {
     }
 }
 
-pub(crate) fn parse_instruction_kind<Input, Error>(
-    input: Input,
-) -> IResult<Input, InstructionKind, Error>
-where
-    Input: InputTake + Compare<&'static str> + Clone,
-    Error: nom::error::ParseError<Input> + nom::error::ContextError<Input>,
-{
-    use InstructionKind as K;
+impl InstructionKind {
+    /// Longest mnemonic, in bytes
+    const MAX_LEN: usize = 8;
 
-    // `alt` only allows for 21-member tuples so we need to trick a bit by nesting them
-    alt((
-        alt((
-            context("add", value(K::Add, tag_no_case("add"))),
-            context("and", value(K::And, tag_no_case("and"))),
-            context("call", value(K::Call, tag_no_case("call"))),
-            context("cmp", value(K::Cmp, tag_no_case("cmp"))),
-            context("div", value(K::Div, tag_no_case("div"))),
-            context("fas", value(K::Fas, tag_no_case("fas"))),
-            context("in", value(K::In, tag_no_case("in"))),
-            context("jmp", value(K::Jmp, tag_no_case("jmp"))),
-            context("jeq", value(K::Jeq, tag_no_case("jeq"))),
-            context("jne", value(K::Jne, tag_no_case("jne"))),
-            context("jle", value(K::Jle, tag_no_case("jle"))),
-            context("jlt", value(K::Jlt, tag_no_case("jlt"))),
-            context("jge", value(K::Jge, tag_no_case("jge"))),
-            context("jgt", value(K::Jgt, tag_no_case("jgt"))),
-            context("ld", value(K::Ld, tag_no_case("ld"))),
-            context("mul", value(K::Mul, tag_no_case("mul"))),
-            context("neg", value(K::Neg, tag_no_case("neg"))),
-            context("nop", value(K::Nop, tag_no_case("nop"))),
-            context("not", value(K::Not, tag_no_case("not"))),
-            context("or", value(K::Or, tag_no_case("or"))),
-            context("out", value(K::Out, tag_no_case("out"))),
-        )),
-        alt((
-            context("pop", value(K::Pop, tag_no_case("pop"))),
-            context("push", value(K::Push, tag_no_case("push"))),
-            context("reset", value(K::Reset, tag_no_case("reset"))),
-            context("rti", value(K::Rti, tag_no_case("rti"))),
-            context("rtn", value(K::Rtn, tag_no_case("rtn"))),
-            context("shl", value(K::Shl, tag_no_case("shl"))),
-            context("shr", value(K::Shr, tag_no_case("shr"))),
-            context("st", value(K::St, tag_no_case("st"))),
-            context("sub", value(K::Sub, tag_no_case("sub"))),
-            context("swap", value(K::Swap, tag_no_case("swap"))),
-            context("trap", value(K::Trap, tag_no_case("trap"))),
-            context("xor", value(K::Xor, tag_no_case("xor"))),
-            context("debugreg", value(K::DebugReg, tag_no_case("debugreg"))),
-        )),
-    ))(input)
+    /// Find the instruction of a mnemonic, ignoring its case
+    fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+        use InstructionKind as K;
+
+        // Lowercase in a buffer on the stack, so that a single `match` finds the instruction
+        // without allocating. The match is compiled as a dispatch on the length first.
+        let bytes = mnemonic.as_bytes();
+        if bytes.len() > Self::MAX_LEN {
+            return None;
+        }
+        let mut buffer = [0; Self::MAX_LEN];
+        let lowercase = &mut buffer[..bytes.len()];
+        lowercase.copy_from_slice(bytes);
+        lowercase.make_ascii_lowercase();
+
+        let kind = match &*lowercase {
+            b"add" => K::Add,
+            b"and" => K::And,
+            b"brk" => K::Brk,
+            b"call" => K::Call,
+            b"cas" => K::Cas,
+            b"cmp" => K::Cmp,
+            b"div" => K::Div,
+            b"fas" => K::Fas,
+            b"halt" => K::Halt,
+            b"in" => K::In,
+            b"jmp" => K::Jmp,
+            b"jeq" => K::Jeq,
+            b"jne" => K::Jne,
+            b"jle" => K::Jle,
+            b"jlt" => K::Jlt,
+            b"jge" => K::Jge,
+            b"jgt" => K::Jgt,
+            b"jleu" => K::Jleu,
+            b"jltu" => K::Jltu,
+            b"jgeu" => K::Jgeu,
+            b"jgtu" => K::Jgtu,
+            b"ld" => K::Ld,
+            b"mod" => K::Mod,
+            b"mul" => K::Mul,
+            b"neg" => K::Neg,
+            b"nop" => K::Nop,
+            b"not" => K::Not,
+            b"or" => K::Or,
+            b"out" => K::Out,
+            b"pop" => K::Pop,
+            b"push" => K::Push,
+            b"reset" => K::Reset,
+            b"rol" => K::Rol,
+            b"ror" => K::Ror,
+            b"rti" => K::Rti,
+            b"rtn" => K::Rtn,
+            b"shl" => K::Shl,
+            b"shr" => K::Shr,
+            b"st" => K::St,
+            b"sub" => K::Sub,
+            b"swap" => K::Swap,
+            b"trap" => K::Trap,
+            b"xor" => K::Xor,
+            b"debugreg" => K::DebugReg,
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
+
+/// Parses the mnemonic of an instruction, as a whole word
+pub(crate) fn parse_instruction_kind<'a, Error: ParseError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, InstructionKind, Error> {
+    let (rest, word) = alpha1(input)?;
+    let kind = InstructionKind::from_mnemonic(word).ok_or_else(|| {
+        nom::Err::Error(Error::from_error_kind(input, nom::error::ErrorKind::Tag))
+    })?;
+    Ok((rest, kind))
 }
 
 /// Represents an instruction argument
@@ -144,12 +185,54 @@ pub(crate) enum InstructionArgument<L> {
     #[display("[{0.inner}]")]
     Indirect(Located<Reg, L>),
 
+    /// An indirect memory access, incrementing the register afterwards
+    #[display("[{0.inner}]+")]
+    PostIncrement(Located<Reg, L>),
+
+    /// An indirect memory access, decrementing the register first
+    #[display("-[{0.inner}]")]
+    PreDecrement(Located<Reg, L>),
+
     /// An indexed memory access (register + offset)
     #[display("[{register.inner} {value.inner:+}]")]
     Indexed {
         register: Located<Reg, L>,
         value: Located<Node<L>, L>,
     },
+
+    /// A scaled indexed memory access (base register + index register * scale)
+    #[display("[{base.inner} + {index.inner} * {scale.inner:#}]")]
+    Scaled {
+        base: Located<Reg, L>,
+        index: Located<Reg, L>,
+        scale: Located<Node<L>, L>,
+    },
+
+    /// An address, encoded relative to the program counter
+    #[display("{0.inner}(%pc)")]
+    Relative(Located<Node<L>, L>),
+
+    /// A memory access at an address encoded relative to the program counter
+    #[display("[{0.inner}(%pc)]")]
+    RelativeDirect(Located<Node<L>, L>),
+}
+
+impl<L> InstructionArgument<L> {
+    /// Addressing mode of this argument
+    pub(crate) const fn mode(&self) -> AddressingMode {
+        match self {
+            Self::Value(_) => AddressingMode::Immediate,
+            Self::Register(_) => AddressingMode::Register,
+            Self::Direct(_) => AddressingMode::Direct,
+            Self::Indirect(_) => AddressingMode::Indirect,
+            Self::PostIncrement(_) => AddressingMode::PostIncrement,
+            Self::PreDecrement(_) => AddressingMode::PreDecrement,
+            Self::Indexed { .. } => AddressingMode::Indexed,
+            Self::Scaled { .. } => AddressingMode::Scaled,
+            Self::Relative(_) => AddressingMode::Relative,
+            Self::RelativeDirect(_) => AddressingMode::RelativeDirect,
+        }
+    }
 }
 
 impl<L, P> MapLocation<P> for InstructionArgument<L>
@@ -173,11 +256,28 @@ where
                 let i = i.map_location_only(parent);
                 InstructionArgument::Indirect(i)
             }
+            InstructionArgument::PostIncrement(r) => {
+                InstructionArgument::PostIncrement(r.map_location_only(parent))
+            }
+            InstructionArgument::PreDecrement(r) => {
+                InstructionArgument::PreDecrement(r.map_location_only(parent))
+            }
             InstructionArgument::Indexed { register, value } => {
                 let register = register.map_location_only(parent);
                 let value = value.map_location(parent);
                 InstructionArgument::Indexed { register, value }
             }
+            InstructionArgument::Scaled { base, index, scale } => InstructionArgument::Scaled {
+                base: base.map_location_only(parent),
+                index: index.map_location_only(parent),
+                scale: scale.map_location(parent),
+            },
+            InstructionArgument::Relative(v) => {
+                InstructionArgument::Relative(v.map_location(parent))
+            }
+            InstructionArgument::RelativeDirect(v) => {
+                InstructionArgument::RelativeDirect(v.map_location(parent))
+            }
         }
     }
 }
@@ -188,12 +288,19 @@ pub(crate) fn parse_instruction_argument<'a, Error: ParseError<&'a str>>(
 ) -> IResult<&'a str, InstructionArgument<RelativeLocation>, Error> {
     use InstructionArgument::{Register, Value};
     alt((
+        // Pre-decrements start like negated immediate values, which cut after the minus sign
+        context("pre-decremented memory access", parse_pre_decrement),
+        // Relative addresses start like immediate values
+        context("relative address", parse_relative),
         context("immediate value", map(parse_expression, Value)),
         context("register", map(parse_register, Register)),
         // Order is important here: indexed must be before direct because the indirect one cuts
         // directly after square bracket
+        context("scaled indexed memory access", parse_scaled),
         context("indexed memory access", parse_indexed),
+        context("post-incremented memory access", parse_post_increment),
         context("indirect memory access", parse_indirect),
+        context("relative memory access", parse_relative_direct),
         context("direct memory access", parse_direct),
     ))(input)
 }
@@ -202,8 +309,18 @@ pub(crate) fn parse_instruction_argument<'a, Error: ParseError<&'a str>>(
 #[display(style = "lowercase")]
 pub enum DirectiveKind {
     Addr,
+    Byte,
+    Extern,
+
+    /// Checks the digest of a range of the memory once the program halts
+    #[display("final_assert")]
+    FinalAssert,
+
+    Global,
+    Long,
     Space,
     String,
+    Weak,
     Word,
 }
 
@@ -228,22 +345,89 @@ where
 
     alt((
         context("addr", value(K::Addr, tag_no_case("addr"))),
+        context("byte", value(K::Byte, tag_no_case("byte"))),
+        context("extern", value(K::Extern, tag_no_case("extern"))),
+        context(
+            "final_assert",
+            value(K::FinalAssert, tag_no_case("final_assert")),
+        ),
+        context("global", value(K::Global, tag_no_case("global"))),
+        context("long", value(K::Long, tag_no_case("long"))),
         context("space", value(K::Space, tag_no_case("space"))),
         context("string", value(K::String, tag_no_case("string"))),
+        context("weak", value(K::Weak, tag_no_case("weak"))),
         context("word", value(K::Word, tag_no_case("word"))),
     ))(input)
 }
 
+/// A section of the program, selected by the `.text`, `.data` and `.bss` directives
+#[derive(Display, FromStr, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[display(style = "lowercase")]
+pub enum SectionKind {
+    /// The code of the program
+    Text,
+
+    /// Initialized data
+    Data,
+
+    /// Reserved space
+    Bss,
+}
+
+impl<L> AstNode<L> for SectionKind {
+    fn kind(&self) -> NodeKind {
+        NodeKind::SectionKind
+    }
+
+    fn content(&self) -> Option<String> {
+        Some(format!("{self}"))
+    }
+}
+
+pub(crate) fn parse_section_kind<Input, Error>(input: Input) -> IResult<Input, SectionKind, Error>
+where
+    Input: InputTake + Compare<&'static str> + Clone,
+    Error: nom::error::ParseError<Input> + nom::error::ContextError<Input>,
+{
+    use SectionKind as K;
+
+    alt((
+        context("text", value(K::Text, tag_no_case("text"))),
+        context("data", value(K::Data, tag_no_case("data"))),
+        context("bss", value(K::Bss, tag_no_case("bss"))),
+    ))(input)
+}
+
 /// Represents a directive argument
-#[derive(Clone, Debug, PartialEq, Display)]
+#[derive(Clone, Debug, PartialEq)]
 pub(crate) enum DirectiveArgument<L> {
-    /// A string literal (`.string` directive)
-    #[display("{0:?}")]
+    /// A string literal (`.string` and `.byte` directives)
     StringLiteral(String),
 
-    /// An expression (`.addr`, `.word`, `.space` directives)
-    #[display("{0}")]
+    /// An expression (`.addr`, `.word`, `.long`, `.space` directives), or a label (`.weak`, `.global`
+    /// and `.extern` directives)
     Expression(Node<L>),
+
+    /// Comma-separated expressions (`.final_assert` and `.byte` directives)
+    List(Vec<Located<Node<L>, L>>),
+}
+
+impl<L> std::fmt::Display for DirectiveArgument<L> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DirectiveArgument::StringLiteral(s) => write!(f, "{s:?}"),
+            DirectiveArgument::Expression(e) => write!(f, "{e}"),
+            DirectiveArgument::List(expressions) => {
+                for (index, expression) in expressions.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", expression.inner)?;
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
 impl<L, P> MapLocation<P> for DirectiveArgument<L>
@@ -259,6 +443,9 @@ where
                 let n = n.map_location(parent);
                 DirectiveArgument::Expression(n)
             }
+            DirectiveArgument::List(expressions) => {
+                DirectiveArgument::List(expressions.map_location(parent))
+            }
         }
     }
 }
@@ -268,6 +455,7 @@ impl<L: Clone> AstNode<L> for DirectiveArgument<L> {
         match self {
             DirectiveArgument::StringLiteral(_) => NodeKind::StringLiteral,
             DirectiveArgument::Expression(e) => e.kind(),
+            DirectiveArgument::List(_) => NodeKind::ExpressionList,
         }
     }
 
@@ -275,6 +463,7 @@ impl<L: Clone> AstNode<L> for DirectiveArgument<L> {
         match self {
             DirectiveArgument::StringLiteral(s) => Some(s.clone()),
             DirectiveArgument::Expression(e) => e.content(),
+            DirectiveArgument::List(_) => None,
         }
     }
 
@@ -282,6 +471,9 @@ impl<L: Clone> AstNode<L> for DirectiveArgument<L> {
         match self {
             DirectiveArgument::StringLiteral(_) => Vec::new(),
             DirectiveArgument::Expression(e) => e.children(),
+            DirectiveArgument::List(expressions) => {
+                expressions.iter().map(Located::to_node).collect()
+            }
         }
     }
 }
@@ -302,6 +494,38 @@ pub(crate) fn parse_directive_argument<'a, Error: ParseError<&'a str>>(
     ))(input)
 }
 
+/// Parse the comma-separated expressions of a directive taking several arguments
+pub(crate) fn parse_directive_list<'a, Error: ParseError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, DirectiveArgument<RelativeLocation>, Error> {
+    let mut expressions = Vec::new();
+    let mut cursor = input;
+    loop {
+        let start = cursor;
+        let (rest, expression) = context("expression", parse_expression)(cursor)?;
+        expressions.push(expression.with_location((input, start, rest)));
+
+        let (rest, comma) = opt(tuple((space0, char(','), space0)))(rest)?;
+        if comma.is_none() {
+            return Ok((rest, DirectiveArgument::List(expressions)));
+        }
+        cursor = rest;
+    }
+}
+
+/// Parse the argument of a `.byte` directive, a string or comma-separated byte values
+pub(crate) fn parse_byte_argument<'a, Error: ParseError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, DirectiveArgument<RelativeLocation>, Error> {
+    alt((
+        context(
+            "string literal",
+            map(parse_string_literal, DirectiveArgument::StringLiteral),
+        ),
+        parse_directive_list,
+    ))(input)
+}
+
 impl<L> From<&str> for DirectiveArgument<L> {
     fn from(literal: &str) -> Self {
         Self::StringLiteral(literal.to_string())
@@ -318,27 +542,142 @@ impl<L> From<i128> for DirectiveArgument<L> {
 pub enum ComputeError<L> {
     #[error("could not evaluate argument")]
     Evaluation(#[from] EvaluationError<L>),
+
+    #[error("{value} is out of range for {operand}, expected a value between {min} and {max}")]
+    OutOfRange {
+        /// Kind of operand, like "a memory address"
+        operand: &'static str,
+        value: Value,
+        min: Value,
+        max: Value,
+        location: Option<L>,
+    },
+}
+
+impl<L> ComputeError<L> {
+    /// Location of the innermost sub-expression which failed to evaluate, if known
+    pub fn location(&self) -> Option<&L> {
+        match self {
+            Self::Evaluation(e) => e.location(),
+            Self::OutOfRange { location, .. } => location.as_ref(),
+        }
+    }
+}
+
+/// Check that an evaluated operand fits in its field
+pub(crate) fn check_range<L: Clone, V: TryFrom<Value> + Into<Value>>(
+    operand: &'static str,
+    value: Value,
+    min: V,
+    max: V,
+    location: Option<&L>,
+) -> Result<V, ComputeError<L>> {
+    let (min, max) = (min.into(), max.into());
+    if (min..=max).contains(&value) {
+        if let Ok(value) = V::try_from(value) {
+            return Ok(value);
+        }
+    }
+
+    Err(ComputeError::OutOfRange {
+        operand,
+        value,
+        min,
+        max,
+        location: location.cloned(),
+    })
+}
+
+/// Evaluate a located expression, keeping its location in the error
+fn evaluate_located<L: Clone, C: Context, V: TryFrom<Value>>(
+    node: &Located<Node<L>, L>,
+    context: &C,
+) -> Result<V, EvaluationError<L>> {
+    node.inner
+        .evaluate(context)
+        .map_err(|inner| EvaluationError::Expression {
+            location: node.location.clone(),
+            inner: Box::new(inner),
+        })
+}
+
+/// Evaluate an address and encode it relative to the program counter, which points to the
+/// instruction following the one at `at` when it executes
+fn evaluate_relative<L: Clone, C: Context>(
+    v: &Located<Node<L>, L>,
+    context: &C,
+    at: Address,
+) -> Result<Word, ComputeError<L>> {
+    let value = evaluate_located(v, context)?;
+    let address = check_range(
+        "a memory address",
+        value,
+        0,
+        MEMORY_SIZE - 1,
+        Some(&v.location),
+    )?;
+    Ok(Word::from(address) - Word::from(at) - 1)
 }
 
 impl<L: Clone> InstructionArgument<L> {
+    /// Evaluate the argument of the instruction placed at the address `at`
+    ///
+    /// Immediate values, index offsets and scales must fit in a word of the given machine.
     pub(crate) fn evaluate<C: Context>(
         &self,
         context: &C,
+        at: Address,
+        arch: Arch,
     ) -> Result<ImmRegDirIndIdx, ComputeError<L>> {
         match self {
             Self::Value(v) => {
                 let value = v.evaluate(context)?;
+                let value = check_range("an immediate value", value, arch.min(), arch.max(), None)?;
                 Ok(ImmRegDirIndIdx::Imm(Imm(value)))
             }
             Self::Register(register) => Ok(ImmRegDirIndIdx::Reg(*register)),
             Self::Direct(v) => {
-                let value = v.inner.evaluate(context)?;
+                let value = evaluate_located(v, context)?;
+                let value = check_range(
+                    "a memory address",
+                    value,
+                    0,
+                    MEMORY_SIZE - 1,
+                    Some(&v.location),
+                )?;
                 Ok(ImmRegDirIndIdx::Dir(Dir(value)))
             }
             Self::Indirect(register) => Ok(ImmRegDirIndIdx::Ind(Ind(register.inner))),
+            Self::PostIncrement(register) => Ok(ImmRegDirIndIdx::Inc(Inc(register.inner))),
+            Self::PreDecrement(register) => Ok(ImmRegDirIndIdx::Dec(Dec(register.inner))),
             Self::Indexed { register, value } => {
-                let value = value.inner.evaluate(context)?;
-                Ok(ImmRegDirIndIdx::Idx(Idx(register.inner, value)))
+                let offset = evaluate_located(value, context)?;
+                let offset = check_range(
+                    "an index offset",
+                    offset,
+                    arch.min(),
+                    arch.max(),
+                    Some(&value.location),
+                )?;
+                Ok(ImmRegDirIndIdx::Idx(Idx(register.inner, offset)))
+            }
+            Self::Scaled { base, index, scale } => {
+                let factor = evaluate_located(scale, context)?;
+                let factor = check_range(
+                    "a scale",
+                    factor,
+                    arch.min(),
+                    arch.max(),
+                    Some(&scale.location),
+                )?;
+                Ok(ImmRegDirIndIdx::Sca(Sca(base.inner, index.inner, factor)))
+            }
+            Self::Relative(v) => Ok(ImmRegDirIndIdx::Rel(Rel(evaluate_relative(
+                v, context, at,
+            )?))),
+            Self::RelativeDirect(v) => {
+                let offset = evaluate_relative(v, context, at)?;
+                Ok(ImmRegDirIndIdx::Idx(Idx(Reg::PC, offset)))
             }
         }
     }
@@ -351,7 +690,12 @@ impl<L: Clone> AstNode<L> for InstructionArgument<L> {
             InstructionArgument::Register(_) => NodeKind::Register,
             InstructionArgument::Direct(_) => NodeKind::Direct,
             InstructionArgument::Indirect(_) => NodeKind::Indirect,
+            InstructionArgument::PostIncrement(_) => NodeKind::PostIncrement,
+            InstructionArgument::PreDecrement(_) => NodeKind::PreDecrement,
             InstructionArgument::Indexed { .. } => NodeKind::Indexed,
+            InstructionArgument::Scaled { .. } => NodeKind::Scaled,
+            InstructionArgument::Relative(_) => NodeKind::Relative,
+            InstructionArgument::RelativeDirect(_) => NodeKind::RelativeDirect,
         }
     }
 
@@ -367,10 +711,18 @@ impl<L: Clone> AstNode<L> for InstructionArgument<L> {
             InstructionArgument::Value(e) => e.children(),
             InstructionArgument::Register(_) => Vec::new(),
             InstructionArgument::Direct(e) => vec![e.to_node()],
-            InstructionArgument::Indirect(r) => vec![r.to_node()],
+            InstructionArgument::Indirect(r)
+            | InstructionArgument::PostIncrement(r)
+            | InstructionArgument::PreDecrement(r) => vec![r.to_node()],
             InstructionArgument::Indexed { register, value } => {
                 vec![register.to_node(), value.to_node()]
             }
+            InstructionArgument::Scaled { base, index, scale } => {
+                vec![base.to_node(), index.to_node(), scale.to_node()]
+            }
+            InstructionArgument::Relative(e) | InstructionArgument::RelativeDirect(e) => {
+                vec![e.to_node()]
+            }
         }
     }
 }
@@ -409,13 +761,13 @@ fn parse_indexed<'a, Error: ParseError<&'a str>>(
     let (rest, sign) = alt((value(Plus, char('+')), value(Minus, char('-'))))(rest)?;
     let (rest, _) = space0(rest)?;
 
-    let expression_start = rest;
-    let (rest, value) = parse_expression(rest)?;
-
-    let value = match sign {
-        Plus => value,
-        Minus => Node::Invert(Box::new(value).with_location((input, expression_start, rest))),
+    // A minus sign only applies to the first term, so `[%a - 2 + 1]` is `%a - 1`, and is parsed
+    // with the expression
+    let expression_start = match sign {
+        Plus => rest,
+        Minus => sign_start,
     };
+    let (rest, value) = parse_expression(expression_start)?;
     let value = value.with_location((input, sign_start, rest));
 
     let (rest, _) = space0(rest)?;
@@ -437,6 +789,76 @@ fn parse_direct<'a, Error: ParseError<&'a str>>(
     Ok((rest, InstructionArgument::Direct(value)))
 }
 
+fn parse_scaled<'a, Error: ParseError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, InstructionArgument<RelativeLocation>, Error> {
+    let (rest, _) = char('[')(input)?;
+    let (rest, _) = space0(rest)?;
+
+    let start = rest;
+    let (rest, base) = parse_register(rest)?;
+    let base = base.with_location((input, start, rest));
+    let (rest, _) = space0(rest)?;
+    let (rest, _) = char('+')(rest)?;
+    let (rest, _) = space0(rest)?;
+
+    let start = rest;
+    let (rest, index) = parse_register(rest)?;
+    let index = index.with_location((input, start, rest));
+    let (rest, _) = space0(rest)?;
+
+    // Without a scale, the index is added as is
+    let (rest, scale) = match opt(pair(char('*'), space0))(rest)? {
+        (rest, Some(_)) => {
+            let start = rest;
+            let (rest, scale) = parse_atom(rest)?;
+            (rest, scale.with_location((input, start, rest)))
+        }
+        (rest, None) => (rest, Node::Literal(1).with_location((input, rest, rest))),
+    };
+
+    let (rest, _) = space0(rest)?;
+    let (rest, _) = char(']')(rest)?;
+
+    Ok((rest, InstructionArgument::Scaled { base, index, scale }))
+}
+
+/// Parse the `(%pc)` suffix of relative addresses
+fn parse_relative_suffix<'a, Error: ParseError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, (), Error> {
+    let (rest, _) = space0(input)?;
+    let (rest, _) = char('(')(rest)?;
+    let (rest, _) = space0(rest)?;
+    let (rest, _) = tag_no_case("%pc")(rest)?;
+    let (rest, _) = space0(rest)?;
+    let (rest, _) = char(')')(rest)?;
+    Ok((rest, ()))
+}
+
+fn parse_relative<'a, Error: ParseError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, InstructionArgument<RelativeLocation>, Error> {
+    let (rest, value) = parse_expression(input)?;
+    let value = value.with_location((input, input, rest));
+    let (rest, ()) = parse_relative_suffix(rest)?;
+    Ok((rest, InstructionArgument::Relative(value)))
+}
+
+fn parse_relative_direct<'a, Error: ParseError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, InstructionArgument<RelativeLocation>, Error> {
+    let (rest, _) = char('[')(input)?;
+    let (rest, _) = space0(rest)?;
+    let start = rest;
+    let (rest, value) = parse_expression(rest)?;
+    let value = value.with_location((input, start, rest));
+    let (rest, ()) = parse_relative_suffix(rest)?;
+    let (rest, _) = space0(rest)?;
+    let (rest, _) = char(']')(rest)?;
+    Ok((rest, InstructionArgument::RelativeDirect(value)))
+}
+
 fn parse_indirect<'a, Error: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, InstructionArgument<RelativeLocation>, Error> {
@@ -450,11 +872,59 @@ fn parse_indirect<'a, Error: ParseError<&'a str>>(
     Ok((rest, InstructionArgument::Indirect(register)))
 }
 
+fn parse_post_increment<'a, Error: ParseError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, InstructionArgument<RelativeLocation>, Error> {
+    let (rest, _) = char('[')(input)?;
+    let (rest, _) = space0(rest)?;
+    let start = rest;
+    let (rest, register) = parse_register(rest)?;
+    let register = register.with_location((input, start, rest));
+    let (rest, _) = space0(rest)?;
+    let (rest, _) = char(']')(rest)?;
+    let (rest, _) = char('+')(rest)?;
+    Ok((rest, InstructionArgument::PostIncrement(register)))
+}
+
+fn parse_pre_decrement<'a, Error: ParseError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, InstructionArgument<RelativeLocation>, Error> {
+    let (rest, _) = char('-')(input)?;
+    let (rest, _) = space0(rest)?;
+    let (rest, _) = char('[')(rest)?;
+    let (rest, _) = space0(rest)?;
+    let start = rest;
+    let (rest, register) = parse_register(rest)?;
+    let register = register.with_location((input, start, rest));
+    let (rest, _) = space0(rest)?;
+    let (rest, _) = char(']')(rest)?;
+    Ok((rest, InstructionArgument::PreDecrement(register)))
+}
+
 #[cfg(test)]
 mod tests {
     use pretty_assertions::assert_eq;
 
     use super::*;
+    use crate::parser::expression::EmptyContext;
+
+    #[test]
+    fn parse_instruction_kind_test() {
+        for mnemonic in crate::runtime::mnemonics() {
+            let (rest, kind) = parse_instruction_kind::<()>(mnemonic).unwrap();
+            assert_eq!(rest, "");
+            assert_eq!(kind.to_string(), mnemonic);
+        }
+
+        let (rest, kind) = parse_instruction_kind::<()>("JLEU label").unwrap();
+        assert_eq!(rest, " label");
+        assert_eq!(kind, InstructionKind::Jleu);
+
+        // Only whole words are mnemonics
+        assert!(parse_instruction_kind::<()>("ldx %a").is_err());
+        assert!(parse_instruction_kind::<()>("debugregs").is_err());
+        assert!(parse_instruction_kind::<()>("").is_err());
+    }
 
     #[test]
     fn parse_register_test() {
@@ -483,6 +953,72 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_post_increment_pre_decrement_test() {
+        let (input, node) = parse_instruction_argument::<()>("[%a]+").unwrap();
+        assert_eq!(input, "");
+        assert_eq!(
+            node,
+            InstructionArgument::PostIncrement(Reg::A.with_location((1, 2)))
+        );
+
+        let (input, node) = parse_instruction_argument::<()>("- [ %sp ]").unwrap();
+        assert_eq!(input, "");
+        assert_eq!(
+            node,
+            InstructionArgument::PreDecrement(Reg::SP.with_location((4, 3)))
+        );
+        assert_eq!(node.to_string(), "-[%sp]");
+
+        // Negated values are still immediate values
+        let (_, node) = parse_instruction_argument::<()>("-5").unwrap();
+        assert_eq!(node.to_string(), "-5");
+    }
+
+    #[test]
+    fn parse_scaled_test() {
+        let (input, node) = parse_scaled::<()>("[%a + %b * 4]").unwrap();
+        assert_eq!(input, "");
+        assert_eq!(
+            node,
+            InstructionArgument::Scaled {
+                base: Reg::A.with_location((1, 2)),
+                index: Reg::B.with_location((6, 2)),
+                scale: Node::Literal(4).with_location((11, 1)),
+            }
+        );
+
+        let (_, node) = parse_instruction_argument::<()>("[%sp+%a]").unwrap();
+        assert_eq!(node.to_string(), "[%sp + %a * 1]");
+        let (_, node) = parse_instruction_argument::<()>("[%a + %b * (ROW + 1)]").unwrap();
+        assert_eq!(node.to_string(), "[%a + %b * (ROW + 1)]");
+
+        // The scale is a single term
+        assert!(parse_instruction_argument::<()>("[%a + %b * 2 + 1]").is_err());
+    }
+
+    #[test]
+    fn parse_relative_test() {
+        let (input, node) = parse_instruction_argument::<()>("loop(%pc)").unwrap();
+        assert_eq!(input, "");
+        assert_eq!(
+            node,
+            InstructionArgument::Relative(Node::Variable("loop".into()).with_location((0, 4)))
+        );
+
+        let (input, node) = parse_instruction_argument::<()>("[ table + 2 ( %PC ) ]").unwrap();
+        assert_eq!(input, "");
+        assert_eq!(node.to_string(), "[table + 2(%pc)]");
+
+        // Without the suffix, this is an immediate value
+        let (input, node) = parse_instruction_argument::<()>("loop").unwrap();
+        assert_eq!(input, "");
+        assert_eq!(
+            node,
+            InstructionArgument::Value(Node::Variable("loop".into()))
+        );
+    }
+
     #[test]
     fn parse_indexed_test() {
         let (input, node) = parse_indexed::<()>("[%a+2]").unwrap();
@@ -494,5 +1030,19 @@ mod tests {
                 value: Node::Literal(2).with_location((3, 2)),
             }
         );
+
+        let (input, node) = parse_indexed::<()>("[%sp - 2 + 1]").unwrap();
+        assert_eq!(input, "");
+        assert_eq!(node.to_string(), "[%sp - 2 + 1]");
+        let InstructionArgument::Indexed { value, .. } = node else {
+            panic!("expected an indexed argument");
+        };
+        let offset: Word = value.inner.evaluate(&EmptyContext).unwrap();
+        assert_eq!(offset, -1);
+
+        let (_, node) = parse_indexed::<()>("[%a + -3]").unwrap();
+        assert_eq!(node.to_string(), "[%a - 3]");
+        let (_, node) = parse_indexed::<()>("[%b + (1 - 2) * 4]").unwrap();
+        assert_eq!(node.to_string(), "[%b + (1 - 2) * 4]");
     }
 }
@@ -0,0 +1,123 @@
+//! Decoding of the source files into text
+//!
+//! Sources are expected in UTF-8, but files saved on other systems often start with a byte order
+//! mark, are encoded in UTF-16, or end their lines with `\r\n` or `\r`. Those are transparently
+//! converted, and anything else is rejected with the position of the first invalid byte.
+
+use thiserror::Error;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+const UTF16_LE_BOM: &[u8] = b"\xFF\xFE";
+const UTF16_BE_BOM: &[u8] = b"\xFE\xFF";
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum EncodingError {
+    #[error("invalid UTF-8 at line {line}, column {column}, save the file as UTF-8")]
+    InvalidUtf8 { line: usize, column: usize },
+
+    #[error("invalid UTF-16, save the file as UTF-8")]
+    InvalidUtf16,
+}
+
+/// Decode UTF-16 code units, in little or big endian
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Result<String, EncodingError> {
+    let pairs = bytes.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return Err(EncodingError::InvalidUtf16);
+    }
+
+    let units = pairs.map(|pair| from_bytes([pair[0], pair[1]]));
+    char::decode_utf16(units)
+        .collect::<Result<_, _>>()
+        .map_err(|_| EncodingError::InvalidUtf16)
+}
+
+/// Replace the `\r\n` and `\r` line endings with `\n`
+#[must_use]
+pub fn normalize_line_endings(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+/// Decode the content of a source file
+///
+/// # Errors
+///
+/// Fails if the content is neither valid UTF-8 nor UTF-16 starting with a byte order mark.
+pub fn decode(bytes: &[u8]) -> Result<String, EncodingError> {
+    let text = if let Some(bytes) = bytes.strip_prefix(UTF16_LE_BOM) {
+        decode_utf16(bytes, u16::from_le_bytes)?
+    } else if let Some(bytes) = bytes.strip_prefix(UTF16_BE_BOM) {
+        decode_utf16(bytes, u16::from_be_bytes)?
+    } else {
+        let bytes = bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes);
+        match std::str::from_utf8(bytes) {
+            Ok(text) => text.to_owned(),
+            Err(e) => {
+                // The part before the error is valid, and tells where it is
+                let valid = std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or_default();
+                let valid = normalize_line_endings(valid);
+                let line_start = valid.rfind('\n').map_or(0, |i| i + 1);
+                return Err(EncodingError::InvalidUtf8 {
+                    line: valid.matches('\n').count() + 1,
+                    column: valid[line_start..].chars().count() + 1,
+                });
+            }
+        }
+    };
+
+    Ok(normalize_line_endings(&text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::XorShift;
+
+    #[test]
+    fn decode_test() {
+        assert_eq!(decode(b"main: reset\n"), Ok("main: reset\n".to_owned()));
+        assert_eq!(
+            decode(b"\xEF\xBB\xBFmain:\r\n reset\r"),
+            Ok("main:\n reset\n".to_owned())
+        );
+        assert_eq!(
+            decode(b"\xFF\xFEm\0a\0i\0n\0:\0\n\0"),
+            Ok("main:\n".to_owned())
+        );
+        assert_eq!(
+            decode(b"\xFE\xFF\0m\0a\0i\0n\0:\0\n"),
+            Ok("main:\n".to_owned())
+        );
+        assert_eq!(decode(b"\xFF\xFEm"), Err(EncodingError::InvalidUtf16));
+
+        // Latin-1, as saved by some editors
+        assert_eq!(
+            decode(b"main:\n  .string \"caf\xE9\"\n"),
+            Err(EncodingError::InvalidUtf8 {
+                line: 2,
+                column: 15
+            })
+        );
+        assert_eq!(
+            decode(b"\xE9"),
+            Err(EncodingError::InvalidUtf8 { line: 1, column: 1 })
+        );
+    }
+
+    #[test]
+    fn decode_fuzz_test() {
+        // Deterministic pseudo-random inputs: decoding never panics, and what it produces only
+        // ends its lines with `\n`
+        let mut random = XorShift::new(0x2545_F491_4F6C_DD1D);
+        for _ in 0..2000 {
+            let mut bytes = Vec::new();
+            for _ in 0..random.below(64) {
+                bytes.push(u8::try_from(random.below(256)).unwrap());
+            }
+
+            if let Ok(text) = decode(&bytes) {
+                assert!(!text.contains('\r'));
+            }
+        }
+    }
+}
@@ -4,6 +4,27 @@ use std::{
     path::{Path, PathBuf},
 };
 
+/// Root of the virtual directory holding the bundled library, included with `#include <std/stack.s>`
+pub const LIBRARY_ROOT: &str = "<library>";
+
+/// Files of the bundled library, relative to its root
+const LIBRARY: &[(&str, &str)] = &[
+    ("std/heap.s", include_str!("library/std/heap.s")),
+    ("std/memcpy.s", include_str!("library/std/memcpy.s")),
+    ("std/print.s", include_str!("library/std/print.s")),
+    ("std/stack.s", include_str!("library/std/stack.s")),
+];
+
+/// Get the content of a file of the bundled library, from its path under [`LIBRARY_ROOT`]
+#[must_use]
+pub fn library_file(path: &Path) -> Option<&'static str> {
+    let path = path.strip_prefix(LIBRARY_ROOT).ok()?;
+    LIBRARY
+        .iter()
+        .find(|(name, _)| Path::new(name) == path)
+        .map(|(_, content)| *content)
+}
+
 pub trait Filesystem {
     type File: Read;
 
@@ -19,6 +40,11 @@ pub trait Filesystem {
             .map_or_else(|| self.root(), ToOwned::to_owned) // Default to the "root" path
             .join(path) // And join relative to that
     }
+
+    /// Resolve a path included between angle brackets, in the bundled library
+    fn library(&self, path: &Path) -> PathBuf {
+        Path::new(LIBRARY_ROOT).join(path)
+    }
 }
 
 pub struct InMemoryFilesystem {
@@ -45,13 +71,32 @@ impl Filesystem for InMemoryFilesystem {
 
 pub struct NativeFilesystem {
     root: PathBuf,
+
+    /// Directories searched for the files included between angle brackets, before the library
+    include_dirs: Vec<PathBuf>,
 }
 
 impl NativeFilesystem {
+    /// Resolve paths relative to the given root directory
+    #[must_use]
+    pub const fn new(root: PathBuf) -> Self {
+        NativeFilesystem {
+            root,
+            include_dirs: Vec::new(),
+        }
+    }
+
     pub fn from_env() -> std::io::Result<Self> {
-        Ok(NativeFilesystem {
-            root: std::env::current_dir()?,
-        })
+        Ok(Self::new(std::env::current_dir()?))
+    }
+
+    /// Search these directories for the files included between angle brackets, in order
+    #[must_use]
+    pub fn with_include_dirs(self, include_dirs: Vec<PathBuf>) -> Self {
+        Self {
+            include_dirs,
+            ..self
+        }
     }
 }
 
@@ -65,4 +110,12 @@ impl Filesystem for NativeFilesystem {
     fn root(&self) -> PathBuf {
         self.root.clone()
     }
+
+    fn library(&self, path: &Path) -> PathBuf {
+        self.include_dirs
+            .iter()
+            .map(|dir| self.root.join(dir).join(path))
+            .find(|candidate| candidate.is_file())
+            .unwrap_or_else(|| Path::new(LIBRARY_ROOT).join(path))
+    }
 }
@@ -0,0 +1,28 @@
+// Dynamic allocation, with the allocator device attached on ports 2 and 3
+//
+// Like any in or out instruction, these routines need the supervisor mode.
+
+#if !defined(STD_HEAP_S)
+#define STD_HEAP_S
+
+#include "stack.s"
+
+// Allocate the number of cells given as argument, and return the address of the block in %a,
+// or 0 when the heap is full
+malloc:
+    PROLOGUE
+    ld   ARG1, %b
+    out  %b, [2]
+    in   [2], %a
+    EPILOGUE
+    rtn
+
+// Free the block at the address given as argument
+free:
+    PROLOGUE
+    ld   ARG1, %b
+    out  %b, [3]
+    EPILOGUE
+    rtn
+
+#endif
@@ -0,0 +1,35 @@
+// Copy of memory areas
+
+#if !defined(STD_MEMCPY_S)
+#define STD_MEMCPY_S
+
+#include "stack.s"
+
+// Copy ARG3 cells from the address ARG2 to the address ARG1
+//
+// The arguments are used as counters, so they are modified by the call. Like with any st
+// instruction, characters are copied as their code.
+memcpy:
+    PROLOGUE
+    push %a
+memcpy_loop:
+    ld   [%sp+5], %a     // cells left to copy
+    cmp  0, %a
+    jge  memcpy_end
+    sub  1, %a
+    st   %a, [%sp+5]
+    ld   [%sp+4], %b     // source
+    ld   [%b], %a
+    add  1, %b
+    st   %b, [%sp+4]
+    ld   [%sp+3], %b     // destination
+    st   %a, [%b]
+    add  1, %b
+    st   %b, [%sp+3]
+    jmp  memcpy_loop
+memcpy_end:
+    pop  %a
+    EPILOGUE
+    rtn
+
+#endif
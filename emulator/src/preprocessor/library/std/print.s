@@ -0,0 +1,36 @@
+// Printing on the console, attached on port 0
+//
+// Like any out instruction, these routines need the supervisor mode.
+
+#if !defined(STD_PRINT_S)
+#define STD_PRINT_S
+
+#include "stack.s"
+
+// Print the number given as argument, in decimal
+print_number:
+    PROLOGUE
+    ld   ARG1, %b
+    out  %b, [0]
+    EPILOGUE
+    rtn
+
+// Print the string at the address given as argument, up to the first cell holding zero, like a
+// .word 0 following its .string
+print_string:
+    PROLOGUE
+    push %a
+    ld   [%sp+3], %b
+print_string_loop:
+    ld   [%b], %a
+    cmp  0, %a
+    jeq  print_string_end
+    out  %a, [0]
+    add  1, %b
+    jmp  print_string_loop
+print_string_end:
+    pop  %a
+    EPILOGUE
+    rtn
+
+#endif
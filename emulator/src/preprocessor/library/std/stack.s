@@ -0,0 +1,24 @@
+// Stack frames
+//
+// Routines are called with their arguments pushed in reverse order, and pop them after the
+// call:
+//
+//     push 2         // second argument
+//     push 1         // first argument
+//     call routine
+//     add  2, %sp
+//
+// A routine saves %b with PROLOGUE, and restores it with EPILOGUE before returning with rtn.
+// In between, as long as it pushes nothing else, its arguments are ARG1, ARG2 and ARG3.
+
+#if !defined(STD_STACK_S)
+#define STD_STACK_S
+
+#define PROLOGUE push %b
+#define EPILOGUE pop %b
+
+#define ARG1 [%sp+2]
+#define ARG2 [%sp+3]
+#define ARG3 [%sp+4]
+
+#endif
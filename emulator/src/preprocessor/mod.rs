@@ -1,10 +1,14 @@
 use std::{
+    borrow::Cow,
     collections::HashMap,
     io::Read,
     path::{Path, PathBuf},
 };
 
-use nom::{combinator::all_consuming, error::convert_error, Finish, Offset};
+use nom::{
+    character::complete::space0, combinator::all_consuming, error::convert_error,
+    sequence::terminated, Finish,
+};
 use thiserror::Error;
 use unicode_segmentation::UnicodeSegmentation;
 
@@ -12,14 +16,22 @@ use crate::parser::{
     condition::{
         parse_condition, Context as ConditionContext, EvaluationError as ConditionEvaluationError,
     },
-    expression::EmptyContext as EmptyExpressionContext,
-    location::{AbsoluteLocation, Locatable, Located, MapLocation, RelativeLocation},
-    preprocessor::{parse, Node},
+    expression::{
+        parse_expression, Context as ExpressionContext, EmptyContext as EmptyExpressionContext,
+        Value,
+    },
+    literal::{parse_bool_literal, parse_string_literal},
+    location::{AbsoluteLocation, Locatable, Located, MapLocation},
+    preprocessor::{check_nesting, parse, Misnesting, MisnestingKind, Node},
 };
 
+mod encoding;
 mod fs;
+mod source_map;
 
-pub use fs::{Filesystem, InMemoryFilesystem, NativeFilesystem};
+pub use encoding::{decode, normalize_line_endings, EncodingError};
+pub use fs::{library_file, Filesystem, InMemoryFilesystem, NativeFilesystem, LIBRARY_ROOT};
+pub use source_map::{Expansion, Origin, Provenance, SourceMap};
 
 #[derive(Debug, Error, Clone)]
 pub enum GetFileError {
@@ -31,8 +43,20 @@ pub enum GetFileError {
     #[error("i/o error: {0}")]
     IO(#[from] std::sync::Arc<std::io::Error>),
 
+    /// The file is not valid UTF-8
+    #[error(transparent)]
+    Encoding(#[from] EncodingError),
+
     #[error("parse error: {message}")]
     ParseError { message: String },
+
+    /// A conditional directive without its matching `#if` or `#endif`
+    #[error("{message}")]
+    Misnested {
+        message: String,
+        offset: usize,
+        length: usize,
+    },
 }
 
 struct ParsedFile<L> {
@@ -67,10 +91,40 @@ where
     }
 }
 
+/// Explain a misnesting, telling where the unterminated `#if` was opened
+fn misnested(path: &Path, content: &str, misnesting: Misnesting) -> GetFileError {
+    let message = match misnesting.kind {
+        MisnestingKind::AfterElse(directive) => format!("#{directive} after #else"),
+        MisnestingKind::Unopened(directive) => format!("#{directive} without #if"),
+        MisnestingKind::Unterminated => {
+            let line = content[..misnesting.offset].matches('\n').count() + 1;
+            format!("unterminated #if opened at {}:{line}", path.display())
+        }
+    };
+
+    GetFileError::Misnested {
+        message,
+        offset: misnesting.offset,
+        length: misnesting.length,
+    }
+}
+
 #[derive(Default)]
 struct ParserCache {
     files: HashMap<PathBuf, Result<ParsedFile<AbsoluteLocation<PathBuf>>, GetFileError>>,
     sources: HashMap<PathBuf, String>,
+
+    /// Offset of the start of each line of the sources
+    line_starts: HashMap<PathBuf, Vec<usize>>,
+}
+
+/// Resolve the path of an included file, relative to the including one or in the bundled library
+fn inclusion_path<FS: Filesystem>(fs: &FS, from: &Path, path: &str, library: bool) -> PathBuf {
+    if library {
+        fs.library(Path::new(path))
+    } else {
+        fs.relative(Some(from), Path::new(path))
+    }
 }
 
 impl ParserCache {
@@ -93,19 +147,31 @@ impl ParserCache {
         }
 
         let res = (|| {
-            let content = {
+            let content = if path.starts_with(LIBRARY_ROOT) {
+                let content = library_file(path).ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::NotFound, "not in the library")
+                });
+                content.map_err(std::sync::Arc::new)?.to_owned()
+            } else {
                 let mut f = fs.open(path).map_err(std::sync::Arc::new)?;
-                let mut buf = String::new();
-                f.read_to_string(&mut buf).map_err(std::sync::Arc::new)?;
-                buf
+                let mut buf = Vec::new();
+                f.read_to_end(&mut buf).map_err(std::sync::Arc::new)?;
+                decode(&buf)?
             };
 
             self.sources.insert(path.to_path_buf(), content.clone());
+            let line_starts = std::iter::once(0)
+                .chain(content.match_indices('\n').map(|(index, _)| index + 1))
+                .collect();
+            self.line_starts.insert(path.to_path_buf(), line_starts);
 
             let (_, chunks) = all_consuming(parse)(content.as_str())
                 .finish()
-                .map_err(|e| GetFileError::ParseError {
-                    message: convert_error(content.as_str(), e),
+                .map_err(|e| match check_nesting(&content) {
+                    Err(misnesting) => misnested(path, &content, misnesting),
+                    Ok(()) => GetFileError::ParseError {
+                        message: convert_error(content.as_str(), e),
+                    },
                 })?;
 
             let parent = AbsoluteLocation {
@@ -119,17 +185,19 @@ impl ParserCache {
         let mut paths: Vec<PathBuf> = Vec::new();
         if let Ok(ref file) = res {
             file.walk(|node| {
-                if let Node::Inclusion { path: inclusion } = node {
-                    paths.push(inclusion.inner.clone().into());
+                if let Node::Inclusion {
+                    path: inclusion,
+                    library,
+                } = node
+                {
+                    paths.push(inclusion_path(fs, path, &inclusion.inner, *library));
                 }
             });
         }
 
         self.files.insert(path.to_path_buf(), res);
 
-        let parent = path;
         for path in paths {
-            let path = fs.relative(Some(parent), &path);
             self.fill(&path, fs);
         }
     }
@@ -143,10 +211,13 @@ pub enum PreprocessorError<L> {
     #[error("user error: {message}")]
     UserError { location: L, message: String },
 
+    #[error("{message}")]
+    Misnested { location: L, message: String },
+
     #[error("invalid syntax in condition")]
     ConditionParse { location: L },
 
-    #[error("could not evaluate condition")]
+    #[error("could not evaluate condition: {0}")]
     ConditionEvaluation(#[from] ConditionEvaluationError<L>),
 }
 
@@ -155,6 +226,7 @@ impl<L> PreprocessorError<L> {
         match self {
             PreprocessorError::GetFile { .. } => None,
             PreprocessorError::UserError { location, .. }
+            | PreprocessorError::Misnested { location, .. }
             | PreprocessorError::ConditionParse { location } => Some(location),
             PreprocessorError::ConditionEvaluation(e) => Some(e.location()),
         }
@@ -164,13 +236,42 @@ impl<L> PreprocessorError<L> {
 #[derive(Default)]
 struct Context {
     definitions: HashMap<String, Option<String>>,
+
+    /// Where the definitions were made, missing for the predefined ones
+    locations: HashMap<String, AbsoluteLocation<PathBuf>>,
+
+    /// Where the `#include` directives being processed are, from the outermost one
+    inclusions: Vec<Origin>,
+
+    /// The last `#line` directive of the file being processed
+    line_marker: Option<LineMarker>,
+}
+
+/// A `#line` directive, renaming the lines which follow it in its file
+struct LineMarker {
+    /// Line of the directive in its file
+    at: usize,
+
+    /// Number given to the line following the directive
+    line: usize,
+
+    file: PathBuf,
+}
+
+impl LineMarker {
+    fn rename(&self, origin: &Origin) -> Origin {
+        Origin {
+            file: self.file.clone(),
+            line: (self.line + origin.line).saturating_sub(self.at + 1),
+        }
+    }
 }
 
 impl ConditionContext for Context {
-    type ExpressionContext = EmptyExpressionContext;
+    type ExpressionContext = Self;
 
     fn get_expression_context(&self) -> &Self::ExpressionContext {
-        &EmptyExpressionContext
+        self
     }
 
     fn is_defined(&self, variable: &str) -> bool {
@@ -178,6 +279,20 @@ impl ConditionContext for Context {
     }
 }
 
+/// Definitions are variables in conditions, valued by evaluating their content. Their content
+/// being already expanded, it is evaluated without any variable.
+impl ExpressionContext for Context {
+    fn resolve_variable(&self, variable: &str) -> Option<Value> {
+        let content = self.definitions.get(variable)?.as_deref()?.trim();
+        if let Ok((_, literal)) = all_consuming(parse_bool_literal::<_, ()>)(content) {
+            return Some(literal.into());
+        }
+
+        let (_, node) = all_consuming(parse_expression::<()>)(content).ok()?;
+        node.evaluate(&EmptyExpressionContext).ok()
+    }
+}
+
 impl Context {
     fn define(&mut self, key: String, content: Option<String>) {
         self.definitions.insert(key, content);
@@ -185,27 +300,67 @@ impl Context {
 
     fn undefine(&mut self, key: &str) {
         self.definitions.remove(key);
+        self.locations.remove(key);
     }
 
-    fn replace<'a>(&'a self, input: &'a str) -> Vec<Located<&'a str, RelativeLocation>> {
+    /// The definitions which would be expanded in the input, in order of appearance, with where
+    /// they were defined
+    fn expansions<'a>(&self, input: &'a str) -> Vec<(&'a str, Option<&AbsoluteLocation<PathBuf>>)> {
+        let mut expansions: Vec<(&str, _)> = Vec::new();
+        for word in input.split_word_bounds() {
+            if self.definitions.contains_key(word) && expansions.iter().all(|(w, _)| *w != word) {
+                expansions.push((word, self.locations.get(word)));
+            }
+        }
+        expansions
+    }
+
+    /// Replace the definitions in the input, which is borrowed if there is nothing to replace
+    fn replace<'a>(&self, input: &'a str) -> Cow<'a, str> {
+        let defined = |word| self.definitions.contains_key(word);
+        if self.definitions.is_empty() || !input.split_word_bounds().any(defined) {
+            return Cow::Borrowed(input);
+        }
+
         input
             .split_word_bounds()
-            .filter_map(|word| {
-                let location = RelativeLocation::from((input.offset(word), word.len()));
-                let replaced = match self.definitions.get(word) {
-                    Some(Some(r)) => r,
-                    Some(None) => return None,
-                    None => word,
-                };
-                Some(replaced.with_location(location))
+            .filter_map(|word| match self.definitions.get(word) {
+                Some(Some(r)) => Some(r.as_str()),
+                Some(None) => None,
+                None => Some(word),
             })
-            .collect()
+            .collect::<String>()
+            .into()
+    }
+}
+
+/// The preprocessed output, written line by line
+#[derive(Default)]
+struct Output {
+    source: String,
+    lines: usize,
+
+    /// Where the lines come from, only tracked when a source map is requested
+    map: Option<SourceMap>,
+}
+
+impl Output {
+    fn push(&mut self, line: &str, provenance: impl FnOnce() -> Provenance) {
+        if self.lines > 0 {
+            self.source.push('\n');
+        }
+        if let Some(map) = &mut self.map {
+            map.push(self.source.len(), provenance());
+        }
+        self.source.push_str(line);
+        self.lines += 1;
     }
 }
 
 pub struct Preprocessor<FS> {
     cache: ParserCache,
     fs: FS,
+    definitions: HashMap<String, Option<String>>,
 }
 
 impl<FS> Preprocessor<FS> {
@@ -213,9 +368,21 @@ impl<FS> Preprocessor<FS> {
         Self {
             cache: ParserCache::new(),
             fs,
+            definitions: HashMap::new(),
         }
     }
 
+    /// Define a macro before preprocessing, like a `#define` at the top of the entrypoint
+    pub fn define(&mut self, key: impl Into<String>, content: Option<String>) {
+        self.definitions.insert(key.into(), content);
+    }
+
+    #[must_use]
+    pub fn and_define(mut self, key: impl Into<String>, content: Option<String>) -> Self {
+        self.define(key, content);
+        self
+    }
+
     #[must_use]
     pub fn and_load(mut self, entrypoint: &Path) -> Self
     where
@@ -241,40 +408,204 @@ impl<FS> Preprocessor<FS> {
         &self,
         entrypoint: &Path,
     ) -> Result<String, PreprocessorError<AbsoluteLocation<PathBuf>>>
+    where
+        FS: Filesystem,
+    {
+        let output = self.run(entrypoint, Output::default())?;
+        Ok(output.source)
+    }
+
+    /// Preprocess a file, also returning where each line of the output comes from
+    pub fn preprocess_with_map(
+        &self,
+        entrypoint: &Path,
+    ) -> Result<(String, SourceMap), PreprocessorError<AbsoluteLocation<PathBuf>>>
+    where
+        FS: Filesystem,
+    {
+        let output = Output {
+            map: Some(SourceMap::default()),
+            ..Output::default()
+        };
+        let output = self.run(entrypoint, output)?;
+        Ok((output.source, output.map.unwrap_or_default()))
+    }
+
+    fn run(
+        &self,
+        entrypoint: &Path,
+        mut output: Output,
+    ) -> Result<Output, PreprocessorError<AbsoluteLocation<PathBuf>>>
     where
         FS: Filesystem,
     {
         let path = self.fs.relative(None, entrypoint);
-        let mut ctx = Context::default();
-        let chunks = self.preprocess_path(&path, &mut ctx)?;
+        let mut ctx = Context {
+            definitions: self.definitions.clone(),
+            ..Context::default()
+        };
+        self.preprocess_path(&path, &mut ctx, &mut output)?;
+        Ok(output)
+    }
+
+    /// The file and line of a location
+    fn origin(&self, location: &AbsoluteLocation<PathBuf>) -> Origin {
+        let file = &location.file;
+        let line = self.cache.line_starts.get(file).map_or(0, |starts| {
+            starts.partition_point(|&start| start <= location.offset)
+        });
+        Origin {
+            file: file.clone(),
+            line,
+        }
+    }
 
-        Ok(chunks.join("\n"))
+    /// The file and line of a location, as renamed by the `#line` directive in effect
+    fn presumed_origin(&self, location: &AbsoluteLocation<PathBuf>, ctx: &Context) -> Origin {
+        let origin = self.origin(location);
+        match &ctx.line_marker {
+            Some(marker) => marker.rename(&origin),
+            None => origin,
+        }
     }
 
     fn preprocess_path(
         &self,
         path: &Path,
         ctx: &mut Context,
-    ) -> Result<Vec<String>, PreprocessorError<AbsoluteLocation<PathBuf>>>
+        output: &mut Output,
+    ) -> Result<(), PreprocessorError<AbsoluteLocation<PathBuf>>>
     where
         FS: Filesystem,
     {
-        let mut buf = Vec::new();
-        let file =
-            self.cache
-                .get_file(path)
-                .as_ref()
-                .map_err(|inner| PreprocessorError::GetFile {
+        let file = self
+            .cache
+            .get_file(path)
+            .as_ref()
+            .map_err(|inner| match inner {
+                GetFileError::Misnested {
+                    message,
+                    offset,
+                    length,
+                } => PreprocessorError::Misnested {
+                    location: AbsoluteLocation {
+                        offset: *offset,
+                        length: *length,
+                        file: path.to_path_buf(),
+                    },
+                    message: message.clone(),
+                },
+                _ => PreprocessorError::GetFile {
                     path: path.to_path_buf(),
                     inner: inner.clone(),
-                })?;
+                },
+            })?;
 
-        for chunk in &file.chunks {
-            let content = self.process_chunk(chunk, ctx, path)?;
-            buf.extend(content);
+        self.process_chunks(&file.chunks, ctx, path, output)
+    }
+
+    fn process_chunks(
+        &self,
+        chunks: &[Located<Node<AbsoluteLocation<PathBuf>>, AbsoluteLocation<PathBuf>>],
+        ctx: &mut Context,
+        open_path: &Path,
+        output: &mut Output,
+    ) -> Result<(), PreprocessorError<AbsoluteLocation<PathBuf>>>
+    where
+        FS: Filesystem,
+    {
+        for chunk in chunks {
+            self.process_chunk(chunk, ctx, open_path, output)?;
         }
+        Ok(())
+    }
 
-        Ok(buf)
+    /// Write lines of text, replacing the definitions in them and the `.incbin` directives
+    fn process_raw(
+        &self,
+        content: &str,
+        location: &AbsoluteLocation<PathBuf>,
+        ctx: &Context,
+        open_path: &Path,
+        output: &mut Output,
+    ) -> Result<(), PreprocessorError<AbsoluteLocation<PathBuf>>>
+    where
+        FS: Filesystem,
+    {
+        let start = self.presumed_origin(location, ctx);
+        for (index, line) in content.split('\n').enumerate() {
+            // Replace the definitions in the line
+            let mut text = ctx.replace(line);
+            if let Some(bytes) = self.include_binary(&text, open_path)? {
+                text = Cow::Owned(bytes);
+            }
+            output.push(&text, || Provenance {
+                origin: Origin {
+                    file: start.file.clone(),
+                    line: start.line + index,
+                },
+                included_from: ctx.inclusions.iter().rev().cloned().collect(),
+                expansions: ctx
+                    .expansions(line)
+                    .into_iter()
+                    .map(|(name, location)| Expansion {
+                        name: name.to_owned(),
+                        defined_at: location.map(|location| self.origin(location)),
+                    })
+                    .collect(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Replace an `.incbin "file"` directive with a `.byte` directive holding the bytes of the
+    /// file, whose path is relative to the one being processed
+    ///
+    /// Returns `None` if the line has no such directive.
+    fn include_binary(
+        &self,
+        line: &str,
+        open_path: &Path,
+    ) -> Result<Option<String>, PreprocessorError<AbsoluteLocation<PathBuf>>>
+    where
+        FS: Filesystem,
+    {
+        const DIRECTIVE: &str = ".incbin";
+        let Some(index) = line.to_ascii_lowercase().find(DIRECTIVE) else {
+            return Ok(None);
+        };
+
+        // The directive can only follow a label, and malformed ones are reported by the parser
+        let (prefix, rest) = line.split_at(index);
+        let label = prefix.trim();
+        if !(label.is_empty() || label.ends_with(':') && !label.contains('"')) {
+            return Ok(None);
+        }
+        let rest = &rest[DIRECTIVE.len()..];
+        if !rest.starts_with([' ', '\t']) {
+            return Ok(None);
+        }
+        let Ok(("", file)) = parse_string_literal::<()>(rest.trim()) else {
+            return Ok(None);
+        };
+
+        let path = inclusion_path(&self.fs, open_path, &file, false);
+        let read = || -> std::io::Result<Vec<u8>> {
+            let mut bytes = Vec::new();
+            self.fs.open(&path)?.read_to_end(&mut bytes)?;
+            Ok(bytes)
+        };
+        let bytes = read().map_err(|e| PreprocessorError::GetFile {
+            path: path.clone(),
+            inner: GetFileError::IO(std::sync::Arc::new(e)),
+        })?;
+
+        // An empty file leaves the label alone
+        if bytes.is_empty() {
+            return Ok(Some(prefix.trim_end().to_owned()));
+        }
+        let bytes: Vec<_> = bytes.iter().map(u8::to_string).collect();
+        Ok(Some(format!("{prefix}.byte {}", bytes.join(", "))))
     }
 
     fn process_chunk(
@@ -282,15 +613,14 @@ impl<FS> Preprocessor<FS> {
         chunk: &Located<Node<AbsoluteLocation<PathBuf>>, AbsoluteLocation<PathBuf>>,
         ctx: &mut Context,
         open_path: &Path,
-    ) -> Result<Vec<String>, PreprocessorError<AbsoluteLocation<PathBuf>>>
+        output: &mut Output,
+    ) -> Result<(), PreprocessorError<AbsoluteLocation<PathBuf>>>
     where
         FS: Filesystem,
     {
         match &chunk.inner {
             Node::Raw { ref content } => {
-                // Replace the definitions in the content
-                let replaced = ctx.replace(content);
-                Ok(vec![replaced.into_iter().map(|l| l.inner).collect()])
+                self.process_raw(content, &chunk.location, ctx, open_path, output)
             }
 
             Node::Error { ref message } => {
@@ -305,7 +635,7 @@ impl<FS> Preprocessor<FS> {
                 // Remove a definition
                 let key = &key.inner;
                 ctx.undefine(key);
-                Ok(Vec::new()) // Generates no text
+                Ok(()) // Generates no text
             }
 
             Node::Definition {
@@ -316,67 +646,82 @@ impl<FS> Preprocessor<FS> {
                 let key = key.inner.clone();
                 let content = content.as_ref().map(|i| &i.inner);
                 // First replace existing definitions in the content
-                let content =
-                    content.map(|c| ctx.replace(c).into_iter().map(|l| l.inner).collect());
+                let content = content.map(|c| ctx.replace(c).into_owned());
                 // Then add the definition
+                ctx.locations.insert(key.clone(), chunk.location.clone());
                 ctx.define(key, content);
-                Ok(Vec::new()) // Generates no text
+                Ok(()) // Generates no text
             }
 
-            Node::Inclusion { path: ref include } => {
+            Node::Inclusion {
+                path: ref include,
+                library,
+            } => {
                 // Include a file
-                let include: PathBuf = include.inner.clone().into();
                 // First resolve its path
-                let path = self.fs.relative(Some(open_path), &include);
+                let path = inclusion_path(&self.fs, open_path, &include.inner, *library);
                 // Then process it
-                let content = self.preprocess_path(&path, ctx)?;
-                Ok(content)
+                // The `#line` directives only apply to the file they are in
+                ctx.inclusions
+                    .push(self.presumed_origin(&chunk.location, ctx));
+                let line_marker = ctx.line_marker.take();
+                let result = self.preprocess_path(&path, ctx, output);
+                ctx.line_marker = line_marker;
+                ctx.inclusions.pop();
+                result
+            }
+
+            Node::Line { line, file } => {
+                // Without a file name, the lines keep their current one
+                let file = match file {
+                    Some(file) => PathBuf::from(&file.inner),
+                    None => self.presumed_origin(&chunk.location, ctx).file,
+                };
+                ctx.line_marker = Some(LineMarker {
+                    at: self.origin(&chunk.location).line,
+                    line: *line,
+                    file,
+                });
+                Ok(()) // Generates no text
             }
 
             Node::Condition { branches, fallback } => {
                 for branch in branches.iter() {
-                    let condition: String = ctx
-                        .replace(&branch.condition.inner)
-                        .into_iter()
-                        .map(|l| l.inner)
-                        .collect();
-
-                    let (_, expression) =
-                        parse_condition(&condition).finish().map_err(|_: ()| {
-                            PreprocessorError::ConditionParse {
-                                location: branch.condition.location.clone(),
-                            }
-                        })?; // TODO: wrap the error
-
-                    let expression = expression
-                        .map_location(&branch.condition.location)
-                        .with_location(branch.condition.location.clone());
-
-                    if expression.evaluate(ctx)? {
-                        let mut buf = Vec::new();
-                        for chunk in &branch.body.inner {
-                            let chunks = self.process_chunk(chunk, ctx, open_path)?;
-                            buf.extend(chunks);
-                        }
-                        return Ok(buf);
+                    if evaluate_condition(&branch.condition, ctx)? {
+                        return self.process_chunks(&branch.body.inner, ctx, open_path, output);
                     }
                 }
 
                 if let Some(ref body) = fallback {
-                    let mut buf = Vec::new();
-                    for chunk in &body.inner {
-                        let chunks = self.process_chunk(chunk, ctx, open_path)?;
-                        buf.extend(chunks);
-                    }
-                    return Ok(buf);
+                    return self.process_chunks(&body.inner, ctx, open_path, output);
                 }
 
-                Ok(Vec::new())
+                Ok(())
             }
         }
     }
 }
 
+/// Evaluate the condition of an `#if` or `#elif` directive
+fn evaluate_condition(
+    condition: &Located<String, AbsoluteLocation<PathBuf>>,
+    ctx: &Context,
+) -> Result<bool, PreprocessorError<AbsoluteLocation<PathBuf>>> {
+    // Definitions are not replaced, but resolved as variables, so the locations point into the
+    // condition as written
+    let (_, expression) = all_consuming(terminated(parse_condition, space0))(&condition.inner)
+        .finish()
+        .map_err(|_: ()| PreprocessorError::ConditionParse {
+            location: condition.location.clone(),
+        })?; // TODO: wrap the error
+
+    let expression = expression
+        .map_location(&condition.location)
+        .with_location(condition.location.clone());
+
+    Ok(expression.evaluate(ctx)?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -394,6 +739,20 @@ mod tests {
                 .into(),
             );
             t.insert("/foo.S".into(), "this is foo.S".into());
+            t.insert(
+                "/incbin.S".into(),
+                indoc::indoc! {r#"
+                    #include "data/nested.S"
+                    data: .incbin "data/bytes.bin"
+                        .INCBIN "data/empty.bin"
+                    name: .string ".incbin"
+                "#}
+                .into(),
+            );
+            t.insert("/data/nested.S".into(), "  .incbin \"bytes.bin\"".into());
+            t.insert("/data/bytes.bin".into(), "AB\u{e9}".into());
+            t.insert("/data/empty.bin".into(), String::new());
+            t.insert("/missing-incbin.S".into(), ".incbin \"missing.bin\"".into());
             t.insert(
                 "/error.S".into(),
                 indoc::indoc! {r#"
@@ -412,6 +771,7 @@ mod tests {
                 "#}
                 .into(),
             );
+            t.insert("/predefined.S".into(), "hello NAME".into());
             t.insert(
                 "/double-define.S".into(),
                 indoc::indoc! {r#"
@@ -449,9 +809,36 @@ mod tests {
                     nested
                     #endif
                     #endif
+
+                    #define STACK_SIZE 1024
+                    #if STACK_SIZE > 512 && defined(STACK_SIZE)
+                    numeric
+                    #endif
+
+                    #if STACK_SIZE / 1024 - 1
+                    not zero
+                    #endif
                 "#}
                 .into(),
             );
+            t.insert(
+                "/unterminated.S".into(),
+                indoc::indoc! {r"
+                    #if true
+                    #if false
+                    #endif
+                "}
+                .into(),
+            );
+            t.insert(
+                "/condition-error.S".into(),
+                indoc::indoc! {r"
+                    #define NAME hello
+                    #if 1 < NAME
+                    #endif
+                "}
+                .into(),
+            );
             t
         })
     }
@@ -479,6 +866,183 @@ mod tests {
         );
     }
 
+    #[test]
+    fn incbin_test() {
+        // The paths are relative to the file including the bytes
+        let res = preprocess("/incbin.S").unwrap();
+        assert_eq!(
+            res,
+            indoc::indoc! {r#"
+                  .byte 65, 66, 195, 169
+                data: .byte 65, 66, 195, 169
+
+                name: .string ".incbin"
+            "#}
+        );
+
+        let res = preprocess("/missing-incbin.S");
+        assert!(
+            matches!(res, Err(PreprocessorError::GetFile { path, .. }) if path == Path::new("/missing.bin"))
+        );
+    }
+
+    #[test]
+    fn source_map_test() {
+        let mut preprocessor = Preprocessor::new(fs());
+        let path = Path::new("/inclusion.S");
+        preprocessor.load(path);
+        let (res, map) = preprocessor.preprocess_with_map(path).unwrap();
+
+        let origin = |line: &str| map.origin(res.find(line).unwrap()).unwrap().to_string();
+        assert_eq!(origin("this is before"), "/inclusion.S:1");
+        assert_eq!(origin("this is foo.S"), "/foo.S:1");
+        assert_eq!(origin("this is after"), "/inclusion.S:3");
+    }
+
+    #[test]
+    fn provenance_test() {
+        let mut preprocessor = Preprocessor::new(fs());
+        let path = Path::new("/inclusion.S");
+        preprocessor.load(path);
+        let (res, map) = preprocessor.preprocess_with_map(path).unwrap();
+        let provenance = map.provenance(res.find("this is foo.S").unwrap()).unwrap();
+        assert_eq!(provenance.included_from[0].to_string(), "/inclusion.S:2");
+        assert_eq!(
+            map.annotate(&res),
+            indoc::indoc! {r"
+                this is before foo.S
+                this is foo.S  // included from /inclusion.S:2
+                this is after foo.S
+            "}
+        );
+
+        let path = Path::new("/define.S");
+        let preprocessor = Preprocessor::new(fs())
+            .and_define("NAME", Some("world".into()))
+            .and_load(path)
+            .and_load(Path::new("/predefined.S"));
+        let (res, map) = preprocessor.preprocess_with_map(path).unwrap();
+        assert_eq!(
+            map.annotate(&res),
+            indoc::indoc! {r"
+                hello world  // expanded from macro FOO at /define.S:1
+                helloFOO
+                hello FOO
+            "}
+        );
+
+        let path = Path::new("/predefined.S");
+        let (res, map) = preprocessor.preprocess_with_map(path).unwrap();
+        assert_eq!(
+            map.annotate(&res),
+            "hello world  // expanded from predefined macro NAME"
+        );
+    }
+
+    #[test]
+    fn line_test() {
+        let path = Path::new("/line.S");
+        let source = indoc::indoc! {r#"
+            #line 10 "gen.c"
+            first
+            second
+            #include "foo.S"
+            third
+            #line 1
+            fourth
+        "#};
+        let files = HashMap::from([
+            (path.to_path_buf(), source.to_owned()),
+            ("/foo.S".into(), "this is foo.S".to_owned()),
+        ]);
+        let preprocessor = Preprocessor::new(InMemoryFilesystem::new(files)).and_load(path);
+        let (res, map) = preprocessor.preprocess_with_map(path).unwrap();
+
+        let origin = |line: &str| map.origin(res.find(line).unwrap()).unwrap().to_string();
+        assert_eq!(origin("first"), "gen.c:10");
+        assert_eq!(origin("second"), "gen.c:11");
+        assert_eq!(origin("this is foo.S"), "/foo.S:1");
+        assert_eq!(origin("third"), "gen.c:13");
+        assert_eq!(origin("fourth"), "gen.c:1");
+        let provenance = map.provenance(res.find("this is foo.S").unwrap()).unwrap();
+        assert_eq!(provenance.included_from[0].to_string(), "gen.c:12");
+
+        // Preprocessing the output with its line markers keeps the same origins
+        let marked = map.line_markers(&res);
+        let path = Path::new("/marked.S");
+        let files = HashMap::from([(path.to_path_buf(), marked)]);
+        let preprocessor = Preprocessor::new(InMemoryFilesystem::new(files)).and_load(path);
+        let (again, remapped) = preprocessor.preprocess_with_map(path).unwrap();
+        assert_eq!(again, res);
+        let origins =
+            |map: &SourceMap| -> Vec<_> { map.lines().map(|p| p.origin.clone()).collect() };
+        assert_eq!(origins(&remapped), origins(&map));
+    }
+
+    #[test]
+    fn library_test() {
+        use crate::runtime::{Cell, Channel, Console, Reg};
+
+        let path = Path::new("/library.S");
+        let source = indoc::indoc! {r#"
+            #include <std/print.s>
+            #include <std/memcpy.s>
+            #include <std/print.s>
+
+            main:
+                push 3
+                push source
+                push destination
+                call memcpy
+                add  3, %sp
+                push greeting
+                call print_string
+                ld   [destination+2], %a
+                push %a
+                call print_number
+                add  2, %sp
+                reset
+
+            greeting: .string "hello "
+                      .word 0
+            source: .word 10
+                    .word 20
+                    .word 30
+            destination: .space 3
+        "#};
+        let files = HashMap::from([(path.to_path_buf(), source.to_owned())]);
+        let preprocessor = Preprocessor::new(InMemoryFilesystem::new(files)).and_load(path);
+        let (res, map) = preprocessor.preprocess_with_map(path).unwrap();
+        let origin = map.origin(res.find("print_number:").unwrap()).unwrap();
+        assert_eq!(origin.file, Path::new(LIBRARY_ROOT).join("std/print.s"));
+
+        let program = crate::parse(&res).unwrap();
+        let (mut computer, debug_info) = crate::compile(program.inner, "main").unwrap();
+        // The console needs the supervisor mode
+        computer
+            .registers
+            .set(Reg::SR, Cell::Word(0b010_0000_0000))
+            .unwrap();
+        let (console, output) = Console::new();
+        computer.attach(0, console).unwrap();
+        computer.run().unwrap();
+
+        assert_eq!(output.take(Channel::Stdout), "hello 30");
+        let destination = debug_info.labels["destination"];
+        assert_eq!(computer.memory.get(destination).unwrap(), &Cell::Word(10));
+        assert_eq!(
+            computer.memory.get(destination + 1).unwrap(),
+            &Cell::Word(20)
+        );
+
+        // The library is there whatever the filesystem
+        let path = Path::new("<library>/std/stack.s");
+        let preprocessor =
+            Preprocessor::new(InMemoryFilesystem::new(HashMap::new())).and_load(path);
+        assert!(preprocessor.preprocess(path).is_ok());
+        assert!(library_file(Path::new("<library>/std/missing.s")).is_none());
+    }
+
     #[test]
     fn condition_test() {
         let res = preprocess("/condition.S").unwrap();
@@ -493,8 +1057,27 @@ mod tests {
                 definition
 
                 nested
+
+                numeric
+
             "#}
         );
+
+        let error = preprocess("/condition-error.S").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "could not evaluate condition: undefined variable \"NAME\""
+        );
+        let location = error.location().unwrap();
+        assert_eq!((location.offset, location.length), (27, 4));
+
+        let error = preprocess("/unterminated.S").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unterminated #if opened at /unterminated.S:1"
+        );
+        let location = error.location().unwrap();
+        assert_eq!((location.offset, location.length), (0, 8));
     }
 
     #[test]
@@ -518,6 +1101,15 @@ mod tests {
         );
     }
 
+    #[test]
+    fn predefined_test() {
+        let path = Path::new("/predefined.S");
+        let preprocessor = Preprocessor::new(fs())
+            .and_define("NAME", Some("world".into()))
+            .and_load(path);
+        assert_eq!(preprocessor.preprocess(path).unwrap(), "hello world");
+    }
+
     #[test]
     fn user_error_test() {
         let res = preprocess("/error.S");
@@ -0,0 +1,133 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+/// The file and line a line of preprocessed output comes from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Origin {
+    pub file: PathBuf,
+
+    /// Line number in the file, starting at 1
+    pub line: usize,
+}
+
+impl std::fmt::Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file.display(), self.line)
+    }
+}
+
+/// A macro expanded in a line of preprocessed output
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Expansion {
+    pub name: String,
+
+    /// Where the macro was defined, `None` if it was defined before preprocessing
+    pub defined_at: Option<Origin>,
+}
+
+/// How a line of preprocessed output was produced
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Provenance {
+    pub origin: Origin,
+
+    /// The `#include` directives leading to the line, from the innermost one
+    pub included_from: Vec<Origin>,
+
+    /// The macros expanded in the line
+    pub expansions: Vec<Expansion>,
+}
+
+/// Maps offsets in the preprocessed output back to the original files
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    /// Start offset of each output line, with its provenance
+    lines: Vec<(usize, Provenance)>,
+}
+
+impl SourceMap {
+    pub(crate) fn push(&mut self, offset: usize, provenance: Provenance) {
+        self.lines.push((offset, provenance));
+    }
+
+    /// Find where the given offset of the preprocessed output comes from
+    #[must_use]
+    pub fn origin(&self, offset: usize) -> Option<&Origin> {
+        self.provenance(offset).map(|provenance| &provenance.origin)
+    }
+
+    /// Find how the line at the given offset of the preprocessed output was produced
+    #[must_use]
+    pub fn provenance(&self, offset: usize) -> Option<&Provenance> {
+        let index = match self
+            .lines
+            .binary_search_by_key(&offset, |(start, _)| *start)
+        {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        self.lines.get(index).map(|(_, provenance)| provenance)
+    }
+
+    /// The provenance of each line of the preprocessed output, in order
+    pub fn lines(&self) -> impl Iterator<Item = &Provenance> {
+        self.lines.iter().map(|(_, provenance)| provenance)
+    }
+
+    /// Annotate the preprocessed output with comments telling where each line comes from
+    ///
+    /// Lines from an included file are annotated when the inclusion starts, and lines with macros
+    /// when they were expanded, so the output keeps the same lines.
+    #[must_use]
+    pub fn annotate(&self, source: &str) -> String {
+        let mut output = Vec::new();
+        let mut previous: Option<&Provenance> = None;
+        for (line, provenance) in source.split('\n').zip(self.lines()) {
+            let mut notes = Vec::new();
+            let included = previous.is_none_or(|p| p.included_from != provenance.included_from);
+            if let Some(inclusion) = provenance.included_from.first().filter(|_| included) {
+                notes.push(format!("included from {inclusion}"));
+            }
+            for expansion in &provenance.expansions {
+                notes.push(match &expansion.defined_at {
+                    Some(origin) => format!("expanded from macro {} at {origin}", expansion.name),
+                    None => format!("expanded from predefined macro {}", expansion.name),
+                });
+            }
+
+            output.push(match (line.is_empty(), notes.is_empty()) {
+                (_, true) => line.to_owned(),
+                (true, false) => format!("// {}", notes.join(", ")),
+                (false, false) => format!("{line}  // {}", notes.join(", ")),
+            });
+            previous = Some(provenance);
+        }
+        output.join("\n")
+    }
+
+    /// Insert `#line` directives in the preprocessed output, where the lines stop following each
+    /// other in their original file
+    ///
+    /// Preprocessing the result again keeps the lines attributed to their original files, which
+    /// lets programs generating assembly point back to their own sources.
+    #[must_use]
+    pub fn line_markers(&self, source: &str) -> String {
+        let mut output = Vec::new();
+        let mut previous: Option<&Origin> = None;
+        for (line, provenance) in source.split('\n').zip(self.lines()) {
+            let origin = &provenance.origin;
+            let follows =
+                previous.is_some_and(|p| p.file == origin.file && p.line + 1 == origin.line);
+            if !follows {
+                let file = origin.file.display().to_string();
+                let file = file.replace('\\', "\\\\").replace('"', "\\\"");
+                output.push(format!("#line {} \"{file}\"", origin.line));
+            }
+
+            output.push(line.to_owned());
+            previous = Some(origin);
+        }
+        output.join("\n")
+    }
+}
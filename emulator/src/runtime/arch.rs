@@ -0,0 +1,356 @@
+//! Width of the words of the emulated machine
+//!
+//! The Zorglub-33 has 64-bit words, but the first exercises of the course use a simplified
+//! variant with narrower words. Words are always stored sign-extended to 64 bits: the arithmetic
+//! instructions truncate their results to the width of the machine, and set the carry and
+//! overflow flags at that width.
+//!
+//! The order of the cells of the values spanning two words, and of the bytes packed in a word,
+//! is given by an [`Endianness`].
+
+use std::str::FromStr;
+
+use parse_display::{Display, FromStr};
+use thiserror::Error;
+
+use crate::constants::{Word, WORD_BITS};
+
+/// Narrowest supported word width, in bits
+const MIN_WORD_BITS: u32 = 8;
+
+/// Description of the emulated machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[display("{word_bits}-bit")]
+pub struct Arch {
+    word_bits: u32,
+}
+
+impl Default for Arch {
+    fn default() -> Self {
+        Self {
+            word_bits: WORD_BITS,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("invalid word width {0:?}, expected a number of bits between 8 and 64")]
+pub struct ArchParseError(String);
+
+impl FromStr for Arch {
+    type Err = ArchParseError;
+
+    /// Parse a word width, like `16`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim()
+            .parse()
+            .ok()
+            .and_then(Self::new)
+            .ok_or_else(|| ArchParseError(s.to_owned()))
+    }
+}
+
+impl Arch {
+    /// A machine with words of the given width, between 8 and 64 bits
+    #[must_use]
+    pub const fn new(word_bits: u32) -> Option<Self> {
+        if word_bits < MIN_WORD_BITS || word_bits > WORD_BITS {
+            return None;
+        }
+        Some(Self { word_bits })
+    }
+
+    #[must_use]
+    pub const fn word_bits(self) -> u32 {
+        self.word_bits
+    }
+
+    /// Number of bytes held by a word, like the ones packed by `.byte` directives
+    #[must_use]
+    pub const fn word_bytes(self) -> usize {
+        let bytes = (self.word_bits / 8) as usize;
+        if bytes == 0 {
+            1
+        } else {
+            bytes
+        }
+    }
+
+    /// Smallest signed value held by a word
+    #[must_use]
+    pub const fn min(self) -> Word {
+        Word::MIN >> (WORD_BITS - self.word_bits)
+    }
+
+    /// Largest signed value held by a word
+    #[must_use]
+    pub const fn max(self) -> Word {
+        Word::MAX >> (WORD_BITS - self.word_bits)
+    }
+
+    const fn mask(self) -> u64 {
+        u64::MAX >> (WORD_BITS - self.word_bits)
+    }
+
+    /// Truncate a word to the width of the machine, sign-extending it back
+    #[must_use]
+    pub const fn wrap(self, word: Word) -> Word {
+        let shift = WORD_BITS - self.word_bits;
+        (word << shift) >> shift
+    }
+
+    /// The bits of a word, as an unsigned value
+    #[must_use]
+    pub const fn unsigned(self, word: Word) -> u64 {
+        word.cast_unsigned() & self.mask()
+    }
+
+    /// Truncate the exact result of an operation, telling if it overflowed as a signed word
+    fn truncate(self, exact: i128) -> (Word, bool) {
+        // Only the low bits are kept, as the hardware would
+        #[allow(clippy::cast_possible_truncation)]
+        let result = self.wrap(exact as Word);
+        (result, i128::from(result) != exact)
+    }
+
+    /// Check if an unsigned result needs a carry
+    fn carries(self, exact: u128) -> bool {
+        exact > u128::from(self.mask())
+    }
+
+    /// `a + b`, with its carry and signed overflow
+    pub(crate) fn add(self, a: Word, b: Word) -> (Word, bool, bool) {
+        let (a, b) = (self.wrap(a), self.wrap(b));
+        let (result, overflow) = self.truncate(i128::from(a) + i128::from(b));
+        let carry = self.carries(u128::from(self.unsigned(a)) + u128::from(self.unsigned(b)));
+        (result, carry, overflow)
+    }
+
+    /// `a - b`, with its borrow as the carry and signed overflow
+    pub(crate) fn sub(self, a: Word, b: Word) -> (Word, bool, bool) {
+        let (a, b) = (self.wrap(a), self.wrap(b));
+        let (result, overflow) = self.truncate(i128::from(a) - i128::from(b));
+        (result, self.unsigned(a) < self.unsigned(b), overflow)
+    }
+
+    /// `a * b`, with its unsigned and signed overflows
+    pub(crate) fn mul(self, a: Word, b: Word) -> (Word, bool, bool) {
+        let (a, b) = (self.wrap(a), self.wrap(b));
+        let (result, overflow) = self.truncate(i128::from(a) * i128::from(b));
+        let carry = self.carries(u128::from(self.unsigned(a)) * u128::from(self.unsigned(b)));
+        (result, carry, overflow)
+    }
+
+    /// `a / b`, with its signed overflow. The divisor must not be zero
+    pub(crate) fn div(self, a: Word, b: Word) -> (Word, bool) {
+        self.truncate(i128::from(self.wrap(a)) / i128::from(self.wrap(b)))
+    }
+
+    /// `-a`, with its signed overflow
+    pub(crate) fn neg(self, a: Word) -> (Word, bool) {
+        self.truncate(-i128::from(self.wrap(a)))
+    }
+
+    /// `a << n`, if `n` is smaller than the width
+    pub(crate) fn shl(self, a: Word, n: u32) -> Option<Word> {
+        (n < self.word_bits).then(|| self.wrap(a << n))
+    }
+
+    /// `a >> n`, copying the sign bit, if `n` is smaller than the width
+    pub(crate) fn shr(self, a: Word, n: u32) -> Option<Word> {
+        (n < self.word_bits).then(|| self.wrap(a) >> n)
+    }
+
+    /// Rotate the bits of a word to the left, larger amounts wrapping around
+    pub(crate) fn rotate_left(self, a: Word, n: u32) -> Word {
+        let n = n % self.word_bits;
+        if n == 0 {
+            return self.wrap(a);
+        }
+
+        let bits = self.unsigned(a);
+        let rotated = ((bits << n) | (bits >> (self.word_bits - n))) & self.mask();
+        self.wrap(rotated.cast_signed())
+    }
+
+    /// Rotate the bits of a word to the right, larger amounts wrapping around
+    pub(crate) fn rotate_right(self, a: Word, n: u32) -> Word {
+        self.rotate_left(a, self.word_bits - n % self.word_bits)
+    }
+}
+
+/// Order of the parts of a value stored in multiple parts, from the lowest address or bit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Display, FromStr)]
+#[display(style = "lowercase")]
+pub enum Endianness {
+    /// The least significant part first
+    #[default]
+    Little,
+
+    /// The most significant part first
+    Big,
+}
+
+/// Number of bytes of the widest words
+const WORD_BYTES: usize = std::mem::size_of::<Word>();
+
+impl Endianness {
+    /// Put the low and high parts of a value in memory order
+    #[must_use]
+    pub fn order<T>(self, [low, high]: [T; 2]) -> [T; 2] {
+        match self {
+            Self::Little => [low, high],
+            Self::Big => [high, low],
+        }
+    }
+
+    /// Split a value spanning two words of a machine, like the ones of `.long` directives, in
+    /// memory order
+    #[must_use]
+    pub fn split(self, value: i128, arch: Arch) -> [Word; 2] {
+        // The low word keeps the low bits, and the high one the sign
+        #[allow(clippy::cast_possible_truncation)]
+        let parts = [
+            arch.wrap(value as Word),
+            arch.wrap((value >> arch.word_bits) as Word),
+        ];
+        self.order(parts)
+    }
+
+    /// Join a value spanning two words of a machine, given in memory order
+    #[must_use]
+    pub fn join(self, cells: [Word; 2], arch: Arch) -> i128 {
+        let [low, high] = self.order(cells);
+        (i128::from(high) << arch.word_bits) | i128::from(arch.unsigned(low))
+    }
+
+    /// Pack bytes in the words of a machine, like `.byte` directives, padding the last word with
+    /// zeros
+    #[must_use]
+    pub fn pack(self, bytes: &[u8], arch: Arch) -> Vec<Word> {
+        let size = arch.word_bytes();
+        bytes
+            .chunks(size)
+            .map(|chunk| {
+                let mut word = [0; WORD_BYTES];
+                word[..chunk.len()].copy_from_slice(chunk);
+                let bits = match self {
+                    Self::Little => u64::from_le_bytes(word),
+                    Self::Big => u64::from_be_bytes(word) >> (8 * (WORD_BYTES - size)),
+                };
+                arch.wrap(bits.cast_signed())
+            })
+            .collect()
+    }
+
+    /// Unpack the bytes of words packed by [`Endianness::pack`]
+    #[must_use]
+    pub fn unpack(self, words: &[Word], arch: Arch) -> Vec<u8> {
+        let size = arch.word_bytes();
+        words
+            .iter()
+            .flat_map(|word| {
+                let bits = arch.unsigned(*word);
+                match self {
+                    Self::Little => bits.to_le_bytes()[..size].to_vec(),
+                    Self::Big => bits.to_be_bytes()[WORD_BYTES - size..].to_vec(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arch_test() {
+        let wide = Arch::default();
+        let narrow: Arch = "16".parse().unwrap();
+        assert_eq!(narrow.to_string(), "16-bit");
+        assert!("4".parse::<Arch>().is_err());
+        assert!("65".parse::<Arch>().is_err());
+
+        assert_eq!(narrow.wrap(0x7fff), 0x7fff);
+        assert_eq!(narrow.wrap(0x8000), -0x8000);
+        assert_eq!(narrow.wrap(70000), 4464);
+        assert_eq!(narrow.unsigned(-1), 0xffff);
+        assert_eq!((narrow.min(), narrow.max()), (-0x8000, 0x7fff));
+        assert_eq!((wide.min(), wide.max()), (Word::MIN, Word::MAX));
+
+        // The full width behaves as the plain 64-bit operations
+        for (a, b) in [
+            (1, 2),
+            (Word::MAX, 1),
+            (Word::MIN, -1),
+            (-5, 3),
+            (0, Word::MIN),
+        ] {
+            let (result, overflow) = a.overflowing_add(b);
+            let (_, carry) = a.cast_unsigned().overflowing_add(b.cast_unsigned());
+            assert_eq!(wide.add(a, b), (result, carry, overflow));
+
+            let (result, overflow) = a.overflowing_sub(b);
+            let (_, carry) = a.cast_unsigned().overflowing_sub(b.cast_unsigned());
+            assert_eq!(wide.sub(a, b), (result, carry, overflow));
+
+            let (result, overflow) = a.overflowing_mul(b);
+            let (_, carry) = a.cast_unsigned().overflowing_mul(b.cast_unsigned());
+            assert_eq!(wide.mul(a, b), (result, carry, overflow));
+
+            assert_eq!(wide.div(a, b), a.overflowing_div(b));
+            assert_eq!(wide.neg(a), a.overflowing_neg());
+            assert_eq!(wide.rotate_left(a, 3), a.rotate_left(3));
+            assert_eq!(wide.rotate_right(a, 67), a.rotate_right(67));
+        }
+
+        assert_eq!(narrow.add(0x7fff, 1), (-0x8000, false, true));
+        assert_eq!(narrow.add(-1, 1), (0, true, false));
+        assert_eq!(narrow.sub(0, 1), (-1, true, false));
+        assert_eq!(narrow.mul(0x100, 0x100), (0, true, true));
+        assert_eq!(narrow.div(-0x8000, -1), (-0x8000, true));
+        assert_eq!(narrow.neg(-0x8000), (-0x8000, true));
+        assert_eq!(narrow.shl(1, 15), Some(-0x8000));
+        assert_eq!(narrow.shl(1, 16), None);
+        assert_eq!(narrow.shr(-0x8000, 15), Some(-1));
+        assert_eq!(narrow.rotate_left(-0x8000, 1), 1);
+        assert_eq!(narrow.rotate_right(1, 1), -0x8000);
+    }
+
+    #[test]
+    fn endianness_test() {
+        use Endianness::{Big, Little};
+
+        assert_eq!("big".parse(), Ok(Big));
+        assert_eq!(Little.to_string(), "little");
+
+        let wide = Arch::default();
+        let value = (5 << 64) | 0xffff_ffff_ffff_fffe;
+        assert_eq!(Little.split(value, wide), [-2, 5]);
+        assert_eq!(Big.split(value, wide), [5, -2]);
+        for value in [value, -1, i128::from(Word::MIN) - 1, 0] {
+            assert_eq!(Little.join(Little.split(value, wide), wide), value);
+            assert_eq!(Big.join(Big.split(value, wide), wide), value);
+        }
+
+        let bytes = b"ABCDEFGHIJ";
+        assert_eq!(Little.pack(bytes, wide), [0x4847_4645_4443_4241, 0x4a49]);
+        assert_eq!(Big.pack(bytes, wide), [0x4142_4344_4546_4748, 0x494a << 48]);
+        assert_eq!(&Big.unpack(&Big.pack(bytes, wide), wide)[..10], bytes);
+        assert_eq!(Little.unpack(&Little.pack(bytes, wide), wide).len(), 16);
+
+        // Narrower words hold fewer bytes, and a long spans two of them
+        let narrow = Arch::new(16).unwrap();
+        assert_eq!(narrow.word_bytes(), 2);
+        assert_eq!(Arch::new(12).unwrap().word_bytes(), 1);
+        assert_eq!(Little.pack(b"ABC", narrow), [0x4241, 0x43]);
+        assert_eq!(Big.pack(b"\xffA", narrow), [-0xbf]);
+        assert_eq!(Big.unpack(&Big.pack(b"\xffA", narrow), narrow), b"\xffA");
+        assert_eq!(Little.split(0x12_3456, narrow), [0x3456, 0x12]);
+        assert_eq!(Little.join([-1, 0], narrow), 0xffff);
+        for value in [0x12_3456, -1, -0x8000_0000] {
+            assert_eq!(Big.join(Big.split(value, narrow), narrow), value);
+        }
+    }
+}
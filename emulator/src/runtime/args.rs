@@ -0,0 +1,139 @@
+//! Program arguments, loaded in memory before running the program
+//!
+//! The arguments are stored in a fixed region of memory, right before the program:
+//!
+//!  - [`ARGS_COUNT`] holds the number of arguments
+//!  - [`ARGS_VECTOR`] holds the address of an array with the address of each argument
+//!  - each argument is a string of characters, terminated by a `'\0'`
+
+use thiserror::Error;
+
+use crate::constants::{Address, ARGS_COUNT, ARGS_END, ARGS_VECTOR};
+
+use super::{Cell, Computer};
+
+#[derive(Debug, Error)]
+pub enum ArgsError {
+    #[error("program arguments need {needed} memory cells, but only {available} are available")]
+    TooLong { needed: usize, available: usize },
+
+    #[error("program arguments would overwrite the program at address {address}")]
+    Overlap { address: Address },
+}
+
+impl Computer {
+    /// Load the program arguments in memory
+    ///
+    /// Fails without writing anything if the program already uses one of the cells the arguments
+    /// need.
+    pub fn set_args<S: AsRef<str>>(&mut self, args: &[S]) -> Result<(), ArgsError> {
+        let strings: usize = args.iter().map(|a| a.as_ref().chars().count() + 1).sum();
+        let needed = 2 + args.len() + strings;
+        let available = (ARGS_END - ARGS_COUNT) as usize;
+        if needed > available {
+            return Err(ArgsError::TooLong { needed, available });
+        }
+
+        // Checked above to fit in the region
+        #[allow(clippy::cast_possible_truncation)]
+        let end = ARGS_COUNT + needed as Address;
+        if let Some(address) = (ARGS_COUNT..end).find(|&a| self.memory.is_occupied(a)) {
+            return Err(ArgsError::Overlap { address });
+        }
+
+        // The region was checked to fit, so the sizes and addresses below can not overflow
+        #[allow(clippy::cast_possible_truncation)]
+        let count = args.len() as Address;
+        let mut cells: Vec<Cell> = Vec::with_capacity(needed);
+        let vector = ARGS_VECTOR + 1;
+        cells.push(count.into());
+        cells.push(vector.into());
+
+        let mut string = vector + count;
+        for arg in args {
+            cells.push(string.into());
+            #[allow(clippy::cast_possible_truncation)]
+            let size = arg.as_ref().chars().count() as Address;
+            string += size + 1;
+        }
+
+        for arg in args {
+            cells.extend(arg.as_ref().chars().map(Cell::Char));
+            cells.push(Cell::Char('\0'));
+        }
+
+        for (address, cell) in (ARGS_COUNT..).zip(cells) {
+            *self.memory.get_mut(address).unwrap() = cell;
+        }
+
+        Ok(())
+    }
+
+    /// Read the program arguments back from memory
+    #[must_use]
+    pub fn argv(&self) -> Vec<String> {
+        let cell = |address: Address| self.memory.get(address).ok();
+        let count = cell(ARGS_COUNT)
+            .and_then(|c| c.extract_address().ok())
+            .unwrap_or(0);
+        let Some(vector) = cell(ARGS_VECTOR).and_then(|c| c.extract_address().ok()) else {
+            return Vec::new();
+        };
+
+        (vector..vector.saturating_add(count))
+            .filter_map(|address| cell(address)?.extract_address().ok())
+            .map(|start| {
+                (start..)
+                    .map_while(|address| match cell(address) {
+                        Some(Cell::Char(c)) if *c != '\0' => Some(*c),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn args_test() {
+        let mut computer = Computer::default();
+        assert!(computer.argv().is_empty());
+
+        computer.set_args(&["first", "", "3"]).unwrap();
+        assert_eq!(computer.memory.get(ARGS_COUNT).unwrap(), &Cell::Word(3));
+        assert_eq!(computer.argv(), vec!["first", "", "3"]);
+
+        let long = "x".repeat(1000);
+        assert!(matches!(
+            Computer::default().set_args(&[long]),
+            Err(ArgsError::TooLong { .. })
+        ));
+
+        // Data of the program in the region is kept
+        let mut computer = Computer::default();
+        computer.write(ARGS_COUNT + 10, 42_i64).unwrap();
+        computer.set_args(&["a"]).unwrap();
+        assert!(matches!(
+            computer.set_args(&["a"]),
+            Err(ArgsError::Overlap {
+                address: ARGS_COUNT
+            })
+        ));
+
+        let mut computer = Computer::default();
+        computer.write(ARGS_COUNT + 4, 42_i64).unwrap();
+        assert!(matches!(
+            computer.set_args(&["ab"]),
+            Err(ArgsError::Overlap { address }) if address == ARGS_COUNT + 4
+        ));
+        assert_eq!(
+            computer.memory.get(ARGS_COUNT + 4).unwrap(),
+            &Cell::Word(42)
+        );
+        assert!(computer.argv().is_empty());
+    }
+}
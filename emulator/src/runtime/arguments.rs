@@ -1,8 +1,7 @@
 //! Structures to represent most of argument combinations
 
 use parse_display::Display;
-
-use self::conversions::ArgKind;
+use serde::{Deserialize, Serialize};
 
 use super::registers::Reg;
 
@@ -11,149 +10,281 @@ use crate::constants as C;
 pub use conversions::ArgConversionError;
 pub use traits::{ExtractError, ExtractValue, ResolveAddress};
 
+/// Addressing mode of an instruction argument, shared by the parser, the compiler and the
+/// processor
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Display, Serialize, Deserialize)]
+#[display(style = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum AddressingMode {
+    Immediate,
+    Register,
+    Direct,
+    Indirect,
+    Indexed,
+    Scaled,
+    PostIncrement,
+    PreDecrement,
+    Relative,
+    /// A memory access relative to the program counter, compiled to an indexed access on `%pc`
+    RelativeDirect,
+}
+
+impl AddressingMode {
+    /// CPU cycles count to use an argument with this addressing mode
+    #[must_use]
+    pub const fn cost(self) -> usize {
+        match self {
+            Self::Immediate | Self::Register | Self::Relative => 0,
+            Self::Direct
+            | Self::Indirect
+            | Self::Indexed
+            | Self::Scaled
+            | Self::PostIncrement
+            | Self::PreDecrement
+            | Self::RelativeDirect => 1,
+        }
+    }
+}
+
 /**
  * First, individual argument types (`Reg` is defined in `crate::runtime::registers::Reg`)
  */
 
 /// An immediate value
-#[derive(PartialEq, Eq, Clone, Debug, Display)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Display, Serialize, Deserialize)]
 #[display("{0}")]
 pub struct Imm(pub C::Word);
 
-impl Imm {
-    /// CPU cycles count to use this value
-    pub const fn cost() -> usize {
-        0
-    }
-}
+/// An address relative to the program counter, which already points to the next instruction
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Display, Serialize, Deserialize)]
+#[display("%pc{0:+}")]
+pub struct Rel(pub C::Word);
 
 /// A direct memory access
-#[derive(PartialEq, Eq, Clone, Debug, Display)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Display, Serialize, Deserialize)]
 #[display("[{0}]")]
 pub struct Dir(pub C::Address);
 
-impl Dir {
-    /// CPU cycles count to use this value
-    pub const fn cost() -> usize {
-        1
-    }
-}
-
 /// An indirect memory access (from a register value)
-#[derive(PartialEq, Eq, Clone, Debug, Display)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Display, Serialize, Deserialize)]
 #[display("[{0}]")]
 pub struct Ind(pub Reg);
 
-impl Ind {
-    /// CPU cycles count to use this value
-    pub const fn cost() -> usize {
-        1
-    }
-}
-
 /// An indexed memory access (from a register value and an offset)
-#[derive(PartialEq, Eq, Clone, Debug, Display)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Display, Serialize, Deserialize)]
 #[display("[{0}{1:+}]")]
 pub struct Idx(pub Reg, pub C::Word);
 
-impl Idx {
-    /// CPU cycles count to use this value
-    pub const fn cost() -> usize {
-        1
-    }
-}
+/// A scaled indexed memory access (from a base register value, plus an index register value
+/// multiplied by a scale)
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Display, Serialize, Deserialize)]
+#[display("[{0}+{1}*{2}]")]
+pub struct Sca(pub Reg, pub Reg, pub C::Word);
+
+/// A memory access at a register value, incrementing it afterwards
+///
+/// Like the pre-decrement, the register is updated before the instruction executes, so the
+/// address is resolved from the updated value minus one.
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Display, Serialize, Deserialize)]
+#[display("[{0}]+")]
+pub struct Inc(pub Reg);
+
+/// A memory access at a register value, decrementing it first
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Display, Serialize, Deserialize)]
+#[display("-[{0}]")]
+pub struct Dec(pub Reg);
 
 /**
  * Then define the combination of argument types needed
  */
 
-#[derive(PartialEq, Eq, Clone, Debug, Display)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Display, Serialize, Deserialize)]
 #[display("{0}")]
+#[serde(rename_all = "lowercase")]
 pub enum ImmRegDirIndIdx {
     Imm(Imm),
     Reg(Reg),
     Dir(Dir),
     Ind(Ind),
     Idx(Idx),
+    Sca(Sca),
+    Inc(Inc),
+    Dec(Dec),
+    Rel(Rel),
 }
 
 impl ImmRegDirIndIdx {
-    /// CPU cycles count to use this value
-    pub const fn cost(&self) -> usize {
+    /// Addressing modes accepted by this argument
+    pub const MODES: &'static [AddressingMode] = &[
+        AddressingMode::Immediate,
+        AddressingMode::Register,
+        AddressingMode::Direct,
+        AddressingMode::Indirect,
+        AddressingMode::Indexed,
+        AddressingMode::Scaled,
+        AddressingMode::PostIncrement,
+        AddressingMode::PreDecrement,
+        AddressingMode::Relative,
+    ];
+
+    /// Addressing mode of this argument
+    pub const fn mode(&self) -> AddressingMode {
         match self {
-            ImmRegDirIndIdx::Imm(_) => Imm::cost(),
-            ImmRegDirIndIdx::Reg(_) => Reg::cost(),
-            ImmRegDirIndIdx::Dir(_) => Dir::cost(),
-            ImmRegDirIndIdx::Ind(_) => Ind::cost(),
-            ImmRegDirIndIdx::Idx(_) => Idx::cost(),
+            Self::Imm(_) => AddressingMode::Immediate,
+            Self::Reg(_) => AddressingMode::Register,
+            Self::Dir(_) => AddressingMode::Direct,
+            Self::Ind(_) => AddressingMode::Indirect,
+            Self::Idx(_) => AddressingMode::Indexed,
+            Self::Sca(_) => AddressingMode::Scaled,
+            Self::Inc(_) => AddressingMode::PostIncrement,
+            Self::Dec(_) => AddressingMode::PreDecrement,
+            Self::Rel(_) => AddressingMode::Relative,
         }
     }
 
-    /// Get the kind of this argument
-    /// This is used to build nice errors on conversions
-    const fn kind(&self) -> conversions::ArgKind {
+    /// CPU cycles count to use this value
+    pub const fn cost(&self) -> usize {
+        self.mode().cost()
+    }
+
+    /// Register updated by a post-increment or pre-decrement, with the amount added to it
+    pub const fn register_update(&self) -> Option<(Reg, C::Word)> {
         match self {
-            Self::Imm(_) => ArgKind::Imm,
-            Self::Reg(_) => ArgKind::Reg,
-            Self::Dir(_) => ArgKind::Dir,
-            Self::Ind(_) => ArgKind::Ind,
-            Self::Idx(_) => ArgKind::Idx,
+            Self::Inc(Inc(reg)) => Some((*reg, 1)),
+            Self::Dec(Dec(reg)) => Some((*reg, -1)),
+            _ => None,
         }
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Debug, Display)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Display, Serialize, Deserialize)]
 #[display("{0}")]
+#[serde(rename_all = "lowercase")]
 pub enum DirIndIdx {
     Dir(Dir),
     Ind(Ind),
     Idx(Idx),
+    Sca(Sca),
+    Inc(Inc),
+    Dec(Dec),
 }
 
 impl DirIndIdx {
+    /// Addressing modes accepted by this argument
+    pub const MODES: &'static [AddressingMode] = &[
+        AddressingMode::Direct,
+        AddressingMode::Indirect,
+        AddressingMode::Indexed,
+        AddressingMode::Scaled,
+        AddressingMode::PostIncrement,
+        AddressingMode::PreDecrement,
+    ];
+
+    /// Addressing mode of this argument
+    pub const fn mode(&self) -> AddressingMode {
+        match self {
+            Self::Dir(_) => AddressingMode::Direct,
+            Self::Ind(_) => AddressingMode::Indirect,
+            Self::Idx(_) => AddressingMode::Indexed,
+            Self::Sca(_) => AddressingMode::Scaled,
+            Self::Inc(_) => AddressingMode::PostIncrement,
+            Self::Dec(_) => AddressingMode::PreDecrement,
+        }
+    }
+
+    /// CPU cycles count to use this value
     pub const fn cost(&self) -> usize {
+        self.mode().cost()
+    }
+
+    /// Register updated by a post-increment or pre-decrement, with the amount added to it
+    pub const fn register_update(&self) -> Option<(Reg, C::Word)> {
         match self {
-            DirIndIdx::Dir(_) => Dir::cost(),
-            DirIndIdx::Ind(_) => Ind::cost(),
-            DirIndIdx::Idx(_) => Idx::cost(),
+            Self::Inc(Inc(reg)) => Some((*reg, 1)),
+            Self::Dec(Dec(reg)) => Some((*reg, -1)),
+            _ => None,
         }
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Debug, Display)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Display, Serialize, Deserialize)]
 #[display("{0}")]
+#[serde(rename_all = "lowercase")]
 pub enum RegDirIndIdx {
     Reg(Reg),
     Dir(Dir),
     Ind(Ind),
     Idx(Idx),
+    Sca(Sca),
+    Inc(Inc),
+    Dec(Dec),
 }
 
 impl RegDirIndIdx {
+    /// Addressing modes accepted by this argument
+    pub const MODES: &'static [AddressingMode] = &[
+        AddressingMode::Register,
+        AddressingMode::Direct,
+        AddressingMode::Indirect,
+        AddressingMode::Indexed,
+        AddressingMode::Scaled,
+        AddressingMode::PostIncrement,
+        AddressingMode::PreDecrement,
+    ];
+
+    /// Addressing mode of this argument
+    pub const fn mode(&self) -> AddressingMode {
+        match self {
+            Self::Reg(_) => AddressingMode::Register,
+            Self::Dir(_) => AddressingMode::Direct,
+            Self::Ind(_) => AddressingMode::Indirect,
+            Self::Idx(_) => AddressingMode::Indexed,
+            Self::Sca(_) => AddressingMode::Scaled,
+            Self::Inc(_) => AddressingMode::PostIncrement,
+            Self::Dec(_) => AddressingMode::PreDecrement,
+        }
+    }
+
+    /// CPU cycles count to use this value
     pub const fn cost(&self) -> usize {
+        self.mode().cost()
+    }
+
+    /// Register updated by a post-increment or pre-decrement, with the amount added to it
+    pub const fn register_update(&self) -> Option<(Reg, C::Word)> {
         match self {
-            RegDirIndIdx::Reg(_) => Reg::cost(),
-            RegDirIndIdx::Dir(_) => Dir::cost(),
-            RegDirIndIdx::Ind(_) => Ind::cost(),
-            RegDirIndIdx::Idx(_) => Idx::cost(),
+            Self::Inc(Inc(reg)) => Some((*reg, 1)),
+            Self::Dec(Dec(reg)) => Some((*reg, -1)),
+            _ => None,
         }
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Debug, Display)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Display, Serialize, Deserialize)]
 #[display("{0}")]
+#[serde(rename_all = "lowercase")]
 pub enum ImmReg {
     Imm(Imm),
     Reg(Reg),
 }
 
 impl ImmReg {
-    pub const fn cost(&self) -> usize {
+    /// Addressing modes accepted by this argument
+    pub const MODES: &'static [AddressingMode] =
+        &[AddressingMode::Immediate, AddressingMode::Register];
+
+    /// Addressing mode of this argument
+    pub const fn mode(&self) -> AddressingMode {
         match self {
-            ImmReg::Imm(_) => Imm::cost(),
-            ImmReg::Reg(_) => Reg::cost(),
+            Self::Imm(_) => AddressingMode::Immediate,
+            Self::Reg(_) => AddressingMode::Register,
         }
     }
+
+    /// CPU cycles count to use this value
+    pub const fn cost(&self) -> usize {
+        self.mode().cost()
+    }
 }
 
 /// Traits used to extract values and addresses specified by arguments
@@ -165,9 +296,11 @@ mod traits {
     use super::super::{
         memory::{CellError, MemoryError, TryFromCell},
         registers::Reg,
-        Cell, Computer, Registers,
+        Cell, Computer, Registers, ShadowViolation,
+    };
+    use super::{
+        Dec, Dir, DirIndIdx, Idx, Imm, ImmReg, ImmRegDirIndIdx, Inc, Ind, RegDirIndIdx, Rel, Sca,
     };
-    use super::{Dir, DirIndIdx, Idx, Imm, ImmReg, ImmRegDirIndIdx, Ind, RegDirIndIdx};
 
     use crate::constants as C;
 
@@ -185,6 +318,9 @@ mod traits {
 
         #[error("invalid address: {0}")]
         InvalidAddress(#[from] std::num::TryFromIntError),
+
+        #[error(transparent)]
+        Shadow(#[from] ShadowViolation),
     }
 
     pub trait ExtractValue {
@@ -205,12 +341,14 @@ mod traits {
     impl<T: ResolveAddress> ExtractValue for T {
         fn extract_cell(&self, c: &Computer) -> Result<Cell, ExtractError> {
             let addr = self.resolve_address(&c.registers)?;
+            c.check_access(addr, false)?;
             let cell = c.memory.get(addr)?;
             Ok(cell.clone())
         }
 
         fn extract_word(&self, c: &Computer) -> Result<C::Word, ExtractError> {
             let addr = self.resolve_address(&c.registers)?;
+            c.check_access(addr, false)?;
             let cell = c.memory.get(addr)?;
             Ok(cell.extract_word()?)
         }
@@ -238,20 +376,56 @@ mod traits {
             let cell = c.get(&self.0);
             // and try converting it to a word
             let addr = C::Word::try_from_cell(&cell)?;
-            // add the offset
-            let addr = addr + self.1;
+            // add the offset, an overflow giving an address out of the memory
+            let addr = addr.saturating_add(self.1);
             // and convert it to an address
             let addr = C::Address::try_from_cell(&addr.into())?;
             Ok(addr)
         }
     }
 
+    impl ResolveAddress for Sca {
+        fn resolve_address(&self, c: &Registers) -> Result<C::Address, CellError> {
+            // Get the registers values
+            let base = C::Word::try_from_cell(&c.get(&self.0))?;
+            let index = C::Word::try_from_cell(&c.get(&self.1))?;
+            // scale the index and add it, an overflow giving an address out of the memory
+            let addr = base.saturating_add(index.saturating_mul(self.2));
+            // and convert it to an address
+            let addr = C::Address::try_from_cell(&addr.into())?;
+            Ok(addr)
+        }
+    }
+
+    impl ResolveAddress for Inc {
+        fn resolve_address(&self, c: &Registers) -> Result<C::Address, CellError> {
+            // The register was already incremented, wrapping around from the largest word
+            let word = C::Word::try_from_cell(&c.get(&self.0))?;
+            let addr = word
+                .checked_sub(1)
+                .ok_or(CellError::InvalidAddress { word })?;
+            let addr = C::Address::try_from_cell(&addr.into())?;
+            Ok(addr)
+        }
+    }
+
+    impl ResolveAddress for Dec {
+        fn resolve_address(&self, c: &Registers) -> Result<C::Address, CellError> {
+            // The register was already decremented
+            let addr = C::Address::try_from_cell(&c.get(&self.0))?;
+            Ok(addr)
+        }
+    }
+
     impl ResolveAddress for DirIndIdx {
         fn resolve_address(&self, c: &Registers) -> Result<C::Address, CellError> {
             match self {
                 DirIndIdx::Dir(a) => a.resolve_address(c),
                 DirIndIdx::Ind(a) => a.resolve_address(c),
                 DirIndIdx::Idx(a) => a.resolve_address(c),
+                DirIndIdx::Sca(a) => a.resolve_address(c),
+                DirIndIdx::Inc(a) => a.resolve_address(c),
+                DirIndIdx::Dec(a) => a.resolve_address(c),
             }
         }
     }
@@ -264,6 +438,10 @@ mod traits {
                 ImmRegDirIndIdx::Dir(a) => a.extract_cell(c),
                 ImmRegDirIndIdx::Ind(a) => a.extract_cell(c),
                 ImmRegDirIndIdx::Idx(a) => a.extract_cell(c),
+                ImmRegDirIndIdx::Sca(a) => a.extract_cell(c),
+                ImmRegDirIndIdx::Inc(a) => a.extract_cell(c),
+                ImmRegDirIndIdx::Dec(a) => a.extract_cell(c),
+                ImmRegDirIndIdx::Rel(a) => a.extract_cell(c),
             }
         }
 
@@ -274,6 +452,10 @@ mod traits {
                 ImmRegDirIndIdx::Dir(a) => a.extract_word(c),
                 ImmRegDirIndIdx::Ind(a) => a.extract_word(c),
                 ImmRegDirIndIdx::Idx(a) => a.extract_word(c),
+                ImmRegDirIndIdx::Sca(a) => a.extract_word(c),
+                ImmRegDirIndIdx::Inc(a) => a.extract_word(c),
+                ImmRegDirIndIdx::Dec(a) => a.extract_word(c),
+                ImmRegDirIndIdx::Rel(a) => a.extract_word(c),
             }
         }
     }
@@ -288,6 +470,16 @@ mod traits {
         }
     }
 
+    impl ExtractValue for Rel {
+        fn extract_cell(&self, c: &Computer) -> Result<Cell, ExtractError> {
+            Ok(Cell::Word(self.extract_word(c)?))
+        }
+
+        fn extract_word(&self, c: &Computer) -> Result<C::Word, ExtractError> {
+            Ok(C::Word::from(c.registers.pc).saturating_add(self.0))
+        }
+    }
+
     impl ExtractValue for Reg {
         fn extract_cell(&self, c: &Computer) -> Result<Cell, ExtractError> {
             Ok(c.registers.get(self))
@@ -321,6 +513,9 @@ mod traits {
                 RegDirIndIdx::Dir(a) => a.extract_cell(c),
                 RegDirIndIdx::Ind(a) => a.extract_cell(c),
                 RegDirIndIdx::Idx(a) => a.extract_cell(c),
+                RegDirIndIdx::Sca(a) => a.extract_cell(c),
+                RegDirIndIdx::Inc(a) => a.extract_cell(c),
+                RegDirIndIdx::Dec(a) => a.extract_cell(c),
             }
         }
     }
@@ -330,36 +525,15 @@ mod traits {
 mod conversions {
     use std::convert::TryFrom;
 
-    use parse_display::Display;
     use thiserror::Error;
 
-    use super::{DirIndIdx, ImmReg, ImmRegDirIndIdx, Reg, RegDirIndIdx};
-
-    /// Valid argument kinds
-    #[derive(PartialEq, Eq, Clone, Debug, Display)]
-    #[display(style = "lowercase")]
-    pub enum ArgKind {
-        Imm,
-        Reg,
-        Ind,
-        Dir,
-        Idx,
-    }
-
-    /// Type alias used in argument conversion errors
-    type K = ArgKind;
+    use super::{AddressingMode, DirIndIdx, ImmReg, ImmRegDirIndIdx, Reg, RegDirIndIdx};
 
-    /// A list of argument kinds, wrap for display purposes
+    /// A list of addressing modes, wrap for display purposes
     #[derive(Debug)]
-    struct ArgKinds(Vec<ArgKind>);
+    struct Modes(&'static [AddressingMode]);
 
-    impl<T: Into<Vec<ArgKind>>> From<T> for ArgKinds {
-        fn from(l: T) -> Self {
-            Self(l.into())
-        }
-    }
-
-    impl std::fmt::Display for ArgKinds {
+    impl std::fmt::Display for Modes {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             for (index, entry) in self.0.iter().enumerate() {
                 if index != 0 {
@@ -375,9 +549,15 @@ mod conversions {
     #[derive(Error, Debug)]
     #[error("invalid argument type '{got}', expected '{expected}'")]
     pub struct ArgConversionError {
-        /// The list of kind of arguments that were accepted
-        expected: ArgKinds,
-        got: ArgKind,
+        /// The addressing modes that were accepted
+        expected: Modes,
+        got: AddressingMode,
+    }
+
+    impl From<std::convert::Infallible> for ArgConversionError {
+        fn from(infallible: std::convert::Infallible) -> Self {
+            match infallible {}
+        }
     }
 
     impl TryFrom<ImmRegDirIndIdx> for ImmReg {
@@ -388,8 +568,8 @@ mod conversions {
                 ImmRegDirIndIdx::Imm(a) => Ok(Self::Imm(a)),
                 ImmRegDirIndIdx::Reg(a) => Ok(Self::Reg(a)),
                 other => Err(ArgConversionError {
-                    expected: [K::Imm, K::Reg].into(),
-                    got: other.kind(),
+                    expected: Modes(ImmReg::MODES),
+                    got: other.mode(),
                 }),
             }
         }
@@ -403,9 +583,12 @@ mod conversions {
                 ImmRegDirIndIdx::Dir(a) => Ok(Self::Dir(a)),
                 ImmRegDirIndIdx::Ind(a) => Ok(Self::Ind(a)),
                 ImmRegDirIndIdx::Idx(a) => Ok(Self::Idx(a)),
+                ImmRegDirIndIdx::Sca(a) => Ok(Self::Sca(a)),
+                ImmRegDirIndIdx::Inc(a) => Ok(Self::Inc(a)),
+                ImmRegDirIndIdx::Dec(a) => Ok(Self::Dec(a)),
                 other => Err(ArgConversionError {
-                    expected: [K::Dir, K::Ind, K::Idx].into(),
-                    got: other.kind(),
+                    expected: Modes(DirIndIdx::MODES),
+                    got: other.mode(),
                 }),
             }
         }
@@ -418,8 +601,8 @@ mod conversions {
             match value {
                 ImmRegDirIndIdx::Reg(a) => Ok(a),
                 other => Err(ArgConversionError {
-                    expected: [K::Reg].into(),
-                    got: other.kind(),
+                    expected: Modes(&[AddressingMode::Register]),
+                    got: other.mode(),
                 }),
             }
         }
@@ -434,10 +617,15 @@ mod conversions {
                 ImmRegDirIndIdx::Dir(a) => Ok(Self::Dir(a)),
                 ImmRegDirIndIdx::Ind(a) => Ok(Self::Ind(a)),
                 ImmRegDirIndIdx::Idx(a) => Ok(Self::Idx(a)),
-                other @ ImmRegDirIndIdx::Imm(_) => Err(ArgConversionError {
-                    expected: [K::Reg, K::Dir, K::Ind, K::Idx].into(),
-                    got: other.kind(),
-                }),
+                ImmRegDirIndIdx::Sca(a) => Ok(Self::Sca(a)),
+                ImmRegDirIndIdx::Inc(a) => Ok(Self::Inc(a)),
+                ImmRegDirIndIdx::Dec(a) => Ok(Self::Dec(a)),
+                other @ (ImmRegDirIndIdx::Imm(_) | ImmRegDirIndIdx::Rel(_)) => {
+                    Err(ArgConversionError {
+                        expected: Modes(RegDirIndIdx::MODES),
+                        got: other.mode(),
+                    })
+                }
             }
         }
     }
@@ -450,11 +638,50 @@ mod conversions {
                 RegDirIndIdx::Dir(a) => Ok(Self::Dir(a)),
                 RegDirIndIdx::Ind(a) => Ok(Self::Ind(a)),
                 RegDirIndIdx::Idx(a) => Ok(Self::Idx(a)),
+                RegDirIndIdx::Sca(a) => Ok(Self::Sca(a)),
+                RegDirIndIdx::Inc(a) => Ok(Self::Inc(a)),
+                RegDirIndIdx::Dec(a) => Ok(Self::Dec(a)),
                 RegDirIndIdx::Reg(_) => Err(ArgConversionError {
-                    expected: [K::Dir, K::Ind, K::Idx].into(),
-                    got: K::Reg,
+                    expected: Modes(DirIndIdx::MODES),
+                    got: AddressingMode::Register,
                 }),
             }
         }
     }
+
+    impl From<ImmReg> for ImmRegDirIndIdx {
+        fn from(value: ImmReg) -> Self {
+            match value {
+                ImmReg::Imm(a) => Self::Imm(a),
+                ImmReg::Reg(a) => Self::Reg(a),
+            }
+        }
+    }
+
+    impl From<DirIndIdx> for ImmRegDirIndIdx {
+        fn from(value: DirIndIdx) -> Self {
+            match value {
+                DirIndIdx::Dir(a) => Self::Dir(a),
+                DirIndIdx::Ind(a) => Self::Ind(a),
+                DirIndIdx::Idx(a) => Self::Idx(a),
+                DirIndIdx::Sca(a) => Self::Sca(a),
+                DirIndIdx::Inc(a) => Self::Inc(a),
+                DirIndIdx::Dec(a) => Self::Dec(a),
+            }
+        }
+    }
+
+    impl From<RegDirIndIdx> for ImmRegDirIndIdx {
+        fn from(value: RegDirIndIdx) -> Self {
+            match value {
+                RegDirIndIdx::Reg(a) => Self::Reg(a),
+                RegDirIndIdx::Dir(a) => Self::Dir(a),
+                RegDirIndIdx::Ind(a) => Self::Ind(a),
+                RegDirIndIdx::Idx(a) => Self::Idx(a),
+                RegDirIndIdx::Sca(a) => Self::Sca(a),
+                RegDirIndIdx::Inc(a) => Self::Inc(a),
+                RegDirIndIdx::Dec(a) => Self::Dec(a),
+            }
+        }
+    }
 }
@@ -0,0 +1,77 @@
+//! Calling a single routine, to test it apart from the rest of the program
+//!
+//! Routines follow the calling convention of `<std/stack.s>`: their arguments are pushed in
+//! reverse order before the `call`, and they return their result in `%a`.
+
+use crate::constants::{Address, Word};
+
+use super::{Computer, ProcessorError};
+
+impl Computer {
+    /// Call the routine at the given address, and return the value it left in `%a`
+    ///
+    /// The execution stops once the routine returns to the caller, leaving `%pc` and `%sp` as
+    /// they were before the call. Routines are usually found in [`DebugInfo::labels`]. Errors
+    /// stop the call, including the routine resetting or halting the computer, but breakpoints
+    /// are ignored.
+    ///
+    /// [`DebugInfo::labels`]: crate::compiler::DebugInfo::labels
+    pub fn call(&mut self, routine: Address, args: &[Word]) -> Result<Word, ProcessorError> {
+        for arg in args.iter().rev() {
+            self.push(*arg)?;
+        }
+
+        // The routine is done once it returns to the current address with the same stack
+        let frame = self.registers.sp;
+        let return_address = self.registers.pc;
+        self.push(Word::from(return_address))?;
+        self.jump(routine);
+
+        while self.registers.pc != return_address || self.registers.sp != frame {
+            match self.step() {
+                Ok(()) | Err(ProcessorError::Breakpoint) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let count = Address::try_from(args.len()).unwrap_or(Address::MAX);
+        self.registers.sp = self.registers.sp.saturating_add(count);
+        Ok(self.registers.a.extract_word()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{compile, parse, runtime::ProcessorError};
+
+    #[test]
+    fn call_test() {
+        let source = "main: reset\n\
+                      sub: ld [%sp+1], %a\n\
+                          sub [%sp+2], %a\n\
+                          rtn\n\
+                      less3: brk\n\
+                          ld [%sp+1], %a\n\
+                          push 3\n\
+                          push %a\n\
+                          call sub\n\
+                          add 2, %sp\n\
+                          rtn\n\
+                      broken: reset\n";
+        let program = parse(source).unwrap();
+        let (mut computer, debug_info) = compile(program.inner, "main").unwrap();
+        let (pc, sp) = (computer.registers.pc, computer.registers.sp);
+
+        assert_eq!(
+            computer.call(debug_info.labels["sub"], &[10, 4]).unwrap(),
+            6
+        );
+        assert_eq!(computer.call(debug_info.labels["less3"], &[10]).unwrap(), 7);
+        assert_eq!((computer.registers.pc, computer.registers.sp), (pc, sp));
+
+        assert!(matches!(
+            computer.call(debug_info.labels["broken"], &[]),
+            Err(ProcessorError::Reset)
+        ));
+    }
+}
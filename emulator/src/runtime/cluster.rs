@@ -0,0 +1,286 @@
+//! Simulation of multiple computers running side by side
+//!
+//! Each computer has its own registers and memory, except for a designated window of addresses
+//! which is shared by all of them. Only one computer is stepped at a time, following a schedule,
+//! and the shared window is synchronized around each step. This makes the interleaving of
+//! instructions between computers explicit and reproducible.
+
+use std::ops::Range;
+
+use thiserror::Error;
+use tracing::debug;
+
+use crate::constants::Address;
+
+use super::{Cell, Computer, ProcessorError};
+
+/// Order in which the computers of a cluster are stepped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    /// Step each running computer in turn
+    RoundRobin,
+
+    /// Pick a running computer at random before each step, from the given seed
+    Random { seed: u64 },
+}
+
+#[derive(Debug, Error)]
+pub enum ClusterError {
+    #[error("computer {index}: {source}")]
+    Processor {
+        index: usize,
+        source: ProcessorError,
+    },
+
+    #[error("invalid shared window {start}..{end}")]
+    InvalidWindow { start: Address, end: Address },
+}
+
+/// A group of computers sharing a memory window
+pub struct Cluster {
+    computers: Vec<Computer>,
+    halted: Vec<bool>,
+    window: Range<Address>,
+    shared: Vec<Cell>,
+    schedule: Schedule,
+    next: usize,
+    state: u64,
+}
+
+impl Cluster {
+    /// Create an empty cluster, sharing the given range of addresses
+    pub fn new(window: Range<Address>, schedule: Schedule) -> Result<Self, ClusterError> {
+        let memory_size = Computer::default().memory.size();
+        if window.start > window.end || window.end as usize > memory_size {
+            return Err(ClusterError::InvalidWindow {
+                start: window.start,
+                end: window.end,
+            });
+        }
+
+        let state = match schedule {
+            Schedule::RoundRobin => 0,
+            // xorshift needs a non-zero state
+            Schedule::Random { seed } => seed.max(1),
+        };
+
+        Ok(Self {
+            computers: Vec::new(),
+            halted: Vec::new(),
+            shared: vec![Cell::Empty; window.len()],
+            window,
+            schedule,
+            next: 0,
+            state,
+        })
+    }
+
+    /// Add a computer to the cluster, returning its index
+    ///
+    /// The content of the shared window is taken from the first computer added.
+    pub fn add(&mut self, computer: Computer) -> usize {
+        if self.computers.is_empty() {
+            for (cell, address) in self.shared.iter_mut().zip(self.window.clone()) {
+                *cell = computer.memory.get(address).cloned().unwrap_or_default();
+            }
+        }
+
+        self.computers.push(computer);
+        self.halted.push(false);
+        self.computers.len() - 1
+    }
+
+    #[must_use]
+    pub fn computers(&self) -> &[Computer] {
+        &self.computers
+    }
+
+    /// Check if a computer was reset
+    #[must_use]
+    pub fn is_halted(&self, index: usize) -> bool {
+        self.halted.get(index).copied().unwrap_or(true)
+    }
+
+    /// Get the content of the shared window
+    #[must_use]
+    pub fn shared(&self) -> &[Cell] {
+        &self.shared
+    }
+
+    /// Pick the next computer to step, among those still running
+    fn pick(&mut self) -> Option<usize> {
+        let running: Vec<usize> = (0..self.computers.len())
+            .filter(|&index| !self.halted[index])
+            .collect();
+
+        if running.is_empty() {
+            return None;
+        }
+
+        match self.schedule {
+            Schedule::RoundRobin => {
+                let index = running
+                    .iter()
+                    .copied()
+                    .find(|&index| index >= self.next)
+                    .unwrap_or(running[0]);
+                self.next = index + 1;
+                Some(index)
+            }
+            Schedule::Random { .. } => {
+                // xorshift64, good enough for scheduling and stable across platforms
+                self.state ^= self.state << 13;
+                self.state ^= self.state >> 7;
+                self.state ^= self.state << 17;
+                let pick = usize::try_from(self.state % running.len() as u64).unwrap_or(0);
+                Some(running[pick])
+            }
+        }
+    }
+
+    /// Step one computer of the cluster
+    ///
+    /// Returns the index of the computer which was stepped, or `None` if all of them were reset.
+    pub fn step(&mut self) -> Result<Option<usize>, ClusterError> {
+        let Some(index) = self.pick() else {
+            return Ok(None);
+        };
+
+        let computer = &mut self.computers[index];
+
+        // Bring the shared window up to date in the computer memory
+        for (cell, address) in self.shared.iter().zip(self.window.clone()) {
+            if computer
+                .memory
+                .get(address)
+                .is_ok_and(|current| current != cell)
+            {
+                // The window was checked to be in bounds when creating the cluster
+                *computer.memory.get_mut(address).unwrap() = cell.clone();
+            }
+        }
+
+        let result = computer.step();
+
+        // And publish whatever it wrote in the window
+        for (cell, address) in self.shared.iter_mut().zip(self.window.clone()) {
+            if let Ok(current) = computer.memory.get(address) {
+                if current != cell {
+                    *cell = current.clone();
+                }
+            }
+        }
+
+        match result {
+            // Breakpoints are only meant for the interactive mode
+            Ok(()) | Err(ProcessorError::Breakpoint) => {}
+            Err(ProcessorError::Reset | ProcessorError::Halt) => {
+                debug!(index, "Computer reset");
+                self.halted[index] = true;
+            }
+            Err(source) => return Err(ClusterError::Processor { index, source }),
+        }
+
+        Ok(Some(index))
+    }
+
+    /// Run the cluster until all the computers are reset
+    pub fn run(&mut self) -> Result<(), ClusterError> {
+        while self.step()?.is_some() {}
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Reg;
+    use crate::{compile, parse};
+
+    fn computer(source: &str) -> Computer {
+        let program = parse(source).unwrap();
+        compile(program.inner, "main").unwrap().0
+    }
+
+    fn producer_consumer(schedule: Schedule) -> Cluster {
+        let mut cluster = Cluster::new(500..502, schedule).unwrap();
+        // Produces values 1 to 5 in the slot, waiting for the consumer to clear it
+        cluster.add(computer(
+            "main: ld 0, %b\n\
+             next: add 1, %b\n\
+             wait: ld [500], %a\n\
+                 cmp 0, %a\n\
+                 jne wait\n\
+                 st %b, [500]\n\
+                 cmp 5, %b\n\
+                 jne next\n\
+                 reset\n",
+        ));
+        // Consumes the values, summing them in [501], going through a breakpoint
+        cluster.add(computer(
+            "main: ld 0, %b\n\
+             wait: ld [500], %a\n\
+                 cmp 0, %a\n\
+                 jeq wait\n\
+                 brk\n\
+                 add %a, %b\n\
+                 st %b, [501]\n\
+                 ld 0, %a\n\
+                 st %a, [500]\n\
+                 cmp 15, %b\n\
+                 jne wait\n\
+                 reset\n",
+        ));
+        cluster
+    }
+
+    #[test]
+    fn round_robin_test() {
+        let mut cluster = producer_consumer(Schedule::RoundRobin);
+        cluster.run().unwrap();
+        assert!(cluster.is_halted(0) && cluster.is_halted(1));
+        assert_eq!(cluster.shared()[1], Cell::Word(15));
+        assert_eq!(
+            cluster.computers()[1].registers.get(&Reg::B),
+            Cell::Word(15)
+        );
+    }
+
+    #[test]
+    fn compare_and_swap_test() {
+        // Each computer increments the counter in [501] 10 times, under the lock in [500]
+        let source = "main: ld 0, %a\n\
+                          ld 1, %b\n\
+                          cas [500], %b\n\
+                          jne main\n\
+                          ld [501], %a\n\
+                          add 1, %a\n\
+                          st %a, [501]\n\
+                          ld 0, %a\n\
+                          st %a, [500]\n\
+                          ld [600], %a\n\
+                          add 1, %a\n\
+                          st %a, [600]\n\
+                          cmp 10, %a\n\
+                          jne main\n\
+                          reset\n";
+
+        let mut cluster = Cluster::new(500..502, Schedule::Random { seed: 7 }).unwrap();
+        cluster.add(computer(source));
+        cluster.add(computer(source));
+        cluster.run().unwrap();
+        assert_eq!(cluster.shared(), &[Cell::Word(0), Cell::Word(20)]);
+    }
+
+    #[test]
+    fn random_schedule_test() {
+        let mut first = producer_consumer(Schedule::Random { seed: 42 });
+        first.run().unwrap();
+        assert_eq!(first.shared()[1], Cell::Word(15));
+
+        // The same seed gives the same interleaving
+        let mut second = producer_consumer(Schedule::Random { seed: 42 });
+        second.run().unwrap();
+        assert_eq!(first.computers()[0].cycles, second.computers()[0].cycles);
+    }
+}
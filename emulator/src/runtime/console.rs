@@ -0,0 +1,124 @@
+//! A console device, to print text from programs
+//!
+//! The console has two output ports: `0` for the standard output and `1` for the error output.
+//! Writing a character to a port appends it to the corresponding channel, and writing a word
+//! appends its decimal representation. The host reads the channels through a [`ConsoleOutput`],
+//! which can be polled while the program runs.
+
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::constants::Address;
+
+use super::{Cell, Device, WouldBlock};
+
+/// Output channels of a console
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug, Default)]
+struct Buffers {
+    stdout: String,
+    stderr: String,
+}
+
+/// The console device, attached to a computer
+#[derive(Debug, Clone)]
+pub struct Console {
+    buffers: Arc<Mutex<Buffers>>,
+    written: usize,
+}
+
+/// Host side of a console, to read what the program wrote
+#[derive(Debug, Clone)]
+pub struct ConsoleOutput {
+    buffers: Arc<Mutex<Buffers>>,
+}
+
+impl Console {
+    /// Create a console, along with the handle to read its output
+    #[must_use]
+    pub fn new() -> (Self, ConsoleOutput) {
+        let buffers = Arc::new(Mutex::new(Buffers::default()));
+        (
+            Self {
+                buffers: Arc::clone(&buffers),
+                written: 0,
+            },
+            ConsoleOutput { buffers },
+        )
+    }
+}
+
+impl ConsoleOutput {
+    /// Take the text written on a channel since the last call
+    #[must_use]
+    pub fn take(&self, channel: Channel) -> String {
+        let mut buffers = self.buffers.lock().unwrap_or_else(PoisonError::into_inner);
+        match channel {
+            Channel::Stdout => std::mem::take(&mut buffers.stdout),
+            Channel::Stderr => std::mem::take(&mut buffers.stderr),
+        }
+    }
+}
+
+impl Device for Console {
+    fn ports(&self) -> Address {
+        2
+    }
+
+    fn read(&mut self, _port: Address) -> Result<Cell, WouldBlock> {
+        Ok(Cell::Empty)
+    }
+
+    fn write(&mut self, port: Address, value: Cell) -> Result<(), WouldBlock> {
+        let mut buffers = self.buffers.lock().unwrap_or_else(PoisonError::into_inner);
+        let buffer = if port == 0 {
+            &mut buffers.stdout
+        } else {
+            &mut buffers.stderr
+        };
+
+        let length = buffer.len();
+        match value {
+            Cell::Char(c) => buffer.push(c),
+            Cell::Word(w) => buffer.push_str(&w.to_string()),
+            Cell::Empty | Cell::Instruction(_) => {}
+        }
+        self.written += buffer.len() - length;
+
+        Ok(())
+    }
+
+    fn output_bytes(&self) -> usize {
+        self.written
+    }
+
+    fn hash_state(&self, mut state: &mut dyn Hasher) -> bool {
+        // The host only reads the output, which grows with each write
+        self.written.hash(&mut state);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn console_test() {
+        let (mut console, output) = Console::new();
+        console.write(0, Cell::Char('h')).unwrap();
+        console.write(0, Cell::Char('i')).unwrap();
+        console.write(1, Cell::Word(42)).unwrap();
+        assert_eq!(output.take(Channel::Stdout), "hi");
+        assert_eq!(output.take(Channel::Stdout), "");
+
+        console.write(0, Cell::Char('!')).unwrap();
+        assert_eq!(output.take(Channel::Stdout), "!");
+        assert_eq!(output.take(Channel::Stderr), "42");
+    }
+}
@@ -0,0 +1,90 @@
+//! Evaluation of expressions against the state of a running computer
+//!
+//! The debugger parses them with [`parse_runtime_expression`], and evaluates them with a
+//! [`MachineContext`], which gives the value of the registers, memory cells and labels they
+//! refer to.
+
+use std::collections::HashMap;
+
+use nom::combinator::all_consuming;
+
+use crate::constants::Address;
+use crate::parser::expression::{Context, Value};
+use crate::parser::parse_register;
+
+use super::Computer;
+
+/// A computer with the labels of its program, resolving the variables of runtime expressions
+pub struct MachineContext<'a> {
+    computer: &'a Computer,
+    labels: &'a HashMap<String, Address>,
+}
+
+impl<'a> MachineContext<'a> {
+    #[must_use]
+    pub const fn new(computer: &'a Computer, labels: &'a HashMap<String, Address>) -> Self {
+        Self { computer, labels }
+    }
+}
+
+impl Context for MachineContext<'_> {
+    fn resolve_memory(&self, address: Value) -> Option<Value> {
+        let address = Address::try_from(address).ok()?;
+        let cell = self.computer.memory.get(address).ok()?;
+        cell.extract_word().ok().map(Value::from)
+    }
+
+    fn resolve_variable(&self, variable: &str) -> Option<Value> {
+        if let Some(&address) = self.labels.get(variable) {
+            return Some(address.into());
+        }
+
+        // `pc` and `sp` can be written without their `%`, unless a label has the same name
+        let register = match variable {
+            "pc" | "sp" => format!("%{variable}"),
+            _ => variable.to_owned(),
+        };
+        let (_, reg) = all_consuming(parse_register::<()>)(register.as_str()).ok()?;
+        let word = self.computer.registers.get(&reg).extract_word().ok()?;
+        Some(word.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::expression::{parse_runtime_expression, EvaluationError};
+    use crate::{compile, parse};
+
+    #[test]
+    fn machine_context_test() {
+        let source = "main: ld 3, %a\n    push %a\n    reset\ncounter: .word 40\n";
+        let program = parse(source).unwrap();
+        let (mut computer, debug_info) = compile(program.inner, "main").unwrap();
+        computer.step().unwrap();
+        computer.step().unwrap();
+
+        let context = MachineContext::new(&computer, &debug_info.labels);
+        let evaluate = |expression: &str| {
+            let (_, node) = all_consuming(parse_runtime_expression::<()>)(expression).unwrap();
+            node.evaluate::<_, Value>(&context)
+        };
+
+        let counter = debug_info.labels["counter"];
+        assert_eq!(evaluate("[counter] + %a * 2"), Ok(46));
+        assert_eq!(evaluate("[ %sp ]"), Ok(3));
+        assert_eq!(evaluate("pc - main"), Ok(2));
+        assert_eq!(evaluate("%sp"), Ok(i128::from(computer.registers.sp)));
+        assert_eq!(evaluate("counter"), Ok(i128::from(counter)));
+        assert_eq!(evaluate("[[counter] - 40 + counter]"), Ok(40));
+
+        // Instructions and unknown names can not be resolved
+        assert_eq!(
+            evaluate("[main]").unwrap_err().innermost(),
+            &EvaluationError::InvalidMemory {
+                address: i128::from(debug_info.labels["main"])
+            }
+        );
+        assert!(evaluate("missing").is_err());
+    }
+}
@@ -0,0 +1,258 @@
+//! Checking the calling convention of `<std/stack.s>` while the program runs
+//!
+//! Each `call` opens a frame recording the stack pointer, the return address and the callee-saved
+//! `%b` register. The matching `rtn` must find the stack balanced, the return address untouched
+//! and `%b` restored.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::constants::{Address, Word};
+
+use super::{Cell, Computer, Reg};
+
+/// The part of the calling convention broken by a routine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// The stack pointer is not back where the call left it, by the given number of cells
+    Unbalanced { offset: i64 },
+
+    /// The return address on the stack was overwritten
+    ReturnAddress { found: Cell },
+
+    /// A callee-saved register was not restored
+    CalleeSaved {
+        reg: Reg,
+        expected: Cell,
+        found: Cell,
+    },
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unbalanced { offset } if *offset < 0 => {
+                write!(f, "left {} extra values on the stack", -offset)
+            }
+            Self::Unbalanced { offset } => write!(f, "popped {offset} values too many"),
+            Self::ReturnAddress { found } => {
+                write!(f, "overwrote its return address with {found}")
+            }
+            Self::CalleeSaved {
+                reg,
+                expected,
+                found,
+            } => write!(f, "did not restore {reg} (was {expected}, now {found})"),
+        }
+    }
+}
+
+/// A routine breaking the calling convention, found by the [`ConventionChecker`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConventionViolation {
+    /// Address of the routine
+    pub routine: Address,
+
+    /// Label of the routine, if any
+    pub label: Option<String>,
+
+    /// Address of the `call` instruction
+    pub call_site: Address,
+
+    /// Address of the `rtn` instruction
+    pub address: Address,
+
+    pub violation: Violation,
+}
+
+impl std::fmt::Display for ConventionViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.label {
+            Some(label) => write!(f, "routine {label}")?,
+            None => write!(f, "routine at address {}", self.routine)?,
+        }
+        write!(
+            f,
+            " (called from address {}) {} before returning at address {}",
+            self.call_site, self.violation, self.address
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Frame {
+    routine: Address,
+    call_site: Address,
+    sp: Address,
+    return_address: Word,
+    b: Cell,
+}
+
+/// Watches the calls and returns of a program to find routines breaking the calling convention
+#[derive(Debug, Default)]
+pub struct ConventionChecker {
+    names: BTreeMap<Address, String>,
+    pending: Option<(Address, Address, Cell)>,
+    frames: Vec<Frame>,
+}
+
+impl ConventionChecker {
+    /// Create a checker naming the routines with the given labels
+    #[must_use]
+    pub fn new(labels: &HashMap<String, Address>) -> Self {
+        let mut names: BTreeMap<Address, String> = BTreeMap::new();
+        for (label, &address) in labels {
+            let name = names.entry(address).or_insert_with(|| label.clone());
+            if label < name {
+                name.clone_from(label);
+            }
+        }
+
+        Self {
+            names,
+            ..Self::default()
+        }
+    }
+
+    /// Number of routines currently running
+    #[must_use]
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Check the state of the computer, before each step
+    pub fn check(&mut self, computer: &Computer) -> Option<ConventionViolation> {
+        let registers = &computer.registers;
+
+        // The frame of a call is only known once it jumped to the routine
+        if let Some((call_site, sp, b)) = self.pending.take() {
+            let return_address = Word::from(call_site) + 1;
+            let pushed = computer.memory.get(registers.sp).ok();
+            if registers.sp.checked_add(1) == Some(sp)
+                && pushed == Some(&Cell::Word(return_address))
+            {
+                self.frames.push(Frame {
+                    routine: registers.pc,
+                    call_site,
+                    sp: registers.sp,
+                    return_address,
+                    b,
+                });
+            }
+        }
+
+        let instruction = computer
+            .memory
+            .get(registers.pc)
+            .ok()
+            .and_then(|cell| cell.extract_instruction().ok())?;
+
+        if instruction.is_call() {
+            self.pending = Some((registers.pc, registers.sp, registers.b.clone()));
+            return None;
+        }
+
+        if !instruction.is_return() {
+            return None;
+        }
+
+        // Returns without a matching call, like the end of `Computer::call`, are not checked
+        let frame = self.frames.pop()?;
+        let violation = if registers.sp == frame.sp {
+            let found = computer
+                .memory
+                .get(registers.sp)
+                .map_or(Cell::Empty, Clone::clone);
+            if found != Cell::Word(frame.return_address) {
+                Violation::ReturnAddress { found }
+            } else if registers.b != frame.b {
+                Violation::CalleeSaved {
+                    reg: Reg::B,
+                    expected: frame.b,
+                    found: registers.b.clone(),
+                }
+            } else {
+                return None;
+            }
+        } else {
+            Violation::Unbalanced {
+                offset: i64::from(registers.sp) - i64::from(frame.sp),
+            }
+        };
+
+        Some(ConventionViolation {
+            routine: frame.routine,
+            label: self.names.get(&frame.routine).cloned(),
+            call_site: frame.call_site,
+            address: registers.pc,
+            violation,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile, parse};
+
+    fn check(source: &str) -> Option<ConventionViolation> {
+        let program = parse(source).unwrap();
+        let (mut computer, debug_info) = compile(program.inner, "main").unwrap();
+        let mut checker = ConventionChecker::new(&debug_info.labels);
+        for _ in 0..1000 {
+            if let Some(found) = checker.check(&computer) {
+                return Some(found);
+            }
+            if computer.step().is_err() {
+                break;
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn respected_convention_test() {
+        assert_eq!(
+            check(
+                "main: ld 1, %b\n    push 4\n    call double\n    add 1, %sp\n    reset\n\
+                 double: push %b\n    ld [%sp+2], %b\n    ld %b, %a\n    add %b, %a\n\
+                 \x20   pop %b\n    rtn\n"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn violations_test() {
+        let start = crate::constants::PROGRAM_START;
+
+        let found = check("main: call f\n    reset\nf: push 1\n    rtn\n").unwrap();
+        assert_eq!(found.label.as_deref(), Some("f"));
+        assert_eq!(found.call_site, start);
+        assert_eq!(found.address, start + 3);
+        assert_eq!(found.violation, Violation::Unbalanced { offset: -1 });
+        assert_eq!(
+            found.to_string(),
+            "routine f (called from address 1000) left 1 extra values on the stack before \
+             returning at address 1003"
+        );
+
+        let found =
+            check("main: call f\n    reset\nf: ld 5, %a\n    st %a, [%sp]\n    rtn\n").unwrap();
+        assert_eq!(
+            found.violation,
+            Violation::ReturnAddress {
+                found: Cell::Word(5)
+            }
+        );
+
+        let found = check("main: ld 2, %b\n    call f\n    reset\nf: ld 3, %b\n    rtn\n").unwrap();
+        assert_eq!(
+            found.violation,
+            Violation::CalleeSaved {
+                reg: Reg::B,
+                expected: Cell::Word(2),
+                found: Cell::Word(3)
+            }
+        );
+    }
+}
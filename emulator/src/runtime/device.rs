@@ -0,0 +1,187 @@
+//! I/O controllers, accessed with the `in` and `out` instructions
+//!
+//! Devices are attached to the computer on a range of ports, starting at a base address. The
+//! `in` and `out` instructions address those ports instead of the memory.
+
+use std::hash::Hasher;
+use std::ops::Range;
+
+use thiserror::Error;
+
+use crate::constants::Address;
+
+use super::Cell;
+
+/// Returned by a device which can not complete an access yet
+///
+/// The instruction doing the access is executed again on the next step, until the device is
+/// ready.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WouldBlock;
+
+/// An I/O controller
+pub trait Device: Send + Sync + DeviceClone {
+    /// Number of ports used by the device
+    fn ports(&self) -> Address;
+
+    /// Read a value from a port, relative to the base address of the device
+    fn read(&mut self, port: Address) -> Result<Cell, WouldBlock>;
+
+    /// Write a value to a port, relative to the base address of the device
+    fn write(&mut self, port: Address, value: Cell) -> Result<(), WouldBlock>;
+
+    /// Check if the device requests a hardware interrupt
+    fn interrupt(&self) -> bool {
+        false
+    }
+
+    /// Called after each step of the computer, with the number of cycles it took
+    fn tick(&mut self, _cycles: usize) {}
+
+    /// Number of bytes sent to the host so far, counted against the output limit
+    fn output_bytes(&self) -> usize {
+        0
+    }
+
+    /// Hash the state of the device, compared between steps to detect infinite loops
+    ///
+    /// Returns `false` when the host also changes the state, like the inbox of a mailbox, which
+    /// turns the detection off.
+    fn hash_state(&self, _state: &mut dyn Hasher) -> bool {
+        false
+    }
+}
+
+/// Clone a device attached to a computer, implemented for all the devices which are [`Clone`]
+///
+/// The clone of a device may share what the host sees of it with the original, like the output of
+/// a console or the inbox of a mailbox endpoint. The clone of a [`Heap`](super::Heap) does not,
+/// and allocates on its own.
+pub trait DeviceClone {
+    fn clone_box(&self) -> Box<dyn Device>;
+}
+
+impl<D: Device + Clone + 'static> DeviceClone for D {
+    fn clone_box(&self) -> Box<dyn Device> {
+        Box::new(self.clone())
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("ports {start}..{end} overlap with another device")]
+pub struct PortConflict {
+    start: Address,
+    end: Address,
+}
+
+pub(crate) enum DeviceError {
+    /// No device is attached on this port
+    Unmapped,
+
+    /// The device is not ready
+    Blocked,
+}
+
+impl From<WouldBlock> for DeviceError {
+    fn from(_: WouldBlock) -> Self {
+        Self::Blocked
+    }
+}
+
+/// Devices attached to a computer
+#[derive(Default)]
+pub(crate) struct Devices {
+    attached: Vec<(Range<Address>, Box<dyn Device>)>,
+}
+
+impl Clone for Devices {
+    fn clone(&self) -> Self {
+        Self {
+            attached: self
+                .attached
+                .iter()
+                .map(|(ports, device)| (ports.clone(), device.clone_box()))
+                .collect(),
+        }
+    }
+}
+
+impl Devices {
+    /// Attach a device, returning the range of ports it uses
+    pub(crate) fn attach(
+        &mut self,
+        base: Address,
+        device: Box<dyn Device>,
+    ) -> Result<Range<Address>, PortConflict> {
+        let ports = base..base.saturating_add(device.ports());
+        if self
+            .attached
+            .iter()
+            .any(|(other, _)| ports.start < other.end && other.start < ports.end)
+        {
+            return Err(PortConflict {
+                start: ports.start,
+                end: ports.end,
+            });
+        }
+
+        self.attached.push((ports.clone(), device));
+        Ok(ports)
+    }
+
+    fn find(
+        &mut self,
+        port: Address,
+    ) -> Result<(&mut (dyn Device + 'static), Address), DeviceError> {
+        self.attached
+            .iter_mut()
+            .find(|(ports, _)| ports.contains(&port))
+            .map(|(ports, device)| (device.as_mut(), port - ports.start))
+            .ok_or(DeviceError::Unmapped)
+    }
+
+    pub(crate) fn read(&mut self, port: Address) -> Result<Cell, DeviceError> {
+        let (device, port) = self.find(port)?;
+        Ok(device.read(port)?)
+    }
+
+    pub(crate) fn write(&mut self, port: Address, value: Cell) -> Result<(), DeviceError> {
+        let (device, port) = self.find(port)?;
+        Ok(device.write(port, value)?)
+    }
+
+    /// Hash the state of the devices, returning `false` if one of them can not be hashed
+    pub(crate) fn hash_state(&self, state: &mut dyn Hasher) -> bool {
+        self.attached.iter().all(|(ports, device)| {
+            state.write_u64(u64::from(ports.start));
+            device.hash_state(state)
+        })
+    }
+
+    /// Check if any device requests an interrupt
+    pub(crate) fn interrupt(&self) -> bool {
+        self.attached.iter().any(|(_, device)| device.interrupt())
+    }
+
+    pub(crate) fn tick(&mut self, cycles: usize) {
+        for (_, device) in &mut self.attached {
+            device.tick(cycles);
+        }
+    }
+
+    pub(crate) fn output_bytes(&self) -> usize {
+        self.attached
+            .iter()
+            .map(|(_, device)| device.output_bytes())
+            .sum()
+    }
+
+    /// First port after all the attached devices
+    pub(crate) fn end(&self) -> Address {
+        self.attached
+            .iter()
+            .map(|(ports, _)| ports.end)
+            .max()
+            .unwrap_or(0)
+    }
+}
@@ -0,0 +1,175 @@
+//! Digests of the memory, to check the state a program ends in with a single value
+//!
+//! A digest is a 64-bit FNV-1a hash of the cells of a range, each one hashed with its type and
+//! value, so it does not depend on the machine running it. There is no binary encoding of the
+//! instructions, so they are hashed as their disassembly: the digest of a range holding some
+//! changes if the way instructions are printed changes. The `.final_assert START, LENGTH, DIGEST`
+//! directive checks the digest of a range once the program halts, making [`Computer::step`] fail
+//! with a [`FinalAssertionFailed`] error if it differs.
+
+use std::ops::Range;
+
+use thiserror::Error;
+
+use crate::constants::Address;
+
+use super::{Cell, Computer};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Expected digest of a range of the memory once the program halted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FinalAssertion {
+    pub range: Range<Address>,
+    pub digest: u64,
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error(
+    "final assertion failed: the digest of the cells {} to {} is {found:#018x}, \
+     expected {expected:#018x}",
+    range.start,
+    range.end.saturating_sub(1)
+)]
+pub struct FinalAssertionFailed {
+    pub range: Range<Address>,
+    pub expected: u64,
+    pub found: u64,
+}
+
+/// A 64-bit FNV-1a hash
+struct Fnv(u64);
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+impl Computer {
+    /// Hash the cells of a range of the memory, stopping at its end
+    #[must_use]
+    pub fn memory_digest(&self, range: Range<Address>) -> u64 {
+        let mut hash = Fnv(FNV_OFFSET_BASIS);
+        for address in range {
+            let Ok(cell) = self.memory.get(address) else {
+                break;
+            };
+
+            match cell {
+                Cell::Empty => hash.write(&[0]),
+                Cell::Word(word) => {
+                    hash.write(&[1]);
+                    hash.write(&word.to_le_bytes());
+                }
+                Cell::Char(c) => {
+                    hash.write(&[2]);
+                    hash.write(&u32::from(*c).to_le_bytes());
+                }
+                Cell::Instruction(instruction) => {
+                    hash.write(&[3]);
+                    hash.write(instruction.to_string().as_bytes());
+                    hash.write(&[0]);
+                }
+            }
+        }
+        hash.0
+    }
+
+    /// Add an expected digest, checked once the program halts
+    pub fn add_final_assertion(&mut self, assertion: FinalAssertion) {
+        self.final_assertions.push(assertion);
+    }
+
+    /// Check the digests of the final assertions, in the order they were added
+    pub fn check_final_assertions(&self) -> Result<(), FinalAssertionFailed> {
+        for assertion in &self.final_assertions {
+            let found = self.memory_digest(assertion.range.clone());
+            if found != assertion.digest {
+                return Err(FinalAssertionFailed {
+                    range: assertion.range.clone(),
+                    expected: assertion.digest,
+                    found,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::ProcessorError;
+    use crate::{compile, parse};
+
+    #[test]
+    fn memory_digest_test() {
+        let source = "main: ld 5, %a\n    st %a, [result]\n    reset\n\
+                      result: .word 0\ntext: .string \"ok\"\n";
+        let program = parse(source).unwrap();
+        let (mut computer, debug_info) = compile(program.inner, "main").unwrap();
+        let result = debug_info.labels["result"];
+
+        // The same for the same cells, and sensitive to their values and types
+        let before = computer.memory_digest(result..result + 3);
+        assert_eq!(before, computer.clone().memory_digest(result..result + 3));
+        assert_eq!(computer.memory_digest(0..0), FNV_OFFSET_BASIS);
+        let text = debug_info.labels["text"];
+        let mut words = computer.clone();
+        *words.memory.get_mut(text).unwrap() = Cell::Word(u32::from('o').into());
+        assert_ne!(
+            words.memory_digest(text..text + 2),
+            computer.memory_digest(text..text + 2)
+        );
+
+        computer.run().unwrap();
+        let after = computer.memory_digest(result..result + 3);
+        assert_ne!(before, after);
+
+        // The digest stops at the end of the memory
+        let size = Address::try_from(computer.memory.size()).unwrap();
+        assert_eq!(
+            computer.memory_digest(size - 2..size),
+            computer.memory_digest(size - 2..size + 10)
+        );
+    }
+
+    #[test]
+    fn final_assert_test() {
+        let source = "main: ld 5, %a\n    st %a, [result]\n    reset\n\
+                      result: .word 0\n";
+        let program = parse(source).unwrap();
+        let (mut computer, debug_info) = compile(program.inner, "main").unwrap();
+        let result = debug_info.labels["result"];
+        let mut expected = computer.clone();
+        expected.run().unwrap();
+        let digest = expected.memory_digest(result..result + 1);
+
+        // The directive is checked when the program resets
+        let check = |digest: u64| {
+            let source = format!("{source}.final_assert result, 1, {digest:#x}\n");
+            let program = parse(&source).unwrap();
+            let (mut computer, _) = compile(program.inner, "main").unwrap();
+            computer.run()
+        };
+        assert!(check(digest).is_ok());
+        let Err(ProcessorError::FinalAssertion(failed)) = check(digest ^ 1) else {
+            panic!("the final assertion should fail");
+        };
+        assert_eq!(failed.range, result..result + 1);
+        assert_eq!((failed.expected, failed.found), (digest ^ 1, digest));
+
+        computer.add_final_assertion(FinalAssertion {
+            range: result..result + 1,
+            digest,
+        });
+        assert!(computer.check_final_assertions().is_err());
+        // Once the program ran, the assertion holds
+        assert!(computer.run().is_ok());
+    }
+}
@@ -0,0 +1,99 @@
+//! Text dumps of the memory of the computer
+//!
+//! A hexdump shows a few cells per line, each one as the 16 hexadecimal digits of its value, and
+//! the characters they hold on the right, like the classic `hexdump -C`. Since the cells are not
+//! bytes, the ones holding an instruction or nothing are shown with a marker instead:
+//!
+//! ```text
+//!  1000             insn             insn 0000000000000041 000000000000000a  |##A.|
+//! ```
+
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::constants::Address;
+
+use super::{Cell, Computer};
+
+/// Number of cells on each line of a hexdump
+pub const HEXDUMP_WIDTH: usize = 4;
+
+/// The value of a cell in a hexdump, and its character
+fn hexdump_cell(cell: &Cell) -> (String, char) {
+    match cell {
+        Cell::Word(word) => {
+            let c = u8::try_from(*word)
+                .ok()
+                .filter(|byte| byte.is_ascii_graphic() || *byte == b' ')
+                .map_or('.', char::from);
+            (format!("{:016x}", word.cast_unsigned()), c)
+        }
+        Cell::Char(c) => {
+            let shown = if c.is_control() { '.' } else { *c };
+            (format!("{:016x}", u32::from(*c)), shown)
+        }
+        Cell::Instruction(_) => (format!("{:>16}", "insn"), '#'),
+        Cell::Empty => (format!("{:>16}", "·"), ' '),
+    }
+}
+
+impl Computer {
+    /// Dump the cells of a range of the memory, stopping at its end
+    #[must_use]
+    pub fn hexdump(&self, range: Range<Address>) -> String {
+        let mut dump = String::new();
+        let end = range
+            .end
+            .min(Address::try_from(self.memory.size()).unwrap_or(Address::MAX));
+        let addresses: Vec<Address> = (range.start..end).collect();
+        for line in addresses.chunks(HEXDUMP_WIDTH) {
+            let (cells, chars): (Vec<String>, String) = line
+                .iter()
+                .filter_map(|&address| self.memory.get(address).ok())
+                .map(hexdump_cell)
+                .unzip();
+
+            // Writing to a string cannot fail
+            let _ = writeln!(
+                dump,
+                "{:>5}  {:<width$}  |{chars}|",
+                line[0],
+                cells.join(" "),
+                width = HEXDUMP_WIDTH * 17 - 1,
+            );
+        }
+        dump
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{compile, parse};
+
+    #[test]
+    fn hexdump_test() {
+        let source = "main: reset\ntext: .string \"Hi!\\n\"\nvalue: .word -1\n";
+        let program = parse(source).unwrap();
+        let (computer, debug_info) = compile(program.inner, "main").unwrap();
+        let main = debug_info.labels["main"];
+
+        let dump = computer.hexdump(main..main + 7);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            format!(
+                "{main:>5}  {:>16} 0000000000000048 0000000000000069 0000000000000021  |#Hi!|",
+                "insn"
+            )
+        );
+        assert!(lines[1].starts_with(&format!("{:>5}  000000000000000a", main + 4)));
+        assert!(lines[1].contains(" ffffffffffffffff "));
+        assert!(lines[1].ends_with("  |.. |"));
+
+        // The dump stops at the end of the memory
+        let size = u32::try_from(computer.memory.size()).unwrap();
+        assert_eq!(computer.hexdump(size - 1..size + 10).lines().count(), 1);
+        assert!(computer.hexdump(size..size + 10).is_empty());
+    }
+}
@@ -1,10 +1,10 @@
 use thiserror::Error;
 
-use crate::constants::Word;
+use crate::constants::{Address, Word};
 
 use super::memory::MemoryError;
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone)]
 pub enum Exception {
     #[error("hardware interrupt")]
     HardwareInterrupt,
@@ -23,6 +23,21 @@ pub enum Exception {
 
     #[error("invalid memory access ({0})")]
     InvalidMemoryAccess(#[from] MemoryError),
+
+    /// Only raised when [`MachineConfig::trap_on_overflow`] is set
+    ///
+    /// [`MachineConfig::trap_on_overflow`]: super::MachineConfig::trap_on_overflow
+    #[error("arithmetic overflow")]
+    Overflow,
+
+    /// A fault raised while handling another fault, or handlers nested too deeply
+    ///
+    /// It is never delivered to the handler, and stops the processor instead.
+    #[error("double fault: {fault} while handling exception {handling}")]
+    DoubleFault {
+        fault: Box<Exception>,
+        handling: Word,
+    },
 }
 
 impl Exception {
@@ -34,10 +49,57 @@ impl Exception {
             Exception::PrivilegedInstruction => 3,
             Exception::Trap => 4,
             Exception::InvalidMemoryAccess(_) => 5,
+            Exception::Overflow => 6,
+            Exception::DoubleFault { .. } => 7,
         }
     }
 
     pub(crate) fn is_hardware_interrupt(&self) -> bool {
         matches!(self, Exception::HardwareInterrupt)
     }
+
+    /// Whether the exception is raised by an error in the program, unlike interrupts and traps
+    pub(crate) fn is_fault(&self) -> bool {
+        !matches!(self, Exception::HardwareInterrupt | Exception::Trap)
+    }
+}
+
+/// State of the processor when an error stopped it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultContext {
+    /// Address of the instruction being executed
+    pub address: Address,
+
+    /// Name of this address relative to the labels of the program, once they are given
+    pub symbol: Option<String>,
+
+    /// The instruction at this address, if it holds one
+    pub instruction: Option<String>,
+
+    /// Index of the operand of the instruction the error comes from, counted from 0, if known
+    pub operand: Option<usize>,
+
+    /// Whether the processor was in supervisor mode
+    pub supervisor: bool,
+}
+
+impl std::fmt::Display for FaultContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "at address {}", self.address)?;
+        if let Some(symbol) = &self.symbol {
+            write!(f, " <{symbol}>")?;
+        }
+        if let Some(instruction) = &self.instruction {
+            write!(f, " ({instruction})")?;
+        }
+        if let Some(index) = self.operand {
+            write!(f, " from operand {}", index + 1)?;
+        }
+        let mode = if self.supervisor {
+            "supervisor"
+        } else {
+            "user"
+        };
+        write!(f, " in {mode} mode")
+    }
 }
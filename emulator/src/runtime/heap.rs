@@ -0,0 +1,332 @@
+//! An allocator device, managing a heap region of the memory
+//!
+//! The device has two ports. Writing a size to port `0` allocates that many cells in the heap,
+//! and reading port `0` then gives the address of the allocated block, or `0` when there is no
+//! room left. Writing an address to port `1` frees the block starting there. The host checks the
+//! frees and the blocks still allocated through a [`HeapMonitor`].
+
+use std::collections::{BTreeMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use thiserror::Error;
+
+use crate::constants::{Address, Word};
+
+use super::memory::parse_word;
+use super::{Cell, Device, WouldBlock};
+
+/// A misuse of the heap
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum HeapError {
+    #[error("{size} cell(s) allocated at address {address} on step {step} were never freed")]
+    Leak {
+        address: Address,
+        size: Address,
+        step: usize,
+    },
+
+    #[error("address {address} freed twice, on step {step}")]
+    DoubleFree { address: Address, step: usize },
+
+    #[error("address {address} freed on step {step} was not allocated")]
+    InvalidFree { address: Word, step: usize },
+}
+
+/// Region of the memory managed by the allocator, written `START:SIZE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapRegion {
+    pub start: Address,
+    pub size: Address,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid heap region {0:?}, expected START:SIZE")]
+pub struct HeapRegionParseError(String);
+
+impl FromStr for HeapRegion {
+    type Err = HeapRegionParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || HeapRegionParseError(s.to_owned());
+        let (start, size) = s.split_once(':').ok_or_else(error)?;
+        let start = parse_word(start.trim()).ok_or_else(error)?;
+        let size = parse_word(size.trim()).ok_or_else(error)?;
+        Ok(Self {
+            start: Address::try_from(start).map_err(|_| error())?,
+            size: Address::try_from(size).map_err(|_| error())?,
+        })
+    }
+}
+
+impl HeapRegion {
+    fn range(self) -> Range<Address> {
+        self.start..self.start.saturating_add(self.size)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct Allocations {
+    /// Size and allocation step of the blocks, by address
+    blocks: BTreeMap<Address, (Address, usize)>,
+    freed: HashSet<Address>,
+    errors: Vec<HeapError>,
+}
+
+/// The allocator device, attached to a computer
+///
+/// A clone of the heap, like the one of a forked computer, allocates independently from the
+/// original: it starts from a copy of its blocks, and is checked through its own monitor.
+#[derive(Debug)]
+pub struct Heap {
+    region: Range<Address>,
+    allocations: Arc<Mutex<Allocations>>,
+    last: Address,
+    steps: usize,
+}
+
+/// Host side of the allocator, to check how the program used the heap
+#[derive(Debug, Clone)]
+pub struct HeapMonitor {
+    allocations: Arc<Mutex<Allocations>>,
+}
+
+impl Heap {
+    /// Create an allocator for the given region, along with the handle to check it
+    #[must_use]
+    pub fn new(region: HeapRegion) -> (Self, HeapMonitor) {
+        let allocations = Arc::new(Mutex::new(Allocations::default()));
+        (
+            Self {
+                region: region.range(),
+                allocations: Arc::clone(&allocations),
+                last: 0,
+                steps: 0,
+            },
+            HeapMonitor { allocations },
+        )
+    }
+
+    /// Get another handle to check this allocator
+    #[must_use]
+    pub fn monitor(&self) -> HeapMonitor {
+        HeapMonitor {
+            allocations: Arc::clone(&self.allocations),
+        }
+    }
+
+    /// Find the first free block of the given size
+    fn fit(&self, blocks: &BTreeMap<Address, (Address, usize)>, size: Address) -> Option<Address> {
+        let mut start = self.region.start;
+        for (&address, &(length, _)) in blocks {
+            if address.checked_sub(start)? >= size {
+                break;
+            }
+            start = address + length;
+        }
+
+        (start.checked_add(size)? <= self.region.end).then_some(start)
+    }
+}
+
+impl Clone for Heap {
+    fn clone(&self) -> Self {
+        let allocations = self
+            .allocations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        Self {
+            region: self.region.clone(),
+            allocations: Arc::new(Mutex::new(allocations)),
+            last: self.last,
+            steps: self.steps,
+        }
+    }
+}
+
+impl Device for Heap {
+    fn ports(&self) -> Address {
+        2
+    }
+
+    fn read(&mut self, port: Address) -> Result<Cell, WouldBlock> {
+        if port == 0 {
+            Ok(Cell::Word(self.last.into()))
+        } else {
+            Ok(Cell::Empty)
+        }
+    }
+
+    fn write(&mut self, port: Address, value: Cell) -> Result<(), WouldBlock> {
+        let Cell::Word(value) = value else {
+            return Ok(());
+        };
+
+        let mut allocations = self
+            .allocations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let step = self.steps;
+
+        if port == 0 {
+            self.last = Address::try_from(value)
+                .ok()
+                .filter(|size| *size > 0)
+                .and_then(|size| Some((self.fit(&allocations.blocks, size)?, size)))
+                .map_or(0, |(address, size)| {
+                    allocations.blocks.insert(address, (size, step));
+                    allocations.freed.remove(&address);
+                    address
+                });
+            return Ok(());
+        }
+
+        let address = Address::try_from(value).ok();
+        if let Some(address) = address.filter(|a| allocations.blocks.remove(a).is_some()) {
+            allocations.freed.insert(address);
+        } else if let Some(address) = address.filter(|a| allocations.freed.contains(a)) {
+            allocations
+                .errors
+                .push(HeapError::DoubleFree { address, step });
+        } else {
+            allocations.errors.push(HeapError::InvalidFree {
+                address: value,
+                step,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn tick(&mut self, _cycles: usize) {
+        self.steps += 1;
+    }
+
+    fn hash_state(&self, mut state: &mut dyn Hasher) -> bool {
+        // The steps only date the allocations in the reports, they do not change what the
+        // program sees
+        let allocations = self
+            .allocations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for (address, (size, _)) in &allocations.blocks {
+            (address, size).hash(&mut state);
+        }
+        (self.last, allocations.errors.len()).hash(&mut state);
+        true
+    }
+}
+
+impl HeapMonitor {
+    /// Number of blocks currently allocated
+    #[must_use]
+    pub fn allocated(&self) -> usize {
+        let allocations = self
+            .allocations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        allocations.blocks.len()
+    }
+
+    /// Check if an address is in a block currently allocated
+    #[must_use]
+    pub fn contains(&self, address: Address) -> bool {
+        let allocations = self
+            .allocations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        allocations
+            .blocks
+            .range(..=address)
+            .next_back()
+            .is_some_and(|(start, (size, _))| address - start < *size)
+    }
+
+    /// Invalid frees made so far, followed by the blocks still allocated
+    #[must_use]
+    pub fn errors(&self) -> Vec<HeapError> {
+        let allocations = self
+            .allocations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let leaks = allocations
+            .blocks
+            .iter()
+            .map(|(&address, &(size, step))| HeapError::Leak {
+                address,
+                size,
+                step,
+            });
+        allocations.errors.iter().cloned().chain(leaks).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heap_test() {
+        let region: HeapRegion = "5000:10".parse().unwrap();
+        let (mut heap, monitor) = Heap::new(region);
+        let malloc = |heap: &mut Heap, size| {
+            heap.write(0, Cell::Word(size)).unwrap();
+            heap.read(0).unwrap()
+        };
+
+        assert_eq!(malloc(&mut heap, 4), Cell::Word(5000));
+        assert_eq!(malloc(&mut heap, 4), Cell::Word(5004));
+        assert_eq!(malloc(&mut heap, 4), Cell::Word(0));
+        heap.write(1, Cell::Word(5000)).unwrap();
+        assert_eq!(malloc(&mut heap, 3), Cell::Word(5000));
+        assert_eq!(monitor.allocated(), 2);
+        assert!(monitor.contains(5007));
+        assert!(!monitor.contains(5008));
+
+        heap.tick(1);
+        heap.write(1, Cell::Word(5004)).unwrap();
+        heap.write(1, Cell::Word(5004)).unwrap();
+        heap.write(1, Cell::Word(42)).unwrap();
+        assert_eq!(
+            monitor.errors(),
+            vec![
+                HeapError::DoubleFree {
+                    address: 5004,
+                    step: 1
+                },
+                HeapError::InvalidFree {
+                    address: 42,
+                    step: 1
+                },
+                HeapError::Leak {
+                    address: 5000,
+                    size: 3,
+                    step: 0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn clone_test() {
+        let region: HeapRegion = "5000:10".parse().unwrap();
+        let (mut heap, monitor) = Heap::new(region);
+        heap.write(0, Cell::Word(3)).unwrap();
+
+        // Both allocate from the same state, without seeing the blocks of the other
+        let mut fork = heap.clone();
+        heap.write(0, Cell::Word(3)).unwrap();
+        fork.write(0, Cell::Word(3)).unwrap();
+        assert_eq!(heap.read(0).unwrap(), Cell::Word(5003));
+        assert_eq!(fork.read(0).unwrap(), Cell::Word(5003));
+
+        fork.write(1, Cell::Word(5000)).unwrap();
+        assert_eq!(monitor.allocated(), 2);
+        assert!(monitor.contains(5000));
+        assert_eq!(fork.monitor().allocated(), 1);
+        assert!(!fork.monitor().contains(5000));
+    }
+}
@@ -0,0 +1,144 @@
+//! HTML rendering of the state of the computer
+//!
+//! The output is a self-contained fragment, with its own styles, meant to be displayed by
+//! notebooks or the web playground.
+
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::constants::Address;
+
+use super::registers::StatusRegister;
+use super::{Computer, Reg};
+
+/// Number of cells shown before `%pc` in the default memory window
+const WINDOW_BEFORE: Address = 4;
+
+/// Number of cells shown from `%pc` in the default memory window
+const WINDOW_AFTER: Address = 12;
+
+const FLAGS: [(&str, StatusRegister); 6] = [
+    ("C", StatusRegister::CARRY),
+    ("Z", StatusRegister::ZERO),
+    ("N", StatusRegister::NEGATIVE),
+    ("V", StatusRegister::OVERFLOW),
+    ("IE", StatusRegister::INTERRUPT_ENABLE),
+    ("S", StatusRegister::SUPERVISOR),
+];
+
+const STYLE: &str = "<style>\
+    .z33 table { border-collapse: collapse; font-family: monospace; margin: 0.5em 0; }\
+    .z33 th, .z33 td { border: 1px solid #ccc; padding: 0.1em 0.5em; text-align: left; }\
+    .z33 .flag { color: #bbb; }\
+    .z33 .flag.set { color: inherit; font-weight: bold; }\
+    .z33 .pc { background: #fff3b0; }\
+    .z33 .sp { background: #cde7ff; }\
+    </style>";
+
+/// Escape text to be included in HTML
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl Computer {
+    /// Render the registers and the status flags as an HTML table
+    #[must_use]
+    pub fn render_registers_html(&self) -> String {
+        let mut html = String::from("<table class=\"registers\"><tr>");
+        for reg in [Reg::A, Reg::B, Reg::PC, Reg::SP] {
+            let _ = write!(html, "<th>{reg}</th>");
+        }
+        html.push_str("<th>%sr</th></tr><tr>");
+        for reg in [Reg::A, Reg::B, Reg::PC, Reg::SP] {
+            let value = self.registers.get(&reg).to_string();
+            let _ = write!(html, "<td>{}</td>", escape(&value));
+        }
+
+        html.push_str("<td>");
+        for (name, flag) in FLAGS {
+            let class = if self.registers.sr.contains(flag) {
+                "flag set"
+            } else {
+                "flag"
+            };
+            let _ = write!(html, "<span class=\"{class}\">{name}</span> ");
+        }
+        html.push_str("</td></tr></table>");
+        html
+    }
+
+    /// Render a range of memory cells as an HTML table
+    ///
+    /// The cells pointed by `%pc` and `%sp` are highlighted. Addresses out of the memory are
+    /// skipped.
+    #[must_use]
+    pub fn render_memory_html(&self, range: Range<Address>) -> String {
+        let mut html =
+            String::from("<table class=\"memory\"><tr><th>Address</th><th>Value</th></tr>");
+        for address in range {
+            let Ok(cell) = self.memory.get(address) else {
+                continue;
+            };
+
+            let class = if address == self.registers.pc {
+                " class=\"pc\""
+            } else if address == self.registers.sp {
+                " class=\"sp\""
+            } else {
+                ""
+            };
+            let _ = write!(
+                html,
+                "<tr{class}><td>{address}</td><td>{}</td></tr>",
+                escape(&cell.to_string())
+            );
+        }
+        html.push_str("</table>");
+        html
+    }
+
+    /// Render the registers and the memory around `%pc` as a self-contained HTML fragment
+    #[must_use]
+    pub fn render_html(&self) -> String {
+        let pc = self.registers.pc;
+        let window = pc.saturating_sub(WINDOW_BEFORE)..pc.saturating_add(WINDOW_AFTER);
+        format!(
+            "<div class=\"z33\">{STYLE}{}{}</div>",
+            self.render_registers_html(),
+            self.render_memory_html(window)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{compile, parse};
+
+    #[test]
+    fn render_html_test() {
+        let program =
+            parse("main: ld 5, %a\n    cmp 5, %a\n    reset\ntext: .string \"<\"\n").unwrap();
+        let (mut computer, _) = compile(program.inner, "main").unwrap();
+        computer.step().unwrap();
+        computer.step().unwrap();
+
+        let html = computer.render_html();
+        assert!(html.starts_with("<div class=\"z33\"><style>"));
+        assert!(html.contains("<td>5 (0x5)</td>"));
+        assert!(html.contains("<span class=\"flag set\">Z</span>"));
+        assert!(html.contains("<span class=\"flag\">N</span>"));
+        assert!(html.contains("<tr class=\"pc\"><td>1002</td><td>reset</td></tr>"));
+        assert!(html.contains("<td>1003</td><td>&#39;&lt;&#39;</td>"));
+    }
+}
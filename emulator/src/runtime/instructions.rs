@@ -1,237 +1,602 @@
 use std::convert::TryInto;
 
-use parse_display::Display;
+use serde::{Deserialize, Serialize};
 use tracing::{debug, info};
 
 use crate::constants::{Word, INTERRUPT_PC_SAVE, INTERRUPT_SR_SAVE};
 
 use super::{
     arguments::{DirIndIdx, ExtractValue, ImmReg, ImmRegDirIndIdx, RegDirIndIdx, ResolveAddress},
+    device::DeviceError,
     exception::Exception,
-    memory::Cell,
+    memory::{Cell, MemoryError},
     registers::{Reg, StatusRegister},
     Computer, ProcessorError,
 };
 
-#[derive(Debug, Clone, PartialEq, Eq, Display)]
-pub enum Instruction {
-    /// Add a value to a register
-    #[display("add  {0}, {1}")]
-    Add(ImmRegDirIndIdx, Reg),
+/// Define the [`Instruction`] enum, how it is built from the operands of a line and displayed,
+/// and the documentation of each mnemonic taken from the doc comments of its variants
+///
+/// Variants take their operands as a tuple, or as named fields which the display format refers
+/// to, like `#[display("st   {src}, {dst}")]`. The names of those are listed in the documentation.
+/// A variant with a single operand can make it optional with `#[default(value)]`: the operand
+/// then takes this value when left out, and is not displayed when it has it.
+macro_rules! instructions {
+    (
+        $(#[$meta:meta])*
+        pub enum Instruction {
+            $(
+                $(#[doc = $doc:literal])*
+                #[display($display:literal)]
+                $(#[default($default:expr)])?
+                $variant:ident
+                    $(($($field:ty),*))?
+                    $({$($name:ident: $named:ty),* $(,)?})?,
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        pub enum Instruction {
+            $(
+                $(#[doc = $doc])*
+                $variant $(($($field),*))? $({$($name: $named),*})?,
+            )*
+        }
+
+        impl Instruction {
+            /// Build an instruction from the operands written after its mnemonic
+            pub(crate) fn from_operands(
+                kind: $crate::parser::value::InstructionKind,
+                operands: Vec<$crate::runtime::arguments::ImmRegDirIndIdx>,
+            ) -> Result<Self, $crate::compiler::memory::InstructionCompilationError> {
+                use $crate::compiler::memory::{get_none, get_singleton, get_tuple};
+                use $crate::parser::value::InstructionKind;
+
+                // Other kinds only happen when the macro defines some of the instructions, like
+                // in the tests
+                #[allow(unreachable_patterns)]
+                match kind {
+                    $(InstructionKind::$variant => instructions!(
+                        @parse $variant operands
+                        [$($($field),*)?] [$($($name),*)?] [$($default)?]
+                    ),)*
+                    _ => unreachable!("no instruction for {kind}"),
+                }
+            }
+        }
+
+        impl std::fmt::Display for Instruction {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    $(this @ Self::$variant { .. } => instructions!(
+                        @display f this $variant $display
+                        [$($($field),*)?] [$($($name),*)?] [$($default)?]
+                    ),)*
+                }
+            }
+        }
+
+        /// Display format of each instruction, which starts with its mnemonic, with its
+        /// documentation
+        const DOCUMENTATION: &[(&str, &str)] = &[$((
+            $display,
+            instructions!(@doc [$($doc)*] $($($name),*)?),
+        )),*];
+    };
+
+    (@parse $variant:ident $operands:ident [] [] []) => {
+        get_none($operands).map(|()| Self::$variant)
+    };
+
+    (@parse $variant:ident $operands:ident [$a:ty] [] []) => {
+        get_singleton($operands).map(Self::$variant)
+    };
+
+    (@parse $variant:ident $operands:ident [$a:ty] [] [$default:expr]) => {
+        if $operands.is_empty() {
+            Ok(Self::$variant($default))
+        } else {
+            get_singleton($operands).map(Self::$variant)
+        }
+    };
+
+    (@parse $variant:ident $operands:ident [$a:ty, $b:ty] [] []) => {
+        get_tuple($operands).map(|(a, b)| Self::$variant(a, b))
+    };
+
+    (@parse $variant:ident $operands:ident [] [$a:ident] []) => {
+        get_singleton($operands).map(|$a| Self::$variant { $a })
+    };
+
+    (@parse $variant:ident $operands:ident [] [$a:ident, $b:ident] []) => {
+        get_tuple($operands).map(|($a, $b)| Self::$variant { $a, $b })
+    };
+
+    (@display $f:ident $this:ident $variant:ident $display:literal [] [] []) => {{
+        let _ = $this;
+        $f.write_str($display)
+    }};
+
+    (@display $f:ident $this:ident $variant:ident $display:literal [$a:ty] [] []) => {{
+        let Self::$variant(a) = $this else { unreachable!() };
+        write!($f, $display, a)
+    }};
+
+    (@display $f:ident $this:ident $variant:ident $display:literal [$a:ty] [] [$default:expr]) => {{
+        let Self::$variant(a) = $this else { unreachable!() };
+        if *a == $default {
+            // Only the mnemonic, which comes first
+            $f.write_str($display.split_whitespace().next().unwrap_or_default())
+        } else {
+            write!($f, $display, a)
+        }
+    }};
+
+    (@display $f:ident $this:ident $variant:ident $display:literal [$a:ty, $b:ty] [] []) => {{
+        let Self::$variant(a, b) = $this else { unreachable!() };
+        write!($f, $display, a, b)
+    }};
+
+    (@display $f:ident $this:ident $variant:ident $display:literal [] [$($name:ident),+] []) => {{
+        let Self::$variant { $($name),+ } = $this else { unreachable!() };
+        write!($f, $display, $($name = $name),+)
+    }};
+
+    (@doc [$($doc:literal)*] $first:ident $(, $name:ident)*) => {
+        concat!(
+            $($doc, "\n",)*
+            "\nOperands: ",
+            stringify!($first)
+            $(, ", ", stringify!($name))*
+        )
+    };
+
+    (@doc [$($doc:literal)*]) => {
+        concat!($($doc, "\n"),*)
+    };
+}
+
+instructions! {
+    /// An instruction, serialized as `{"add": [{"imm": 5}, "a"]}`, or `"nop"` without arguments
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Instruction {
+        /// Add a value to a register
+        #[display("add  {0}, {1}")]
+        Add(ImmRegDirIndIdx, Reg),
+
+        /// Bitwise `and` with a given value
+        #[display("and  {0}, {1}")]
+        And(ImmRegDirIndIdx, Reg),
+
+        /// Stop the execution and give the control back to the debugger
+        #[display("brk")]
+        Brk,
+
+        /// Push `%pc` and go to the given address
+        #[display("call {0}")]
+        Call(ImmRegDirIndIdx),
 
-    /// Bitwise `and` with a given value
-    #[display("and  {0}, {1}")]
-    And(ImmRegDirIndIdx, Reg),
+        /// Store a register in a memory cell if it equals `%a`, or load this cell in `%a`
+        #[display("cas  {0}, {1}")]
+        Cas(DirIndIdx, Reg),
 
-    /// Push `%pc` and go to the given address
-    #[display("call {0}")]
-    Call(ImmRegDirIndIdx),
+        /// Compare a value with a register
+        #[display("cmp  {0}, {1}")]
+        Cmp(ImmRegDirIndIdx, Reg),
 
-    /// Compare a value with a register
-    #[display("cmp  {0}, {1}")]
-    Cmp(ImmRegDirIndIdx, Reg),
+        /// Divide a register by a value, raising an exception when dividing by zero
+        #[display("div  {0}, {1}")]
+        Div(ImmRegDirIndIdx, Reg),
 
-    /// Divide a register by a value
-    #[display("div  {0}, {1}")]
-    Div(ImmRegDirIndIdx, Reg),
+        /// Load a memory cell to a register and set this cell to 1
+        #[display("fas  {0}, {1}")]
+        Fas(DirIndIdx, Reg),
 
-    /// Load a memory cell to a register and set this cell to 1
-    #[display("fas  {0}, {1}")]
-    Fas(DirIndIdx, Reg),
+        /// Read a value from an I/O controller
+        #[display("in   {0}, {1}")]
+        In(DirIndIdx, Reg),
 
-    /// Read a value from an I/O controller
-    #[display("in   {0}, {1}")]
-    In(DirIndIdx, Reg),
+        /// Unconditional jump
+        #[display("jmp  {0}")]
+        Jmp(ImmRegDirIndIdx),
 
-    /// Unconditional jump
-    #[display("jmp  {0}")]
-    Jmp(ImmRegDirIndIdx),
+        /// Jump if equal
+        #[display("jeq  {0}")]
+        Jeq(ImmRegDirIndIdx),
 
-    /// Jump if equal
-    #[display("jeq  {0}")]
-    Jeq(ImmRegDirIndIdx),
+        /// Jump if not equal
+        #[display("jne  {0}")]
+        Jne(ImmRegDirIndIdx),
 
-    /// Jump if not equal
-    #[display("jne  {0}")]
-    Jne(ImmRegDirIndIdx),
+        /// Jump if less or equal
+        #[display("jle  {0}")]
+        Jle(ImmRegDirIndIdx),
 
-    /// Jump if less or equal
-    #[display("jle  {0}")]
-    Jle(ImmRegDirIndIdx),
+        /// Jump if strictly less
+        #[display("jlt  {0}")]
+        Jlt(ImmRegDirIndIdx),
 
-    /// Jump if strictly less
-    #[display("jlt  {0}")]
-    Jlt(ImmRegDirIndIdx),
+        /// Jump if greater of equal
+        #[display("jge  {0}")]
+        Jge(ImmRegDirIndIdx),
 
-    /// Jump if greater of equal
-    #[display("jge  {0}")]
-    Jge(ImmRegDirIndIdx),
+        /// Jump if strictly greater
+        #[display("jgt  {0}")]
+        Jgt(ImmRegDirIndIdx),
 
-    /// Jump if strictly greater
-    #[display("jgt  {0}")]
-    Jgt(ImmRegDirIndIdx),
+        /// Jump if less or equal, as unsigned words
+        #[display("jleu {0}")]
+        Jleu(ImmRegDirIndIdx),
 
-    /// Load a register with a value
-    #[display("ld   {0}, {1}")]
-    Ld(ImmRegDirIndIdx, Reg),
+        /// Jump if strictly less, as unsigned words
+        #[display("jltu {0}")]
+        Jltu(ImmRegDirIndIdx),
 
-    /// Multiply a value to a register
-    #[display("mul  {0}, {1}")]
-    Mul(ImmRegDirIndIdx, Reg),
+        /// Jump if greater or equal, as unsigned words
+        #[display("jgeu {0}")]
+        Jgeu(ImmRegDirIndIdx),
+
+        /// Jump if strictly greater, as unsigned words
+        #[display("jgtu {0}")]
+        Jgtu(ImmRegDirIndIdx),
+
+        /// Load a register with a value
+        #[display("ld   {0}, {1}")]
+        Ld(ImmRegDirIndIdx, Reg),
+
+        /// Multiply a value to a register, keeping the low bits at the width of the words
+        #[display("mul  {0}, {1}")]
+        Mul(ImmRegDirIndIdx, Reg),
+
+        /// Remainder of the division of a register by a value, with the sign of the register
+        #[display("mod  {0}, {1}")]
+        Mod(ImmRegDirIndIdx, Reg),
+
+        /// Negate a register
+        #[display("neg  {0}")]
+        Neg(Reg),
+
+        /// No-op
+        #[display("nop")]
+        Nop,
+
+        /// Bitwise negation of a register
+        #[display("not  {0}")]
+        Not(Reg),
 
-    #[display("neg  {0}")]
-    Neg(Reg),
+        /// Bitwise `or` with a given value
+        #[display("or   {0}, {1}")]
+        Or(ImmRegDirIndIdx, Reg),
 
-    /// No-op
-    #[display("nop")]
-    Nop,
+        /// Write a value to an I/O controller
+        #[display("out  {0}, {1}")]
+        Out(ImmReg, DirIndIdx),
 
-    /// Bitwise negation of a register
-    #[display("not  {0}")]
-    Not(Reg),
+        /// Pop a value from the stack
+        #[display("pop  {0}")]
+        Pop(Reg),
 
-    /// Bitwise `or` with a given value
-    #[display("or   {0}, {1}")]
-    Or(ImmRegDirIndIdx, Reg),
+        /// Push a value into the stack
+        #[display("push {0}")]
+        Push(ImmReg),
 
-    /// Write a value to an I/O controller
-    #[display("out  {0}, {1}")]
-    Out(ImmReg, DirIndIdx),
+        /// Reset the computer
+        #[display("reset")]
+        Reset,
 
-    /// Pop a value from the stack
-    #[display("pop  {0}")]
-    Pop(Reg),
+        /// Return from an interrupt or an exception
+        #[display("rti")]
+        Rti,
 
-    /// Push a value into the stack
-    #[display("push {0}")]
-    Push(ImmReg),
+        /// Return from a `call`
+        #[display("rtn")]
+        Rtn,
 
-    /// Reset the computer
-    #[display("reset")]
-    Reset,
+        /// Bitwise rotation to the left
+        #[display("rol  {0}, {1}")]
+        Rol(ImmRegDirIndIdx, Reg),
 
-    /// Return from an interrupt or an exception
-    #[display("rti")]
-    Rti,
-
-    /// Return from a `call`
-    #[display("rtn")]
-    Rtn,
+        /// Bitwise rotation to the right
+        #[display("ror  {0}, {1}")]
+        Ror(ImmRegDirIndIdx, Reg),
 
-    /// Bitshift to the left
-    #[display("shl  {0}, {1}")]
-    Shl(ImmRegDirIndIdx, Reg),
+        /// Bitshift to the left
+        #[display("shl  {0}, {1}")]
+        Shl(ImmRegDirIndIdx, Reg),
 
-    /// Bitshift to the right
-    #[display("shr  {0}, {1}")]
-    Shr(ImmRegDirIndIdx, Reg),
+        /// Bitshift to the right
+        #[display("shr  {0}, {1}")]
+        Shr(ImmRegDirIndIdx, Reg),
 
-    /// Store a register value in memory
-    #[display("st   {0}, {1}")]
-    St(Reg, DirIndIdx),
+        /// Store a register value in memory
+        #[display("st   {0}, {1}")]
+        St(Reg, DirIndIdx),
 
-    /// Substract a value from a register
-    #[display("sub  {0}, {1}")]
-    Sub(ImmRegDirIndIdx, Reg),
+        /// Substract a value from a register
+        #[display("sub  {0}, {1}")]
+        Sub(ImmRegDirIndIdx, Reg),
 
-    /// Swap a value and a register
-    #[display("swap {0}, {1}")]
-    Swap(RegDirIndIdx, Reg),
+        /// Swap a value and a register
+        #[display("swap {0}, {1}")]
+        Swap(RegDirIndIdx, Reg),
 
-    /// Start a `trap` exception
-    #[display("trap")]
-    Trap,
+        /// Start a `trap` exception
+        #[display("trap")]
+        Trap,
 
-    /// Bitwise `xor` with a given value
-    #[display("xor  {0}, {1}")]
-    Xor(ImmRegDirIndIdx, Reg),
+        /// Bitwise `xor` with a given value
+        #[display("xor  {0}, {1}")]
+        Xor(ImmRegDirIndIdx, Reg),
 
-    /// Show registers content
-    #[display("debugreg")]
-    DebugReg,
+        /// Stop the processor, which stays on this instruction
+        #[display("halt")]
+        Halt,
+
+        /// Show registers content
+        #[display("debugreg")]
+        DebugReg,
+    }
+}
+
+/// Mnemonics of all the instructions
+pub fn mnemonics() -> impl Iterator<Item = &'static str> {
+    DOCUMENTATION
+        .iter()
+        .filter_map(|(display, _)| display.split_whitespace().next())
+}
+
+/// Documentation of an instruction, from its mnemonic
+#[must_use]
+pub fn help(mnemonic: &str) -> Option<&'static str> {
+    DOCUMENTATION
+        .iter()
+        .find(|(display, _)| {
+            display
+                .split_whitespace()
+                .next()
+                .is_some_and(|m| m.eq_ignore_ascii_case(mnemonic))
+        })
+        .map(|(_, doc)| doc.trim())
+}
+
+impl Computer {
+    /// Raise an overflow exception instead of wrapping around, if the configuration asks for it
+    fn check_overflow(&self, overflow: bool) -> Result<(), Exception> {
+        if overflow && self.config.trap_on_overflow {
+            Err(Exception::Overflow)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Tag the errors coming from an operand of an instruction with its index
+trait OperandResult<T> {
+    fn operand(self, index: usize) -> Result<T, ProcessorError>;
+}
+
+impl<T, E: Into<ProcessorError>> OperandResult<T> for Result<T, E> {
+    fn operand(self, index: usize) -> Result<T, ProcessorError> {
+        self.map_err(|e| match e.into() {
+            // Exceptions are handled by the program, they are not a fault of the operand
+            e @ ProcessorError::Exception(_) => e,
+            e => ProcessorError::Operand {
+                index,
+                inner: Box::new(e),
+            },
+        })
+    }
 }
 
 impl Instruction {
+    /// Check if the instruction calls a subroutine
+    #[must_use]
+    pub fn is_call(&self) -> bool {
+        matches!(self, Self::Call(_))
+    }
+
+    /// Check if the instruction returns from a subroutine
+    #[must_use]
+    pub fn is_return(&self) -> bool {
+        matches!(self, Self::Rtn)
+    }
+
     /// Execute the instruction
+    ///
+    /// A post-incremented or pre-decremented register is updated before the instruction runs, and
+    /// restored if it fails or waits on a blocked device. With those, `push x` is equivalent to
+    /// `st x, -[%sp]`, and `pop x` to `ld [%sp]+, x`.
     #[tracing::instrument(skip(computer))]
     pub(crate) fn execute(&self, computer: &mut Computer) -> Result<(), ProcessorError> {
+        let Some((reg, delta)) = self.register_update() else {
+            return self.run(computer);
+        };
+
+        let saved = computer.registers.get(&reg);
+        let value = reg.extract_word(computer)?.wrapping_add(delta);
+        debug!("Updating {} by {}", reg, delta);
+        computer.set_register(&reg, value.into())?;
+
+        let pc = computer.registers.pc;
+        let result = self.run(computer);
+        // A blocked `in` or `out` moves back to run again on the next step, with the same operands
+        let blocked = matches!(self, Self::In(..) | Self::Out(..)) && computer.registers.pc != pc;
+        if result.is_err() || blocked {
+            // Registers can always be set back to a value they previously held
+            let _ = computer.registers.set(reg, saved);
+        }
+        result
+    }
+
+    /// Register updated by a post-increment or pre-decrement argument of the instruction
+    fn register_update(&self) -> Option<(Reg, Word)> {
+        match self {
+            Self::Add(a, _)
+            | Self::And(a, _)
+            | Self::Call(a)
+            | Self::Cmp(a, _)
+            | Self::Div(a, _)
+            | Self::Jmp(a)
+            | Self::Jeq(a)
+            | Self::Jne(a)
+            | Self::Jle(a)
+            | Self::Jlt(a)
+            | Self::Jge(a)
+            | Self::Jgt(a)
+            | Self::Jleu(a)
+            | Self::Jltu(a)
+            | Self::Jgeu(a)
+            | Self::Jgtu(a)
+            | Self::Ld(a, _)
+            | Self::Mod(a, _)
+            | Self::Mul(a, _)
+            | Self::Or(a, _)
+            | Self::Rol(a, _)
+            | Self::Ror(a, _)
+            | Self::Shl(a, _)
+            | Self::Shr(a, _)
+            | Self::Sub(a, _)
+            | Self::Xor(a, _) => a.register_update(),
+            Self::Cas(a, _)
+            | Self::Fas(a, _)
+            | Self::In(a, _)
+            | Self::Out(_, a)
+            | Self::St(_, a) => a.register_update(),
+            Self::Swap(a, _) => a.register_update(),
+            Self::Brk
+            | Self::Halt
+            | Self::Neg(_)
+            | Self::Nop
+            | Self::Not(_)
+            | Self::Pop(_)
+            | Self::Push(_)
+            | Self::Reset
+            | Self::Rti
+            | Self::Rtn
+            | Self::Trap
+            | Self::DebugReg => None,
+        }
+    }
+
+    /// Execute the instruction, once its registers were updated
+    fn run(&self, computer: &mut Computer) -> Result<(), ProcessorError> {
         match self {
             Self::Add(arg, reg) => {
-                let a = arg.extract_word(computer)?;
-                let b = reg.extract_word(computer)?;
-                let (res, overflow) = a.overflowing_add(b);
+                let a = arg.extract_word(computer).operand(0)?;
+                let b = reg.extract_word(computer).operand(1)?;
+                let (res, carry, overflow) = computer.config.arch.add(a, b);
                 debug!("{} + {} = {}", a, b, res);
-                computer.set_register(reg, res.into())?;
-
-                computer
-                    .registers
-                    .sr
-                    .set(StatusRegister::OVERFLOW, overflow);
+                computer.check_overflow(overflow)?;
+                computer.set_register(reg, res.into()).operand(1)?;
+                computer.registers.sr.update(res, carry, overflow);
             }
 
             Self::And(arg, reg) => {
-                let a = arg.extract_word(computer)?;
-                let b = reg.extract_word(computer)?;
+                let a = arg.extract_word(computer).operand(0)?;
+                let b = reg.extract_word(computer).operand(1)?;
                 let res = a & b;
                 debug!("{} & {} = {}", a, b, res);
-                computer.set_register(reg, res.into())?;
+                computer.set_register(reg, res.into()).operand(1)?;
+                computer.registers.sr.update(res, false, false);
             }
 
+            // `%pc` already points to the next instruction, so the execution resumes after it
+            Self::Brk => return Err(ProcessorError::Breakpoint),
+
             Self::Call(arg) => {
                 // Push PC
                 let pc = computer.registers.pc;
                 computer.push(pc)?;
 
                 // Jump
-                let addr = arg.extract_address(computer)?;
+                let addr = arg.extract_address(computer).operand(0)?;
                 computer.jump(addr);
             }
 
-            Self::Cmp(arg, reg) => {
-                let a = arg.extract_word(computer)?;
-                let b = reg.extract_word(computer)?;
-
-                computer.registers.sr.set(StatusRegister::ZERO, a == b);
-                computer.registers.sr.set(StatusRegister::NEGATIVE, a < b);
+            Self::Cas(addr, reg) => {
+                // Like `fas`, this is atomic because it reads and writes the cell in a single
+                // step, even in a cluster sharing the memory
+                let addr = addr.resolve_address(&computer.registers).operand(0)?;
+                computer.check_access(addr, false).operand(0)?;
+                let current = computer.memory.get(addr)?.extract_word()?;
+                let expected = Reg::A.extract_word(computer)?;
+
+                if current == expected {
+                    let val = reg.extract_cell(computer).operand(1)?;
+                    computer.write(addr, val).operand(0)?;
+                } else {
+                    computer.set_register(&Reg::A, current.into())?;
+                }
 
+                // Set the flags as `cmp [addr], %a` would, so `jeq` jumps if the cell was stored
+                let (res, carry, overflow) = computer.config.arch.sub(current, expected);
+                computer.registers.sr.update(res, carry, overflow);
                 debug!(
-                    "cmp({}, {}) => {:?}",
-                    a,
-                    b,
-                    computer.registers.sr & (StatusRegister::ZERO | StatusRegister::NEGATIVE)
+                    "cas({}, {}) => {}",
+                    current,
+                    expected,
+                    computer.registers.flags()
                 );
             }
 
+            Self::Cmp(arg, reg) => {
+                let a = arg.extract_word(computer).operand(0)?;
+                let b = reg.extract_word(computer).operand(1)?;
+
+                // Set the flags as `a - b` would, without storing the result
+                let (res, carry, overflow) = computer.config.arch.sub(a, b);
+                computer.registers.sr.update(res, carry, overflow);
+
+                debug!("cmp({}, {}) => {}", a, b, computer.registers.flags());
+            }
+
             Self::Div(arg, reg) => {
-                let a = arg.extract_word(computer)?;
-                let b = reg.extract_word(computer)?;
-                let res = b.checked_div(a).ok_or(Exception::DivByZero)?;
+                let a = arg.extract_word(computer).operand(0)?;
+                let b = reg.extract_word(computer).operand(1)?;
+                if a == 0 {
+                    return Err(Exception::DivByZero.into());
+                }
+
+                // Only the minimum word divided by -1 overflows
+                let (res, overflow) = computer.config.arch.div(b, a);
                 debug!("{} / {} = {}", b, a, res);
-                computer.set_register(reg, res.into())?;
+                computer.check_overflow(overflow)?;
+                computer.set_register(reg, res.into()).operand(1)?;
+                computer.registers.sr.update(res, false, overflow);
             }
 
             Self::Fas(addr, reg) => {
-                let addr = addr.resolve_address(&computer.registers)?;
+                let addr = addr.resolve_address(&computer.registers).operand(0)?;
+                computer.check_access(addr, true).operand(0)?;
                 let cell = computer.memory.get_mut(addr)?;
                 let val = cell.clone();
                 *cell = Cell::Word(1);
-                computer.set_register(reg, val)?;
+                computer.set_register(reg, val).operand(1)?;
             }
 
-            Self::In(_, _) => {
+            Self::In(port, reg) => {
                 computer.check_privileged()?;
-                todo!();
+                let port = port.resolve_address(&computer.registers).operand(0)?;
+                match computer.devices.read(port) {
+                    Ok(val) => computer.set_register(reg, val).operand(1)?,
+                    // Execute the instruction again on the next step
+                    Err(DeviceError::Blocked) => computer.registers.pc -= 1,
+                    Err(DeviceError::Unmapped) => {
+                        return Err(MemoryError::InvalidAddress(port).into())
+                    }
+                }
             }
 
             Self::Jmp(arg) => {
-                let val = arg.extract_address(computer)?;
+                let val = arg.extract_address(computer).operand(0)?;
                 debug!("Jumping to address {:#x}", val);
                 computer.registers.pc = val;
             }
 
             Self::Jeq(arg) => {
                 if computer.registers.sr.contains(StatusRegister::ZERO) {
-                    let val = arg.extract_address(computer)?;
+                    let val = arg.extract_address(computer).operand(0)?;
                     debug!("Jumping to address {:#x}", val);
                     computer.registers.pc = val;
                 }
@@ -239,7 +604,7 @@ impl Instruction {
 
             Self::Jne(arg) => {
                 if !computer.registers.sr.contains(StatusRegister::ZERO) {
-                    let val = arg.extract_address(computer)?;
+                    let val = arg.extract_address(computer).operand(0)?;
                     debug!("Jumping to address {:#x}", val);
                     computer.registers.pc = val;
                 }
@@ -247,9 +612,9 @@ impl Instruction {
 
             Self::Jle(arg) => {
                 if computer.registers.sr.contains(StatusRegister::ZERO)
-                    || computer.registers.sr.contains(StatusRegister::NEGATIVE)
+                    || computer.registers.sr.less()
                 {
-                    let val = arg.extract_address(computer)?;
+                    let val = arg.extract_address(computer).operand(0)?;
                     debug!("Jumping to address {:#x}", val);
                     computer.registers.pc = val;
                 }
@@ -257,9 +622,9 @@ impl Instruction {
 
             Self::Jlt(arg) => {
                 if !computer.registers.sr.contains(StatusRegister::ZERO)
-                    && computer.registers.sr.contains(StatusRegister::NEGATIVE)
+                    && computer.registers.sr.less()
                 {
-                    let val = arg.extract_address(computer)?;
+                    let val = arg.extract_address(computer).operand(0)?;
                     debug!("Jumping to address {:#x}", val);
                     computer.registers.pc = val;
                 }
@@ -267,9 +632,9 @@ impl Instruction {
 
             Self::Jge(arg) => {
                 if computer.registers.sr.contains(StatusRegister::ZERO)
-                    || !computer.registers.sr.contains(StatusRegister::NEGATIVE)
+                    || !computer.registers.sr.less()
                 {
-                    let val = arg.extract_address(computer)?;
+                    let val = arg.extract_address(computer).operand(0)?;
                     debug!("Jumping to address {:#x}", val);
                     computer.registers.pc = val;
                 }
@@ -277,76 +642,147 @@ impl Instruction {
 
             Self::Jgt(arg) => {
                 if !computer.registers.sr.contains(StatusRegister::ZERO)
-                    && !computer.registers.sr.contains(StatusRegister::NEGATIVE)
+                    && !computer.registers.sr.less()
+                {
+                    let val = arg.extract_address(computer).operand(0)?;
+                    debug!("Jumping to address {:#x}", val);
+                    computer.registers.pc = val;
+                }
+            }
+
+            Self::Jleu(arg) => {
+                if computer.registers.sr.contains(StatusRegister::ZERO)
+                    || computer.registers.sr.contains(StatusRegister::CARRY)
+                {
+                    let val = arg.extract_address(computer).operand(0)?;
+                    debug!("Jumping to address {:#x}", val);
+                    computer.registers.pc = val;
+                }
+            }
+
+            Self::Jltu(arg) => {
+                if !computer.registers.sr.contains(StatusRegister::ZERO)
+                    && computer.registers.sr.contains(StatusRegister::CARRY)
                 {
-                    let val = arg.extract_address(computer)?;
+                    let val = arg.extract_address(computer).operand(0)?;
+                    debug!("Jumping to address {:#x}", val);
+                    computer.registers.pc = val;
+                }
+            }
+
+            Self::Jgeu(arg) => {
+                if computer.registers.sr.contains(StatusRegister::ZERO)
+                    || !computer.registers.sr.contains(StatusRegister::CARRY)
+                {
+                    let val = arg.extract_address(computer).operand(0)?;
+                    debug!("Jumping to address {:#x}", val);
+                    computer.registers.pc = val;
+                }
+            }
+
+            Self::Jgtu(arg) => {
+                if !computer.registers.sr.contains(StatusRegister::ZERO)
+                    && !computer.registers.sr.contains(StatusRegister::CARRY)
+                {
+                    let val = arg.extract_address(computer).operand(0)?;
                     debug!("Jumping to address {:#x}", val);
                     computer.registers.pc = val;
                 }
             }
 
             Self::Ld(arg, reg) => {
-                let val = arg.extract_cell(computer)?;
-                computer.set_register(reg, val)?;
+                let val = arg.extract_cell(computer).operand(0)?;
+                computer.set_register(reg, val).operand(1)?;
+            }
+
+            Self::Mod(arg, reg) => {
+                let a = arg.extract_word(computer).operand(0)?;
+                let b = reg.extract_word(computer).operand(1)?;
+                if a == 0 {
+                    return Err(Exception::DivByZero.into());
+                }
+
+                // The minimum word modulo -1 is zero, even though the division overflows
+                let res = computer.config.arch.wrap(b.wrapping_rem(a));
+                debug!("{} % {} = {}", b, a, res);
+                computer.set_register(reg, res.into()).operand(1)?;
+                computer.registers.sr.update(res, false, false);
             }
 
             Self::Mul(arg, reg) => {
-                let a = arg.extract_word(computer)?;
-                let b = reg.extract_word(computer)?;
-                let (res, overflow) = a.overflowing_mul(b);
+                let a = arg.extract_word(computer).operand(0)?;
+                let b = reg.extract_word(computer).operand(1)?;
+                let (res, carry, overflow) = computer.config.arch.mul(a, b);
                 debug!("{} * {} = {}", a, b, res);
-                computer.set_register(reg, res.into())?;
-
-                computer
-                    .registers
-                    .sr
-                    .set(StatusRegister::OVERFLOW, overflow);
+                computer.check_overflow(overflow)?;
+                computer.set_register(reg, res.into()).operand(1)?;
+                computer.registers.sr.update(res, carry, overflow);
             }
 
             Self::Neg(reg) => {
-                let val = reg.extract_word(computer)?;
-                let res = -val;
-                let res = res as Word;
+                let val = reg.extract_word(computer).operand(0)?;
+                // Only the minimum word overflows, and stays the same. Like `0 - val`, this
+                // borrows unless the value is zero
+                let (res, overflow) = computer.config.arch.neg(val);
                 debug!("-{} = {}", val, res);
-                computer.set_register(reg, res.into())?;
+                computer.check_overflow(overflow)?;
+                computer.set_register(reg, res.into()).operand(0)?;
+                computer.registers.sr.update(res, val != 0, overflow);
             }
 
             Self::Nop => {}
 
             Self::Not(reg) => {
-                let val = reg.extract_word(computer)?;
+                let val = reg.extract_word(computer).operand(0)?;
                 let res = !val;
                 debug!("!{} = {}", val, res);
-                computer.set_register(reg, res.into())?;
+                computer.set_register(reg, res.into()).operand(0)?;
+                computer.registers.sr.update(res, false, false);
             }
 
             Self::Or(arg, reg) => {
-                let a = arg.extract_word(computer)?;
-                let b = reg.extract_word(computer)?;
+                let a = arg.extract_word(computer).operand(0)?;
+                let b = reg.extract_word(computer).operand(1)?;
                 let res = a | b;
                 debug!("{} | {} = {}", a, b, res);
-                computer.set_register(reg, res.into())?;
+                computer.set_register(reg, res.into()).operand(1)?;
+                computer.registers.sr.update(res, false, false);
             }
 
-            Self::Out(_, _) => {
+            Self::Out(val, port) => {
                 computer.check_privileged()?;
-                todo!()
+                let val = val.extract_cell(computer).operand(0)?;
+                let port = port.resolve_address(&computer.registers).operand(1)?;
+                match computer.devices.write(port, val) {
+                    Ok(()) => {}
+                    // Execute the instruction again on the next step
+                    Err(DeviceError::Blocked) => computer.registers.pc -= 1,
+                    Err(DeviceError::Unmapped) => {
+                        return Err(MemoryError::InvalidAddress(port).into())
+                    }
+                }
             }
 
             Self::Pop(reg) => {
                 let val = computer.pop()?.clone();
                 debug!("pop => {:?}", val);
-                computer.set_register(reg, val)?;
+                computer.set_register(reg, val).operand(0)?;
             }
 
             Self::Push(val) => {
-                let val = val.extract_cell(computer)?;
+                let val = val.extract_cell(computer).operand(0)?;
                 debug!("push({:?})", val);
                 computer.push(val)?;
             }
 
             Self::Reset => return Err(ProcessorError::Reset),
 
+            Self::Halt => {
+                // Unlike `reset`, stepping again executes this instruction again
+                computer.registers.pc -= 1;
+                return Err(ProcessorError::Halt);
+            }
+
             Self::Rti => {
                 computer.check_privileged()?;
                 computer.registers.pc =
@@ -354,6 +790,7 @@ impl Instruction {
                 computer.registers.sr = StatusRegister::from_bits_truncate(
                     computer.memory.get(INTERRUPT_SR_SAVE)?.extract_word()?,
                 );
+                computer.leave_handler()?;
             }
 
             Self::Rtn => {
@@ -363,68 +800,103 @@ impl Instruction {
                 computer.registers.pc = ret; // and jump to it
             }
 
+            Self::Rol(arg, reg) => {
+                let a = arg.extract_word(computer).operand(0)?;
+                let b = reg.extract_word(computer).operand(1)?;
+
+                // Like shifts, negative amounts are invalid, but larger ones wrap around
+                let b: u32 = b.try_into().map_err(|_| Exception::InvalidInstruction)?;
+                let res = computer.config.arch.rotate_left(a, b);
+
+                debug!("{} rol {} = {}", a, b, res);
+                computer.set_register(reg, res.into()).operand(1)?;
+                computer.registers.sr.update(res, false, false);
+            }
+
+            Self::Ror(arg, reg) => {
+                let a = arg.extract_word(computer).operand(0)?;
+                let b = reg.extract_word(computer).operand(1)?;
+
+                let b: u32 = b.try_into().map_err(|_| Exception::InvalidInstruction)?;
+                let res = computer.config.arch.rotate_right(a, b);
+
+                debug!("{} ror {} = {}", a, b, res);
+                computer.set_register(reg, res.into()).operand(1)?;
+                computer.registers.sr.update(res, false, false);
+            }
+
             Self::Shl(arg, reg) => {
-                let a = arg.extract_word(computer)?;
-                let b = reg.extract_word(computer)?;
+                let a = arg.extract_word(computer).operand(0)?;
+                let b = reg.extract_word(computer).operand(1)?;
 
                 let b: u32 = b.try_into().map_err(|_| Exception::InvalidInstruction)?;
-                let res = a.checked_shl(b).ok_or(Exception::InvalidInstruction)?;
+                let res = computer
+                    .config
+                    .arch
+                    .shl(a, b)
+                    .ok_or(Exception::InvalidInstruction)?;
 
                 debug!("{} << {} = {}", a, b, res);
-                computer.set_register(reg, res.into())?;
+                computer.set_register(reg, res.into()).operand(1)?;
+                computer.registers.sr.update(res, false, false);
             }
 
             Self::Shr(arg, reg) => {
-                let a = arg.extract_word(computer)?;
-                let b = reg.extract_word(computer)?;
+                let a = arg.extract_word(computer).operand(0)?;
+                let b = reg.extract_word(computer).operand(1)?;
 
                 let b: u32 = b.try_into().map_err(|_| Exception::InvalidInstruction)?;
-                let res = a.checked_shr(b).ok_or(Exception::InvalidInstruction)?;
+                // This is an arithmetic shift, the sign bit is copied to the left
+                let res = computer
+                    .config
+                    .arch
+                    .shr(a, b)
+                    .ok_or(Exception::InvalidInstruction)?;
 
                 debug!("{} >> {} = {}", a, b, res);
-                computer.set_register(reg, res.into())?;
+                computer.set_register(reg, res.into()).operand(1)?;
+                computer.registers.sr.update(res, false, false);
             }
 
             Self::St(reg, address) => {
-                let val = reg.extract_word(computer)?;
-                let address = address.resolve_address(&computer.registers)?;
-                computer.write(address, val)?;
+                let val = reg.extract_word(computer).operand(0)?;
+                let address = address.resolve_address(&computer.registers).operand(1)?;
+                computer.write(address, val).operand(1)?;
             }
 
             Self::Sub(arg, reg) => {
-                let a = arg.extract_word(computer)?;
-                let b = reg.extract_word(computer)?;
-                let (res, overflow) = b.overflowing_sub(a);
-                computer.set_register(reg, res.into())?;
+                let a = arg.extract_word(computer).operand(0)?;
+                let b = reg.extract_word(computer).operand(1)?;
+                // The carry is the borrow of the unsigned substraction
+                let (res, carry, overflow) = computer.config.arch.sub(b, a);
+                computer.check_overflow(overflow)?;
+                computer.set_register(reg, res.into()).operand(1)?;
 
                 debug!("{} - {} = {}", b, a, res);
 
-                computer
-                    .registers
-                    .sr
-                    .set(StatusRegister::OVERFLOW, overflow);
+                computer.registers.sr.update(res, carry, overflow);
             }
 
             Self::Swap(arg, reg) => {
                 // First we extract the value from both arguments
-                let value = arg.extract_cell(computer)?;
-                let value2 = reg.extract_cell(computer)?;
+                let value = arg.extract_cell(computer).operand(0)?;
+                let value2 = reg.extract_cell(computer).operand(1)?;
 
                 // And set the value of the register specified by the second argument
-                computer.set_register(reg, value)?;
+                computer.set_register(reg, value).operand(1)?;
 
                 // Then handle the two kind of cases: a register and a memory address
                 if let RegDirIndIdx::Reg(arg) = arg {
                     // Set the value of the register by the first argument
-                    computer.set_register(arg, value2)?;
+                    computer.set_register(arg, value2).operand(0)?;
                 } else {
                     // Convert the reg/dir/ind/idx arg to only dir/ind/idx, since we already
                     // handled the reg case. Despite the unwrap, this should never fail.
                     let arg: DirIndIdx = arg.clone().try_into().unwrap();
 
                     // Resolve the address and write the second value
-                    let addr = arg.resolve_address(&computer.registers)?;
-                    computer.write(addr, value2)?;
+                    let addr = arg.resolve_address(&computer.registers).operand(0)?;
+                    computer.write(addr, value2).operand(0)?;
                 }
             }
 
@@ -433,11 +905,12 @@ impl Instruction {
             }
 
             Self::Xor(arg, reg) => {
-                let a = arg.extract_word(computer)?;
-                let b = reg.extract_word(computer)?;
+                let a = arg.extract_word(computer).operand(0)?;
+                let b = reg.extract_word(computer).operand(1)?;
                 let res = a ^ b;
                 debug!("{} ^ {} = {}", a, b, res);
-                computer.set_register(reg, res.into())?;
+                computer.set_register(reg, res.into()).operand(1)?;
+                computer.registers.sr.update(res, false, false);
             }
 
             Self::DebugReg => {
@@ -461,14 +934,17 @@ impl Instruction {
             | Self::And(a, _)
             | Self::Cmp(a, _)
             | Self::Mul(a, _)
+            | Self::Mod(a, _)
             | Self::Ld(a, _)
             | Self::Sub(a, _)
             | Self::Xor(a, _)
+            | Self::Rol(a, _)
+            | Self::Ror(a, _)
             | Self::Shl(a, _)
             | Self::Shr(a, _) => 1 + a.cost() + Reg::cost(),
 
             // dir|ind|idx, reg
-            Self::Fas(a, _) | Self::In(a, _) => 1 + a.cost() + Reg::cost(),
+            Self::Cas(a, _) | Self::Fas(a, _) | Self::In(a, _) => 1 + a.cost() + Reg::cost(),
 
             // imm|reg|dir|ind|idx
             Self::Call(a)
@@ -478,7 +954,11 @@ impl Instruction {
             | Self::Jle(a)
             | Self::Jlt(a)
             | Self::Jge(a)
-            | Self::Jgt(a) => 1 + a.cost(),
+            | Self::Jgt(a)
+            | Self::Jleu(a)
+            | Self::Jltu(a)
+            | Self::Jgeu(a)
+            | Self::Jgtu(a) => 1 + a.cost(),
 
             // reg
             Self::Pop(_) | Self::Neg(_) | Self::Not(_) => 1 + Reg::cost(),
@@ -495,7 +975,96 @@ impl Instruction {
             // reg|dir|ind|idx, reg
             Self::Swap(a, _) => 1 + a.cost() + Reg::cost(),
 
-            Self::Nop | Self::Reset | Self::Rti | Self::Rtn | Self::Trap => 1,
+            Self::Brk
+            | Self::Halt
+            | Self::Nop
+            | Self::Reset
+            | Self::Rti
+            | Self::Rtn
+            | Self::Trap => 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod named {
+        use super::{Reg, RegDirIndIdx};
+
+        instructions! {
+            #[derive(Debug)]
+            pub enum Instruction {
+                /// Swap a value and a register
+                #[display("swap {value}, {reg}")]
+                Swap { value: RegDirIndIdx, reg: Reg },
+            }
         }
+
+        pub(super) const SWAP: &(&str, &str) = &DOCUMENTATION[0];
+    }
+
+    mod optional {
+        use super::Reg;
+
+        instructions! {
+            #[derive(Debug, PartialEq)]
+            pub enum Instruction {
+                /// Negate a register, `%a` when left out
+                #[display("neg  {0}")]
+                #[default(Reg::A)]
+                Neg(Reg),
+            }
+        }
+
+        pub(super) const NEG: &(&str, &str) = &DOCUMENTATION[0];
+    }
+
+    #[test]
+    fn named_fields_test() {
+        let instruction = named::Instruction::Swap {
+            value: RegDirIndIdx::Reg(Reg::B),
+            reg: Reg::A,
+        };
+        assert_eq!(
+            instruction.to_string(),
+            Instruction::Swap(RegDirIndIdx::Reg(Reg::B), Reg::A).to_string()
+        );
+        assert_eq!(named::SWAP.0, "swap {value}, {reg}");
+        assert_eq!(
+            named::SWAP.1,
+            " Swap a value and a register\n\nOperands: value, reg"
+        );
+        assert_eq!(help("swap"), Some("Swap a value and a register"));
+    }
+
+    #[test]
+    fn default_operand_test() {
+        use crate::compiler::memory::InstructionCompilationError;
+        use crate::parser::value::InstructionKind;
+        use optional::Instruction::Neg;
+
+        let neg = |operands| optional::Instruction::from_operands(InstructionKind::Neg, operands);
+        assert_eq!(neg(vec![]).unwrap(), Neg(Reg::A));
+        assert_eq!(
+            neg(vec![ImmRegDirIndIdx::Reg(Reg::B)]).unwrap(),
+            Neg(Reg::B)
+        );
+        assert!(matches!(
+            neg(vec![
+                ImmRegDirIndIdx::Reg(Reg::A),
+                ImmRegDirIndIdx::Reg(Reg::B)
+            ]),
+            Err(InstructionCompilationError::InvalidArgumentNumber {
+                expected: 1,
+                got: 2
+            })
+        ));
+
+        // The default operand is left out
+        assert_eq!(Neg(Reg::A).to_string(), "neg");
+        assert_eq!(Neg(Reg::B).to_string(), "neg  %b");
+        assert_eq!(optional::NEG.0, "neg  {0}");
     }
 }
@@ -0,0 +1,296 @@
+//! Resource limits, to stop pathological programs
+//!
+//! Limits are configured on a computer with [`Computer::set_config`], and are counted from that
+//! point. Once the computer used all it is allowed to of a resource, the next [`Computer::step`]
+//! fails with a [`LimitExceeded`] error.
+
+use std::time::{Duration, Instant};
+
+use parse_display::Display;
+use thiserror::Error;
+
+use super::{Arch, Computer, Endianness, EntryState};
+
+/// Number of steps between two checks of the wall-clock timeout
+const TIMEOUT_CHECK_INTERVAL: usize = 1024;
+
+/// Resources a computer may use while running, `None` meaning unlimited, how it handles
+/// arithmetic overflows, the width and byte order of its words, and the state it starts from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MachineConfig {
+    /// Maximum number of executed instructions
+    pub max_steps: Option<usize>,
+
+    /// Maximum number of cycles
+    pub max_cycles: Option<usize>,
+
+    /// Maximum number of writes to the memory
+    pub max_memory_writes: Option<usize>,
+
+    /// Maximum wall-clock time. Not supported on `wasm32-unknown-unknown`, which has no clock
+    pub timeout: Option<Duration>,
+
+    /// Maximum number of bytes sent to the host by the devices, like the console
+    pub max_output_bytes: Option<usize>,
+
+    /// Raise an [`Exception::Overflow`] on signed overflows in `add`, `sub`, `mul`, `div` and
+    /// `neg`, instead of wrapping around
+    ///
+    /// [`Exception::Overflow`]: super::Exception::Overflow
+    pub trap_on_overflow: bool,
+
+    /// Width of the words. The words already in the registers and memory are truncated to it
+    pub arch: Arch,
+
+    /// Order of the cells of the values spanning two words, and of the bytes packed in a word
+    pub endianness: Endianness,
+
+    /// Registers set by [`Computer::set_config`], to run a routine from a prepared state
+    pub entry: EntryState,
+}
+
+/// A resource limited by a [`MachineConfig`]
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+#[display(style = "kebab-case")]
+pub enum Limit {
+    Steps,
+    Cycles,
+    MemoryWrites,
+    Timeout,
+    OutputBytes,
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("{limit} limit exceeded (maximum {maximum})")]
+pub struct LimitExceeded {
+    pub limit: Limit,
+
+    /// The configured maximum, in milliseconds for the timeout
+    pub maximum: u128,
+}
+
+/// Usage of the resources when the configuration was set
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Usage {
+    steps: usize,
+    cycles: usize,
+    memory_writes: usize,
+    output_bytes: usize,
+    start: Option<Instant>,
+}
+
+impl Computer {
+    /// Limit the resources used from now on, and set the registers of the entry state
+    pub fn set_config(&mut self, config: MachineConfig) {
+        self.registers.apply(&config.entry);
+        self.registers.map_words(|word| config.arch.wrap(word));
+        self.memory.map_words(|word| config.arch.wrap(word));
+        self.usage = Some(Usage {
+            steps: self.steps,
+            cycles: self.cycles,
+            memory_writes: self.memory.writes(),
+            output_bytes: self.devices.output_bytes(),
+            start: config.timeout.map(|_| Instant::now()),
+        });
+        self.config = config;
+    }
+
+    #[must_use]
+    pub fn config(&self) -> &MachineConfig {
+        &self.config
+    }
+
+    /// Check if a limit is exceeded, before executing a step
+    pub(crate) fn check_limits(&self) -> Result<(), LimitExceeded> {
+        let Some(usage) = &self.usage else {
+            return Ok(());
+        };
+
+        let exceeded = |limit, maximum: usize| LimitExceeded {
+            limit,
+            maximum: maximum as u128,
+        };
+
+        let config = &self.config;
+        if let Some(max) = config.max_steps {
+            if self.steps - usage.steps >= max {
+                return Err(exceeded(Limit::Steps, max));
+            }
+        }
+
+        if let Some(max) = config.max_cycles {
+            if self.cycles - usage.cycles >= max {
+                return Err(exceeded(Limit::Cycles, max));
+            }
+        }
+
+        if let Some(max) = config.max_memory_writes {
+            if self.memory.writes() - usage.memory_writes >= max {
+                return Err(exceeded(Limit::MemoryWrites, max));
+            }
+        }
+
+        if let Some(max) = config.max_output_bytes {
+            if self.devices.output_bytes() - usage.output_bytes >= max {
+                return Err(exceeded(Limit::OutputBytes, max));
+            }
+        }
+
+        if let (Some(timeout), Some(start)) = (config.timeout, usage.start) {
+            if self.steps.is_multiple_of(TIMEOUT_CHECK_INTERVAL) && start.elapsed() > timeout {
+                return Err(LimitExceeded {
+                    limit: Limit::Timeout,
+                    maximum: timeout.as_millis(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{Word, INTERRUPT_EXCEPTION, INTERRUPT_HANDLER};
+    use crate::runtime::registers::StatusRegister;
+    use crate::runtime::{Cell, Console, Exception, ProcessorError};
+    use crate::{compile, parse};
+
+    fn computer(source: &str) -> Computer {
+        let program = parse(source).unwrap();
+        compile(program.inner, "main").unwrap().0
+    }
+
+    #[test]
+    fn step_limit_test() {
+        let mut computer = computer("main: jmp main\n");
+        computer.set_config(MachineConfig {
+            max_steps: Some(10),
+            ..MachineConfig::default()
+        });
+
+        let error = computer.run().unwrap_err();
+        assert!(matches!(
+            error,
+            ProcessorError::LimitExceeded(LimitExceeded {
+                limit: Limit::Steps,
+                maximum: 10,
+            })
+        ));
+        assert_eq!(computer.steps, 10);
+    }
+
+    #[test]
+    fn limits_boundary_test() {
+        // Each program uses 2 of the limited resource before its last step: a limit of 2 stops it
+        // there, one of 3 lets it reset
+        let run = |source: &str, config: fn(usize) -> MachineConfig, max: usize| {
+            let mut computer = computer(source);
+            computer.registers.sr.insert(StatusRegister::SUPERVISOR);
+            let (console, _output) = Console::new();
+            computer.attach(0, console).unwrap();
+            computer.set_config(config(max));
+            match computer.run() {
+                Ok(()) => None,
+                Err(ProcessorError::LimitExceeded(e)) => Some(e.limit),
+                result => panic!("unexpected result {result:?}"),
+            }
+        };
+
+        let cases: [(&str, fn(usize) -> MachineConfig, Limit); 4] = [
+            (
+                "main: nop\n    nop\n    reset\n",
+                |max| MachineConfig {
+                    max_steps: Some(max),
+                    ..MachineConfig::default()
+                },
+                Limit::Steps,
+            ),
+            (
+                "main: nop\n    nop\n    reset\n",
+                |max| MachineConfig {
+                    max_cycles: Some(max),
+                    ..MachineConfig::default()
+                },
+                Limit::Cycles,
+            ),
+            (
+                "main: push %a\n    push %a\n    reset\n",
+                |max| MachineConfig {
+                    max_memory_writes: Some(max),
+                    ..MachineConfig::default()
+                },
+                Limit::MemoryWrites,
+            ),
+            (
+                "main: ld 7, %a\n    out %a, [0]\n    out %a, [0]\n    reset\n",
+                |max| MachineConfig {
+                    max_output_bytes: Some(max),
+                    ..MachineConfig::default()
+                },
+                Limit::OutputBytes,
+            ),
+        ];
+
+        for (source, config, limit) in cases {
+            assert_eq!(run(source, config, 2), Some(limit), "{limit}");
+            assert_eq!(run(source, config, 3), None, "{limit}");
+        }
+    }
+
+    #[test]
+    fn memory_writes_limit_test() {
+        let mut computer = computer("main: push %a\n    jmp main\n");
+        computer.set_config(MachineConfig {
+            max_memory_writes: Some(3),
+            ..MachineConfig::default()
+        });
+
+        let error = computer.run().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "memory-writes limit exceeded (maximum 3)"
+        );
+    }
+
+    #[test]
+    fn output_limit_test() {
+        let mut computer = computer("main: ld 7, %a\nloop: out %a, [0]\n    jmp loop\n");
+        computer.registers.sr.insert(StatusRegister::SUPERVISOR);
+        let (console, _output) = Console::new();
+        computer.attach(0, console).unwrap();
+        computer.set_config(MachineConfig {
+            max_output_bytes: Some(5),
+            ..MachineConfig::default()
+        });
+
+        let error = computer.run().unwrap_err();
+        assert_eq!(error.to_string(), "output-bytes limit exceeded (maximum 5)");
+    }
+
+    #[test]
+    fn trap_on_overflow_test() {
+        let source = "main: ld 0x7FFFFFFFFFFFFFFF, %a\n    add 1, %a\n    reset\n\
+                      .addr 200\nhandler: rti\n";
+
+        // Wrapping around by default
+        let mut wrapping = computer(source);
+        wrapping.run().unwrap();
+        assert_eq!(wrapping.registers.a, Cell::Word(Word::MIN));
+
+        let mut computer = computer(source);
+        computer.set_config(MachineConfig {
+            trap_on_overflow: true,
+            ..MachineConfig::default()
+        });
+        computer.step().unwrap();
+        computer.step().unwrap();
+        assert_eq!(computer.registers.a, Cell::Word(Word::MAX));
+        assert_eq!(computer.registers.pc, INTERRUPT_HANDLER);
+        assert_eq!(
+            computer.memory.get(INTERRUPT_EXCEPTION).unwrap(),
+            &Cell::Word(Exception::Overflow.code())
+        );
+    }
+}
@@ -0,0 +1,222 @@
+//! A mailbox device, to exchange messages between two computers
+//!
+//! A mailbox has two endpoints, each one with its own inbox. Each endpoint can be attached to a
+//! computer as a [`Device`], or used directly by the host. The ports of an endpoint are:
+//!
+//!  - `0`: send register. Writing to it sends a message, blocking while the other inbox is full
+//!  - `1`: receive register. Reading from it takes a message, blocking while the inbox is empty
+//!  - `2`: status register. Reading from it gives the number of messages in the inbox
+//!  - `3`: control register. Writing `1` to it enables interrupts while the inbox is not empty,
+//!    writing `0` disables them
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::constants::{Address, Word};
+
+use super::{Cell, Device, WouldBlock};
+
+const SEND: Address = 0;
+const RECEIVE: Address = 1;
+const STATUS: Address = 2;
+const CONTROL: Address = 3;
+
+type Inbox = Arc<Mutex<VecDeque<Cell>>>;
+
+/// One end of a mailbox
+///
+/// Clones of an endpoint, like the ones of a forked computer, share its inbox and outbox: a
+/// message sent to the endpoint is received by only one of them.
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    inbox: Inbox,
+    outbox: Inbox,
+    capacity: usize,
+    interrupt: bool,
+}
+
+/// Create the two connected endpoints of a mailbox, with inboxes of a given capacity
+#[must_use]
+pub fn mailbox(capacity: usize) -> (Endpoint, Endpoint) {
+    let first = Inbox::default();
+    let second = Inbox::default();
+    (
+        Endpoint {
+            inbox: Arc::clone(&first),
+            outbox: Arc::clone(&second),
+            capacity,
+            interrupt: false,
+        },
+        Endpoint {
+            inbox: second,
+            outbox: first,
+            capacity,
+            interrupt: false,
+        },
+    )
+}
+
+impl Endpoint {
+    /// Send a message to the other endpoint
+    pub fn send(&self, message: Cell) -> Result<(), WouldBlock> {
+        let mut outbox = self.outbox.lock().unwrap_or_else(PoisonError::into_inner);
+        if outbox.len() >= self.capacity {
+            return Err(WouldBlock);
+        }
+
+        outbox.push_back(message);
+        Ok(())
+    }
+
+    /// Take the oldest message sent by the other endpoint
+    pub fn receive(&self) -> Result<Cell, WouldBlock> {
+        self.inbox
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop_front()
+            .ok_or(WouldBlock)
+    }
+
+    /// Number of messages waiting in the inbox
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        self.inbox
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+}
+
+impl Device for Endpoint {
+    fn ports(&self) -> Address {
+        4
+    }
+
+    fn read(&mut self, port: Address) -> Result<Cell, WouldBlock> {
+        match port {
+            RECEIVE => self.receive(),
+            STATUS => Ok(Cell::Word(
+                Word::try_from(self.pending()).unwrap_or(Word::MAX),
+            )),
+            CONTROL => Ok(Cell::Word(self.interrupt.into())),
+            _ => Ok(Cell::Empty),
+        }
+    }
+
+    fn write(&mut self, port: Address, value: Cell) -> Result<(), WouldBlock> {
+        match port {
+            SEND => self.send(value),
+            CONTROL => {
+                self.interrupt = value != Cell::Empty && value != Cell::Word(0);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn interrupt(&self) -> bool {
+        self.interrupt && self.pending() > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::cluster::{Cluster, Schedule};
+    use crate::runtime::{Computer, Reg, StatusRegister};
+    use crate::{compile, parse};
+
+    fn computer(source: &str, endpoint: Endpoint) -> Computer {
+        let program = parse(source).unwrap();
+        let (mut computer, _) = compile(program.inner, "main").unwrap();
+        computer.attach(10, endpoint).unwrap();
+        computer
+    }
+
+    #[test]
+    fn producer_consumer_test() {
+        let (producer, consumer) = mailbox(1);
+        let mut cluster = Cluster::new(0..0, Schedule::RoundRobin).unwrap();
+        // Programs start in user mode, a trap is needed to access the I/O ports
+        let prelude = "main: trap\n\
+                 jmp start\n\
+             .addr 200\n\
+                 ld [101], %a\n\
+                 or 0x200, %a\n\
+                 st %a, [101]\n\
+                 rti\n";
+        cluster.add(computer(
+            &format!(
+                "{prelude}start: ld 0, %b\n\
+                 next: add 1, %b\n\
+                     out %b, [10]\n\
+                     cmp 5, %b\n\
+                     jne next\n\
+                     reset\n"
+            ),
+            producer,
+        ));
+        cluster.add(computer(
+            &format!(
+                "{prelude}start: ld 0, %b\n\
+                 next: in [11], %a\n\
+                     add %a, %b\n\
+                     cmp 15, %b\n\
+                     jne next\n\
+                     reset\n"
+            ),
+            consumer,
+        ));
+
+        cluster.run().unwrap();
+        assert_eq!(
+            cluster.computers()[1].registers.get(&Reg::B),
+            Cell::Word(15)
+        );
+    }
+
+    #[test]
+    fn blocked_post_increment_test() {
+        let (host, device) = mailbox(1);
+        let mut computer = computer(
+            "main: ld 11, %b\n\
+                 in [%b]+, %a\n\
+                 reset\n",
+            device,
+        );
+        // Reading the I/O ports needs the supervisor mode
+        computer.registers.sr.insert(StatusRegister::SUPERVISOR);
+
+        // While the inbox is empty, the instruction waits without incrementing %b again
+        for _ in 0..4 {
+            computer.step().unwrap();
+        }
+        assert_eq!(computer.registers.get(&Reg::B), Cell::Word(11));
+        assert_eq!(computer.registers.get(&Reg::A), Cell::Empty);
+
+        host.send(Cell::Word(42)).unwrap();
+        computer.step().unwrap();
+        assert_eq!(computer.registers.get(&Reg::B), Cell::Word(12));
+        assert_eq!(computer.registers.get(&Reg::A), Cell::Word(42));
+    }
+
+    #[test]
+    fn interrupt_on_receive_test() {
+        let (host, device) = mailbox(4);
+        let mut endpoint = device;
+        assert!(!Device::interrupt(&endpoint));
+
+        endpoint.write(CONTROL, Cell::Word(1)).unwrap();
+        assert!(!Device::interrupt(&endpoint));
+        assert_eq!(endpoint.read(RECEIVE), Err(WouldBlock));
+
+        host.send(Cell::Word(42)).unwrap();
+        assert!(Device::interrupt(&endpoint));
+        assert_eq!(endpoint.read(STATUS), Ok(Cell::Word(1)));
+        assert_eq!(endpoint.read(RECEIVE), Ok(Cell::Word(42)));
+        assert!(!Device::interrupt(&endpoint));
+
+        endpoint.write(SEND, Cell::Word(7)).unwrap();
+        assert_eq!(host.receive(), Ok(Cell::Word(7)));
+    }
+}
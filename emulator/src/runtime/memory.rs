@@ -1,11 +1,12 @@
 use std::convert::TryInto;
+use std::sync::Arc;
 
-use parse_display::Display;
+use serde::{Serialize, Serializer};
 use thiserror::Error;
 
 use crate::constants::{Address, Char, Word, MEMORY_SIZE};
 
-use super::instructions::Instruction;
+use super::{instructions::Instruction, Arch};
 
 /// Type of cells
 ///
@@ -28,29 +29,78 @@ pub enum CellError {
 }
 
 /// Represents a cell in memory and in general purpose registers
-#[derive(Debug, Clone, PartialEq, Eq, Display)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Cell {
     /// An instruction
     ///
     /// The instruction can be a big type, so only a reference is saved here.
-    #[display("{0}")]
     Instruction(Box<Instruction>),
 
     /// An unsigned word
     ///
     /// In contrast, a word is small enough to be copied.
-    #[display("{0}")]
     Word(Word),
 
     /// A signle char
-    #[display("{:?}")]
     Char(Char),
 
     /// An empty cell, no value was ever set here,
-    #[display("0")]
     Empty,
 }
 
+impl Cell {
+    /// Display the cell with the bits of its word at the width of the given machine
+    #[must_use]
+    pub fn display(&self, arch: Arch) -> CellDisplay<'_> {
+        CellDisplay { cell: self, arch }
+    }
+}
+
+impl std::fmt::Display for Cell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.display(Arch::default()).fmt(f)
+    }
+}
+
+/// A cell displayed on a given machine, as returned by [`Cell::display`]
+pub struct CellDisplay<'a> {
+    cell: &'a Cell,
+    arch: Arch,
+}
+
+impl std::fmt::Display for CellDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.cell {
+            Cell::Instruction(instruction) => write!(f, "{instruction}"),
+            Cell::Word(word) => write!(f, "{word} ({:#x})", self.arch.unsigned(*word)),
+            Cell::Char(c) => write!(f, "{c:?}"),
+            Cell::Empty => write!(f, "0"),
+        }
+    }
+}
+
+/// Serialized form of a cell, with instructions rendered as their disassembly
+#[derive(Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+enum SerializedCell {
+    Instruction(String),
+    Word(Word),
+    Char(Char),
+    Empty,
+}
+
+impl Serialize for Cell {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Instruction(instruction) => SerializedCell::Instruction(instruction.to_string()),
+            Self::Word(word) => SerializedCell::Word(*word),
+            Self::Char(c) => SerializedCell::Char(*c),
+            Self::Empty => SerializedCell::Empty,
+        }
+        .serialize(serializer)
+    }
+}
+
 impl Default for Cell {
     fn default() -> Self {
         Cell::Empty
@@ -68,6 +118,15 @@ impl Cell {
         }
     }
 
+    /// Apply a function to the cell if it holds a word
+    #[must_use]
+    pub(crate) fn map_word(self, f: impl FnOnce(Word) -> Word) -> Self {
+        match self {
+            Self::Word(word) => Self::Word(f(word)),
+            cell => cell,
+        }
+    }
+
     /// Extract a word from the cell.
     ///
     /// If the cell is empty, it extracts "0"
@@ -193,18 +252,135 @@ impl TryFromCell for Char {
 }
 
 /// Represents errors related to memory manipulations
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Error)]
 pub enum MemoryError {
     /// The given address was invalid
     #[error("invalid address {0}")]
     InvalidAddress(Address),
 }
 
+/// Initial content of the memory cells which were not assigned by the program
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InitPolicy {
+    /// Unassigned cells are empty, and read as zero
+    #[default]
+    Zero,
+
+    /// Unassigned cells hold the given word
+    Pattern(Word),
+
+    /// Unassigned cells hold pseudo-random words, generated from the given seed
+    Random(u64),
+}
+
+#[derive(Debug, Error)]
+#[error("invalid memory initialization {0:?}, expected \"zero\", a number or \"random(seed)\"")]
+pub struct InitPolicyParseError(String);
+
+impl std::str::FromStr for InitPolicy {
+    type Err = InitPolicyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || InitPolicyParseError(s.to_owned());
+        let s = s.trim();
+        if s == "zero" {
+            return Ok(Self::Zero);
+        }
+
+        if s == "random" {
+            return Ok(Self::Random(0));
+        }
+
+        if let Some(seed) = s
+            .strip_prefix("random(")
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            return seed.trim().parse().map(Self::Random).map_err(|_| error());
+        }
+
+        parse_word(s).map(Self::Pattern).ok_or_else(error)
+    }
+}
+
+/// Parse a word written in decimal, or in hexadecimal with a `0x` prefix
+pub(crate) fn parse_word(s: &str) -> Option<Word> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s),
+    };
+    let value = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Word::from_str_radix(hex, 16)
+    } else {
+        digits.parse()
+    }
+    .ok()?;
+
+    Some(if negative { -value } else { value })
+}
+
+impl InitPolicy {
+    /// Initial value of the cell at an address
+    fn cell(self, address: Address) -> Cell {
+        match self {
+            Self::Zero => Cell::Empty,
+            Self::Pattern(word) => Cell::Word(word),
+            Self::Random(seed) => {
+                // splitmix64, seeded by the address so the value does not depend on the order in
+                // which the cells are initialized
+                let mut z =
+                    seed.wrapping_add(u64::from(address).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+                z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+                Cell::Word(Word::from_le_bytes((z ^ (z >> 31)).to_le_bytes()))
+            }
+        }
+    }
+}
+
+/// Number of cells in a memory page
+const PAGE_SIZE: usize = 256;
+
+/// Number of cells tracked by a single word of the occupancy bitmap
+const BITMAP_WORD: usize = u64::BITS as usize;
+
+/// Value returned when reading a cell from a page that was never allocated
+static EMPTY_CELL: Cell = Cell::Empty;
+
+/// A page of memory cells
+#[derive(Clone)]
+struct Page {
+    cells: Box<[Cell]>,
+
+    /// Instructions decoded from the cells of this page, invalidated on writes
+    decoded: Box<[Option<Arc<Instruction>>]>,
+}
+
+impl Page {
+    fn new() -> Self {
+        Self {
+            cells: vec![Cell::Empty; PAGE_SIZE].into_boxed_slice(),
+            decoded: vec![None; PAGE_SIZE].into_boxed_slice(),
+        }
+    }
+}
+
 /// Holds the memory cells of the computer.
 ///
-/// It has 65536 cells by default.
+/// Cells are stored in fixed-size pages which are only allocated on the first write, so untouched
+/// regions of the memory cost nothing. An occupancy bitmap keeps track of the cells which were
+/// written at least once.
+///
+/// Pages are shared between clones of a memory, and only copied when one of them writes to it,
+/// so forking the state of a computer is cheap.
+#[derive(Clone)]
 pub struct Memory {
-    inner: Vec<Cell>,
+    size: usize,
+    pages: Vec<Option<Arc<Page>>>,
+    occupancy: Vec<u64>,
+    writes: usize,
 }
 
 impl Default for Memory {
@@ -216,35 +392,295 @@ impl Default for Memory {
 impl Memory {
     /// Create a new memory component with a given size
     pub(crate) fn new(size: usize) -> Self {
-        let inner = std::iter::repeat(Cell::Empty) // Fill the memory with empty cells
-            .take(size)
+        let pages = std::iter::repeat_with(|| None)
+            .take(size.div_ceil(PAGE_SIZE))
             .collect();
-        Self { inner }
+        let occupancy = vec![0; size.div_ceil(BITMAP_WORD)];
+        Self {
+            size,
+            pages,
+            occupancy,
+            writes: 0,
+        }
+    }
+
+    /// Total number of cells in the memory
+    #[must_use]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Convert an address to an index in the memory, checking its bounds
+    fn index(&self, address: Address) -> Result<usize, MemoryError> {
+        let index: usize = address
+            .try_into()
+            .map_err(|_e| MemoryError::InvalidAddress(address))?;
+
+        if index < self.size {
+            Ok(index)
+        } else {
+            Err(MemoryError::InvalidAddress(address))
+        }
+    }
+
+    /// The page holding a cell, allocated if needed, and copied if shared with another memory
+    fn page_mut(&mut self, index: usize) -> &mut Page {
+        let page = self.pages[index / PAGE_SIZE].get_or_insert_with(|| Arc::new(Page::new()));
+        Arc::make_mut(page)
     }
 
     /// Get a cell at an address
     ///
     /// It fails if the address is invalid or out of bounds.
     pub fn get(&self, address: Address) -> Result<&Cell, MemoryError> {
-        let addr: usize = address
-            .try_into()
-            .map_err(|_e| MemoryError::InvalidAddress(address))?;
-
-        self.inner
-            .get(addr)
-            .ok_or(MemoryError::InvalidAddress(address))
+        let index = self.index(address)?;
+        let cell = self.pages[index / PAGE_SIZE]
+            .as_ref()
+            .map_or(&EMPTY_CELL, |page| &page.cells[index % PAGE_SIZE]);
+        Ok(cell)
     }
 
     /// Get a mutable reference to a cell at an address
     ///
-    /// It fails if the address is invalid or out of bounds.
+    /// It fails if the address is invalid or out of bounds. The cell is marked as occupied, and its
+    /// decoded instruction, if any, is invalidated.
     pub(crate) fn get_mut(&mut self, address: Address) -> Result<&mut Cell, MemoryError> {
-        let addr: usize = address
-            .try_into()
-            .map_err(|_e| MemoryError::InvalidAddress(address))?;
+        let index = self.index(address)?;
+        self.occupancy[index / BITMAP_WORD] |= 1 << (index % BITMAP_WORD);
+        self.writes += 1;
+
+        let page = self.page_mut(index);
+        page.decoded[index % PAGE_SIZE] = None;
+        Ok(&mut page.cells[index % PAGE_SIZE])
+    }
+
+    /// Decode the instruction stored at an address
+    ///
+    /// The decoded instruction is cached until the cell is written again, so executing the same
+    /// code multiple times does not need to copy the instruction out of the cell each time.
+    /// Returns `None` if the cell does not hold an instruction.
+    pub(crate) fn decode(
+        &mut self,
+        address: Address,
+    ) -> Result<Option<Arc<Instruction>>, MemoryError> {
+        let index = self.index(address)?;
+        let Some(page) = self.pages[index / PAGE_SIZE].as_mut().map(Arc::make_mut) else {
+            return Ok(None);
+        };
+
+        let offset = index % PAGE_SIZE;
+        if let Some(ref instruction) = page.decoded[offset] {
+            return Ok(Some(Arc::clone(instruction)));
+        }
+
+        let Ok(instruction) = page.cells[offset].extract_instruction() else {
+            return Ok(None);
+        };
+
+        let instruction = Arc::new(instruction.clone());
+        page.decoded[offset] = Some(Arc::clone(&instruction));
+        Ok(Some(instruction))
+    }
+
+    /// Set the content of the cells which were never written to, following a policy
+    ///
+    /// Those cells are still not marked as occupied.
+    pub fn initialize(&mut self, policy: InitPolicy) {
+        if policy == InitPolicy::Zero {
+            return;
+        }
+
+        for index in 0..self.size {
+            if self.occupancy[index / BITMAP_WORD] & (1 << (index % BITMAP_WORD)) != 0 {
+                continue;
+            }
+
+            // Indexes are bounded by the memory size, which fits in an address
+            #[allow(clippy::cast_possible_truncation)]
+            let address = index as Address;
+            let page = self.page_mut(index);
+            page.cells[index % PAGE_SIZE] = policy.cell(address);
+            page.decoded[index % PAGE_SIZE] = None;
+        }
+    }
+
+    /// Apply a function to the words of the memory, without counting it as writes
+    pub(crate) fn map_words(&mut self, f: impl Fn(Word) -> Word) {
+        let changes = |cell: &Cell| matches!(cell, Cell::Word(word) if f(*word) != *word);
+        for page in self.pages.iter_mut().flatten() {
+            // Pages shared with another memory are only copied if they change
+            if !page.cells.iter().any(changes) {
+                continue;
+            }
+
+            for cell in &mut Arc::make_mut(page).cells {
+                if let Cell::Word(word) = cell {
+                    *word = f(*word);
+                }
+            }
+        }
+    }
+
+    /// Number of writes to the memory since it was created
+    #[must_use]
+    pub fn writes(&self) -> usize {
+        self.writes
+    }
+
+    /// Check if a cell was ever written to
+    #[must_use]
+    pub fn is_occupied(&self, address: Address) -> bool {
+        self.index(address).is_ok_and(|index| {
+            self.occupancy[index / BITMAP_WORD] & (1 << (index % BITMAP_WORD)) != 0
+        })
+    }
+
+    /// Iterate over the cells which were written at least once, in address order
+    pub fn occupied(&self) -> impl Iterator<Item = (Address, &Cell)> {
+        self.occupancy
+            .iter()
+            .enumerate()
+            .filter(|(_, &bits)| bits != 0)
+            .flat_map(move |(word, &bits)| {
+                (0..BITMAP_WORD)
+                    .filter(move |bit| bits & (1 << bit) != 0)
+                    .map(move |bit| word * BITMAP_WORD + bit)
+            })
+            .map(move |index| {
+                // Indexes are bounded by the memory size, which fits in an address
+                #[allow(clippy::cast_possible_truncation)]
+                let address = index as Address;
+                let page = self.pages[index / PAGE_SIZE].as_ref().unwrap();
+                (address, &page.cells[index % PAGE_SIZE])
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cell_display_test() {
+        assert_eq!(Cell::Word(42).to_string(), "42 (0x2a)");
+        assert_eq!(Cell::Word(-1).to_string(), "-1 (0xffffffffffffffff)");
+        let narrow = Arch::new(16).unwrap();
+        assert_eq!(Cell::Word(-1).display(narrow).to_string(), "-1 (0xffff)");
+        assert_eq!(Cell::Char('a').to_string(), "'a'");
+        assert_eq!(Cell::Empty.to_string(), "0");
+        assert_eq!(
+            Cell::Instruction(Box::new(Instruction::Reset)).to_string(),
+            "reset"
+        );
+    }
+
+    #[test]
+    fn cell_serialize_test() {
+        let cells = vec![
+            Cell::Instruction(Box::new(Instruction::Reset)),
+            Cell::Word(-1),
+            Cell::Char('a'),
+            Cell::Empty,
+        ];
+        assert_eq!(
+            serde_json::to_value(cells).unwrap(),
+            serde_json::json!([
+                { "type": "instruction", "value": "reset" },
+                { "type": "word", "value": -1 },
+                { "type": "char", "value": "a" },
+                { "type": "empty" },
+            ])
+        );
+    }
+
+    #[test]
+    fn unallocated_pages_read_as_empty_test() {
+        let memory = Memory::new(1000);
+        assert_eq!(memory.get(0).unwrap(), &Cell::Empty);
+        assert_eq!(memory.get(999).unwrap(), &Cell::Empty);
+        assert!(!memory.is_occupied(999));
+        assert_eq!(memory.occupied().count(), 0);
+    }
+
+    #[test]
+    fn out_of_bounds_test() {
+        let mut memory = Memory::new(1000);
+        assert!(matches!(
+            memory.get(1000),
+            Err(MemoryError::InvalidAddress(1000))
+        ));
+        assert!(matches!(
+            memory.get_mut(1000),
+            Err(MemoryError::InvalidAddress(1000))
+        ));
+        assert!(!memory.is_occupied(1000));
+    }
+
+    #[test]
+    fn occupancy_test() {
+        let mut memory = Memory::new(1000);
+        *memory.get_mut(300).unwrap() = Cell::Word(42);
+        *memory.get_mut(5).unwrap() = Cell::Char('a');
+        *memory.get_mut(6).unwrap() = Cell::Empty;
+
+        assert_eq!(memory.get(300).unwrap(), &Cell::Word(42));
+        assert_eq!(memory.get(301).unwrap(), &Cell::Empty);
+        assert!(memory.is_occupied(300));
+        assert!(!memory.is_occupied(301));
+
+        let occupied: Vec<_> = memory.occupied().collect();
+        assert_eq!(
+            occupied,
+            vec![
+                (5, &Cell::Char('a')),
+                (6, &Cell::Empty),
+                (300, &Cell::Word(42))
+            ]
+        );
+    }
+
+    #[test]
+    fn initialize_test() {
+        assert_eq!("zero".parse::<InitPolicy>().unwrap(), InitPolicy::Zero);
+        assert_eq!(
+            "0xDEAD".parse::<InitPolicy>().unwrap(),
+            InitPolicy::Pattern(0xDEAD)
+        );
+        assert_eq!(
+            "random(42)".parse::<InitPolicy>().unwrap(),
+            InitPolicy::Random(42)
+        );
+        assert!("random(x)".parse::<InitPolicy>().is_err());
+
+        let mut memory = Memory::new(1000);
+        *memory.get_mut(300).unwrap() = Cell::Word(42);
+        memory.initialize(InitPolicy::Pattern(0xDEAD));
+        assert_eq!(memory.get(300).unwrap(), &Cell::Word(42));
+        assert_eq!(memory.get(301).unwrap(), &Cell::Word(0xDEAD));
+        assert!(!memory.is_occupied(301));
+
+        let mut first = Memory::new(1000);
+        let mut second = Memory::new(1000);
+        first.initialize(InitPolicy::Random(7));
+        second.initialize(InitPolicy::Random(7));
+        assert_eq!(first.get(12).unwrap(), second.get(12).unwrap());
+        assert_ne!(first.get(12).unwrap(), first.get(13).unwrap());
+    }
+
+    #[test]
+    fn decode_cache_invalidation_test() {
+        let mut memory = Memory::new(1000);
+        assert!(memory.decode(10).unwrap().is_none());
+
+        *memory.get_mut(10).unwrap() = Instruction::Nop.into();
+        assert_eq!(*memory.decode(10).unwrap().unwrap(), Instruction::Nop);
+        // Second decoding hits the cache
+        assert_eq!(*memory.decode(10).unwrap().unwrap(), Instruction::Nop);
+
+        *memory.get_mut(10).unwrap() = Instruction::Rtn.into();
+        assert_eq!(*memory.decode(10).unwrap().unwrap(), Instruction::Rtn);
 
-        self.inner
-            .get_mut(addr)
-            .ok_or(MemoryError::InvalidAddress(address))
+        *memory.get_mut(10).unwrap() = Cell::Word(3);
+        assert!(memory.decode(10).unwrap().is_none());
     }
 }
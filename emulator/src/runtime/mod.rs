@@ -1,25 +1,76 @@
 //! The actual emulator runtime
 
 use std::fmt::Debug;
+use std::sync::Arc;
 use thiserror::Error;
 use tracing::{debug, info, trace};
 
+use crate::compiler::Symbols;
 use crate::constants as C;
 
+mod arch;
+mod args;
 pub(crate) mod arguments;
+mod call;
+pub mod cluster;
+mod console;
+mod context;
+mod convention;
+mod device;
+mod digest;
+mod dump;
 mod exception;
+mod heap;
+mod html;
 mod instructions;
+mod limits;
+mod mailbox;
 mod memory;
 mod registers;
+mod replay;
+mod ring;
+mod scheduler;
+mod search;
+mod shadow;
+mod snapshot;
+mod timer;
+mod watchdog;
 
-pub use self::arguments::ExtractValue;
-pub use self::exception::Exception;
+pub use self::arch::{Arch, ArchParseError, Endianness};
+pub use self::args::ArgsError;
+pub use self::arguments::{AddressingMode, ExtractValue};
+pub use self::console::{Channel, Console, ConsoleOutput};
+pub use self::context::MachineContext;
+pub use self::convention::{ConventionChecker, ConventionViolation, Violation};
+pub use self::device::{Device, DeviceClone, PortConflict, WouldBlock};
+pub use self::digest::{FinalAssertion, FinalAssertionFailed};
+pub use self::dump::HEXDUMP_WIDTH;
+pub use self::exception::{Exception, FaultContext};
+pub use self::heap::{Heap, HeapError, HeapMonitor, HeapRegion, HeapRegionParseError};
 pub(crate) use self::instructions::Instruction;
-pub(crate) use self::memory::{Cell, Memory};
-pub use self::registers::{Reg, Registers};
+pub use self::instructions::{help, mnemonics};
+pub use self::limits::{Limit, LimitExceeded, MachineConfig};
+pub use self::mailbox::{mailbox, Endpoint};
+pub(crate) use self::memory::Memory;
+pub use self::memory::{Cell, CellDisplay, InitPolicy, InitPolicyParseError};
+pub use self::registers::{
+    AssignmentParseError, EntryState, Flags, FlagsParseError, Reg, RegisterAssignment, Registers,
+    RegistersDisplay,
+};
+pub use self::replay::{Delivery, InterruptLog, InterruptSource, ReplayParseError};
+pub use self::ring::RingBuffer;
+pub use self::scheduler::{SchedulerMetrics, SchedulerReport, Slice};
+pub use self::search::SearchPattern;
+pub use self::shadow::{Shadow, ShadowViolation};
+pub use self::snapshot::{
+    CellChange, RegisterChange, Snapshot, SnapshotError, StateDiff, SNAPSHOT_VERSION,
+};
+pub use self::timer::Timer;
+pub use self::watchdog::{InfiniteLoop, LoopDetector};
 
 use self::arguments::{ExtractError, Ind, ResolveAddress};
-use self::memory::{CellError, MemoryError};
+use self::device::Devices;
+pub(crate) use self::memory::{CellError, MemoryError};
 use self::registers::StatusRegister;
 
 #[derive(Error, Debug)]
@@ -31,7 +82,10 @@ pub enum ProcessorError {
     CellError(#[from] CellError),
 
     #[error("extract word: {0}")]
-    Extract(#[from] ExtractError),
+    Extract(ExtractError),
+
+    #[error(transparent)]
+    Shadow(#[from] ShadowViolation),
 
     #[error("invalid value for register {reg}: {inner}")]
     InvalidRegister { reg: Reg, inner: CellError },
@@ -41,6 +95,78 @@ pub enum ProcessorError {
 
     #[error("computer reset")]
     Reset,
+
+    /// A `halt` instruction was executed, `%pc` staying on it
+    #[error("computer halted")]
+    Halt,
+
+    /// A `brk` instruction was executed, stepping again resumes after it
+    #[error("breakpoint")]
+    Breakpoint,
+
+    #[error(transparent)]
+    LimitExceeded(#[from] LimitExceeded),
+
+    /// The program halted in a state other than the one expected by a `.final_assert` directive
+    #[error(transparent)]
+    FinalAssertion(#[from] FinalAssertionFailed),
+
+    /// An error reading or writing an operand of the instruction, counted from 0. It becomes the
+    /// operand of the [`FaultContext`] when the error stops the processor
+    #[error("{inner} (operand {})", index + 1)]
+    Operand {
+        index: usize,
+        inner: Box<ProcessorError>,
+    },
+
+    /// An error which stopped the processor, with the state it was in
+    #[error("{inner} {context}")]
+    Fault {
+        inner: Box<ProcessorError>,
+        context: FaultContext,
+    },
+}
+
+impl ProcessorError {
+    /// The error itself, without the context of the fault
+    #[must_use]
+    pub fn cause(&self) -> &Self {
+        match self {
+            Self::Fault { inner, .. } | Self::Operand { inner, .. } => inner.cause(),
+            error => error,
+        }
+    }
+
+    /// Name the address of the fault after the labels of the program
+    #[must_use]
+    pub fn with_symbols(mut self, symbols: &Symbols) -> Self {
+        if let Self::Fault { context, .. } = &mut self {
+            context.symbol = symbols.lookup(context.address).map(|s| s.to_string());
+        }
+        self
+    }
+
+    /// Whether the error comes from the program, unlike the ones stopping it on purpose
+    fn is_fault(&self) -> bool {
+        matches!(
+            self,
+            Self::Exception(_)
+                | Self::CellError(_)
+                | Self::Extract(_)
+                | Self::Shadow(_)
+                | Self::InvalidRegister { .. }
+                | Self::InvalidAddress { .. }
+        )
+    }
+}
+
+impl From<ExtractError> for ProcessorError {
+    fn from(e: ExtractError) -> Self {
+        match e {
+            ExtractError::Shadow(violation) => Self::Shadow(violation),
+            e => Self::Extract(e),
+        }
+    }
 }
 
 // Implement a MemoryError -> ProcessorError conversion to simplify code
@@ -52,11 +178,40 @@ impl From<MemoryError> for ProcessorError {
 
 type Result<T> = std::result::Result<T, ProcessorError>;
 
-#[derive(Default)]
+/// Maximum number of nested interrupt handlers, deeper ones raising a double fault
+pub const MAX_HANDLER_DEPTH: usize = 16;
+
+/// An interrupt handler being executed
+#[derive(Debug, Clone)]
+pub(crate) struct HandlerFrame {
+    /// Whether the handler was entered because of a fault
+    fault: bool,
+
+    /// Code of the exception being handled
+    code: C::Word,
+
+    /// State saved by the outer handler, restored when returning from this one
+    outer: Option<[Cell; 3]>,
+}
+
+#[derive(Clone, Default)]
 pub struct Computer {
     pub registers: Registers,
     pub memory: Memory,
     pub cycles: usize,
+    pub steps: usize,
+    pub(crate) devices: Devices,
+    pub(crate) config: MachineConfig,
+    pub(crate) usage: Option<self::limits::Usage>,
+
+    /// Interrupt handlers entered and not yet returned from, the innermost last
+    pub(crate) handlers: Vec<HandlerFrame>,
+
+    /// Tags checked on each access to the memory, if enabled
+    pub(crate) shadow: Option<Shadow>,
+
+    /// Digests of the memory checked once the program halts
+    pub(crate) final_assertions: Vec<FinalAssertion>,
 }
 
 impl std::fmt::Debug for Computer {
@@ -70,16 +225,67 @@ impl std::fmt::Debug for Computer {
 }
 
 impl Computer {
+    /// Attach an I/O controller, on ports starting at the given base address
+    ///
+    /// Fails if its ports overlap with another device.
+    pub fn attach<D: Device + 'static>(
+        &mut self,
+        base: C::Address,
+        device: D,
+    ) -> std::result::Result<std::ops::Range<C::Address>, PortConflict> {
+        self.devices.attach(base, Box::new(device))
+    }
+
+    /// Check the accesses of the instructions to the memory with a [`Shadow`] memory
+    pub fn set_shadow(&mut self, shadow: Shadow) {
+        self.shadow = Some(shadow);
+    }
+
+    /// Check an access of an instruction to the memory, if the shadow memory is enabled
+    pub(crate) fn check_access(
+        &self,
+        address: C::Address,
+        write: bool,
+    ) -> std::result::Result<(), ShadowViolation> {
+        match &self.shadow {
+            Some(shadow) => shadow.check(address, write, self.registers.sp),
+            None => Ok(()),
+        }
+    }
+
     pub(crate) fn write<T: Into<Cell> + Debug>(
         &mut self,
         address: C::Address,
         value: T,
     ) -> Result<()> {
+        self.check_access(address, true)?;
+        let arch = self.config.arch;
         let cell = self.memory.get_mut(address)?;
-        *cell = value.into();
+        *cell = value.into().map_word(|word| arch.wrap(word));
         Ok(())
     }
 
+    /// Read a value spanning two cells from an address, like the ones of `.long` directives
+    ///
+    /// The cells are ordered by the endianness of the machine.
+    pub fn read_long(&self, address: C::Address) -> Result<i128> {
+        let word = |address| -> Result<C::Word> { Ok(self.memory.get(address)?.extract_word()?) };
+        let cells = [word(address)?, word(address.wrapping_add(1))?];
+        Ok(self.config.endianness.join(cells, self.config.arch))
+    }
+
+    /// Read bytes packed in the cells starting at an address, like the ones of `.byte` directives
+    pub fn read_bytes(&self, address: C::Address, count: usize) -> Result<Vec<u8>> {
+        let words = count.div_ceil(self.config.arch.word_bytes());
+        let words = (address..)
+            .take(words)
+            .map(|address| Ok(self.memory.get(address)?.extract_word()?))
+            .collect::<Result<Vec<_>>>()?;
+        let mut bytes = self.config.endianness.unpack(&words, self.config.arch);
+        bytes.truncate(count);
+        Ok(bytes)
+    }
+
     /// Set the value of a register
     ///
     /// If the instruction tries to set the %sr register, it checks if the processor is running in
@@ -91,8 +297,9 @@ impl Computer {
             self.check_privileged()?;
         }
 
+        let arch = self.config.arch;
         self.registers
-            .set(reg, val)
+            .set(reg, val.map_word(|word| arch.wrap(word)))
             .map_err(|inner| ProcessorError::InvalidRegister { reg, inner })
     }
 
@@ -101,12 +308,11 @@ impl Computer {
         self.registers.pc = address;
     }
 
-    fn decode_instruction(&mut self) -> Result<&Instruction> {
+    fn decode_instruction(&mut self) -> Result<Arc<Instruction>> {
         let address = Ind(Reg::PC).resolve_address(&self.registers)?;
-        let cell = self.memory.get(address)?;
+        let instruction = self.memory.decode(address)?;
         self.registers.pc += 1;
-        cell.extract_instruction()
-            .map_err(|_| Exception::InvalidInstruction.into())
+        instruction.ok_or_else(|| Exception::InvalidInstruction.into())
     }
 
     pub fn next_instruction(&mut self) -> Result<String> {
@@ -121,37 +327,119 @@ impl Computer {
 
     #[tracing::instrument(skip(self), level = "debug", fields(cost))]
     pub fn step(&mut self) -> Result<()> {
+        self.check_limits()?;
+
         // Wrapping the part that can be recovered from in another function
         fn inner(c: &mut Computer) -> Result<usize> {
+            if c.interrupts_enabled() && c.devices.interrupt() {
+                return Err(Exception::HardwareInterrupt.into());
+            }
+
             let inst = c.decode_instruction()?;
             let cost = inst.cost();
             tracing::Span::current().record("cost", cost);
             info!("Executing instruction \"{}\"", inst);
-            // This clone is necessary as `inst` is borrowed from `self`.
-            // The computer might modify the cell where the instruction is stored when executing it.
-            inst.clone().execute(c)?;
+            // The decoded instruction is shared with the memory cache, which means the computer
+            // can safely modify the cell where the instruction is stored when executing it.
+            inst.execute(c)?;
             Ok(cost)
         }
 
-        let cost = inner(self).or_else(|e| {
-            if let ProcessorError::Exception(e) = e {
-                self.recover_from_exception(&e)
-                    .map_err(ProcessorError::Exception)
-                    .map(|_| 1) // TODO: fixed cost for exceptions?
-            } else {
-                Err(e)
-            }
-        })?;
+        let address = self.registers.pc;
+        let supervisor = self.registers.sr.contains(StatusRegister::SUPERVISOR);
+        let cost = inner(self)
+            .or_else(|e| {
+                if let ProcessorError::Exception(e) = e {
+                    self.recover_from_exception(&e)
+                        .map_err(ProcessorError::Exception)
+                        .map(|_| 1) // TODO: fixed cost for exceptions?
+                } else {
+                    Err(e)
+                }
+            })
+            .map_err(|e| {
+                let (e, operand) = match e {
+                    ProcessorError::Operand { index, inner } => (*inner, Some(index)),
+                    e => (e, None),
+                };
+                if matches!(e, ProcessorError::Reset | ProcessorError::Halt) {
+                    if let Err(failed) = self.check_final_assertions() {
+                        return failed.into();
+                    }
+                }
+                if !e.is_fault() {
+                    return e;
+                }
+
+                let instruction = self
+                    .memory
+                    .get(address)
+                    .ok()
+                    .and_then(|cell| cell.extract_instruction().ok())
+                    .map(ToString::to_string);
+                ProcessorError::Fault {
+                    inner: Box::new(e),
+                    context: FaultContext {
+                        address,
+                        symbol: None,
+                        instruction,
+                        operand,
+                        supervisor,
+                    },
+                }
+            })?;
         self.cycles += cost;
+        self.steps += 1;
+        self.devices.tick(cost);
         trace!("Register state {:?}", self.registers);
         Ok(())
     }
 
+    /// Enter the interrupt handler
+    ///
+    /// Handlers can be interrupted by other exceptions: the state saved by the outer handler is
+    /// then restored when the inner one returns with `rti`. A fault in the handler of another
+    /// fault is a double fault, which stops the processor, as does nesting more than
+    /// [`MAX_HANDLER_DEPTH`] handlers. Without any instruction at the handler address, the
+    /// exception itself stops the processor.
     pub fn recover_from_exception(
         &mut self,
         exception: &Exception,
     ) -> std::result::Result<(), Exception> {
-        debug!(exception = %exception, "Recovering from exception");
+        debug!(exception = %exception, depth = self.handlers.len(), "Recovering from exception");
+
+        if self
+            .memory
+            .get(C::INTERRUPT_HANDLER)?
+            .extract_instruction()
+            .is_err()
+        {
+            return Err(exception.clone());
+        }
+
+        let outer = match self.handlers.last() {
+            Some(handler)
+                if (handler.fault && exception.is_fault())
+                    || self.handlers.len() >= MAX_HANDLER_DEPTH =>
+            {
+                return Err(Exception::DoubleFault {
+                    fault: Box::new(exception.clone()),
+                    handling: handler.code,
+                });
+            }
+            Some(_) => Some([
+                self.memory.get(C::INTERRUPT_PC_SAVE)?.clone(),
+                self.memory.get(C::INTERRUPT_SR_SAVE)?.clone(),
+                self.memory.get(C::INTERRUPT_EXCEPTION)?.clone(),
+            ]),
+            None => None,
+        };
+        self.handlers.push(HandlerFrame {
+            fault: exception.is_fault(),
+            code: exception.code(),
+            outer,
+        });
+
         *(self.memory.get_mut(C::INTERRUPT_PC_SAVE)?) = self.registers.get(&Reg::PC);
         *(self.memory.get_mut(C::INTERRUPT_SR_SAVE)?) = self.registers.get(&Reg::SR);
         *(self.memory.get_mut(C::INTERRUPT_EXCEPTION)?) = exception.code().into();
@@ -164,6 +452,42 @@ impl Computer {
         Ok(())
     }
 
+    /// Leave the innermost interrupt handler, restoring the state saved by the outer one
+    fn leave_handler(&mut self) -> Result<()> {
+        let Some(handler) = self.handlers.pop() else {
+            return Ok(());
+        };
+
+        if let Some([pc, sr, code]) = handler.outer {
+            *(self.memory.get_mut(C::INTERRUPT_PC_SAVE)?) = pc;
+            *(self.memory.get_mut(C::INTERRUPT_SR_SAVE)?) = sr;
+            *(self.memory.get_mut(C::INTERRUPT_EXCEPTION)?) = code;
+        }
+        Ok(())
+    }
+
+    /// Number of interrupt handlers being executed, nested ones included
+    #[must_use]
+    pub fn interrupt_depth(&self) -> usize {
+        self.handlers.len()
+    }
+
+    /// Exit code of the program, taken from `%a` once it reset or halted
+    ///
+    /// Like on Unix, only the lowest 8 bits of the value are kept. Returns `None` if `%a` does not
+    /// hold a word.
+    #[must_use]
+    pub fn exit_code(&self) -> Option<u8> {
+        let word = self.registers.a.extract_word().ok()?;
+        Some(word.to_le_bytes()[0])
+    }
+
+    /// Check if hardware interrupts are enabled in the status register
+    #[must_use]
+    pub fn interrupts_enabled(&self) -> bool {
+        self.registers.sr.contains(StatusRegister::INTERRUPT_ENABLE)
+    }
+
     fn check_privileged(&self) -> Result<()> {
         if self.registers.sr.contains(StatusRegister::SUPERVISOR) {
             Ok(())
@@ -172,12 +496,16 @@ impl Computer {
         }
     }
 
+    /// Run the program until it resets or halts
+    ///
+    /// This stops early with [`ProcessorError::Breakpoint`] on `brk` instructions, and calling it
+    /// again resumes the execution.
     #[tracing::instrument(skip(self))]
     pub fn run(&mut self) -> Result<()> {
         loop {
             match self.step() {
                 Ok(_) => {}
-                Err(ProcessorError::Reset) => return Ok(()),
+                Err(ProcessorError::Reset | ProcessorError::Halt) => return Ok(()),
                 Err(v) => return Err(v),
             }
         }
@@ -216,7 +544,7 @@ mod tests {
 
     use crate::constants::Word;
 
-    use super::arguments::{Idx, Imm, ImmRegDirIndIdx};
+    use super::arguments::{Dec, DirIndIdx, Idx, Imm, ImmReg, ImmRegDirIndIdx, Inc, Sca};
     use super::*;
 
     #[test]
@@ -233,6 +561,26 @@ mod tests {
         let instruction = Instruction::Add(ImmRegDirIndIdx::Idx(Idx(Reg::B, 0x10)), Reg::A);
         instruction.execute(&mut computer).unwrap();
         assert_eq!(computer.registers.get(&Reg::A), Cell::Word(105));
+
+        // Negative offsets go backwards, and overflowing ones out of the memory
+        computer.registers.set(Reg::B, Cell::Word(0x52)).unwrap();
+        let instruction = Instruction::Add(ImmRegDirIndIdx::Idx(Idx(Reg::B, -0x10)), Reg::A);
+        instruction.execute(&mut computer).unwrap();
+        assert_eq!(computer.registers.get(&Reg::A), Cell::Word(205));
+
+        computer
+            .registers
+            .set(Reg::B, Cell::Word(Word::MAX))
+            .unwrap();
+        let instruction = Instruction::Add(ImmRegDirIndIdx::Idx(Idx(Reg::B, 1)), Reg::A);
+        assert!(instruction.execute(&mut computer).is_err());
+
+        // Scaled index: 0x36 + 3 * 4
+        computer.registers.set(Reg::A, Cell::Word(3)).unwrap();
+        computer.registers.set(Reg::B, Cell::Word(0x36)).unwrap();
+        let instruction = Instruction::Ld(ImmRegDirIndIdx::Sca(Sca(Reg::B, Reg::A, 4)), Reg::A);
+        instruction.execute(&mut computer).unwrap();
+        assert_eq!(computer.registers.get(&Reg::A), Cell::Word(100));
     }
 
     #[test]
@@ -273,6 +621,181 @@ mod tests {
         assert_eq!(computer.registers.pc, start + 3);
     }
 
+    #[test]
+    fn breakpoint_test() {
+        let mut computer = Computer::default();
+        let start: C::Address = 0x100;
+        computer.write(start, Instruction::Brk).unwrap();
+        computer
+            .write(
+                start + 1,
+                Instruction::Ld(ImmRegDirIndIdx::Imm(Imm(42)), Reg::A),
+            )
+            .unwrap();
+        computer.write(start + 2, Instruction::Reset).unwrap();
+
+        // The execution stops after the breakpoint, and resumes from there
+        computer.jump(start);
+        assert!(matches!(computer.run(), Err(ProcessorError::Breakpoint)));
+        assert_eq!(computer.registers.pc, start + 1);
+        computer.run().unwrap();
+        assert_eq!(computer.registers.a, Cell::Word(42));
+    }
+
+    #[test]
+    fn halt_test() {
+        let mut computer = Computer::default();
+        let start: C::Address = 0x100;
+        computer
+            .write(start, Instruction::Ld(ImmRegDirIndIdx::Imm(Imm(3)), Reg::A))
+            .unwrap();
+        computer.write(start + 1, Instruction::Halt).unwrap();
+
+        // The processor stays on the `halt`, and reports the exit code like `reset`
+        computer.jump(start);
+        computer.run().unwrap();
+        assert_eq!(computer.registers.pc, start + 1);
+        assert_eq!(computer.exit_code(), Some(3));
+        assert!(matches!(computer.step(), Err(ProcessorError::Halt)));
+        assert_eq!(computer.registers.pc, start + 1);
+    }
+
+    #[test]
+    fn nested_handler_test() {
+        let computer = |source: &str| {
+            let program = crate::parse(source).unwrap();
+            crate::compile(program.inner, "main").unwrap().0
+        };
+
+        // The handler traps once from itself, and still returns to the program afterwards
+        let mut c = computer(
+            "main: trap\n\
+                 reset\n\
+             .addr 200\n\
+             handler: add 1, %a\n\
+                 cmp 1, %a\n\
+                 jne inner\n\
+                 trap\n\
+                 ld [100], %b\n\
+                 rti\n\
+             inner: rti\n",
+        );
+        c.run().unwrap();
+        assert_eq!(c.registers.a, Cell::Word(2));
+        assert_eq!(c.registers.b, Cell::Word((C::PROGRAM_START + 1).into()));
+        assert_eq!(c.interrupt_depth(), 0);
+
+        // A fault in the handler of a fault stops the processor
+        let mut c = computer("main: div 0, %a\n.addr 200\nhandler: div 0, %a\n");
+        let error = c.run().unwrap_err();
+        assert!(matches!(
+            error.cause(),
+            ProcessorError::Exception(Exception::DoubleFault { fault, handling: 1 })
+                if matches!(**fault, Exception::DivByZero)
+        ));
+        assert_eq!(c.interrupt_depth(), 1);
+        assert!(matches!(
+            error,
+            ProcessorError::Fault { context, .. } if context == FaultContext {
+                address: C::INTERRUPT_HANDLER,
+                symbol: None,
+                instruction: Some("div  0, %a".to_owned()),
+                operand: None,
+                supervisor: true,
+            }
+        ));
+
+        // Without a handler, the exception itself stops the processor
+        let mut c = computer("main: ld 0, %b\ndiv %b, %a\nreset\n");
+        let error = c.run().unwrap_err();
+        assert!(matches!(
+            error,
+            ProcessorError::Fault { ref inner, ref context }
+                if matches!(**inner, ProcessorError::Exception(Exception::DivByZero))
+                    && context.address == C::PROGRAM_START + 1
+        ));
+        assert_eq!(c.interrupt_depth(), 0);
+
+        let mut c = computer("main: trap\n");
+        let error = c.run().unwrap_err();
+        assert!(matches!(
+            error.cause(),
+            ProcessorError::Exception(Exception::Trap)
+        ));
+        assert_eq!(c.interrupt_depth(), 0);
+
+        // As does nesting too many handlers, instead of looping on the handler
+        let mut c = computer("main: trap\n.addr 200\nhandler: trap\n");
+        let error = c.run().unwrap_err();
+        assert!(matches!(
+            error.cause(),
+            ProcessorError::Exception(Exception::DoubleFault { handling: 4, .. })
+        ));
+        assert_eq!(c.interrupt_depth(), MAX_HANDLER_DEPTH);
+    }
+
+    #[test]
+    fn self_modifying_code_test() {
+        let mut computer = Computer::default();
+        let start: C::Address = 0x100;
+        computer
+            .write(
+                start,
+                Instruction::Add(ImmRegDirIndIdx::Imm(Imm(1)), Reg::A),
+            )
+            .unwrap();
+
+        computer.jump(start);
+        computer.step().unwrap();
+        assert_eq!(computer.registers.a, Cell::Word(1));
+
+        // Overwriting the cell must invalidate the decoded instruction
+        computer
+            .write(
+                start,
+                Instruction::Add(ImmRegDirIndIdx::Imm(Imm(10)), Reg::A),
+            )
+            .unwrap();
+        computer.jump(start);
+        computer.step().unwrap();
+        assert_eq!(computer.registers.a, Cell::Word(11));
+    }
+
+    #[test]
+    fn fork_test() {
+        fn thread_safe<T: Clone + Send + Sync>() {}
+        thread_safe::<Computer>();
+
+        let mut computer = Computer::default();
+        let start: C::Address = 0x100;
+        computer
+            .write(
+                start,
+                Instruction::Add(ImmRegDirIndIdx::Imm(Imm(1)), Reg::A),
+            )
+            .unwrap();
+        computer.attach(0, timer::Timer::new(10)).unwrap();
+        computer.jump(start);
+        computer.step().unwrap();
+
+        // Writes of the fork are not seen by the original computer, and the other way around
+        let mut fork = computer.clone();
+        fork.write(
+            start,
+            Instruction::Add(ImmRegDirIndIdx::Imm(Imm(10)), Reg::A),
+        )
+        .unwrap();
+        computer.write(0x200, 7_i64).unwrap();
+        fork.jump(start);
+        fork.step().unwrap();
+        computer.jump(start);
+        computer.step().unwrap();
+        assert_eq!(fork.registers.a, Cell::Word(11));
+        assert_eq!(computer.registers.a, Cell::Word(2));
+        assert!(!fork.memory.is_occupied(0x200));
+        assert_eq!(fork.devices.end(), 1);
+    }
+
     #[test]
     fn call_test() {
         let mut computer = Computer::default();
@@ -356,21 +879,285 @@ mod tests {
         assert_eq!(computer.registers.sp, stack);
     }
 
-    /*
+    #[test]
+    fn exit_code_test() {
+        let mut computer = Computer::default();
+        assert_eq!(computer.exit_code(), Some(0));
+
+        computer.registers.a = Cell::Word(3);
+        assert_eq!(computer.exit_code(), Some(3));
+
+        computer.registers.a = Cell::Word(-1);
+        assert_eq!(computer.exit_code(), Some(255));
+
+        computer.registers.a = Instruction::Reset.into();
+        assert_eq!(computer.exit_code(), None);
+    }
+
     #[test]
     fn overflow_test() {
         let mut computer = Computer::default();
-        computer.registers.a = 0xFFFF;
+        computer.registers.a = Cell::Word(Word::MAX);
+
+        // Signed overflow, without unsigned carry
+        let instruction = Instruction::Add(ImmRegDirIndIdx::Imm(Imm(1)), Reg::A);
+        instruction.execute(&mut computer).unwrap();
+        assert_eq!(computer.registers.a, Cell::Word(Word::MIN));
+        assert!(computer.registers.overflow());
+        assert!(!computer.registers.carry());
+
+        // Unsigned carry, without signed overflow
+        computer.registers.a = Cell::Word(-1);
+        instruction.execute(&mut computer).unwrap();
+        assert_eq!(computer.registers.a, Cell::Word(0));
+        assert!(!computer.registers.overflow());
+        assert!(computer.registers.carry());
+
+        // Unsigned borrow
+        let instruction = Instruction::Sub(ImmRegDirIndIdx::Imm(Imm(1)), Reg::A);
+        instruction.execute(&mut computer).unwrap();
+        assert_eq!(computer.registers.a, Cell::Word(-1));
+        assert!(!computer.registers.overflow());
+        assert!(computer.registers.carry());
+
+        computer.registers.a = Cell::Word(Word::MIN);
+        Instruction::Neg(Reg::A).execute(&mut computer).unwrap();
+        assert_eq!(computer.registers.a, Cell::Word(Word::MIN));
+        assert!(computer.registers.overflow());
+
+        let instruction = Instruction::Div(ImmRegDirIndIdx::Imm(Imm(-1)), Reg::A);
+        instruction.execute(&mut computer).unwrap();
+        assert_eq!(computer.registers.a, Cell::Word(Word::MIN));
+        assert!(computer.registers.overflow());
+
+        let instruction = Instruction::Mod(ImmRegDirIndIdx::Imm(Imm(-1)), Reg::A);
+        instruction.execute(&mut computer).unwrap();
+        assert_eq!(computer.registers.a, Cell::Word(0));
+        assert!(!computer.registers.overflow());
+    }
+
+    #[test]
+    fn arch_test() {
+        let mut computer = Computer::default();
+        computer.write(0x100, 70000_i64).unwrap();
+        computer.registers.a = Cell::Word(0x7fff);
+        computer.set_config(MachineConfig {
+            arch: Arch::new(16).unwrap(),
+            ..MachineConfig::default()
+        });
+        assert_eq!(computer.memory.get(0x100).unwrap(), &Cell::Word(4464));
+
+        let instruction = Instruction::Add(ImmRegDirIndIdx::Imm(Imm(1)), Reg::A);
+        instruction.execute(&mut computer).unwrap();
+        assert_eq!(computer.registers.a, Cell::Word(-0x8000));
+        assert!(computer.registers.overflow());
+        assert!(!computer.registers.carry());
+
+        Instruction::Ld(ImmRegDirIndIdx::Imm(Imm(0x1ffff)), Reg::A)
+            .execute(&mut computer)
+            .unwrap();
+        assert_eq!(computer.registers.a, Cell::Word(-1));
+
+        let instruction = Instruction::Shl(ImmRegDirIndIdx::Imm(Imm(16)), Reg::A);
+        assert!(instruction.execute(&mut computer).is_err());
+    }
+
+    #[test]
+    fn mod_test() {
+        let mut computer = Computer::default();
+
+        // The remainder has the sign of the register, like a division rounding towards zero
+        for (a, b, res) in [(17, 5, 2), (-17, 5, -2), (17, -5, 2), (-17, -5, -2)] {
+            computer.registers.a = Cell::Word(a);
+            let instruction = Instruction::Mod(ImmRegDirIndIdx::Imm(Imm(b)), Reg::A);
+            instruction.execute(&mut computer).unwrap();
+            assert_eq!(computer.registers.a, Cell::Word(res), "{a} % {b}");
+        }
+
+        let instruction = Instruction::Mod(ImmRegDirIndIdx::Imm(Imm(0)), Reg::A);
+        assert!(matches!(
+            instruction.execute(&mut computer),
+            Err(ProcessorError::Exception(Exception::DivByZero))
+        ));
+    }
+
+    #[test]
+    fn help_test() {
+        assert_eq!(help("add"), Some("Add a value to a register"));
+        assert_eq!(
+            help("JLTU"),
+            Some("Jump if strictly less, as unsigned words")
+        );
+        assert_eq!(help("foo"), None);
+        assert!(mnemonics().any(|mnemonic| mnemonic == "debugreg"));
+        assert!(mnemonics().all(|mnemonic| help(mnemonic).is_some()));
+    }
+
+    #[test]
+    fn instruction_serde_test() {
+        let instruction = Instruction::Add(ImmRegDirIndIdx::Idx(Idx(Reg::SP, -1)), Reg::A);
+        let value = serde_json::to_value(&instruction).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({ "add": [{ "idx": ["sp", -1] }, "a"] })
+        );
+        assert_eq!(
+            serde_json::from_value::<Instruction>(value).unwrap(),
+            instruction
+        );
+
+        let value = serde_json::to_value(Instruction::Nop).unwrap();
+        assert_eq!(value, serde_json::json!("nop"));
+        assert_eq!(
+            serde_json::from_value::<Instruction>(value).unwrap(),
+            Instruction::Nop
+        );
+    }
+
+    #[test]
+    fn flags_test() {
+        let mut computer = Computer::default();
+        computer.registers.a = Cell::Word(3);
+
+        // ALU instructions set the flags from their result
+        let instruction = Instruction::Sub(ImmRegDirIndIdx::Imm(Imm(3)), Reg::A);
+        instruction.execute(&mut computer).unwrap();
+        assert_eq!(computer.registers.flags(), "Z---");
+
+        let instruction = Instruction::Sub(ImmRegDirIndIdx::Imm(Imm(1)), Reg::A);
+        instruction.execute(&mut computer).unwrap();
+        assert_eq!(computer.registers.flags(), "-NC-");
+
+        let instruction = Instruction::Or(ImmRegDirIndIdx::Imm(Imm(4)), Reg::A);
+        instruction.execute(&mut computer).unwrap();
+        assert_eq!(computer.registers.flags(), "-N--");
+
+        // The signed comparison stays correct when the substraction overflows
+        computer.registers.a = Cell::Word(1);
+        let instruction = Instruction::Cmp(ImmRegDirIndIdx::Imm(Imm(Word::MIN)), Reg::A);
+        instruction.execute(&mut computer).unwrap();
+        assert_eq!(computer.registers.flags(), "---V");
+
+        computer.registers.pc = 0;
+        Instruction::Jlt(ImmRegDirIndIdx::Imm(Imm(10)))
+            .execute(&mut computer)
+            .unwrap();
+        assert_eq!(computer.registers.pc, 10);
+    }
+
+    #[test]
+    fn unsigned_compare_test() {
+        let mut computer = Computer::default();
+        computer.registers.a = Cell::Word(1);
 
-        let instruction = Instruction::Add(Arg::Value(Value::Imm(1)), Reg::A);
+        // -1 is lower than 1 as a signed word, but greater as an unsigned one
+        let instruction = Instruction::Cmp(ImmRegDirIndIdx::Imm(Imm(-1)), Reg::A);
         instruction.execute(&mut computer).unwrap();
-        assert_eq!(computer.registers.a, 0);
-        assert!(computer.registers.sr.contains(StatusRegister::OVERFLOW));
+        assert!(computer.registers.negative());
+        assert!(!computer.registers.carry());
+        assert!(!computer.registers.zero());
 
-        let instruction = Instruction::Add(Arg::Value(Value::Imm(1)), Reg::A);
+        let jumps = [
+            (Instruction::Jlt(ImmRegDirIndIdx::Imm(Imm(10))), true),
+            (Instruction::Jgt(ImmRegDirIndIdx::Imm(Imm(10))), false),
+            (Instruction::Jltu(ImmRegDirIndIdx::Imm(Imm(10))), false),
+            (Instruction::Jgtu(ImmRegDirIndIdx::Imm(Imm(10))), true),
+            (Instruction::Jleu(ImmRegDirIndIdx::Imm(Imm(10))), false),
+            (Instruction::Jgeu(ImmRegDirIndIdx::Imm(Imm(10))), true),
+        ];
+        for (instruction, taken) in jumps {
+            computer.registers.pc = 0;
+            instruction.execute(&mut computer).unwrap();
+            assert_eq!(computer.registers.pc == 10, taken, "{instruction}");
+        }
+    }
+
+    #[test]
+    fn post_increment_pre_decrement_test() {
+        let mut stack = Computer::default();
+        let mut explicit = Computer::default();
+        for computer in [&mut stack, &mut explicit] {
+            computer.registers.sp = 100;
+            computer.registers.a = Cell::Word(7);
+        }
+
+        // push and pop are a pre-decremented store and a post-incremented load
+        Instruction::Push(ImmReg::Reg(Reg::A))
+            .execute(&mut stack)
+            .unwrap();
+        Instruction::St(Reg::A, DirIndIdx::Dec(Dec(Reg::SP)))
+            .execute(&mut explicit)
+            .unwrap();
+        assert_eq!(stack.registers.sp, 99);
+        assert_eq!(explicit.registers.sp, 99);
+        assert_eq!(explicit.memory.get(99).unwrap(), &Cell::Word(7));
+
+        Instruction::Pop(Reg::B).execute(&mut stack).unwrap();
+        Instruction::Ld(ImmRegDirIndIdx::Inc(Inc(Reg::SP)), Reg::B)
+            .execute(&mut explicit)
+            .unwrap();
+        assert_eq!(stack.registers.sp, 100);
+        assert_eq!(explicit.registers.sp, 100);
+        assert_eq!(explicit.registers.b, Cell::Word(7));
+
+        // The register is restored when the instruction fails
+        explicit.registers.a = Cell::Word(-1);
+        let instruction = Instruction::Ld(ImmRegDirIndIdx::Inc(Inc(Reg::A)), Reg::B);
+        assert!(instruction.execute(&mut explicit).is_err());
+        assert_eq!(explicit.registers.a, Cell::Word(-1));
+
+        // Incrementing the largest word wraps around, which is not an address either
+        explicit.registers.a = Cell::Word(Word::MAX);
+        assert!(instruction.execute(&mut explicit).is_err());
+        assert_eq!(explicit.registers.a, Cell::Word(Word::MAX));
+    }
+
+    #[test]
+    fn rotate_test() {
+        let mut computer = Computer::default();
+
+        // Bits shifted out come back on the other side
+        computer.registers.a = Cell::Word(4);
+        let instruction = Instruction::Rol(ImmRegDirIndIdx::Imm(Imm(Word::MIN + 1)), Reg::A);
+        instruction.execute(&mut computer).unwrap();
+        assert_eq!(computer.registers.a, Cell::Word(0b11000));
+
+        computer.registers.a = Cell::Word(1);
+        let instruction = Instruction::Ror(ImmRegDirIndIdx::Imm(Imm(1)), Reg::A);
         instruction.execute(&mut computer).unwrap();
-        assert_eq!(computer.registers.a, 1);
-        assert!(!computer.registers.sr.contains(StatusRegister::OVERFLOW));
+        assert_eq!(computer.registers.a, Cell::Word(Word::MIN));
+        assert_eq!(computer.registers.flags(), "-N--");
+
+        // Rotating by the word size gives the value back
+        computer.registers.a = Cell::Word(64);
+        let instruction = Instruction::Rol(ImmRegDirIndIdx::Imm(Imm(42)), Reg::A);
+        instruction.execute(&mut computer).unwrap();
+        assert_eq!(computer.registers.a, Cell::Word(42));
+
+        computer.registers.a = Cell::Word(-1);
+        assert!(instruction.execute(&mut computer).is_err());
+    }
+
+    #[test]
+    fn operand_fault_test() {
+        let source = "main: ld -1, %b\n    jmp %b\n";
+        let program = crate::parse(source).unwrap();
+        let (mut computer, debug_info) = crate::compile(program.inner, "main").unwrap();
+        let error = computer.run().unwrap_err();
+        let ProcessorError::Fault { context, .. } = &error else {
+            panic!("expected a fault, got {error}");
+        };
+        assert_eq!(context.operand, Some(0));
+        assert!(error.to_string().contains("(jmp  %b) from operand 1"));
+
+        // Locations are relative to the instruction here
+        let location = &debug_info.operands[&context.address][0];
+        assert_eq!((location.offset(), location.length()), (4, 2));
+        assert_eq!(debug_info.operands[&C::PROGRAM_START].len(), 2);
+
+        // Once the labels are given, the address is named after them
+        let expected = format!("at address {} <main+1> (jmp  %b)", context.address);
+        let error = error.with_symbols(&debug_info.symbols());
+        assert!(error.to_string().contains(&expected));
     }
-    */
 }
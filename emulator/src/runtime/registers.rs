@@ -1,5 +1,6 @@
 use bitflags::bitflags;
 use parse_display::Display;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::{
@@ -7,20 +8,48 @@ use crate::{
     constants as C,
 };
 
-use super::memory::{Cell, CellError, TryFromCell};
+use super::arch::Arch;
+use super::arguments::AddressingMode;
+use super::memory::{parse_word, Cell, CellError, TryFromCell};
 
 bitflags! {
     #[derive(Default)]
     pub struct StatusRegister: C::Word {
+        /// The unsigned result did not fit in a word
         const CARRY            = 0b000_0000_0001;
+
+        /// The result is zero
         const ZERO             = 0b000_0000_0010;
+
+        /// The result is negative, as a signed word
         const NEGATIVE         = 0b000_0000_0100;
+
+        /// The signed result did not fit in a word
         const OVERFLOW         = 0b000_0000_1000;
+
         const INTERRUPT_ENABLE = 0b001_0000_0000;
         const SUPERVISOR       = 0b010_0000_0000;
     }
 }
 
+impl StatusRegister {
+    /// Update the arithmetic flags with the result of an ALU instruction
+    ///
+    /// All ALU instructions go through this, so the flags are always set the same way. `cmp`
+    /// updates them with the result of the substraction of its operands.
+    pub(crate) fn update(&mut self, result: C::Word, carry: bool, overflow: bool) {
+        self.set(Self::ZERO, result == 0);
+        self.set(Self::NEGATIVE, result < 0);
+        self.set(Self::CARRY, carry);
+        self.set(Self::OVERFLOW, overflow);
+    }
+
+    /// Check if the last comparison was strictly lower, as signed words
+    pub(crate) fn less(self) -> bool {
+        self.contains(Self::NEGATIVE) != self.contains(Self::OVERFLOW)
+    }
+}
+
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Registers {
     /// General purpose
@@ -51,6 +80,75 @@ impl Registers {
         }
     }
 
+    /// Check if the `CARRY` flag is set
+    #[must_use]
+    pub fn carry(&self) -> bool {
+        self.sr.contains(StatusRegister::CARRY)
+    }
+
+    /// Check if the `ZERO` flag is set
+    #[must_use]
+    pub fn zero(&self) -> bool {
+        self.sr.contains(StatusRegister::ZERO)
+    }
+
+    /// Check if the `NEGATIVE` flag is set
+    #[must_use]
+    pub fn negative(&self) -> bool {
+        self.sr.contains(StatusRegister::NEGATIVE)
+    }
+
+    /// Check if the `OVERFLOW` flag is set
+    #[must_use]
+    pub fn overflow(&self) -> bool {
+        self.sr.contains(StatusRegister::OVERFLOW)
+    }
+
+    /// The arithmetic flags, as `ZNCV`, with a `-` for each flag not set
+    #[must_use]
+    pub fn flags(&self) -> String {
+        [
+            ('Z', StatusRegister::ZERO),
+            ('N', StatusRegister::NEGATIVE),
+            ('C', StatusRegister::CARRY),
+            ('V', StatusRegister::OVERFLOW),
+        ]
+        .into_iter()
+        .map(|(name, flag)| if self.sr.contains(flag) { name } else { '-' })
+        .collect()
+    }
+
+    /// Set the registers given by an entry state
+    pub fn apply(&mut self, entry: &EntryState) {
+        if let Some(a) = entry.a {
+            self.a = Cell::Word(a);
+        }
+        if let Some(b) = entry.b {
+            self.b = Cell::Word(b);
+        }
+        if let Some(pc) = entry.pc {
+            self.pc = pc;
+        }
+        if let Some(sp) = entry.sp {
+            self.sp = sp;
+        }
+        if let Some(sr) = entry.sr {
+            self.sr = StatusRegister::from_bits_truncate(sr);
+        }
+        if let Some(flags) = entry.flags {
+            self.sr.set(StatusRegister::ZERO, flags.zero);
+            self.sr.set(StatusRegister::NEGATIVE, flags.negative);
+            self.sr.set(StatusRegister::CARRY, flags.carry);
+            self.sr.set(StatusRegister::OVERFLOW, flags.overflow);
+        }
+    }
+
+    /// Apply a function to the general purpose registers holding words
+    pub(crate) fn map_words(&mut self, f: impl Fn(C::Word) -> C::Word) {
+        self.a = std::mem::take(&mut self.a).map_word(&f);
+        self.b = std::mem::take(&mut self.b).map_word(&f);
+    }
+
     pub(crate) fn get_word(&self, reg: Reg) -> Result<C::Word, CellError> {
         match reg {
             Reg::A => self.a.extract_word(),
@@ -77,18 +175,48 @@ impl Registers {
     }
 }
 
+impl Registers {
+    /// Display the registers with the bits of their words at the width of the given machine
+    #[must_use]
+    pub fn display(&self, arch: Arch) -> RegistersDisplay<'_> {
+        RegistersDisplay {
+            registers: self,
+            arch,
+        }
+    }
+}
+
 impl std::fmt::Display for Registers {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.display(Arch::default()).fmt(f)
+    }
+}
+
+/// Registers displayed on a given machine, as returned by [`Registers::display`]
+pub struct RegistersDisplay<'a> {
+    registers: &'a Registers,
+    arch: Arch,
+}
+
+impl std::fmt::Display for RegistersDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let registers = self.registers;
         write!(
             f,
-            "%a = {} | %b = {} | %pc = {} | %sp = {} | %sr = {:?}",
-            self.a, self.b, self.pc, self.sp, self.sr
+            "%a = {} | %b = {} | %pc = {} | %sp = {} | %sr = {:?} | flags = {}",
+            registers.a.display(self.arch),
+            registers.b.display(self.arch),
+            registers.pc,
+            registers.sp,
+            registers.sr,
+            registers.flags()
         )
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
 #[display("%{}", style = "lowercase")]
+#[serde(rename_all = "lowercase")]
 pub enum Reg {
     /// General purpose
     A,
@@ -109,8 +237,7 @@ pub enum Reg {
 impl Reg {
     /// CPU cycles count to use this value
     pub(crate) const fn cost() -> usize {
-        // Accessing a register does not take any CPU cycle
-        0
+        AddressingMode::Register.cost()
     }
 }
 
@@ -124,6 +251,92 @@ impl<L> AstNode<L> for Reg {
     }
 }
 
+/// The arithmetic flags of the status register
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Flags {
+    pub zero: bool,
+    pub negative: bool,
+    pub carry: bool,
+    pub overflow: bool,
+}
+
+#[derive(Error, Debug)]
+#[error("invalid flags {0:?}, expected letters among ZNCV")]
+pub struct FlagsParseError(String);
+
+impl std::str::FromStr for Flags {
+    type Err = FlagsParseError;
+
+    /// Parse the letters of the set flags, like `ZC` or `Z-C-` as shown by [`Registers::flags`]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut flags = Self::default();
+        for c in s.trim().chars() {
+            match c.to_ascii_uppercase() {
+                'Z' => flags.zero = true,
+                'N' => flags.negative = true,
+                'C' => flags.carry = true,
+                'V' => flags.overflow = true,
+                '-' => {}
+                _ => return Err(FlagsParseError(s.to_owned())),
+            }
+        }
+        Ok(flags)
+    }
+}
+
+/// Values of the registers before running a program, `None` keeping the current value
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EntryState {
+    pub a: Option<C::Word>,
+    pub b: Option<C::Word>,
+    pub pc: Option<C::Address>,
+    pub sp: Option<C::Address>,
+    pub sr: Option<C::Word>,
+
+    /// Arithmetic flags, set after `sr`
+    pub flags: Option<Flags>,
+}
+
+impl EntryState {
+    /// Set the value of a register, checking that `%pc` and `%sp` get an address
+    pub fn set(&mut self, reg: Reg, value: C::Word) -> Result<(), CellError> {
+        let address = || C::Address::try_from_cell(&Cell::Word(value));
+        match reg {
+            Reg::A => self.a = Some(value),
+            Reg::B => self.b = Some(value),
+            Reg::PC => self.pc = Some(address()?),
+            Reg::SP => self.sp = Some(address()?),
+            Reg::SR => self.sr = Some(value),
+        }
+        Ok(())
+    }
+}
+
+/// A value given to a register, written like `%a=5`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterAssignment {
+    pub reg: Reg,
+    pub value: C::Word,
+}
+
+#[derive(Error, Debug)]
+#[error("invalid register assignment {0:?}, expected REG=VALUE")]
+pub struct AssignmentParseError(String);
+
+impl std::str::FromStr for RegisterAssignment {
+    type Err = AssignmentParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || AssignmentParseError(s.to_owned());
+        let (reg, value) = s.split_once('=').ok_or_else(error)?;
+        Ok(Self {
+            reg: reg.trim().parse().map_err(|_| error())?,
+            value: parse_word(value.trim()).ok_or_else(error)?,
+        })
+    }
+}
+
 #[derive(Error, Debug)]
 #[error("could not parse register")]
 pub struct RegisterParseError;
@@ -142,3 +355,30 @@ impl std::str::FromStr for Reg {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_state_test() {
+        let mut entry = EntryState {
+            flags: Some("Z-C-".parse().unwrap()),
+            ..EntryState::default()
+        };
+        for assignment in ["%a=5", "sp = 0x2000", "%sr=512"] {
+            let RegisterAssignment { reg, value } = assignment.parse().unwrap();
+            entry.set(reg, value).unwrap();
+        }
+        assert!(entry.set(Reg::PC, -1).is_err());
+        assert!("%a".parse::<RegisterAssignment>().is_err());
+        assert!("ZX".parse::<Flags>().is_err());
+
+        let mut registers = Registers::default();
+        registers.apply(&entry);
+        assert_eq!(registers.a, Cell::Word(5));
+        assert_eq!(registers.sp, 0x2000);
+        assert_eq!(registers.flags(), "Z-C-");
+        assert!(registers.sr.contains(StatusRegister::SUPERVISOR));
+    }
+}
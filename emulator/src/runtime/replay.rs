@@ -0,0 +1,166 @@
+//! Record and replay of hardware interrupts
+//!
+//! Interrupts coming from outside the computer (a timer, or the user injecting them) are the only
+//! source of non-determinism while running a program. Recording the step at which each one was
+//! delivered is enough to reproduce the exact same execution later on.
+//!
+//! The log is a text file, with one delivery per line: the step index followed by the source of
+//! the interrupt (e.g. `1234 timer`). Empty lines and lines starting with `#` are ignored.
+
+use parse_display::{Display, FromStr};
+use thiserror::Error;
+
+use super::{Computer, Exception};
+
+/// Where an interrupt came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, FromStr)]
+#[display(style = "lowercase")]
+pub enum InterruptSource {
+    /// A periodic timer
+    Timer,
+
+    /// An interrupt injected by the user
+    Keyboard,
+}
+
+/// A hardware interrupt delivered before executing a given step
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, FromStr)]
+#[display("{step} {source}")]
+pub struct Delivery {
+    pub step: usize,
+    pub source: InterruptSource,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid interrupt delivery on line {line}: {text:?}")]
+pub struct ReplayParseError {
+    line: usize,
+    text: String,
+}
+
+/// Ordered list of interrupt deliveries
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InterruptLog {
+    deliveries: Vec<Delivery>,
+    cursor: usize,
+}
+
+impl InterruptLog {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deliver an interrupt to the computer, and record it
+    pub fn deliver(
+        &mut self,
+        computer: &mut Computer,
+        source: InterruptSource,
+    ) -> Result<(), Exception> {
+        computer.recover_from_exception(&Exception::HardwareInterrupt)?;
+        self.deliveries.push(Delivery {
+            step: computer.steps,
+            source,
+        });
+        Ok(())
+    }
+
+    /// Deliver the recorded interrupts due before the next step of the computer
+    ///
+    /// Returns the number of interrupts delivered.
+    pub fn replay(&mut self, computer: &mut Computer) -> Result<usize, Exception> {
+        let start = self.cursor;
+        while let Some(delivery) = self.deliveries.get(self.cursor) {
+            if delivery.step > computer.steps {
+                break;
+            }
+
+            computer.recover_from_exception(&Exception::HardwareInterrupt)?;
+            self.cursor += 1;
+        }
+
+        Ok(self.cursor - start)
+    }
+
+    #[must_use]
+    pub fn deliveries(&self) -> &[Delivery] {
+        &self.deliveries
+    }
+}
+
+impl std::fmt::Display for InterruptLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for delivery in &self.deliveries {
+            writeln!(f, "{delivery}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for InterruptLog {
+    type Err = ReplayParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut deliveries: Vec<Delivery> = s
+            .lines()
+            .enumerate()
+            .map(|(index, text)| (index + 1, text.trim()))
+            .filter(|(_, text)| !text.is_empty() && !text.starts_with('#'))
+            .map(|(line, text)| {
+                text.parse().map_err(|_| ReplayParseError {
+                    line,
+                    text: text.to_owned(),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        // Deliveries are replayed in order, which should already be the case in recorded logs
+        deliveries.sort_by_key(|delivery| delivery.step);
+
+        Ok(Self {
+            deliveries,
+            cursor: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile, parse};
+
+    #[test]
+    fn record_replay_test() {
+        let source = "main: add 1, %a\n    jmp main\n.addr 200\nhandler: rti\n";
+        let program = parse(source).unwrap();
+        let (mut computer, _) = compile(program.inner.clone(), "main").unwrap();
+        let mut log = InterruptLog::new();
+        for step in 0..20 {
+            if step % 7 == 3 {
+                log.deliver(&mut computer, InterruptSource::Timer).unwrap();
+            }
+            computer.step().unwrap();
+        }
+
+        let text = log.to_string();
+        assert_eq!(text, "3 timer\n10 timer\n17 timer\n");
+
+        let mut replay: InterruptLog = text.parse().unwrap();
+        let (mut other, _) = compile(program.inner, "main").unwrap();
+        for _ in 0..20 {
+            replay.replay(&mut other).unwrap();
+            other.step().unwrap();
+        }
+
+        assert_eq!(computer.registers, other.registers);
+        assert_eq!(computer.cycles, other.cycles);
+    }
+
+    #[test]
+    fn parse_error_test() {
+        let err = "# comment\n\n12 timer\n13 mouse\n"
+            .parse::<InterruptLog>()
+            .unwrap_err();
+        assert_eq!(err.line, 4);
+    }
+}
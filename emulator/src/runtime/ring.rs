@@ -0,0 +1,99 @@
+//! Bounded buffers of the last items produced while a program runs, like executed instructions
+//!
+//! Long runs would otherwise keep every item: once the buffer is full, the oldest ones are dropped
+//! and only counted.
+
+use std::collections::VecDeque;
+
+use serde::Serialize;
+
+/// The last items pushed, up to a given capacity
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RingBuffer<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+
+    /// Number of items dropped to make room for newer ones
+    dropped: usize,
+}
+
+impl<T> RingBuffer<T> {
+    /// An empty buffer keeping at most `capacity` items. A capacity of 0 only counts the items
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            // Do not allocate a huge buffer upfront for a generous capacity
+            items: VecDeque::with_capacity(capacity.min(1024)),
+            capacity,
+            dropped: 0,
+        }
+    }
+
+    /// Add an item, dropping the oldest one if the buffer is full
+    pub fn push(&mut self, item: T) {
+        if self.capacity == 0 {
+            self.dropped += 1;
+            return;
+        }
+
+        if self.items.len() == self.capacity {
+            self.items.pop_front();
+            self.dropped += 1;
+        }
+        self.items.push_back(item);
+    }
+
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of items dropped since the buffer was created
+    #[must_use]
+    pub const fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// The items kept, from the oldest to the newest
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.items.iter()
+    }
+
+    /// The items kept, from the oldest to the newest, and the number of dropped ones
+    #[must_use]
+    pub fn into_parts(self) -> (Vec<T>, usize) {
+        (self.items.into(), self.dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_buffer_test() {
+        let mut buffer = RingBuffer::new(3);
+        for i in 0..5 {
+            buffer.push(i);
+        }
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.dropped(), 2);
+        assert!(buffer.iter().copied().eq([2, 3, 4]));
+        assert_eq!(buffer.into_parts(), (vec![2, 3, 4], 2));
+
+        let mut counter = RingBuffer::new(0);
+        counter.push("a");
+        assert!(counter.is_empty());
+        assert_eq!(counter.dropped(), 1);
+    }
+}
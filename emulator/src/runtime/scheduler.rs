@@ -0,0 +1,208 @@
+//! Metrics of the programs scheduling tasks with interrupts
+//!
+//! Two things are measured, in cycles:
+//!
+//!  - the latency of the hardware interrupts, from the moment a device raises one to the entry in
+//!    the handler. It grows while the interrupts are disabled
+//!  - the time given to each process. The scheduler marks a context switch by executing the
+//!    instruction at a given address, with the identifier of the process it switches to in `%a`.
+//!    A process runs from one switch to the next one, the scheduler included
+//!
+//! The time slices of the processes make a timeline, which can be exported for a Gantt chart.
+
+use std::fmt::{self, Write};
+
+use crate::constants::{Address, Word};
+
+use super::{Computer, Exception, Reg};
+
+/// A process running without being switched out, between two cycle counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slice {
+    /// Identifier of the process, as given in `%a` when switching to it
+    pub process: Word,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Watches the execution of a program to measure its interrupt latencies and the time of its
+/// processes
+#[derive(Debug, Default)]
+pub struct SchedulerMetrics {
+    /// Address of the instruction marking a context switch, if processes are tracked
+    switch: Option<Address>,
+
+    /// Cycle count when the pending interrupt was first seen
+    raised: Option<usize>,
+    depth: usize,
+    latencies: Vec<usize>,
+
+    /// The running process, and the cycle count since which it runs
+    current: Option<(Word, usize)>,
+    slices: Vec<Slice>,
+}
+
+impl SchedulerMetrics {
+    /// Measure the interrupt latencies, and the time of the processes if the address of the
+    /// context switches is given
+    #[must_use]
+    pub fn new(switch: Option<Address>) -> Self {
+        Self {
+            switch,
+            ..Self::default()
+        }
+    }
+
+    /// Check the state of the computer, after each step
+    pub fn check(&mut self, computer: &Computer) {
+        let cycles = computer.cycles;
+        let depth = computer.interrupt_depth();
+        let entered = depth > self.depth
+            && computer.handlers.last().is_some_and(|handler| {
+                handler.code == Exception::HardwareInterrupt.code() && !handler.fault
+            });
+        self.depth = depth;
+
+        if entered {
+            // Interrupts delivered from outside the computer are never pending
+            let raised = self.raised.take().unwrap_or(cycles);
+            self.latencies.push(cycles - raised);
+        } else if !computer.devices.interrupt() {
+            // Acknowledged by polling the device, without entering the handler
+            self.raised = None;
+        } else if self.raised.is_none() {
+            self.raised = Some(cycles);
+        }
+
+        if self.switch == Some(computer.registers.pc) {
+            let process = computer
+                .registers
+                .get(&Reg::A)
+                .extract_word()
+                .unwrap_or_default();
+            self.close(cycles);
+            self.current = Some((process, cycles));
+        }
+    }
+
+    /// End the time slice of the running process
+    fn close(&mut self, end: usize) {
+        if let Some((process, start)) = self.current.take() {
+            if end > start {
+                self.slices.push(Slice {
+                    process,
+                    start,
+                    end,
+                });
+            }
+        }
+    }
+
+    /// Stop measuring, once the program ended
+    #[must_use]
+    pub fn finish(mut self, computer: &Computer) -> SchedulerReport {
+        self.close(computer.cycles);
+        SchedulerReport {
+            latencies: self.latencies,
+            slices: self.slices,
+        }
+    }
+}
+
+/// What the [`SchedulerMetrics`] measured, shown as a summary
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchedulerReport {
+    /// Latency of each handled hardware interrupt, in order
+    pub latencies: Vec<usize>,
+
+    /// Time slices of the processes, in order
+    pub slices: Vec<Slice>,
+}
+
+impl SchedulerReport {
+    /// Total time of each process, with its number of slices, by identifier
+    #[must_use]
+    pub fn processes(&self) -> Vec<(Word, usize, usize)> {
+        let mut processes: Vec<(Word, usize, usize)> = Vec::new();
+        for slice in &self.slices {
+            let cycles = slice.end - slice.start;
+            match processes.iter_mut().find(|(id, _, _)| *id == slice.process) {
+                Some((_, total, count)) => {
+                    *total += cycles;
+                    *count += 1;
+                }
+                None => processes.push((slice.process, cycles, 1)),
+            }
+        }
+        processes.sort_unstable_by_key(|(id, _, _)| *id);
+        processes
+    }
+
+    /// The timeline, as CSV with one `process,start,end` line per slice
+    #[must_use]
+    pub fn gantt(&self) -> String {
+        let mut csv = String::from("process,start,end\n");
+        for slice in &self.slices {
+            // Writing to a string cannot fail
+            let _ = writeln!(csv, "{},{},{}", slice.process, slice.start, slice.end);
+        }
+        csv
+    }
+}
+
+impl fmt::Display for SchedulerReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Interrupts handled: {}", self.latencies.len())?;
+        if let (Some(min), Some(max)) = (self.latencies.iter().min(), self.latencies.iter().max()) {
+            let total: usize = self.latencies.iter().sum();
+            #[allow(clippy::cast_precision_loss)]
+            let mean = total as f64 / self.latencies.len() as f64;
+            write!(f, ", latency min {min} / mean {mean:.1} / max {max} cycles")?;
+        }
+        writeln!(f)?;
+
+        for (process, cycles, count) in self.processes() {
+            writeln!(f, "Process {process}: {cycles} cycles in {count} slices")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples::example;
+
+    #[test]
+    fn scheduler_metrics_test() {
+        let mut emulator = example("scheduler").unwrap().builder().build().unwrap();
+        let switch = emulator.label("switch");
+        let (sp1, sp2) = (emulator.label("sp1"), emulator.label("sp2"));
+        let mut metrics = SchedulerMetrics::new(switch);
+        while emulator.step().is_ok() {
+            metrics.check(emulator.computer());
+        }
+
+        let report = metrics.finish(emulator.computer());
+        assert!(!report.latencies.is_empty());
+        // The timer interrupts are handled on the next step, since the handler is short
+        assert!(report.latencies.iter().all(|&latency| latency == 1));
+
+        // Each switch goes to the other task, for the whole period of the timer
+        let processes = report.processes();
+        let ids: Vec<Word> = processes.iter().map(|(id, _, _)| *id).collect();
+        let mut expected = [sp1, sp2].map(|sp| Word::from(sp.unwrap()));
+        expected.sort_unstable();
+        assert_eq!(ids, expected);
+        assert!(report
+            .slices
+            .windows(2)
+            .all(|pair| pair[0].process != pair[1].process && pair[0].end == pair[1].start));
+        assert_eq!(report.slices.len(), report.latencies.len());
+
+        let gantt = report.gantt();
+        assert!(gantt.starts_with("process,start,end\n"));
+        assert_eq!(gantt.lines().count(), report.slices.len() + 1);
+        assert!(report.to_string().starts_with("Interrupts handled: "));
+    }
+}
@@ -0,0 +1,134 @@
+//! Search of values, strings and instructions in the memory
+//!
+//! Only the cells written at least once are searched, in increasing address order.
+
+use crate::constants::{Address, Word};
+
+use super::{Cell, Computer};
+
+/// What to look for in the memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchPattern {
+    /// A cell holding this word
+    Word(Word),
+
+    /// Consecutive cells holding the characters of this string, as characters or as their codes
+    String(String),
+
+    /// An instruction, like `ld *, %a`: each operand is compared without its spaces, `*` matches
+    /// any operand, and the operands left out match anything
+    Instruction(String),
+}
+
+/// An instruction, split into its mnemonic and its operands without spaces
+fn split_instruction(text: &str) -> (String, Vec<String>) {
+    let text = text.trim();
+    let (mnemonic, operands) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let operands = operands
+        .split(',')
+        .map(|operand| operand.split_whitespace().collect::<String>())
+        .filter(|operand| !operand.is_empty())
+        .collect();
+    (mnemonic.to_lowercase(), operands)
+}
+
+/// The character held by a cell, if any
+fn cell_char(cell: &Cell) -> Option<char> {
+    match cell {
+        Cell::Char(c) => Some(*c),
+        Cell::Word(word) => u32::try_from(*word).ok().and_then(char::from_u32),
+        _ => None,
+    }
+}
+
+impl Computer {
+    /// Find the addresses of the cells matching a pattern, the first cell of the string for
+    /// strings
+    #[must_use]
+    pub fn search(&self, pattern: &SearchPattern) -> Vec<Address> {
+        match pattern {
+            SearchPattern::Word(word) => self
+                .memory
+                .occupied()
+                .filter(|(_, cell)| **cell == Cell::Word(*word))
+                .map(|(address, _)| address)
+                .collect(),
+
+            SearchPattern::String(string) => {
+                let chars: Vec<char> = string.chars().collect();
+                let Some(&first) = chars.first() else {
+                    return Vec::new();
+                };
+                self.memory
+                    .occupied()
+                    .filter(|(_, cell)| cell_char(cell) == Some(first))
+                    .map(|(address, _)| address)
+                    .filter(|&address| {
+                        chars.iter().zip(address..).all(|(c, address)| {
+                            self.memory.get(address).ok().and_then(cell_char) == Some(*c)
+                        })
+                    })
+                    .collect()
+            }
+
+            SearchPattern::Instruction(pattern) => {
+                let (mnemonic, operands) = split_instruction(pattern);
+                self.memory
+                    .occupied()
+                    .filter(|(_, cell)| {
+                        let Ok(instruction) = cell.extract_instruction() else {
+                            return false;
+                        };
+                        let (found, found_operands) = split_instruction(&instruction.to_string());
+                        found == mnemonic
+                            && operands.len() <= found_operands.len()
+                            && operands
+                                .iter()
+                                .zip(&found_operands)
+                                .all(|(operand, found)| operand == "*" || operand == found)
+                    })
+                    .map(|(address, _)| address)
+                    .collect()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile, parse};
+
+    #[test]
+    fn search_test() {
+        let source = "main: ld [text], %a\n    ld 42, %b\n    st %b, [%sp + 1]\n    reset\n\
+                      text: .string \"lost\"\nvalue: .word 42\ncodes: .word 108\n    .word 111\n";
+        let program = parse(source).unwrap();
+        let (computer, debug_info) = compile(program.inner, "main").unwrap();
+        let label = |name: &str| debug_info.labels[name];
+        let search = |pattern| computer.search(&pattern);
+
+        assert_eq!(search(SearchPattern::Word(42)), [label("value")]);
+        assert!(search(SearchPattern::Word(7)).is_empty());
+
+        // Words holding the codes of the characters match too
+        assert_eq!(
+            search(SearchPattern::String("lo".to_owned())),
+            [label("text"), label("codes")]
+        );
+        assert_eq!(
+            search(SearchPattern::String("lost".to_owned())),
+            [label("text")]
+        );
+        assert!(search(SearchPattern::String(String::new())).is_empty());
+
+        let main = label("main");
+        let instruction = |pattern: &str| search(SearchPattern::Instruction(pattern.to_owned()));
+        assert_eq!(instruction("ld"), [main, main + 1]);
+        assert_eq!(instruction("LD *, %b"), [main + 1]);
+        assert_eq!(instruction("ld 42"), [main + 1]);
+        assert_eq!(instruction("st %b, [%sp+1]"), [main + 2]);
+        assert_eq!(instruction("reset"), [main + 3]);
+        assert!(instruction("ld *, *, *").is_empty());
+    }
+}
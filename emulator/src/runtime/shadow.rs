@@ -0,0 +1,138 @@
+//! Shadow memory, checking the accesses of the program to the memory
+//!
+//! Each cell placed by the program is tagged as code or data. When the shadow memory is enabled,
+//! the instructions reading or writing the memory stop the processor if they access code as
+//! data, or a cell which belongs to nothing: neither the program, the stack, the interrupt
+//! vectors, nor a block allocated on the heap.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::constants::{self as C, Address};
+
+use super::{Computer, HeapMonitor};
+
+/// An access to the memory rejected by the [`Shadow`] memory
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ShadowViolation {
+    #[error("read the instruction at address {address} as data")]
+    ReadCode { address: Address },
+
+    #[error("overwrote the instruction at address {address}")]
+    WriteCode { address: Address },
+
+    #[error("read the unallocated address {address}")]
+    ReadUnallocated { address: Address },
+
+    #[error("wrote to the unallocated address {address}")]
+    WriteUnallocated { address: Address },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tag {
+    Code,
+    Data,
+}
+
+/// Tags of the memory cells, checked on each access
+#[derive(Debug, Clone, Default)]
+pub struct Shadow {
+    tags: HashMap<Address, Tag>,
+    heap: Option<HeapMonitor>,
+}
+
+impl Shadow {
+    /// Tag the cells placed by the program, along with the ones already written in the memory
+    #[must_use]
+    pub fn new(computer: &Computer, placed: impl IntoIterator<Item = Address>) -> Self {
+        let memory = &computer.memory;
+        let tags = placed
+            .into_iter()
+            .chain(memory.occupied().map(|(address, _)| address))
+            .map(|address| {
+                let code = memory
+                    .get(address)
+                    .is_ok_and(|cell| cell.extract_instruction().is_ok());
+                (address, if code { Tag::Code } else { Tag::Data })
+            })
+            .collect();
+
+        Self { tags, heap: None }
+    }
+
+    /// Allow the accesses to the blocks allocated on a heap
+    #[must_use]
+    pub fn with_heap(self, heap: HeapMonitor) -> Self {
+        Self {
+            heap: Some(heap),
+            ..self
+        }
+    }
+
+    pub(crate) fn check(
+        &self,
+        address: Address,
+        write: bool,
+        sp: Address,
+    ) -> Result<(), ShadowViolation> {
+        match self.tags.get(&address) {
+            Some(Tag::Code) if write => Err(ShadowViolation::WriteCode { address }),
+            Some(Tag::Code) => Err(ShadowViolation::ReadCode { address }),
+            Some(Tag::Data) => Ok(()),
+            None if (sp..C::STACK_START).contains(&address)
+                || (C::INTERRUPT_PC_SAVE..=C::INTERRUPT_EXCEPTION).contains(&address)
+                || self
+                    .heap
+                    .as_ref()
+                    .is_some_and(|heap| heap.contains(address)) =>
+            {
+                Ok(())
+            }
+            None if write => Err(ShadowViolation::WriteUnallocated { address }),
+            None => Err(ShadowViolation::ReadUnallocated { address }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::ProcessorError;
+    use crate::{compile, parse};
+
+    fn run(source: &str) -> Result<(), ProcessorError> {
+        let program = parse(source).unwrap();
+        let (mut computer, debug_info) = compile(program.inner, "main").unwrap();
+        let shadow = Shadow::new(&computer, debug_info.locations.keys().copied());
+        computer.set_shadow(shadow);
+        computer.run()
+    }
+
+    #[test]
+    fn shadow_test() {
+        run("main: ld 3, %a\n    st %a, [buf+1]\n    push %a\n    pop %b\n    reset\nbuf: .space 2\n")
+            .unwrap();
+
+        let start = crate::constants::PROGRAM_START;
+        let error = run("main: ld [main], %a\n    reset\n").unwrap_err();
+        assert_eq!(
+            error.cause().to_string(),
+            ShadowViolation::ReadCode { address: start }.to_string()
+        );
+
+        // Writing one cell past the end of a buffer
+        let error =
+            run("main: ld 3, %a\n    st %a, [buf+2]\n    reset\nbuf: .space 2\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "wrote to the unallocated address {} at address {} (st   %a, [{}]) from operand 2 \
+                 in user mode",
+                start + 5,
+                start + 1,
+                start + 5
+            )
+        );
+    }
+}
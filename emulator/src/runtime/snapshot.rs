@@ -0,0 +1,359 @@
+//! Snapshots of the state of a computer, to save it and restore it later
+//!
+//! A snapshot holds the registers, the cells written in the memory and the interrupt handlers
+//! being executed. The devices attached to the computer and its limits are not part of it: they
+//! are kept as they are when restoring a snapshot.
+//!
+//! Two snapshots can also be compared, to list the registers and memory cells which changed
+//! between them.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::constants::{Address, Char, Word};
+
+use super::instructions::Instruction;
+use super::registers::StatusRegister;
+use super::{Cell, Computer, HandlerFrame, Memory, Reg, Registers};
+
+/// Version of the snapshot format
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// A memory cell or register, with instructions kept whole so they can be restored
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+enum SavedCell {
+    Instruction(Instruction),
+    Word(Word),
+    Char(Char),
+    Empty,
+}
+
+impl From<&Cell> for SavedCell {
+    fn from(cell: &Cell) -> Self {
+        match cell {
+            Cell::Instruction(instruction) => Self::Instruction(instruction.as_ref().clone()),
+            Cell::Word(word) => Self::Word(*word),
+            Cell::Char(c) => Self::Char(*c),
+            Cell::Empty => Self::Empty,
+        }
+    }
+}
+
+impl From<SavedCell> for Cell {
+    fn from(cell: SavedCell) -> Self {
+        match cell {
+            SavedCell::Instruction(instruction) => Self::Instruction(Box::new(instruction)),
+            SavedCell::Word(word) => Self::Word(word),
+            SavedCell::Char(c) => Self::Char(c),
+            SavedCell::Empty => Self::Empty,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct SavedHandler {
+    fault: bool,
+    code: Word,
+    outer: Option<[SavedCell; 3]>,
+}
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("unsupported snapshot version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("invalid status register {0:#x}")]
+    InvalidStatus(Word),
+
+    #[error("the snapshot has {saved} memory cells, but this computer only {size}")]
+    MemorySize { saved: usize, size: usize },
+}
+
+/// The state of a computer at some point of its execution
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    a: SavedCell,
+    b: SavedCell,
+    pc: Address,
+    sp: Address,
+    sr: Word,
+    cycles: usize,
+    steps: usize,
+    memory_size: usize,
+
+    /// The cells written at least once, by address
+    memory: Vec<(Address, SavedCell)>,
+    handlers: Vec<SavedHandler>,
+}
+
+impl Snapshot {
+    /// Capture the current state of a computer
+    #[must_use]
+    pub fn capture(computer: &Computer) -> Self {
+        let registers = &computer.registers;
+        Self {
+            version: SNAPSHOT_VERSION,
+            a: (&registers.a).into(),
+            b: (&registers.b).into(),
+            pc: registers.pc,
+            sp: registers.sp,
+            sr: registers.sr.bits(),
+            cycles: computer.cycles,
+            steps: computer.steps,
+            memory_size: computer.memory.size(),
+            memory: computer
+                .memory
+                .occupied()
+                .map(|(address, cell)| (address, cell.into()))
+                .collect(),
+            handlers: computer
+                .handlers
+                .iter()
+                .map(|handler| SavedHandler {
+                    fault: handler.fault,
+                    code: handler.code,
+                    outer: handler
+                        .outer
+                        .as_ref()
+                        .map(|cells| cells.each_ref().map(SavedCell::from)),
+                })
+                .collect(),
+        }
+    }
+
+    /// Put a computer back in the captured state
+    ///
+    /// # Errors
+    ///
+    /// Fails if the snapshot was made by another version of the emulator, or on a computer with
+    /// a bigger memory. The computer is left untouched in that case.
+    pub fn restore(self, computer: &mut Computer) -> Result<(), SnapshotError> {
+        if self.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(self.version));
+        }
+
+        let size = computer.memory.size();
+        if self.memory_size > size {
+            return Err(SnapshotError::MemorySize {
+                saved: self.memory_size,
+                size,
+            });
+        }
+
+        let sr = StatusRegister::from_bits(self.sr).ok_or(SnapshotError::InvalidStatus(self.sr))?;
+
+        let mut memory = Memory::new(size);
+        for (address, cell) in self.memory {
+            // The addresses fit in the memory, since it is at least as big as the saved one
+            if let Ok(target) = memory.get_mut(address) {
+                *target = cell.into();
+            }
+        }
+
+        computer.memory = memory;
+        computer.registers = Registers {
+            a: self.a.into(),
+            b: self.b.into(),
+            pc: self.pc,
+            sp: self.sp,
+            sr,
+        };
+        computer.cycles = self.cycles;
+        computer.steps = self.steps;
+        computer.handlers = self
+            .handlers
+            .into_iter()
+            .map(|handler| HandlerFrame {
+                fault: handler.fault,
+                code: handler.code,
+                outer: handler.outer.map(|cells| cells.map(Cell::from)),
+            })
+            .collect();
+
+        Ok(())
+    }
+
+    /// Value of a register in the captured state
+    fn register(&self, reg: Reg) -> Cell {
+        match reg {
+            Reg::A => self.a.clone().into(),
+            Reg::B => self.b.clone().into(),
+            Reg::PC => self.pc.into(),
+            Reg::SP => self.sp.into(),
+            Reg::SR => self.sr.into(),
+        }
+    }
+
+    /// List the registers and memory cells which differ from another snapshot, taken later
+    #[must_use]
+    pub fn diff(&self, other: &Self) -> StateDiff {
+        let registers = [Reg::A, Reg::B, Reg::PC, Reg::SP, Reg::SR]
+            .into_iter()
+            .filter_map(|reg| {
+                let (old, new) = (self.register(reg), other.register(reg));
+                (old != new).then_some(RegisterChange { reg, old, new })
+            })
+            .collect();
+
+        // Both lists of cells are sorted by address, cells missing on one side being empty
+        let mut memory = Vec::new();
+        let (mut before, mut after) = (
+            self.memory.iter().peekable(),
+            other.memory.iter().peekable(),
+        );
+        loop {
+            let (address, old, new) = match (before.peek(), after.peek()) {
+                (None, None) => break,
+                (Some((address, _)), None) => (*address, before.next(), None),
+                (None, Some((address, _))) => (*address, None, after.next()),
+                (Some((old, _)), Some((new, _))) => match old.cmp(new) {
+                    Ordering::Less => (*old, before.next(), None),
+                    Ordering::Greater => (*new, None, after.next()),
+                    Ordering::Equal => (*old, before.next(), after.next()),
+                },
+            };
+
+            let cell = |saved: Option<&(Address, SavedCell)>| {
+                saved.map_or(Cell::Empty, |(_, cell)| cell.clone().into())
+            };
+            let (old, new) = (cell(old), cell(new));
+            if old != new {
+                memory.push(CellChange { address, old, new });
+            }
+        }
+
+        StateDiff { registers, memory }
+    }
+}
+
+/// A register which changed between two states
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RegisterChange {
+    pub reg: Reg,
+    pub old: Cell,
+    pub new: Cell,
+}
+
+/// A memory cell which changed between two states
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CellChange {
+    pub address: Address,
+    pub old: Cell,
+    pub new: Cell,
+}
+
+/// Differences between two states of a computer, as computed by [`Snapshot::diff`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct StateDiff {
+    pub registers: Vec<RegisterChange>,
+
+    /// The changed cells, by address
+    pub memory: Vec<CellChange>,
+}
+
+impl StateDiff {
+    /// Compare the current states of two computers
+    #[must_use]
+    pub fn between(before: &Computer, after: &Computer) -> Self {
+        Snapshot::capture(before).diff(&Snapshot::capture(after))
+    }
+
+    /// Check if both states are the same
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty() && self.memory.is_empty()
+    }
+
+    /// Addresses of the changed memory cells
+    pub fn addresses(&self) -> impl Iterator<Item = Address> + '_ {
+        self.memory.iter().map(|change| change.address)
+    }
+}
+
+impl fmt::Display for StateDiff {
+    /// One change per line, like `%a: 0 -> 5 (0x5)` or `[1000]: 'a' -> 'b'`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.registers {
+            writeln!(f, "{}: {} -> {}", change.reg, change.old, change.new)?;
+        }
+        for change in &self.memory {
+            writeln!(f, "[{}]: {} -> {}", change.address, change.old, change.new)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile, parse};
+
+    #[test]
+    fn snapshot_test() {
+        let source = "main: ld 5, %b\nloop: push %b\n    sub 1, %b\n    cmp 0, %b\n    \
+                      jne loop\n    reset\n";
+        let program = parse(source).unwrap();
+        let (mut computer, _) = compile(program.inner, "main").unwrap();
+        for _ in 0..7 {
+            computer.step().unwrap();
+        }
+
+        let json = serde_json::to_string(&Snapshot::capture(&computer)).unwrap();
+        let snapshot: Snapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(snapshot, Snapshot::capture(&computer));
+
+        let mut restored = Computer::default();
+        snapshot.restore(&mut restored).unwrap();
+        assert_eq!(restored.registers, computer.registers);
+        assert_eq!(restored.cycles, computer.cycles);
+
+        // Both computers go on the same way
+        computer.run().unwrap();
+        restored.run().unwrap();
+        assert_eq!(restored.registers, computer.registers);
+        assert_eq!(restored.steps, computer.steps);
+        assert!(restored.memory.occupied().eq(computer.memory.occupied()));
+    }
+
+    #[test]
+    fn diff_test() {
+        let source =
+            "main: ld 2, %b\n    push %b\n    st %b, [result]\n    reset\nresult: .word 0\n";
+        let program = parse(source).unwrap();
+        let (mut computer, debug_info) = compile(program.inner, "main").unwrap();
+        let before = computer.clone();
+        assert!(StateDiff::between(&before, &computer).is_empty());
+
+        computer.run().unwrap();
+        let diff = StateDiff::between(&before, &computer);
+        let result = debug_info.labels["result"];
+        let sp = before.registers.sp;
+        assert_eq!(
+            diff.registers
+                .iter()
+                .map(|change| change.reg)
+                .collect::<Vec<_>>(),
+            [Reg::B, Reg::PC, Reg::SP]
+        );
+        assert_eq!(diff.addresses().collect::<Vec<_>>(), [result, sp - 1]);
+        assert_eq!(
+            diff.memory[1],
+            CellChange {
+                address: sp - 1,
+                old: Cell::Empty,
+                new: Cell::Word(2)
+            }
+        );
+        assert!(diff.to_string().starts_with("%b: 0 -> 2 (0x2)\n"));
+
+        // The other way around, the old and new values are swapped
+        let reverse = StateDiff::between(&computer, &before);
+        assert_eq!(reverse.memory[1].old, Cell::Word(2));
+        assert_eq!(reverse.memory[1].new, Cell::Empty);
+    }
+}
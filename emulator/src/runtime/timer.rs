@@ -0,0 +1,72 @@
+//! A programmable timer device
+//!
+//! The timer counts CPU cycles, and expires each time a full period elapsed. It requests a
+//! hardware interrupt while it has unacknowledged expirations. It has a single port:
+//!
+//!  - reading gives the number of expirations since the last read, and acknowledges them
+//!  - writing sets a new period, in cycles, and restarts the timer. A period of `0` stops it
+
+use std::hash::{Hash, Hasher};
+
+use crate::constants::{Address, Word};
+
+use super::{Cell, Device, WouldBlock};
+
+#[derive(Debug, Clone)]
+pub struct Timer {
+    period: usize,
+    elapsed: usize,
+    expired: usize,
+}
+
+impl Timer {
+    /// Create a timer expiring every `period` cycles
+    #[must_use]
+    pub const fn new(period: usize) -> Self {
+        Self {
+            period,
+            elapsed: 0,
+            expired: 0,
+        }
+    }
+}
+
+impl Device for Timer {
+    fn ports(&self) -> Address {
+        1
+    }
+
+    fn read(&mut self, _port: Address) -> Result<Cell, WouldBlock> {
+        let expired = std::mem::take(&mut self.expired);
+        Ok(Cell::Word(Word::try_from(expired).unwrap_or(Word::MAX)))
+    }
+
+    fn write(&mut self, _port: Address, value: Cell) -> Result<(), WouldBlock> {
+        self.period = match value {
+            Cell::Word(period) => usize::try_from(period).unwrap_or(0),
+            _ => 0,
+        };
+        self.elapsed = 0;
+        self.expired = 0;
+        Ok(())
+    }
+
+    fn interrupt(&self) -> bool {
+        self.expired > 0
+    }
+
+    fn tick(&mut self, cycles: usize) {
+        if self.period == 0 {
+            return;
+        }
+
+        self.elapsed += cycles;
+        self.expired += self.elapsed / self.period;
+        self.elapsed %= self.period;
+    }
+
+    fn hash_state(&self, mut state: &mut dyn Hasher) -> bool {
+        (self.period, self.elapsed, self.expired).hash(&mut state);
+        true
+    }
+}
@@ -0,0 +1,239 @@
+//! Detection of programs stuck in an infinite loop
+//!
+//! The state of the computer (its registers, the memory and the attached devices) is hashed after
+//! each step. If the exact same state comes back, the program will loop forever, since the
+//! execution is deterministic. Cycles are found with Brent's algorithm, which only keeps a single
+//! saved state.
+//!
+//! Devices also changed by the host, like mailboxes, make the execution depend on something else
+//! than the computer, and turn the detection off.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+
+use crate::constants::Address;
+
+use super::{Cell, Computer};
+
+/// Number of executed addresses kept to show the body of a loop
+const HISTORY: usize = 256;
+
+/// An infinite loop found by the [`LoopDetector`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InfiniteLoop {
+    /// Address at which the state repeated
+    pub address: Address,
+
+    /// Number of steps executed before detecting the loop
+    pub steps: usize,
+
+    /// Number of steps in one iteration of the loop
+    pub period: usize,
+
+    /// Addresses of the instructions in the loop, in execution order
+    pub body: Vec<Address>,
+}
+
+impl std::fmt::Display for InfiniteLoop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "infinite loop detected at address {} after {} steps",
+            self.address, self.steps
+        )
+    }
+}
+
+fn hash_cell<H: Hasher>(cell: &Cell, state: &mut H) {
+    match cell {
+        Cell::Empty => 0u8.hash(state),
+        Cell::Word(word) => {
+            1u8.hash(state);
+            word.hash(state);
+        }
+        Cell::Char(c) => {
+            2u8.hash(state);
+            c.hash(state);
+        }
+        Cell::Instruction(instruction) => {
+            3u8.hash(state);
+            instruction.hash(state);
+        }
+    }
+}
+
+/// Hash the state of the computer, or `None` if one of its devices can not be hashed
+fn hash_state(computer: &Computer) -> Option<u64> {
+    let mut state = DefaultHasher::new();
+    let registers = &computer.registers;
+    hash_cell(&registers.a, &mut state);
+    hash_cell(&registers.b, &mut state);
+    registers.pc.hash(&mut state);
+    registers.sp.hash(&mut state);
+    registers.sr.bits().hash(&mut state);
+
+    for (address, cell) in computer.memory.occupied() {
+        address.hash(&mut state);
+        hash_cell(cell, &mut state);
+    }
+
+    computer
+        .devices
+        .hash_state(&mut state)
+        .then(|| state.finish())
+}
+
+/// Watches the execution of a program to detect when it loops forever
+#[derive(Debug)]
+pub struct LoopDetector {
+    steps: usize,
+    power: usize,
+    saved: Option<(u64, usize)>,
+    history: VecDeque<Address>,
+}
+
+impl Default for LoopDetector {
+    fn default() -> Self {
+        Self {
+            steps: 0,
+            power: 1,
+            saved: None,
+            history: VecDeque::with_capacity(HISTORY),
+        }
+    }
+}
+
+impl LoopDetector {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check if the loops of a computer can be detected, which is not the case when one of its
+    /// devices is also changed by the host
+    #[must_use]
+    pub fn supports(computer: &Computer) -> bool {
+        computer.devices.hash_state(&mut DefaultHasher::new())
+    }
+
+    /// Check the state of the computer, after each step
+    pub fn check(&mut self, computer: &Computer) -> Option<InfiniteLoop> {
+        self.steps += 1;
+        let address = computer.registers.pc;
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(address);
+
+        let hash = hash_state(computer)?;
+        if let Some((saved, step)) = self.saved {
+            if saved == hash {
+                let period = self.steps - step;
+                let mut body: Vec<Address> = Vec::new();
+                // Instructions executed since the saved state, starting at the repeated address
+                for &address in self.history.iter().rev().skip(1).take(period).rev() {
+                    if !body.contains(&address) {
+                        body.push(address);
+                    }
+                }
+
+                return Some(InfiniteLoop {
+                    address,
+                    steps: self.steps,
+                    period,
+                    body,
+                });
+            }
+        }
+
+        // Move the saved state forward, with increasing distances
+        if self.steps == self.power {
+            self.saved = Some((hash, self.steps));
+            self.power *= 2;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::PROGRAM_START;
+    use crate::runtime::{mailbox, Instruction, Reg, StatusRegister, Timer};
+    use crate::{compile, parse};
+
+    fn computer(source: &str) -> Computer {
+        let program = parse(source).unwrap();
+        compile(program.inner, "main").unwrap().0
+    }
+
+    fn detect(source: &str) -> Option<InfiniteLoop> {
+        detect_on(computer(source))
+    }
+
+    fn detect_on(mut computer: Computer) -> Option<InfiniteLoop> {
+        let mut detector = LoopDetector::new();
+        for _ in 0..10_000 {
+            if computer.step().is_err() {
+                return None;
+            }
+            if let Some(found) = detector.check(&computer) {
+                return Some(found);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn detect_loop_test() {
+        let found = detect(
+            "main: ld 5, %a\nloop: ld [value], %b\n    st %b, [value]\n    jmp loop\nvalue: .word 1\n",
+        )
+        .unwrap();
+        assert_eq!(found.period, 3);
+        let start = PROGRAM_START;
+        assert_eq!(found.address, start + 1);
+        assert_eq!(found.body, vec![start + 1, start + 2, start + 3]);
+    }
+
+    #[test]
+    fn terminating_program_test() {
+        assert_eq!(
+            detect("main: ld 100, %a\nloop: sub 1, %a\n    cmp 0, %a\n    jne loop\n    reset\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn polling_device_test() {
+        // The counter of the timer changes while the program waits for it to expire
+        let mut computer = computer(
+            "main: ld 0, %b\npoll: in [50], %a\n    add %a, %b\n    cmp 3, %b\n    jne poll\n    reset\n",
+        );
+        computer.registers.sr.insert(StatusRegister::SUPERVISOR);
+        computer.attach(50, Timer::new(50)).unwrap();
+        assert!(LoopDetector::supports(&computer));
+        assert_eq!(detect_on(computer), None);
+    }
+
+    #[test]
+    fn self_modifying_code_test() {
+        let computer = computer("main: reset\n");
+        let mut modified = computer.clone();
+        let instruction = Instruction::Neg(Reg::A);
+        *modified.memory.get_mut(PROGRAM_START).unwrap() = Cell::Instruction(Box::new(instruction));
+        assert_ne!(hash_state(&computer), hash_state(&modified));
+    }
+
+    #[test]
+    fn host_device_test() {
+        // The host may send a message at any time, which would end the loop
+        let mut computer = computer("main: jmp main\n");
+        let (_, endpoint) = mailbox(1);
+        computer.attach(10, endpoint).unwrap();
+        assert!(!LoopDetector::supports(&computer));
+        assert_eq!(detect_on(computer), None);
+    }
+}
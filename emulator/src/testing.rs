@@ -0,0 +1,22 @@
+//! Helpers shared by the tests of the crate
+
+/// Deterministic pseudo-random numbers, from a xorshift generator, to build fuzzing inputs
+pub(crate) struct XorShift(u64);
+
+impl XorShift {
+    /// A generator starting from a non-zero seed
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// The next number, below a bound
+    pub(crate) fn below(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        // The remainder is below the bound, which is a `usize`
+        #[allow(clippy::cast_possible_truncation)]
+        let value = (self.0 % bound as u64) as usize;
+        value
+    }
+}
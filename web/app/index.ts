@@ -10,12 +10,29 @@ const samples = {
   handler: () => import("../../samples/handler.S"),
 };
 
+type StackEntry = {
+  address: number;
+  offset: number;
+  value: string;
+  frame: number;
+  annotation?: string;
+};
+
+type MemoryArea = {
+  range: { start: number; end: number };
+  kind: "code" | "data" | "string" | "reserved" | "stack" | "vectors";
+  labels: Array<[string, number]>;
+};
+
 type Output = {
   preprocessed?: Array<[number, string]>;
+  memory_map?: Array<MemoryArea>;
   error?: string;
-  memory?: Array<[number, string]>;
+  stack?: Array<StackEntry>;
   registers?: string;
   instructions?: Array<string>;
+  instructions_dropped?: number;
+  state?: string;
 };
 
 const createSection = (title: string, parent: Element): HTMLOutputElement => {
@@ -53,14 +70,16 @@ const createErrorSection = (title: string, parent: Element): HTMLDivElement => {
   root.appendChild(result);
 
   const consoleOutput = createErrorSection("Console", result);
-  const memoryOutput = createSection("Memory", result);
+  const stateOutput = createErrorSection("State", result);
+  const stackOutput = createSection("Stack", result);
   const instructionsOutput = createSection("Instructions", result);
   const preprocessorOutput = createSection("Preprocessor", result);
+  const memoryMapOutput = createErrorSection("Memory map", result);
   consoleOutput.innerHTML = "Loading compiler...";
 
   document.body.appendChild(root);
 
-  const { dump } = await bindings();
+  const { dump, artifacts, Runner } = await bindings();
 
   const monaco = await import("./monaco");
   editorContainer.classList.remove("loading");
@@ -87,6 +106,71 @@ const createErrorSection = (title: string, parent: Element): HTMLDivElement => {
     selector.appendChild(button);
   });
 
+  const download = (name: string, content: BlobPart) => {
+    const link = document.createElement("a");
+    link.href = URL.createObjectURL(new Blob([content]));
+    link.download = name;
+    link.click();
+    URL.revokeObjectURL(link.href);
+  };
+
+  const downloadButton = document.createElement("button");
+  downloadButton.appendChild(document.createTextNode("Download compiled output"));
+  downloadButton.addEventListener("click", () => {
+    try {
+      const output = artifacts(model.getValue());
+      download("program.lst", output.listing);
+      download("program.map", output.map);
+      download("program.bin", output.image);
+      output.free();
+    } catch (e) {
+      window.alert(`Could not compile the program: ${e}`);
+    }
+  });
+  selector.appendChild(downloadButton);
+
+  // Run the program in slices, yielding to the browser in between so its output shows up as it
+  // gets written
+  const runAsync = async (source: string) => {
+    const runner = new Runner(source);
+    consoleOutput.textContent = "";
+    runner.on_output((channel: string, text: string) => {
+      const span = document.createElement("span");
+      span.classList.add(channel);
+      span.textContent = text;
+      consoleOutput.appendChild(span);
+    });
+
+    try {
+      while (!runner.run_slice(1000)) {
+        const breakpoint = runner.take_breakpoint();
+        if (breakpoint !== undefined) {
+          consoleOutput.appendChild(
+            document.createTextNode(`\n[brk at ${breakpoint}] ${runner.registers()}\n`),
+          );
+        }
+        await new Promise((resolve) => setTimeout(resolve, 0));
+      }
+      consoleOutput.appendChild(document.createTextNode(`\n\n${runner.registers()}`));
+    } finally {
+      runner.free();
+    }
+  };
+
+  const runButton = document.createElement("button");
+  runButton.appendChild(document.createTextNode("Run"));
+  runButton.addEventListener("click", async () => {
+    runButton.disabled = true;
+    try {
+      await runAsync(model.getValue());
+    } catch (e) {
+      consoleOutput.appendChild(document.createTextNode(`\n${e}`));
+    } finally {
+      runButton.disabled = false;
+    }
+  });
+  selector.appendChild(runButton);
+
   const editor = document.createElement("div");
   editor.classList.add("editor");
   editorContainer.appendChild(editor);
@@ -110,14 +194,35 @@ const createErrorSection = (title: string, parent: Element): HTMLDivElement => {
       consoleOutput.innerHTML = v;
     }
 
+    stateOutput.innerHTML = output.state || "-";
+
     preprocessorOutput.value = (output.preprocessed
       ? output.preprocessed.map(([k, v]) => `${k}\t${v}`).join("\n")
       : "-"
     );
 
-    instructionsOutput.value = (output.instructions || ["-"]).join("\n");
-    memoryOutput.value = (output.memory
-      ? output.memory.map(([k, v]) => `${k}:\t${v}`).join("\n") 
+    // High addresses at the top, like the stack grows down
+    memoryMapOutput.replaceChildren(
+      ...(output.memory_map || []).slice().reverse().map(({ range, kind, labels }) => {
+        const area = document.createElement("div");
+        area.classList.add("area", `area-${kind}`);
+        const names = labels.map(([name, address]) => `${name} (${address})`).join(", ");
+        area.textContent = `${range.start}-${range.end - 1}\t${kind} (${range.end - range.start})${names ? `\t${names}` : ""}`;
+        return area;
+      }),
+    );
+
+    const dropped = output.instructions_dropped
+      ? [`(${output.instructions_dropped} earlier instructions not shown)`]
+      : [];
+    instructionsOutput.value = dropped
+      .concat(output.instructions || ["-"])
+      .join("\n");
+    stackOutput.value = (output.stack && output.stack.length
+      ? output.stack
+          .map(({ address, offset, value, annotation }) =>
+            `${address}\t%sp+${offset}\t${value}${annotation ? `\t; ${annotation}` : ""}`)
+          .join("\n")
       : "-"
     );
   };
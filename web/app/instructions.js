@@ -4,8 +4,12 @@ const Instructions = [
     'add',
     /// Bitwise `and` with a given value
     'and',
+    /// Stop the execution and give the control back to the debugger
+    'brk',
     /// Push `%pc` and go to the given address
     'call',
+    /// Store a register in a memory cell if it equals `%a`, or load this cell in `%a`
+    'cas',
     /// Compare a value with a register
     'cmp',
     /// Divide a register by a value
@@ -28,10 +32,21 @@ const Instructions = [
     'jge',
     /// Jump if strictly greater
     'jgt',
+    /// Jump if less or equal, as unsigned words
+    'jleu',
+    /// Jump if strictly less, as unsigned words
+    'jltu',
+    /// Jump if greater or equal, as unsigned words
+    'jgeu',
+    /// Jump if strictly greater, as unsigned words
+    'jgtu',
     /// Load a register with a value
     'ld',
     /// Multiply a value to a register
     'mul',
+    /// Remainder of the division of a register by a value
+    'mod',
+    /// Negate a register
     'neg',
     /// No-op
     'nop',
@@ -51,6 +66,10 @@ const Instructions = [
     'rti',
     /// Return from a `call`
     'rtn',
+    /// Bitwise rotation to the left
+    'rol',
+    /// Bitwise rotation to the right
+    'ror',
     /// Bitshift to the left
     'shl',
     /// Bitshift to the right
@@ -65,6 +84,8 @@ const Instructions = [
     'trap',
     /// Bitwise `xor` with a given value
     'xor',
+    /// Stop the processor, which stays on this instruction
+    'halt',
 ]
 
 
@@ -2,6 +2,7 @@
 #![deny(clippy::all, clippy::pedantic)]
 #![allow(clippy::missing_errors_doc, clippy::missing_panics_doc)]
 
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::path::PathBuf;
 
@@ -14,12 +15,17 @@ use z33_emulator::{
     compiler::CompilationError,
     runtime::ProcessorError,
     compiler::layout,
+    compiler::artifacts,
+    compiler::MemoryArea,
+    compiler::Symbols,
     runtime::Exception::HardwareInterrupt,
     constants as C,
     parser::location::{AbsoluteLocation, MapLocation},
     preprocessor::{InMemoryFilesystem, Preprocessor},
     runtime::Registers,
     runtime::Computer,
+    runtime::Cell,
+    runtime::{Channel, Console, ConsoleOutput, MachineConfig, RingBuffer},
 };
 use codespan_reporting::files::SimpleFiles;
 use codespan_reporting::diagnostic::{Diagnostic, Label};
@@ -27,15 +33,162 @@ use codespan_reporting::diagnostic::{Diagnostic, Label};
 #[derive(Default, Serialize)]
 struct Output {
     preprocessed: Vec<(C::Address, String)>,
-    memory: Option<Vec<(u32, String)>>,
+    /// Areas of the memory map, by kind of content, with the labels pointing into them
+    memory_map: Vec<MemoryArea>,
+    stack: Option<Vec<StackEntry>>,
     error: Option<String>,
     registers: Option<String>,
+    /// The last executed instructions
     instructions: Option<Vec<String>>,
+    /// Number of earlier executed instructions which were not kept
+    instructions_dropped: usize,
+    /// HTML rendering of the registers and memory around %pc at the end of the run
+    state: Option<String>,
 }
 
+/// A cell of the stack, relative to the stack pointer
+#[derive(Serialize)]
+struct StackEntry {
+    address: C::Address,
+    /// Offset from `%sp`
+    offset: C::Address,
+    value: String,
+    /// Index of the call frame, 0 being the innermost
+    frame: usize,
+    annotation: Option<String>,
+}
+
+thread_local! {
+    /// The computer and symbols of the last program ran by `dump`, kept to inspect its memory
+    static LAST_RUN: RefCell<Option<(Computer, Symbols)>> = const { RefCell::new(None) };
+}
+
+/// List the cells between `%sp` and the bottom of the stack, annotating return addresses
+fn stack_view(computer: &Computer, symbols: &Symbols) -> Vec<StackEntry> {
+    let sp = computer.registers.sp;
+    let mut frame = 0;
+    let mut entries = Vec::new();
+    for address in sp..C::STACK_START {
+        let Ok(cell) = computer.memory.get(address) else { break };
+
+        // A return address is a word pointing right after a `call` instruction
+        let return_address = match cell {
+            Cell::Word(w) => C::Address::try_from(*w)
+                .ok()
+                .filter(|&a| a > 0)
+                .filter(|&a| {
+                    let previous = computer.memory.get(a - 1).ok();
+                    matches!(previous.map(|c| c.extract_instruction()), Some(Ok(i)) if i.is_call())
+                }),
+            _ => None,
+        };
+
+        let annotation = if let Some(target) = return_address {
+            Some(format!("return address ({})", symbols.describe(target)))
+        } else if address == sp {
+            Some(String::from("%sp"))
+        } else {
+            None
+        };
+
+        entries.push(StackEntry {
+            address,
+            offset: address - sp,
+            value: cell.to_string(),
+            frame,
+            annotation,
+        });
+
+        if return_address.is_some() {
+            frame += 1;
+        }
+    }
+    entries
+}
+
+/// Compiled output of a program, which can be downloaded from the playground
+#[wasm_bindgen(getter_with_clone)]
+pub struct Artifacts {
+    pub listing: String,
+    pub map: String,
+    pub image: Vec<u8>,
+}
+
+/// Compile a program and produce its listing, label map and memory image
+#[wasm_bindgen]
+pub fn artifacts(source: &str) -> Result<Artifacts, JsValue> {
+    let path = PathBuf::from("-");
+    let files = HashMap::from([(path.clone(), source.to_string())]);
+    let preprocessor = Preprocessor::new(InMemoryFilesystem::new(files)).and_load(&path);
+    let source = preprocessor
+        .preprocess(&path)
+        .map_err(|e| e.to_string())?;
+
+    let program = parse(&source).map_err(|e| e.to_string())?;
+    let program = program.map_location(&AbsoluteLocation::<()>::default());
+    let (computer, debug_info) = compile(program.inner, "main").map_err(|e| e.to_string())?;
+
+    Ok(Artifacts {
+        listing: artifacts::listing(&source, &computer, &debug_info),
+        map: artifacts::map(&debug_info),
+        image: artifacts::image(&computer),
+    })
+}
+
+/// Preprocess a program and tell where each line of the output comes from
+#[wasm_bindgen]
+pub fn provenance(source: &str) -> Result<JsValue, JsValue> {
+    let path = PathBuf::from("-");
+    let files = HashMap::from([(path.clone(), source.to_string())]);
+    let preprocessor = Preprocessor::new(InMemoryFilesystem::new(files)).and_load(&path);
+    let (_, map) = preprocessor
+        .preprocess_with_map(&path)
+        .map_err(|e| e.to_string())?;
+
+    let lines: Vec<_> = map.lines().collect();
+    Ok(serde_wasm_bindgen::to_value(&lines)?)
+}
+
+/// The stack of the last program ran by `dump`, relative to `%sp`
+#[wasm_bindgen]
+pub fn stack() -> Result<JsValue, JsValue> {
+    LAST_RUN.with(|last| {
+        let last = last.borrow();
+        let (computer, symbols) = last.as_ref().ok_or("no program was run")?;
+        Ok(serde_wasm_bindgen::to_value(&stack_view(computer, symbols))?)
+    })
+}
+
+/// Read `len` memory cells from `start` in the memory of the last program ran by `dump`, with
+/// the symbol naming their address, if any
+#[wasm_bindgen]
+pub fn read_range(start: C::Address, len: C::Address) -> Result<JsValue, JsValue> {
+    LAST_RUN.with(|last| {
+        let last = last.borrow();
+        let (computer, symbols) = last.as_ref().ok_or("no program was run")?;
+        let end = start.saturating_add(len).min(C::MEMORY_SIZE);
+        let cells: Vec<_> = (start..end)
+            .filter_map(|address| {
+                let cell = computer.memory.get(address).ok()?;
+                let symbol = symbols.lookup(address).map(|s| s.to_string());
+                Some((address, symbol, cell.to_string()))
+            })
+            .collect();
+        Ok(serde_wasm_bindgen::to_value(&cells)?)
+    })
+}
 
-fn computer_steps(computer: &mut Computer, steps: u32) -> (Vec<String>, Result<(), ProcessorError>) {
-    let mut instructions = Vec::<String>::new();
+
+/// Number of executed instructions kept by `dump` by default
+const DEFAULT_TRACE_CAPACITY: usize = 1000;
+
+/// Run a program, keeping the last executed instructions in a buffer of the given capacity
+fn computer_steps(
+    computer: &mut Computer,
+    steps: u32,
+    capacity: usize,
+) -> (RingBuffer<String>, Result<(), ProcessorError>) {
+    let mut instructions = RingBuffer::new(capacity);
 
     for _ in 0..steps {
         let next_inst = computer.next_instruction();
@@ -47,22 +200,135 @@ fn computer_steps(computer: &mut Computer, steps: u32) -> (Vec<String>, Result<(
             }
         }
         match computer.step() {
-            Ok(_) => {}
-            Err(ProcessorError::Reset) => return (instructions, Ok(())),
+            Ok(_) | Err(ProcessorError::Breakpoint) => {}
+            Err(ProcessorError::Reset | ProcessorError::Halt) => return (instructions, Ok(())),
             Err(v) => return (instructions, Err(v)),
         }
     };
     (instructions, Err(ProcessorError::Exception(HardwareInterrupt)))
 }
 
-fn char_offset(a: &str, b: &str) -> usize {
-    let a = a.as_ptr();
-    let b = b.as_ptr();
-    b as usize - a as usize
+/// Maximum number of instructions executed by a `Runner`
+const RUNNER_MAX_STEPS: usize = 100_000_000;
+
+/// Maximum number of bytes written on the console by a `Runner`
+const RUNNER_MAX_OUTPUT_BYTES: usize = 1 << 20;
+
+/// A program running in slices of steps, for the page to stay responsive while it runs
+///
+/// Text written by the program on the console device (ports 0 and 1) is passed to the output
+/// callback after each slice, or can be polled with `take_stdout` and `take_stderr`.
+#[wasm_bindgen]
+pub struct Runner {
+    computer: Computer,
+    /// Labels naming the address of the errors
+    symbols: Symbols,
+    output: ConsoleOutput,
+    callback: Option<js_sys::Function>,
+    finished: bool,
+    breakpoint: Option<C::Address>,
+}
+
+#[wasm_bindgen]
+impl Runner {
+    #[wasm_bindgen(constructor)]
+    pub fn new(source: &str) -> Result<Runner, JsValue> {
+        let mut files = HashMap::new();
+        let path = PathBuf::from("-");
+        files.insert(path.clone(), source.to_string());
+        let preprocessor = Preprocessor::new(InMemoryFilesystem::new(files)).and_load(&path);
+        let source = preprocessor.preprocess(&path).map_err(|e| e.to_string())?;
+
+        let program = parse(&source).map_err(|e| e.to_string())?;
+        let program = program.map_location(&AbsoluteLocation::<()>::default());
+        let (mut computer, debug_info) =
+            compile(program.inner, "main").map_err(|e| e.to_string())?;
+
+        let (console, output) = Console::new();
+        computer.attach(0, console).map_err(|e| e.to_string())?;
+        computer.set_config(MachineConfig {
+            max_steps: Some(RUNNER_MAX_STEPS),
+            max_output_bytes: Some(RUNNER_MAX_OUTPUT_BYTES),
+            ..MachineConfig::default()
+        });
+
+        Ok(Runner {
+            computer,
+            symbols: debug_info.symbols(),
+            output,
+            callback: None,
+            finished: false,
+            breakpoint: None,
+        })
+    }
+
+    /// Address of the `brk` instruction which stopped the last slice, if any
+    pub fn take_breakpoint(&mut self) -> Option<C::Address> {
+        self.breakpoint.take()
+    }
+
+    /// Set a function called with the channel ("stdout" or "stderr") and the text written on it
+    pub fn on_output(&mut self, callback: js_sys::Function) {
+        self.callback = Some(callback);
+    }
+
+    /// Execute up to `steps` instructions, returns `true` once the program is finished
+    pub fn run_slice(&mut self, steps: u32) -> Result<bool, JsValue> {
+        let mut result = Ok(());
+        for _ in 0..steps {
+            if self.finished {
+                break;
+            }
+
+            match self.computer.step() {
+                Ok(()) => {}
+                Err(ProcessorError::Reset | ProcessorError::Halt) => self.finished = true,
+                // Give the control back to the page, which resumes with the next slice
+                Err(ProcessorError::Breakpoint) => {
+                    self.breakpoint = Some(self.computer.registers.pc - 1);
+                    break;
+                }
+                Err(e) => {
+                    self.finished = true;
+                    result = Err(e);
+                }
+            }
+        }
+
+        if let Some(callback) = &self.callback {
+            for (channel, name) in [(Channel::Stdout, "stdout"), (Channel::Stderr, "stderr")] {
+                let text = self.output.take(channel);
+                if !text.is_empty() {
+                    callback.call2(&JsValue::NULL, &JsValue::from_str(name), &JsValue::from_str(&text))?;
+                }
+            }
+        }
+
+        result.map_err(|e| JsValue::from_str(&e.with_symbols(&self.symbols).to_string()))?;
+        Ok(self.finished)
+    }
+
+    /// Take the text written on the standard output since the last call
+    #[must_use]
+    pub fn take_stdout(&self) -> String {
+        self.output.take(Channel::Stdout)
+    }
+
+    /// Take the text written on the error output since the last call
+    #[must_use]
+    pub fn take_stderr(&self) -> String {
+        self.output.take(Channel::Stderr)
+    }
+
+    #[must_use]
+    pub fn registers(&self) -> String {
+        self.computer.registers.to_string()
+    }
 }
 
+/// Compile and run a program, keeping the last `trace_capacity` executed instructions
 #[wasm_bindgen]
-pub fn dump(source: &str) -> Result<JsValue, JsValue> {
+pub fn dump(source: &str, trace_capacity: Option<usize>) -> Result<JsValue, JsValue> {
     let mut output = Output::default();
     let mut files = HashMap::new();
     let path = PathBuf::from("-");
@@ -86,7 +352,7 @@ pub fn dump(source: &str) -> Result<JsValue, JsValue> {
     let mut files = SimpleFiles::new();
     let file_id = files.add("preprocessed", source);
 
-    let program = parse(&source); // TODO: the error is tied to the input
+    let program = parse(&source);
 
     let program = match program {
         Ok(p) => p,
@@ -96,16 +362,13 @@ pub fn dump(source: &str) -> Result<JsValue, JsValue> {
             let labels: Vec<_> = e
                 .errors
                 .iter()
-                .map(|(location, kind)| {
-                    let message = match kind {
-                        nom::error::VerboseErrorKind::Context(s) => (*s).to_owned(),
-                        nom::error::VerboseErrorKind::Char(c) => format!("expected '{c}'"),
-                        nom::error::VerboseErrorKind::Nom(code) => format!("{code:?}"),
-                    };
-                    let offset = char_offset(source, location);
-
-                    Label::primary(file_id, offset..offset).with_message(message)
+                .map(|(offset, kind)| {
+                    Label::primary(file_id, *offset..*offset).with_message(kind.to_string())
                 })
+                .chain(e.suggestions.iter().map(|suggestion| {
+                    let span = suggestion.offset..(suggestion.offset + suggestion.length);
+                    Label::secondary(file_id, span).with_message(suggestion.to_string())
+                }))
                 .collect();
             let diagnostic = Diagnostic::error().with_message(msg).with_labels(labels);
 
@@ -134,6 +397,7 @@ pub fn dump(source: &str) -> Result<JsValue, JsValue> {
 
     let layout = layout.unwrap();
     output.preprocessed = layout.memory_report();
+    output.memory_map = layout.memory_map();
 
 
     // Compile the Program
@@ -160,14 +424,16 @@ pub fn dump(source: &str) -> Result<JsValue, JsValue> {
                 },
             };
             if let Some(location) = location {
-                let label = Label::primary(
-                    file_id,
-                    location.offset..(location.offset + location.length),
-                );
+                let mut labels = vec![Label::primary(file_id, location.span())];
+                if let CompilationError::MemoryLayout(e) = &e {
+                    labels.extend(e.previous_location().map(|previous| {
+                        Label::secondary(file_id, previous.span()).with_message("previously filled here")
+                    }));
+                }
 
                 let diagnostic = Diagnostic::error()
                     .with_message(msg)
-                    .with_labels(vec![label]);
+                    .with_labels(labels);
 
                 let mut buf = [0u8; 1024];
                 let mut bufWrt =  codespan_reporting::term::termcolor::Ansi::new(&mut buf as &mut [u8]);
@@ -195,32 +461,29 @@ pub fn dump(source: &str) -> Result<JsValue, JsValue> {
 
     // Execute the program
 
-    let (steps, status) = computer_steps(&mut computer, 1000);
+    let capacity = trace_capacity.unwrap_or(DEFAULT_TRACE_CAPACITY);
+    let (steps, status) = computer_steps(&mut computer, 1000, capacity);
+    let (instructions, dropped) = steps.into_parts();
+    output.instructions = Some(instructions);
+    output.instructions_dropped = dropped;
+    let symbols = debug_info.symbols();
+    output.stack = Some(stack_view(&computer, &symbols));
+
+    let registers = format!("{:?}", computer.registers);
+    output.state = Some(computer.render_html());
+
+    let status = status.map_err(|e| e.with_symbols(&symbols));
+    LAST_RUN.with(|last| *last.borrow_mut() = Some((computer, symbols)));
+
     match status {
         Ok(()) => {},
         Err(e) => {
-            output.error = Some(format!("{e:#?}"));
-            output.instructions = Some(steps);
+            output.error = Some(format!("{e}"));
             return Ok(serde_wasm_bindgen::to_value(&output)?);
         }
     };
-    output.instructions = Some(steps);
-
-    let mut memory = Vec::new();
-    for i in (9980..10000).rev() {
-        match computer.memory.get(i) {
-            Ok(value) => match value {
-                //Empty => break,
-                _ => memory.push((i, format!("{:?}", value))),
-            },
-            Err(_) => { 
-                memory.push((0, format!("Err")));  //break
-            },
-        }
-    }
-    output.registers = Some(format!("<b><span style=\"color:#35cc5d\">Execution: OK</span></b>\n\n{:?}", computer.registers));
 
-    output.memory = Some(memory);
+    output.registers = Some(format!("<b><span style=\"color:#35cc5d\">Execution: OK</span></b>\n\n{registers}"));
 
     Ok(serde_wasm_bindgen::to_value(&output)?)
 }